@@ -1,3 +1,9 @@
 fn main() {
+    //migrations are embedded into binaries
+    println!("cargo:rerun-if-changed=migrations");
+    //prefer the protoc specified by PROTOC, fallback to the vendored one
+    if std::env::var_os("PROTOC").is_none() {
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+    }
     tonic_build::compile_protos("proto/signatrust.proto").unwrap();
 }
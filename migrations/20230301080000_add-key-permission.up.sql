@@ -0,0 +1,12 @@
+CREATE TABLE data_key_permission (
+                          id INT AUTO_INCREMENT,
+                          key_id INT NOT NULL,
+                          principal VARCHAR(100) NOT NULL,
+                          role VARCHAR(10) NOT NULL,
+                          PRIMARY KEY(id),
+                          UNIQUE KEY(key_id, principal, role),
+                          FOREIGN KEY (key_id) REFERENCES data_key(id)
+);
+
+-- existing keys are owned by the email they were registered with
+INSERT INTO data_key_permission(key_id, principal, role) SELECT id, email, 'owner' FROM data_key;
@@ -0,0 +1,19 @@
+CREATE TABLE sign_record (
+                          id INT AUTO_INCREMENT,
+                          key_id INT NOT NULL,
+                          key_name VARCHAR(100) NOT NULL,
+                          key_type VARCHAR(10) NOT NULL,
+                          action VARCHAR(20) NOT NULL,
+                          principal VARCHAR(100) NOT NULL,
+                          user_id INT,
+                          file_name VARCHAR(400) NOT NULL,
+                          digest VARCHAR(128) NOT NULL,
+                          detail VARCHAR(400) NOT NULL,
+                          succeed BOOLEAN NOT NULL,
+                          error VARCHAR(1000) NOT NULL,
+                          create_at DATETIME NOT NULL,
+                          PRIMARY KEY(id),
+                          INDEX(key_id, create_at),
+                          INDEX(principal, create_at),
+                          INDEX(create_at)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE timestamp_record (
+                          id BIGINT AUTO_INCREMENT,
+                          key_id INT NOT NULL,
+                          policy VARCHAR(100) NOT NULL,
+                          hash_algorithm VARCHAR(20) NOT NULL,
+                          message_imprint VARCHAR(128) NOT NULL,
+                          nonce VARCHAR(128),
+                          client_address VARCHAR(100) NOT NULL,
+                          gen_time DATETIME NOT NULL,
+                          PRIMARY KEY(id),
+                          INDEX(key_id, gen_time),
+                          INDEX(message_imprint)
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX data_key_update_at ON data_key;
+ALTER TABLE data_key DROP COLUMN update_at;
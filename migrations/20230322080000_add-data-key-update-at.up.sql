@@ -0,0 +1,2 @@
+ALTER TABLE data_key ADD COLUMN update_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP;
+CREATE INDEX data_key_update_at ON data_key(update_at);
@@ -0,0 +1,44 @@
+CREATE TABLE cluster_key (
+                         id SERIAL,
+                         data BYTEA NOT NULL,
+                         algorithm VARCHAR(30),
+                         identity VARCHAR(40) UNIQUE,
+                         create_at TIMESTAMPTZ,
+                         expire_at TIMESTAMPTZ,
+                         PRIMARY KEY(id)
+);
+
+CREATE TABLE data_key (
+                          id SERIAL,
+                          name VARCHAR(100) UNIQUE NOT NULL,
+                          description VARCHAR(200),
+                          "user" VARCHAR(40) NOT NULL,
+                          email VARCHAR(40) NOT NULL,
+                          attributes VARCHAR(1000),
+                          key_type VARCHAR(10) NOT NULL,
+                          private_key TEXT,
+                          public_key TEXT,
+                          certificate TEXT,
+                          create_at TIMESTAMPTZ,
+                          expire_at TIMESTAMPTZ,
+                          key_state VARCHAR(10) NOT NULL,
+                          soft_delete BOOLEAN NOT NULL default FALSE,
+                          PRIMARY KEY(id)
+);
+
+CREATE TABLE "user" (
+                          id SERIAL,
+                          email VARCHAR(60) UNIQUE,
+                          PRIMARY KEY(id)
+);
+
+
+CREATE TABLE token (
+                          id SERIAL,
+                          user_id INT NOT NULL,
+                          token VARCHAR(200),
+                          expire_at TIMESTAMPTZ,
+                          PRIMARY KEY(id),
+                          FOREIGN KEY (user_id) REFERENCES "user"(id)
+
+);
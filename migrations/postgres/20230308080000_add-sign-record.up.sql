@@ -0,0 +1,19 @@
+CREATE TABLE sign_record (
+                          id SERIAL,
+                          key_id INT NOT NULL,
+                          key_name VARCHAR(100) NOT NULL,
+                          key_type VARCHAR(10) NOT NULL,
+                          action VARCHAR(20) NOT NULL,
+                          principal VARCHAR(100) NOT NULL,
+                          user_id INT,
+                          file_name VARCHAR(400) NOT NULL,
+                          digest VARCHAR(128) NOT NULL,
+                          detail VARCHAR(400) NOT NULL,
+                          succeed BOOLEAN NOT NULL,
+                          error VARCHAR(1000) NOT NULL,
+                          create_at TIMESTAMPTZ NOT NULL,
+                          PRIMARY KEY(id)
+);
+CREATE INDEX sign_record_key_id_create_at ON sign_record(key_id, create_at);
+CREATE INDEX sign_record_principal_create_at ON sign_record(principal, create_at);
+CREATE INDEX sign_record_create_at ON sign_record(create_at);
@@ -0,0 +1,13 @@
+CREATE TABLE timestamp_record (
+                          id BIGSERIAL,
+                          key_id INT NOT NULL,
+                          policy VARCHAR(100) NOT NULL,
+                          hash_algorithm VARCHAR(20) NOT NULL,
+                          message_imprint VARCHAR(128) NOT NULL,
+                          nonce VARCHAR(128),
+                          client_address VARCHAR(100) NOT NULL,
+                          gen_time TIMESTAMPTZ NOT NULL,
+                          PRIMARY KEY(id)
+);
+CREATE INDEX timestamp_record_key_id_gen_time ON timestamp_record(key_id, gen_time);
+CREATE INDEX timestamp_record_message_imprint ON timestamp_record(message_imprint);
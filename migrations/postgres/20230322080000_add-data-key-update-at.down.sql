@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS data_key_update_at;
+ALTER TABLE data_key DROP COLUMN update_at;
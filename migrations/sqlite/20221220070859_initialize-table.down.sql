@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS token;
+DROP TABLE IF EXISTS user;
+DROP TABLE IF EXISTS data_key;
+DROP TABLE IF EXISTS cluster_key;
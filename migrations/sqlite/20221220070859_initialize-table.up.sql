@@ -0,0 +1,40 @@
+CREATE TABLE cluster_key (
+                         id INTEGER PRIMARY KEY AUTOINCREMENT,
+                         data BLOB NOT NULL,
+                         algorithm VARCHAR(30),
+                         identity VARCHAR(40) UNIQUE,
+                         create_at DATETIME,
+                         expire_at DATETIME
+);
+
+CREATE TABLE data_key (
+                          id INTEGER PRIMARY KEY AUTOINCREMENT,
+                          name VARCHAR(100) UNIQUE NOT NULL,
+                          description VARCHAR(200),
+                          user VARCHAR(40) NOT NULL,
+                          email VARCHAR(40) NOT NULL,
+                          attributes VARCHAR(1000),
+                          key_type VARCHAR(10) NOT NULL,
+                          private_key TEXT,
+                          public_key TEXT,
+                          certificate TEXT,
+                          create_at DATETIME,
+                          expire_at DATETIME,
+                          key_state VARCHAR(10) NOT NULL,
+                          soft_delete BOOLEAN NOT NULL default 0
+);
+
+CREATE TABLE user (
+                          id INTEGER PRIMARY KEY AUTOINCREMENT,
+                          email VARCHAR(60) UNIQUE
+);
+
+
+CREATE TABLE token (
+                          id INTEGER PRIMARY KEY AUTOINCREMENT,
+                          user_id INT NOT NULL,
+                          token VARCHAR(200),
+                          expire_at DATETIME,
+                          FOREIGN KEY (user_id) REFERENCES user(id)
+
+);
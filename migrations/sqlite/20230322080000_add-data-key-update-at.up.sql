@@ -0,0 +1,3 @@
+-- datetime is stored as text in the same format of the values bound by signatrust
+ALTER TABLE data_key ADD COLUMN update_at DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now'));
+CREATE INDEX data_key_update_at ON data_key(update_at);
@@ -0,0 +1,156 @@
+use crate::domain::audit::entity::{AuditFilter, SignRecord};
+use crate::domain::audit::repository::Repository as AuditRepository;
+use crate::presentation::handler::control::model::user::dto::UserIdentity;
+use crate::util::error::{Error, Result};
+use crate::util::metrics;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::{watch, Mutex};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
+
+//records waiting to be written, the audited operations only wait for the database once it's full
+const AUDIT_QUEUE_SIZE: usize = 10000;
+//12 values are bound for each record, which is kept below the 999 variables limit of sqlite
+const AUDIT_BATCH_SIZE: usize = 64;
+//the operation fails when its record can't be queued within this period
+const AUDIT_ENQUEUE_TIMEOUT: Duration = Duration::from_secs(5);
+const AUDIT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const AUDIT_MAX_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+//the failed batch is retried at most these times once the writer is closing, so that the server can exit
+const AUDIT_CLOSE_RETRIES: usize = 3;
+
+#[async_trait]
+pub trait AuditService: Send + Sync {
+    async fn get_records(&self, user: &UserIdentity, filter: AuditFilter) -> Result<Vec<SignRecord>>;
+}
+
+pub struct DBAuditService<R>
+where
+    R: AuditRepository
+{
+    repository: R,
+}
+
+impl<R> DBAuditService<R>
+    where
+        R: AuditRepository
+{
+    pub fn new(repository: R) -> Self {
+        Self {
+            repository
+        }
+    }
+}
+
+#[async_trait]
+impl<R> AuditService for DBAuditService<R>
+where
+    R: AuditRepository
+{
+    //user can only query the records of keys owned or administrated by himself and the actions performed by himself
+    async fn get_records(&self, user: &UserIdentity, mut filter: AuditFilter) -> Result<Vec<SignRecord>> {
+        filter.visible_to = Some(user.email.clone());
+        self.repository.query(filter).await
+    }
+}
+
+/// Audit records are queued and inserted in batches by the background task, so that the latency of database isn't
+/// added to the audited operations. The batches failed to insert are retried until they are saved, once the queue is
+/// full, i.e. the database can't keep up, the operations wait for the queue and fail when their records can't be queued
+/// in `AUDIT_ENQUEUE_TIMEOUT`, so that no operation is left unaudited. The records still queued are written when the
+/// writer is closed on graceful shutdown, only the ones which can't be saved even then are dropped with error logged
+/// and counted in `signatrust_audit_records_dropped_total`.
+#[derive(Clone)]
+pub struct AuditWriter {
+    sender: Sender<SignRecord>,
+    closing: Arc<watch::Sender<bool>>,
+    task: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl AuditWriter {
+    pub fn new<R>(repository: R) -> Self
+    where
+        R: AuditRepository + 'static
+    {
+        let (sender, receiver) = mpsc::channel(AUDIT_QUEUE_SIZE);
+        let closing = Arc::new(watch::channel(false).0);
+        let task = tokio::spawn(AuditWriter::write_batches(repository, receiver, closing.clone()));
+        Self {
+            sender,
+            closing,
+            task: Arc::new(Mutex::new(Some(task))),
+        }
+    }
+
+    pub async fn write(&self, record: SignRecord) -> Result<()> {
+        let err = match timeout(AUDIT_ENQUEUE_TIMEOUT, self.sender.send(record)).await {
+            Ok(Ok(_)) => return Ok(()),
+            Ok(Err(_)) => Error::AuditError("audit writer is closed".to_string()),
+            Err(_) => Error::AuditError("audit queue is full".to_string()),
+        };
+        metrics::AUDIT_RECORDS_DROPPED.inc();
+        error!("failed to queue audit record: {}", err);
+        Err(err)
+    }
+
+    //stop accepting records and wait until the queued ones are written, called on graceful shutdown
+    pub async fn close(&self) {
+        self.closing.send_replace(true);
+        if let Some(task) = self.task.lock().await.take() {
+            if let Err(err) = task.await {
+                error!("audit writer exits abnormally: {}", err);
+            }
+        }
+    }
+
+    //the task holds the closing sender as well, so that waiting for the change never fails
+    async fn write_batches<R: AuditRepository>(repository: R, mut receiver: Receiver<SignRecord>, sender: Arc<watch::Sender<bool>>) {
+        let mut closing = sender.subscribe();
+        loop {
+            if *closing.borrow() {
+                //the records sent before close are still received
+                receiver.close();
+            }
+            let record = tokio::select! {
+                record = receiver.recv() => record,
+                _ = closing.changed(), if !*closing.borrow() => continue,
+            };
+            let Some(record) = record else {
+                break;
+            };
+            let mut records = vec![record];
+            while records.len() < AUDIT_BATCH_SIZE {
+                match receiver.try_recv() {
+                    Ok(record) => records.push(record),
+                    Err(_) => break,
+                }
+            }
+            AuditWriter::save_batch(&repository, records, &mut closing).await;
+        }
+    }
+
+    async fn save_batch<R: AuditRepository>(repository: &R, records: Vec<SignRecord>, closing: &mut watch::Receiver<bool>) {
+        let (count, mut attempts, mut interval) = (records.len(), 0, AUDIT_RETRY_INTERVAL);
+        loop {
+            let err = match repository.create_batch(records.clone()).await {
+                Ok(_) => return,
+                Err(err) => err,
+            };
+            attempts += 1;
+            if *closing.borrow() && attempts >= AUDIT_CLOSE_RETRIES {
+                metrics::AUDIT_RECORDS_DROPPED.inc_by(count as u64);
+                error!("failed to save {} audit records before exit, the records are dropped: {}", count, err);
+                return;
+            }
+            warn!("failed to save {} audit records, will retry in {:?}: {}", count, interval, err);
+            //closing the writer interrupts the wait, hence the server doesn't wait for the longest interval to exit
+            tokio::select! {
+                _ = sleep(interval) => {}
+                _ = closing.changed(), if !*closing.borrow() => {}
+            }
+            interval = (interval * 2).min(AUDIT_MAX_RETRY_INTERVAL);
+        }
+    }
+}
@@ -1,25 +1,85 @@
 
 
 use crate::domain::datakey::repository::Repository as DatakeyRepository;
+use crate::domain::audit::entity::{AuditAction, SignRecord};
+use crate::domain::audit::repository::Repository as AuditRepository;
+use crate::application::audit::AuditWriter;
 use crate::domain::sign_service::SignBackend;
-use crate::util::error::{Result};
+use crate::util::error::{Error, Result};
 use async_trait::async_trait;
-use crate::domain::datakey::entity::{DataKey, KeyState};
-use crate::presentation::handler::control::model::datakey::dto::DataKeyDTO;
+use crate::domain::datakey::entity::{DataKey, KeyFilter, KeyPage, KeyPermission, KeyRole, KeyState};
+use crate::presentation::handler::control::model::datakey::dto::{DataKeyDTO, ImportDataKeyDTO};
+use crate::presentation::handler::control::model::user::dto::UserIdentity;
 
 use crate::util::signer_container::DataKeyContainer;
 use std::collections::HashMap;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tokio::time::{Duration, interval};
+use crate::util::digest::DigestState;
+use crate::util::metrics;
+use std::time::Instant;
+use openssl::hash::{hash, MessageDigest};
+use crate::domain::sign_plugin::{SIGN_TYPE, TIMESTAMP_TOKEN};
+
+//file name of the signed content, sent by client in sign options
+const FILE_NAME: &str = "file_name";
+//principal recorded in audit for the operations performed by background jobs
+const SYSTEM_PRINCIPAL: &str = "system";
+//certificate principals are kept apart from user emails, a certificate named after an user doesn't inherit the user's roles
+pub const CERTIFICATE_PRINCIPAL_PREFIX: &str = "cert:";
+
+//caller of the data server, identified either by api token or by client certificate
+#[derive(Debug, Clone)]
+pub enum SignCaller {
+    User(UserIdentity),
+    //principal of the client certificate, see `SignCaller::certificate`
+    Certificate(String),
+    //built-in service such as the timestamp authority, it's only allowed to sign with the key configured for it
+    //and isn't constrained by the key permissions
+    Service { name: String, key_name: String },
+}
+
+impl SignCaller {
+    //the identity of client certificate is either its email address or common name
+    pub fn certificate(identity: &str) -> Self {
+        SignCaller::Certificate(format!("{}{}", CERTIFICATE_PRINCIPAL_PREFIX, identity))
+    }
+
+    pub fn principal(&self) -> &str {
+        match self {
+            SignCaller::User(user) => &user.email,
+            SignCaller::Certificate(identity) => identity,
+            SignCaller::Service { name, .. } => name,
+        }
+    }
+
+    pub fn user_id(&self) -> Option<i32> {
+        match self {
+            SignCaller::User(user) => Some(user.id),
+            SignCaller::Certificate(_) | SignCaller::Service { .. } => None,
+        }
+    }
+}
 
 #[async_trait]
 pub trait KeyService: Send + Sync{
-    async fn create(&self, data: DataKeyDTO) -> Result<DataKey>;
-    async fn get_all(&self) -> Result<Vec<DataKey>>;
-    async fn get_one(&self, id: i32) -> Result<DataKey>;
-    async fn delete_one(&self, id: i32) -> Result<()>;
-    async fn export_one(&self, id: i32) -> Result<DataKey>;
-    async fn enable(&self, id: i32) -> Result<()>;
-    async fn disable(&self, id: i32) -> Result<()>;
-    async fn sign(&self, key_type: String, key_name: String, options: &HashMap<String, String>, data: Vec<u8>) ->Result<Vec<u8>>;
+    async fn create(&self, user: &UserIdentity, data: DataKeyDTO) -> Result<DataKey>;
+    async fn import(&self, user: &UserIdentity, data: ImportDataKeyDTO) -> Result<DataKey>;
+    async fn get_all(&self, user: &UserIdentity, filter: KeyFilter) -> Result<KeyPage>;
+    async fn get_one(&self, user: &UserIdentity, id: i32) -> Result<DataKey>;
+    async fn delete_one(&self, user: &UserIdentity, id: i32) -> Result<()>;
+    async fn export_one(&self, user: &UserIdentity, id: i32) -> Result<DataKey>;
+    async fn enable(&self, user: &UserIdentity, id: i32) -> Result<()>;
+    async fn disable(&self, user: &UserIdentity, id: i32) -> Result<()>;
+    async fn renew(&self, user: &UserIdentity, id: i32, expire_at: DateTime<Utc>) -> Result<DataKey>;
+    //disable the enabled keys which have been expired, performed by background job
+    async fn disable_expired(&self) -> Result<()>;
+    async fn get_permissions(&self, user: &UserIdentity, id: i32) -> Result<Vec<KeyPermission>>;
+    async fn grant_permission(&self, user: &UserIdentity, permission: KeyPermission) -> Result<()>;
+    async fn revoke_permission(&self, user: &UserIdentity, permission: KeyPermission) -> Result<()>;
+    async fn sign(&self, caller: &SignCaller, key_type: String, key_name: String, options: &HashMap<String, String>, data: Vec<u8>) ->Result<Vec<u8>>;
+    async fn sign_digest(&self, caller: &SignCaller, key_type: String, key_name: String, options: &HashMap<String, String>, digest: DigestState) ->Result<Vec<u8>>;
 }
 
 
@@ -30,8 +90,21 @@ where
     S: SignBackend + ?Sized
 {
     repository: R,
-    sign_service: Box<S>,
-    container: DataKeyContainer<R>
+    sign_service: Arc<S>,
+    container: DataKeyContainer<R>,
+    audit_writer: AuditWriter,
+}
+
+//the caches of this process are dropped for the keys changed by any server since last refresh
+async fn refresh_keys<R, S>(container: &DataKeyContainer<R>, sign_service: &S) -> Result<()>
+where
+    R: DatakeyRepository,
+    S: SignBackend + ?Sized
+{
+    for key in container.refresh().await?.iter() {
+        sign_service.invalidate(key).await;
+    }
+    Ok(())
 }
 
 impl<R, S> DBKeyService<R, S>
@@ -39,12 +112,111 @@ impl<R, S> DBKeyService<R, S>
         R: DatakeyRepository + Clone,
         S: SignBackend + ?Sized
 {
-    pub fn new(repository: R, sign_service: Box<S>) -> Self {
+    pub fn new<A>(repository: R, sign_service: Arc<S>, audit_repository: A) -> Self where A: AuditRepository + 'static {
         Self {
             repository: repository.clone(),
             sign_service,
-            container: DataKeyContainer::new(repository)
+            container: DataKeyContainer::new(repository),
+            audit_writer: AuditWriter::new(audit_repository),
+        }
+    }
+
+    //keys disabled or deleted elsewhere are dropped from the cached keys as well as the loaded sign plugins
+    pub fn start_key_refresh(&self, period: Duration) where R: 'static, S: 'static {
+        let container = self.container.clone();
+        let sign_service = self.sign_service.clone();
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            loop {
+                ticker.tick().await;
+                if let Err(err) = refresh_keys(&container, sign_service.as_ref()).await {
+                    error!("failed to refresh data keys: {}", err);
+                }
+            }
+        });
+    }
+
+    //re-encrypt all of the keys including the deleted ones with the latest cluster key, return the number of keys
+    pub async fn reencrypt_keys(&self) -> Result<usize> {
+        let keys = self.repository.get_all_with_deleted().await?;
+        for mut key in keys.iter().cloned() {
+            self.sign_service.reencrypt_keys(&mut key).await?;
+            self.repository.update_encrypted_keys(&key).await?;
+            info!("key {} has been re-encrypted", key.name);
+        }
+        Ok(keys.len())
+    }
+
+    //the record is written in background, the operation fails once its record can't be queued, see `AuditWriter`
+    async fn audit<T>(&self, mut record: SignRecord, result: Result<T>) -> Result<T> {
+        record.set_result(&result);
+        self.audit_writer.write(record).await?;
+        result
+    }
+
+    //the writer is closed on graceful shutdown of server, so that the queued records are written
+    pub fn audit_writer(&self) -> AuditWriter {
+        self.audit_writer.clone()
+    }
+
+    fn user_record(&self, action: AuditAction, user: &UserIdentity, key_id: i32) -> SignRecord {
+        let mut record = SignRecord::new(action, user.email.clone(), Some(user.id));
+        record.key_id = key_id;
+        record
+    }
+
+    fn sign_record(&self, action: AuditAction, caller: &SignCaller, key_type: &str, key_name: &str, options: &HashMap<String, String>) -> SignRecord {
+        let mut record = SignRecord::new(action, caller.principal().to_string(), caller.user_id());
+        record.key_type = key_type.to_string();
+        record.key_name = key_name.to_string();
+        record.file_name = options.get(FILE_NAME).cloned().unwrap_or_default();
+        record
+    }
+
+    async fn get_permitted_key(&self, user: &UserIdentity, id: i32) -> Result<(DataKey, KeyRole)> {
+        let key = self.repository.get_by_id(id).await?;
+        match self.repository.get_role(key.id, &user.email).await? {
+            Some(role) => Ok((key, role)),
+            None => Err(Error::ForbiddenError(format!("{} has no access to key {}", user.email, key.name))),
+        }
+    }
+
+    async fn get_managed_key(&self, user: &UserIdentity, id: i32) -> Result<(DataKey, KeyRole)> {
+        let (key, role) = self.get_permitted_key(user, id).await?;
+        if !role.can_manage() {
+            return Err(Error::ForbiddenError(format!("{} is not allowed to manage key {}", user.email, key.name)));
         }
+        Ok((key, role))
+    }
+
+    async fn get_signing_key(&self, caller: &SignCaller, key_type: String, key_name: String, options: &HashMap<String, String>) -> Result<DataKey> {
+        if let SignCaller::Service { name, key_name: configured } = caller {
+            if *configured != key_name {
+                return Err(Error::ForbiddenError(format!("{} is not allowed to sign with key {}", name, key_name)));
+            }
+        } else if options.get(SIGN_TYPE).map(String::as_str) == Some(TIMESTAMP_TOKEN) {
+            //timestamp tokens are only issued by the timestamp authority, which records the serial of every token
+            return Err(Error::ForbiddenError(format!("{} is not allowed to sign timestamp token", caller.principal())));
+        }
+        let key = self.container.get_data_key(key_type, key_name).await?;
+        if key.expire_at <= Utc::now() {
+            return Err(Error::KeyExpiredError(key.name));
+        }
+        if matches!(caller, SignCaller::Service { .. }) {
+            return Ok(key);
+        }
+        //the roles are cached along with the key, signing doesn't query database once the key is loaded
+        if self.container.get_role(key.id, caller.principal()).await?.is_none() {
+            return Err(Error::ForbiddenError(format!("{} is not allowed to sign with key {}", caller.principal(), key.name)));
+        }
+        Ok(key)
+    }
+
+    async fn create_with_owner(&self, user: &UserIdentity, key: DataKey) -> Result<DataKey> {
+        let key = self.repository.create(key).await?;
+        self.repository.create_permission(
+            KeyPermission::new(key.id, user.email.clone(), KeyRole::Owner)).await?;
+        Ok(key)
     }
 }
 
@@ -54,43 +226,461 @@ where
     R: DatakeyRepository + Clone,
     S: SignBackend + ?Sized
 {
-    async fn create(&self, data: DataKeyDTO) -> Result<DataKey> {
-        let mut key = DataKey::try_from(data)?;
-        self.sign_service.generate_keys(&mut key).await?;
-        self.repository.create(key).await
+    async fn create(&self, user: &UserIdentity, data: DataKeyDTO) -> Result<DataKey> {
+        let mut record = self.user_record(AuditAction::Create, user, 0);
+        record.key_name = data.name.clone();
+        record.key_type = data.key_type.clone();
+        let result = async {
+            let mut key = DataKey::try_from(data)?;
+            self.sign_service.generate_keys(&mut key).await?;
+            self.create_with_owner(user, key).await
+        }.await;
+        if let Ok(key) = &result {
+            record.key_id = key.id;
+        }
+        self.audit(record, result).await
     }
 
-    async fn get_all(&self) -> Result<Vec<DataKey>> {
-        self.repository.get_all().await
+    async fn import(&self, user: &UserIdentity, data: ImportDataKeyDTO) -> Result<DataKey> {
+        let mut record = self.user_record(AuditAction::Import, user, 0);
+        record.key_name = data.name.clone();
+        record.key_type = data.key_type.clone();
+        let result = async {
+            let mut key = DataKey::try_from(data)?;
+            self.sign_service.import_keys(&mut key).await?;
+            self.create_with_owner(user, key).await
+        }.await;
+        if let Ok(key) = &result {
+            record.key_id = key.id;
+        }
+        self.audit(record, result).await
     }
 
-    async fn get_one(&self, id: i32) -> Result<DataKey> {
-        self.repository.get_by_id(id).await
+    async fn get_all(&self, user: &UserIdentity, mut filter: KeyFilter) -> Result<KeyPage> {
+        filter.visible_to = Some(user.email.clone());
+        self.repository.get_by_filter(filter).await
     }
 
-    async fn delete_one(&self, id: i32) -> Result<()> {
-        let key = self.repository.get_by_id(id).await?;
-        self.repository.delete_by_id(key.id).await
+    async fn get_one(&self, user: &UserIdentity, id: i32) -> Result<DataKey> {
+        Ok(self.get_permitted_key(user, id).await?.0)
+    }
+
+    async fn delete_one(&self, user: &UserIdentity, id: i32) -> Result<()> {
+        let result = async {
+            let (key, role) = self.get_permitted_key(user, id).await?;
+            if role != KeyRole::Owner {
+                return Err(Error::ForbiddenError(format!("only the owner is allowed to delete key {}", key.name)));
+            }
+            self.repository.delete_by_id(key.id).await?;
+            self.sign_service.invalidate(&key).await;
+            Ok(())
+        }.await;
+        self.audit(self.user_record(AuditAction::Delete, user, id), result).await
     }
 
-    async fn export_one(&self, id: i32) -> Result<DataKey> {
-        let mut key = self.repository.get_by_id(id).await?;
+    async fn export_one(&self, user: &UserIdentity, id: i32) -> Result<DataKey> {
+        let mut key = self.get_permitted_key(user, id).await?.0;
         self.sign_service.decode_public_keys(&mut key).await?;
         Ok(key)
     }
 
-    async fn enable(&self, id: i32) -> Result<()> {
-        let key = self.repository.get_by_id(id).await?;
-        self.repository.update_state(key.id, KeyState::Enabled).await
+    async fn enable(&self, user: &UserIdentity, id: i32) -> Result<()> {
+        let result = async {
+            let key = self.get_managed_key(user, id).await?.0;
+            self.repository.update_state(key.id, KeyState::Enabled).await
+        }.await;
+        self.audit(self.user_record(AuditAction::Enable, user, id), result).await
     }
 
-    async fn disable(&self, id: i32) -> Result<()> {
-        let key = self.repository.get_by_id(id).await?;
-        self.repository.update_state(key.id, KeyState::Disabled).await
+    async fn disable(&self, user: &UserIdentity, id: i32) -> Result<()> {
+        let result = async {
+            let key = self.get_managed_key(user, id).await?.0;
+            self.repository.update_state(key.id, KeyState::Disabled).await?;
+            self.sign_service.invalidate(&key).await;
+            Ok(())
+        }.await;
+        self.audit(self.user_record(AuditAction::Disable, user, id), result).await
+    }
+
+    //the key state is unchanged, keys disabled due to expiration should be enabled explicitly
+    async fn renew(&self, user: &UserIdentity, id: i32, expire_at: DateTime<Utc>) -> Result<DataKey> {
+        let mut record = self.user_record(AuditAction::Renew, user, id);
+        record.detail = format!("expire at {}", expire_at.to_rfc3339());
+        let result = async {
+            let mut key = self.get_managed_key(user, id).await?.0;
+            if expire_at <= Utc::now() {
+                return Err(Error::ParameterError("expire time should be later than current time".to_string()));
+            }
+            key.expire_at = expire_at;
+            key.attributes.insert("expire_at".to_string(), expire_at.to_rfc3339());
+            self.sign_service.renew_keys(&mut key).await?;
+            self.repository.update_keys(&key).await?;
+            self.sign_service.invalidate(&key).await;
+            self.repository.get_by_id(key.id).await
+        }.await;
+        self.audit(record, result).await
+    }
+
+    async fn disable_expired(&self) -> Result<()> {
+        for key in self.repository.get_expired_enabled_keys(Utc::now()).await? {
+            let mut record = SignRecord::new(AuditAction::Disable, SYSTEM_PRINCIPAL.to_string(), None);
+            record.key_id = key.id;
+            record.key_name = key.name.clone();
+            record.key_type = key.key_type.to_string();
+            record.detail = format!("expired at {}", key.expire_at.to_rfc3339());
+            let result = self.repository.update_state(key.id, KeyState::Disabled).await;
+            if result.is_ok() {
+                info!("key {} is disabled since it's expired at {}", key.name, key.expire_at);
+                self.sign_service.invalidate(&key).await;
+            }
+            if let Err(err) = self.audit(record, result).await {
+                error!("failed to disable expired key {}: {}", key.name, err);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_permissions(&self, user: &UserIdentity, id: i32) -> Result<Vec<KeyPermission>> {
+        let key = self.get_permitted_key(user, id).await?.0;
+        self.repository.get_permissions(key.id).await
+    }
+
+    async fn grant_permission(&self, user: &UserIdentity, permission: KeyPermission) -> Result<()> {
+        let mut record = self.user_record(AuditAction::Grant, user, permission.key_id);
+        record.detail = format!("{} {}", permission.role, permission.principal);
+        let result = async {
+            let (key, role) = self.get_managed_key(user, permission.key_id).await?;
+            if !role.can_grant(&permission.role) {
+                return Err(Error::ForbiddenError(format!("{} is not allowed to grant {} role of key {}", user.email, permission.role, key.name)));
+            }
+            self.repository.create_permission(permission).await?;
+            self.container.invalidate_roles(key.id).await;
+            Ok(())
+        }.await;
+        self.audit(record, result).await
+    }
+
+    async fn revoke_permission(&self, user: &UserIdentity, permission: KeyPermission) -> Result<()> {
+        let mut record = self.user_record(AuditAction::Revoke, user, permission.key_id);
+        record.detail = format!("{} {}", permission.role, permission.principal);
+        let result = async {
+            let (key, role) = self.get_managed_key(user, permission.key_id).await?;
+            if !role.can_grant(&permission.role) {
+                return Err(Error::ForbiddenError(format!("{} is not allowed to revoke {} role of key {}", user.email, permission.role, key.name)));
+            }
+            self.repository.delete_permission(permission).await?;
+            self.container.invalidate_roles(key.id).await;
+            Ok(())
+        }.await;
+        self.audit(record, result).await
+    }
+
+    async fn sign(&self, caller: &SignCaller, key_type: String, key_name: String, options: &HashMap<String, String>, data: Vec<u8>) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let payload = data.len();
+        let mut record = self.sign_record(AuditAction::Sign, caller, &key_type, &key_name, options);
+        let key = self.get_signing_key(caller, key_type, key_name, options).await;
+        //the payload is only hashed for the permitted callers, rejected requests are recorded without digest
+        let result = match &key {
+            Ok(key) => {
+                record.key_id = key.id;
+                match hash(MessageDigest::sha256(), &data) {
+                    Ok(digest) => {
+                        record.digest = hex::encode(digest);
+                        self.sign_service.sign(key, data, options.clone()).await
+                    }
+                    Err(err) => Err(Error::from(err)),
+                }
+            }
+            Err(err) => Err(err.clone()),
+        };
+        let result = self.audit(record, result).await;
+        metrics::observe_sign("sign_stream", key.as_ref().ok(), payload, start, &result);
+        result
+    }
+
+    async fn sign_digest(&self, caller: &SignCaller, key_type: String, key_name: String, options: &HashMap<String, String>, digest: DigestState) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let payload = digest.state().len() + digest.remainder().len();
+        let mut record = self.sign_record(AuditAction::SignDigest, caller, &key_type, &key_name, options);
+        let key = self.get_signing_key(caller, key_type, key_name, options).await;
+        let result = match &key {
+            Ok(key) => {
+                record.key_id = key.id;
+                record.digest = hex::encode(digest.clone().finalize());
+                self.sign_service.sign_digest(key, digest, options.clone()).await
+            }
+            Err(err) => Err(err.clone()),
+        };
+        let result = self.audit(record, result).await;
+        metrics::observe_sign("sign_digest", key.as_ref().ok(), payload, start, &result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::audit::{AuditService, DBAuditService};
+    use crate::domain::audit::entity::AuditFilter;
+    use crate::infra::database::model::audit::repository::SignRecordRepository;
+    use crate::infra::database::model::datakey::repository::DataKeyRepository;
+    use crate::util::digest::DigestAlgorithm;
+    use crate::util::test_support::{data_key, memory_pool};
+
+    const SIGNER: &str = "signer@example.com";
+    const BUILDER: &str = "cert:builder";
+
+    #[derive(Default)]
+    struct FakeBackend {
+        invalidated: std::sync::Mutex<Vec<i32>>,
+    }
+
+    #[async_trait]
+    impl SignBackend for FakeBackend {
+        async fn generate_keys(&self, _data_key: &mut DataKey) -> Result<()> { Ok(()) }
+        async fn import_keys(&self, _data_key: &mut DataKey) -> Result<()> { Ok(()) }
+        async fn sign(&self, _data_key: &DataKey, _content: Vec<u8>, _options: HashMap<String, String>) -> Result<Vec<u8>> {
+            Ok(b"signature".to_vec())
+        }
+        async fn sign_digest(&self, _data_key: &DataKey, _digest: DigestState, _options: HashMap<String, String>) -> Result<Vec<u8>> {
+            Ok(b"signature".to_vec())
+        }
+        async fn decode_public_keys(&self, _data_key: &mut DataKey) -> Result<()> { Ok(()) }
+        async fn renew_keys(&self, _data_key: &mut DataKey) -> Result<()> { Ok(()) }
+        async fn reencrypt_keys(&self, _data_key: &mut DataKey) -> Result<()> { Ok(()) }
+        async fn invalidate(&self, data_key: &DataKey) {
+            self.invalidated.lock().unwrap().push(data_key.id);
+        }
+    }
+
+    async fn key_service() -> (DBKeyService<DataKeyRepository, FakeBackend>, DataKeyRepository, SignRecordRepository) {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool.clone());
+        let audit_repository = SignRecordRepository::new(pool);
+        (DBKeyService::new(repository.clone(), Arc::new(FakeBackend::default()), audit_repository.clone()), repository, audit_repository)
+    }
+
+    //enabled key granted to the principals, most of the tests start with it
+    async fn granted_key(repository: &DataKeyRepository, name: &str, grants: &[(&str, KeyRole)]) -> DataKey {
+        let key = repository.create(data_key(name)).await.unwrap();
+        for (principal, role) in grants {
+            repository.create_permission(KeyPermission::new(key.id, principal.to_string(), role.clone())).await.unwrap();
+        }
+        key
+    }
+
+    async fn sign_once(service: &DBKeyService<DataKeyRepository, FakeBackend>, caller: &SignCaller, key_name: &str) -> Result<Vec<u8>> {
+        service.sign(caller, "pgp".to_string(), key_name.to_string(), &HashMap::new(), vec![1]).await
+    }
+
+    //the audit records are written in background
+    async fn wait_records(audit_repository: &SignRecordRepository, count: usize) -> Vec<SignRecord> {
+        let filter = AuditFilter { limit: 100, ..Default::default() };
+        for _ in 0..100 {
+            let records = audit_repository.query(filter.clone()).await.unwrap();
+            if records.len() >= count {
+                return records;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("audit records aren't written");
+    }
+
+    //audit database which is unavailable for the given number of inserts
+    #[derive(Clone, Default)]
+    struct FlakyAudit {
+        failures: Arc<std::sync::Mutex<usize>>,
+        records: Arc<std::sync::Mutex<Vec<SignRecord>>>,
+    }
+
+    #[async_trait]
+    impl AuditRepository for FlakyAudit {
+        async fn create_batch(&self, mut records: Vec<SignRecord>) -> Result<()> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(Error::DatabaseError("database is unavailable".to_string()));
+            }
+            self.records.lock().unwrap().append(&mut records);
+            Ok(())
+        }
+        async fn query(&self, _filter: AuditFilter) -> Result<Vec<SignRecord>> { Ok(vec![]) }
+    }
+
+    async fn flaky_key_service(failures: usize) -> (DBKeyService<DataKeyRepository, FakeBackend>, FlakyAudit) {
+        let repository = DataKeyRepository::new(memory_pool().await);
+        let audit = FlakyAudit { failures: Arc::new(std::sync::Mutex::new(failures)), ..Default::default() };
+        let service = DBKeyService::new(repository.clone(), Arc::new(FakeBackend::default()), audit.clone());
+        granted_key(&repository, "flaky-key", &[(BUILDER, KeyRole::Signer)]).await;
+        (service, audit)
+    }
+
+    #[tokio::test]
+    async fn test_sign_metrics_labels() {
+        let (service, repository, _) = key_service().await;
+        granted_key(&repository, "metrics-key", &[(BUILDER, KeyRole::Signer)]).await;
+        let caller = SignCaller::certificate("builder");
+
+        sign_once(&service, &caller, "metrics-key").await.unwrap();
+        assert_eq!(metrics::SIGN_REQUESTS.with_label_values(&["pgp", "metrics-key", "sign_stream", "success"]).get(), 1);
+
+        //the requested name is never used as label when the key can't be resolved
+        let unknown = metrics::SIGN_REQUESTS.with_label_values(&[metrics::UNKNOWN, metrics::UNKNOWN, "sign_stream", "failure"]).get();
+        assert!(service.sign(&caller, "whatever".to_string(), "metrics-missing".to_string(), &HashMap::new(), vec![1]).await.is_err());
+        assert!(metrics::SIGN_REQUESTS.with_label_values(&[metrics::UNKNOWN, metrics::UNKNOWN, "sign_stream", "failure"]).get() > unknown);
+        assert!(!metrics::render().unwrap().contains("metrics-missing"));
+        assert!(!metrics::render().unwrap().contains("whatever"));
+    }
+
+    #[tokio::test]
+    async fn test_key_permissions() {
+        let (service, repository, _) = key_service().await;
+        let user = |email: &str, id: i32| UserIdentity { email: email.to_string(), id };
+        let (owner, admin, signer, stranger) = (
+            user("owner@example.com", 1), user("admin@example.com", 2), user(SIGNER, 3), user("stranger@example.com", 4));
+        let key = granted_key(&repository, "acl-key", &[(&owner.email, KeyRole::Owner)]).await;
+        let forbidden = |result: Result<()>| matches!(result, Err(Error::ForbiddenError(_)));
+
+        assert!(service.get_one(&owner, key.id).await.is_ok());
+        assert!(matches!(service.get_one(&stranger, key.id).await, Err(Error::ForbiddenError(_))));
+        service.grant_permission(&owner, KeyPermission::new(key.id, admin.email.clone(), KeyRole::Admin)).await.unwrap();
+        //admins can only grant signers
+        assert!(forbidden(service.grant_permission(&admin, KeyPermission::new(key.id, stranger.email.clone(), KeyRole::Admin)).await));
+        service.grant_permission(&admin, KeyPermission::new(key.id, signer.email.clone(), KeyRole::Signer)).await.unwrap();
+        //granting the same permission again is ignored
+        service.grant_permission(&owner, KeyPermission::new(key.id, signer.email.clone(), KeyRole::Signer)).await.unwrap();
+        assert_eq!(service.get_permissions(&signer, key.id).await.unwrap().len(), 3);
+        assert!(forbidden(service.grant_permission(&signer, KeyPermission::new(key.id, stranger.email.clone(), KeyRole::Signer)).await));
+        assert!(forbidden(service.disable(&signer, key.id).await));
+        assert!(forbidden(service.delete_one(&admin, key.id).await));
+
+        //signers are identified either by api token or by client certificate
+        let sign = |caller: SignCaller| {
+            let service = &service;
+            async move { sign_once(service, &caller, "acl-key").await }
+        };
+        assert!(sign(SignCaller::User(signer.clone())).await.is_ok());
+        service.grant_permission(&admin, KeyPermission::new(key.id, BUILDER.to_string(), KeyRole::Signer)).await.unwrap();
+        assert!(sign(SignCaller::certificate("builder")).await.is_ok());
+        assert!(matches!(sign(SignCaller::certificate("stranger")).await, Err(Error::ForbiddenError(_))));
+        //the certificate named after a permitted user doesn't get the roles of the user
+        assert!(matches!(sign(SignCaller::certificate(SIGNER)).await, Err(Error::ForbiddenError(_))));
+        let tsa = |key_name: &str| SignCaller::Service { name: "timestamp-authority".to_string(), key_name: key_name.to_string() };
+        assert!(sign(tsa("acl-key")).await.is_ok());
+        //the service can't sign with the keys other than its own
+        granted_key(&repository, "other-key", &[]).await;
+        let other = sign_once(&service, &tsa("tsa-key"), "other-key").await;
+        assert!(matches!(other, Err(Error::ForbiddenError(_))));
+        service.revoke_permission(&admin, KeyPermission::new(key.id, signer.email.clone(), KeyRole::Signer)).await.unwrap();
+        assert!(matches!(sign(SignCaller::User(signer.clone())).await, Err(Error::ForbiddenError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_timestamp_token_signed_by_service_only() {
+        let (service, repository, _) = key_service().await;
+        granted_key(&repository, "tsa-key", &[(SIGNER, KeyRole::Owner), (BUILDER, KeyRole::Signer)]).await;
+        let options = HashMap::from([(SIGN_TYPE.to_string(), TIMESTAMP_TOKEN.to_string())]);
+        let user = SignCaller::User(UserIdentity { email: SIGNER.to_string(), id: 1 });
+
+        //even the owner of the key can't get arbitrary timestamp token signed
+        for caller in [user.clone(), SignCaller::certificate("builder")] {
+            let signed = service.sign(&caller, "pgp".to_string(), "tsa-key".to_string(), &options, vec![1]).await;
+            assert!(matches!(signed, Err(Error::ForbiddenError(_))));
+            let digest = DigestState::new(DigestAlgorithm::Sha256);
+            let signed = service.sign_digest(&caller, "pgp".to_string(), "tsa-key".to_string(), &options, digest).await;
+            assert!(matches!(signed, Err(Error::ForbiddenError(_))));
+        }
+        assert!(service.sign(&user, "pgp".to_string(), "tsa-key".to_string(), &HashMap::new(), vec![1]).await.is_ok());
+        let tsa = SignCaller::Service { name: "timestamp-authority".to_string(), key_name: "tsa-key".to_string() };
+        assert!(service.sign(&tsa, "pgp".to_string(), "tsa-key".to_string(), &options, vec![1]).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_refresh_invalidates_changed_keys() {
+        let (service, repository, _) = key_service().await;
+        let key = granted_key(&repository, "refresh-key", &[(BUILDER, KeyRole::Signer)]).await;
+        let caller = SignCaller::certificate("builder");
+        sign_once(&service, &caller, "refresh-key").await.unwrap();
+        refresh_keys(&service.container, service.sign_service.as_ref()).await.unwrap();
+        assert!(service.sign_service.invalidated.lock().unwrap().is_empty());
+
+        //the key is disabled by another server, this server drops its caches on next refresh
+        repository.update_state(key.id, KeyState::Disabled).await.unwrap();
+        refresh_keys(&service.container, service.sign_service.as_ref()).await.unwrap();
+        assert_eq!(*service.sign_service.invalidated.lock().unwrap(), vec![key.id]);
+        assert!(sign_once(&service, &caller, "refresh-key").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sign_audit() {
+        let (service, repository, audit_repository) = key_service().await;
+        let owner = UserIdentity { email: "owner@example.com".to_string(), id: 1 };
+        let key = granted_key(&repository, "audit-key", &[(&owner.email, KeyRole::Owner), (SIGNER, KeyRole::Signer)]).await;
+        let options = HashMap::from([(FILE_NAME.to_string(), "kernel.rpm".to_string())]);
+        let sign = |principal: &str| {
+            let (service, options) = (&service, &options);
+            let caller = SignCaller::User(UserIdentity { email: principal.to_string(), id: 2 });
+            async move { service.sign(&caller, "pgp".to_string(), "audit-key".to_string(), options, b"content".to_vec()).await }
+        };
+        sign(SIGNER).await.unwrap();
+        assert!(sign("stranger@example.com").await.is_err());
+
+        let audit = DBAuditService::new(audit_repository.clone());
+        let filter = AuditFilter { limit: 10, ..Default::default() };
+        let records = wait_records(&audit_repository, 2).await;
+        assert_eq!(records.len(), 2);
+        let (failed, succeed) = (&records[0], &records[1]);
+        assert!(succeed.succeed);
+        assert_eq!((succeed.key_id, succeed.principal.as_str(), succeed.file_name.as_str()), (key.id, SIGNER, "kernel.rpm"));
+        assert_eq!(succeed.digest, hex::encode(hash(MessageDigest::sha256(), b"content").unwrap()));
+        //the payload of the rejected request isn't hashed
+        assert!(!failed.succeed);
+        assert!(failed.digest.is_empty());
+        assert_eq!(failed.key_id, 0);
+
+        //owners see the records of their keys, the rejected request isn't bound to any key
+        let records = audit.get_records(&owner, filter.clone()).await.unwrap();
+        assert_eq!(records.iter().map(|r| r.id).collect::<Vec<i32>>(), vec![succeed.id]);
+
+        //signers and strangers only see the records of their own
+        let visible = |email: &str| {
+            let (audit, filter) = (&audit, filter.clone());
+            let user = UserIdentity { email: email.to_string(), id: 2 };
+            async move {
+                audit.get_records(&user, filter).await.unwrap().into_iter().map(|r| r.principal).collect::<Vec<String>>()
+            }
+        };
+        assert_eq!(visible(SIGNER).await, vec![SIGNER.to_string()]);
+        assert_eq!(visible("stranger@example.com").await, vec!["stranger@example.com".to_string()]);
+        assert!(visible("nobody@example.com").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_audit_retried_and_flushed() {
+        let (service, audit) = flaky_key_service(1).await;
+        let caller = SignCaller::certificate("builder");
+        for _ in 0..3 {
+            sign_once(&service, &caller, "flaky-key").await.unwrap();
+        }
+        //the failed batch is retried and the records still queued are written on close
+        service.audit_writer().close().await;
+        let records = audit.records.lock().unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|r| r.succeed && r.principal == BUILDER));
     }
 
-    async fn sign(&self, key_type: String, key_name: String, options: &HashMap<String, String>, data: Vec<u8>) -> Result<Vec<u8>> {
-        self.sign_service.sign(
-            &self.container.get_data_key(key_type, key_name).await?, data, options.clone()).await
+    #[tokio::test]
+    async fn test_sign_fails_without_audit() {
+        let (service, audit) = flaky_key_service(0).await;
+        service.audit_writer().close().await;
+        //the signature isn't returned when the record can't be queued
+        let caller = SignCaller::certificate("builder");
+        let signature = sign_once(&service, &caller, "flaky-key").await;
+        assert!(matches!(signature, Err(Error::AuditError(_))));
+        let digest = DigestState::new(DigestAlgorithm::Sha256);
+        let signature = service.sign_digest(&caller, "pgp".to_string(), "flaky-key".to_string(), &HashMap::new(), digest).await;
+        assert!(matches!(signature, Err(Error::AuditError(_))));
+        assert!(audit.records.lock().unwrap().is_empty());
     }
 }
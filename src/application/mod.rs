@@ -1,2 +1,4 @@
+pub mod audit;
 pub mod user;
-pub mod datakey;
\ No newline at end of file
+pub mod datakey;
+pub mod timestamp;
@@ -0,0 +1,97 @@
+use crate::domain::datakey::entity::KeyType;
+use crate::domain::datakey::repository::Repository as DatakeyRepository;
+use crate::application::datakey::{KeyService, SignCaller};
+use crate::domain::timestamp::entity::{FailureInfo, TimestampRecord, TimestampRequest, TimestampResponse};
+use crate::domain::timestamp::repository::Repository as TimestampRepository;
+use crate::domain::sign_plugin::{CERT_REQ, SIGN_TYPE, TIMESTAMP_TOKEN};
+use crate::util::der;
+use crate::util::error::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+//principal of the timestamp authority recorded in audit
+const TIMESTAMP_AUTHORITY: &str = "timestamp-authority";
+
+#[async_trait]
+pub trait TimestampService: Send + Sync {
+    //the der encoded TimeStampResp is always returned, failures are reported inside of the response
+    async fn issue(&self, request: &[u8], client_address: String) -> Vec<u8>;
+}
+
+pub struct DBTimestampService<R, T, K>
+where
+    R: DatakeyRepository,
+    T: TimestampRepository,
+    K: KeyService + ?Sized
+{
+    key_repository: R,
+    timestamp_repository: T,
+    key_service: Arc<K>,
+    key_name: String,
+    policy: String,
+    //der encoded policy used to match the policy requested by client
+    policy_oid: Vec<u8>,
+}
+
+impl<R, T, K> DBTimestampService<R, T, K>
+    where
+        R: DatakeyRepository,
+        T: TimestampRepository,
+        K: KeyService + ?Sized
+{
+    pub fn new(key_repository: R, timestamp_repository: T, key_service: Arc<K>, key_name: String, policy: String) -> Result<Self> {
+        let policy_oid = der::oid(&der::parse_oid(&policy)?);
+        Ok(Self {
+            key_repository,
+            timestamp_repository,
+            key_service,
+            key_name,
+            policy,
+            policy_oid,
+        })
+    }
+
+    //the serial number is allocated by recording the request before signing, the token is signed through key service
+    //so that the state and expiration of the key are checked and the signing is audited
+    async fn grant(&self, request: &TimestampRequest, client_address: String) -> Result<Vec<u8>> {
+        let key = self.key_repository.get_enabled_key_by_type_and_name(
+            KeyType::X509.to_string(), self.key_name.clone()).await?;
+        let record = self.timestamp_repository.create(
+            TimestampRecord::new(key.id, self.policy.clone(), request, client_address)).await?;
+        let options = HashMap::from([
+            (SIGN_TYPE.to_string(), TIMESTAMP_TOKEN.to_string()),
+            (CERT_REQ.to_string(), request.cert_req.to_string()),
+        ]);
+        let caller = SignCaller::Service { name: TIMESTAMP_AUTHORITY.to_string(), key_name: self.key_name.clone() };
+        self.key_service.sign(&caller,
+                              KeyType::X509.to_string(), self.key_name.clone(), &options, record.tst_info(request)?).await
+    }
+}
+
+#[async_trait]
+impl<R, T, K> TimestampService for DBTimestampService<R, T, K>
+where
+    R: DatakeyRepository,
+    T: TimestampRepository,
+    K: KeyService + ?Sized
+{
+    async fn issue(&self, request: &[u8], client_address: String) -> Vec<u8> {
+        let request = match TimestampRequest::from_der(request) {
+            Ok(request) => request,
+            Err(failure) => return TimestampResponse::Rejected(failure, "invalid timestamp request".to_string()).to_der(),
+        };
+        if let Some(policy) = &request.policy {
+            if *policy != self.policy_oid {
+                return TimestampResponse::Rejected(FailureInfo::UnacceptedPolicy, "unsupported policy".to_string()).to_der();
+            }
+        }
+        match self.grant(&request, client_address).await {
+            Ok(token) => TimestampResponse::Granted(token).to_der(),
+            Err(e) => {
+                warn!("failed to issue timestamp token: {}", e);
+                TimestampResponse::Rejected(FailureInfo::SystemFailure, "failed to issue timestamp token".to_string()).to_der()
+            }
+        }
+    }
+}
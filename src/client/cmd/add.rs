@@ -3,7 +3,7 @@ use crate::util::error::Result;
 use config::{Config};
 use std::sync::{Arc, atomic::AtomicBool, RwLock};
 use super::traits::SignCommand;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::runtime;
 use crate::client::sign_identity;
 use std::collections::HashMap;
@@ -20,20 +20,54 @@ use crate::client::worker::signer::RemoteSigner;
 use crate::client::worker::splitter::Splitter;
 use crate::client::worker::traits::SignHandler;
 use std::sync::atomic::{AtomicI32, Ordering};
+use tonic::metadata::{Ascii, MetadataValue};
 
 lazy_static! {
     pub static ref FILE_EXTENSION: HashMap<sign_identity::FileType, Vec<&'static str>> = HashMap::from([
         (sign_identity::FileType::RPM, vec!["rpm", "srpm"]),
         (sign_identity::FileType::CheckSum, vec!["txt", "sha256sum"]),
         (sign_identity::FileType::KernelModule, vec!["ko"]),
+        (sign_identity::FileType::Efi, vec!["efi"]),
     ]);
 }
 
+//walk through the specified path and collect the files whose extension matches the file type,
+//files without extension are skipped.
+pub fn collect_file_candidates(path: &Path, file_type: &sign_identity::FileType) -> Result<Vec<PathBuf>> {
+    let extensions = FILE_EXTENSION.get(file_type).ok_or_else(
+        || error::Error::FileNotSupportError(format!("{}", file_type)))?;
+    let is_candidate = |path: &Path| path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.contains(&extension));
+    if path.is_dir() {
+        let mut container = Vec::new();
+        for entry in walkdir::WalkDir::new(path) {
+            match entry {
+                Ok(en)=> {
+                    if en.metadata()?.is_dir() {
+                        continue
+                    }
+                    if is_candidate(en.path()) {
+                        container.push(en.path().to_path_buf());
+                    }
+                },
+                Err(err)=> {
+                    error!("failed to scan file {}, will be skipped", err);
+                }
+            }
+        }
+        return Ok(container);
+    } else if is_candidate(path) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    Err(error::Error::NoFileCandidateError)
+}
+
 #[derive(Args)]
 pub struct CommandAdd {
     #[arg(long)]
     #[arg(value_enum)]
-    #[arg(help = "specify the file type for signing, currently support checksum and rpm")]
+    #[arg(help = "specify the file type for signing, currently support checksum, rpm, kernel module and efi")]
     file_type: sign_identity::FileType,
     #[arg(long)]
     #[arg(value_enum)]
@@ -48,6 +82,9 @@ pub struct CommandAdd {
     #[arg(long)]
     #[arg(help = "skip signed file")]
     skip_signed: bool,
+    #[arg(long)]
+    #[arg(help = "embed rfc3161 timestamp acquired by server into x509 signature")]
+    timestamp: bool,
     #[arg(help = "specify the path which will be used for signing file and directory are supported")]
     path: String,
 }
@@ -66,7 +103,9 @@ pub struct CommandAddHandler {
     config:  Arc<RwLock<Config>>,
     detached: bool,
     skip_signed: bool,
-    max_concurrency: usize
+    timestamp: bool,
+    max_concurrency: usize,
+    token: Option<MetadataValue<Ascii>>,
 }
 
 impl CommandAddHandler {
@@ -75,50 +114,23 @@ impl CommandAddHandler {
         HashMap::from([
             (options::DETACHED.to_string(), self.detached.to_string()),
             (options::SKIP_SIGNED.to_string(), self.skip_signed.to_string()),
-            (options::KEY_TYPE.to_string(), self.key_type.to_string())])
-    }
-    fn collect_file_candidates(&self) -> Result<Vec<sign_identity::SignIdentity>> {
-        if self.path.is_dir() {
-            let mut container = Vec::new();
-            for entry in walkdir::WalkDir::new(self.path.to_str().unwrap()) {
-                match entry {
-                    Ok(en)=> {
-                        if en.metadata()?.is_dir() {
-                            continue
-                        }
-                        if let Some(extension) = en.path().extension() {
-                            if self.file_candidates(extension.to_str().unwrap())? {
-                                container.push(
-                                    sign_identity::SignIdentity::new(
-                                        self.file_type.clone(),
-                                        en.path().to_path_buf(),
-                                        self.key_type.clone(),
-                                        self.key_id.clone(),
-                                        self.get_sign_options()));
-                            }
-                        }
-                    },
-                    Err(err)=> {
-                        error!("failed to scan file {}, will be skipped", err);
-                    }
-                }
-            }
-            return Ok(container);
-        } else if self.file_candidates(self.path.extension().unwrap().to_str().unwrap())? {
-                return Ok(vec![sign_identity::SignIdentity::new(
-                    self.file_type.clone(), self.path.clone(), self.key_type.clone(), self.key_id.clone(), self.get_sign_options())]);
-        }
-        Err(error::Error::NoFileCandidateError)
+            (options::KEY_TYPE.to_string(), self.key_type.to_string()),
+            (options::TIMESTAMP.to_string(), self.timestamp.to_string())])
     }
 
-    fn file_candidates(&self, extension: &str) -> Result<bool> {
-        let collections = FILE_EXTENSION.get(
-            &self.file_type).ok_or(
-            error::Error::FileNotSupportError(format!("{}", self.file_type)))?;
-        if collections.contains(&extension) {
-            return Ok(true)
+    fn get_file_sign_options(&self, path: &Path) -> HashMap<String, String> {
+        let mut sign_options = self.get_sign_options();
+        if let Some(file_name) = path.file_name() {
+            sign_options.insert(options::FILE_NAME.to_string(), file_name.to_string_lossy().to_string());
         }
-        Ok(false)
+        sign_options
+    }
+    fn collect_file_candidates(&self) -> Result<Vec<sign_identity::SignIdentity>> {
+        Ok(collect_file_candidates(&self.path, &self.file_type)?.into_iter().map(|path| {
+            let sign_options = self.get_file_sign_options(&path);
+            sign_identity::SignIdentity::new(
+                self.file_type.clone(), path, self.key_type.clone(), self.key_id.clone(), sign_options)
+        }).collect())
     }
 }
 
@@ -131,6 +143,13 @@ impl SignCommand for CommandAddHandler {
         if worker_threads == 0 {
             worker_threads = num_cpus::get() as usize;
         }
+        let token = config.read()?.get_string("server.token").unwrap_or_default();
+        let token = if token.is_empty() {
+            None
+        } else {
+            Some(MetadataValue::try_from(token).map_err(
+                |_| error::Error::ConfigError("invalid api token configured".to_string()))?)
+        };
         Ok(CommandAddHandler{
             worker_threads,
             buffer_size: config.read()?.get_string("buffer_size")?.parse()?,
@@ -143,11 +162,16 @@ impl SignCommand for CommandAddHandler {
             config: config.clone(),
             detached: command.detached,
             skip_signed: command.skip_signed,
+            timestamp: command.timestamp,
             max_concurrency: config.read()?.get_string("max_concurrency")?.parse()?,
+            token,
         })
     }
 
     fn validate(&self) -> Result<()> {
+        if self.timestamp && self.key_type != sign_identity::KeyType::X509 {
+            return Err(error::Error::InvalidArgumentError("timestamp only support x509 signature".to_string()))
+        }
         FileHandlerFactory::get_handler(&self.file_type).validate_options(&self.get_sign_options())
     }
 
@@ -179,7 +203,7 @@ impl SignCommand for CommandAddHandler {
         runtime.block_on(async {
             let channel = ChannelFactory::new(
                 &lb_config).await.unwrap().get_channel().unwrap();
-            let mut signer = RemoteSigner::new(channel, self.buffer_size);
+            let mut signer = RemoteSigner::new(channel, self.buffer_size, self.token.clone());
             //split file
             let send_handlers = files.into_iter().map(|file|{
                 let task_split_s = split_s.clone();
@@ -291,3 +315,26 @@ impl SignCommand for CommandAddHandler {
         Ok(true)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collect_file_candidates() {
+        let directory = std::env::temp_dir().join(format!("signatrust-candidates-{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("nested")).unwrap();
+        for name in ["a.rpm", "nested/b.srpm", "c.txt", "Makefile"] {
+            std::fs::write(directory.join(name), b"").unwrap();
+        }
+        let mut candidates = collect_file_candidates(&directory, &sign_identity::FileType::RPM).unwrap();
+        candidates.sort();
+        let single = collect_file_candidates(&directory.join("a.rpm"), &sign_identity::FileType::RPM);
+        let no_extension = collect_file_candidates(&directory.join("Makefile"), &sign_identity::FileType::RPM);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(candidates, vec![directory.join("a.rpm"), directory.join("nested/b.srpm")]);
+        assert_eq!(single.unwrap(), vec![directory.join("a.rpm")]);
+        assert!(matches!(no_extension, Err(error::Error::NoFileCandidateError)));
+    }
+}
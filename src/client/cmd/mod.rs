@@ -1,3 +1,4 @@
 pub mod add;
+pub mod verify;
 pub mod traits;
 pub mod options;
\ No newline at end of file
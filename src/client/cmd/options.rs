@@ -1,3 +1,8 @@
 pub const DETACHED: &str = "detached";
 pub const SKIP_SIGNED: &str = "skip_signed";
-pub const KEY_TYPE: &str = "key_type";
\ No newline at end of file
+pub const KEY_TYPE: &str = "key_type";
+pub use crate::domain::sign_plugin::{AUTHENTICODE, SIGN_TYPE};
+//embed rfc3161 timestamp into x509 signature
+pub const TIMESTAMP: &str = "timestamp";
+//recorded in the audit log of data server
+pub const FILE_NAME: &str = "file_name";
\ No newline at end of file
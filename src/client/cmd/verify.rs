@@ -0,0 +1,140 @@
+use clap::{Args};
+use crate::util::error::Result;
+use config::{Config};
+use std::sync::{Arc, atomic::AtomicBool, RwLock};
+use super::traits::SignCommand;
+use std::path::PathBuf;
+use tokio::runtime;
+use crate::client::sign_identity;
+use std::collections::HashMap;
+
+use crate::util::error;
+
+use crate::client::cmd::add::collect_file_candidates;
+use crate::client::cmd::options;
+use crate::client::file_handler::factory::FileHandlerFactory;
+use crate::client::verifier::VerificationKey;
+
+#[derive(Args)]
+pub struct CommandVerify {
+    #[arg(long)]
+    #[arg(value_enum)]
+    #[arg(help = "specify the file type for verification, currently support checksum, rpm, kernel module and efi")]
+    file_type: sign_identity::FileType,
+    #[arg(long)]
+    #[arg(value_enum)]
+    #[arg(help = "specify the key type for verification, currently support pgp and x509")]
+    key_type: sign_identity::KeyType,
+    #[arg(long)]
+    #[arg(help = "specify the key id, public key or certificate will be exported from control server")]
+    key_id: Option<String>,
+    #[arg(long)]
+    #[arg(help = "specify the local public key or certificate file used for verification")]
+    key_file: Option<String>,
+    #[arg(long)]
+    #[arg(help = "verify detached signature")]
+    detached: bool,
+    #[arg(help = "specify the path which will be used for verification, file and directory are supported")]
+    path: String,
+}
+
+
+#[derive(Clone)]
+pub struct CommandVerifyHandler {
+    worker_threads: usize,
+    file_type: sign_identity::FileType,
+    key_type: sign_identity::KeyType,
+    key_id: Option<String>,
+    key_file: Option<String>,
+    path: PathBuf,
+    config: Arc<RwLock<Config>>,
+    detached: bool,
+}
+
+impl CommandVerifyHandler {
+
+    fn get_sign_options(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (options::DETACHED.to_string(), self.detached.to_string()),
+            (options::KEY_TYPE.to_string(), self.key_type.to_string())])
+    }
+
+    async fn load_key(&self) -> Result<VerificationKey> {
+        if let Some(key_file) = &self.key_file {
+            return VerificationKey::from_file(self.key_type.clone(), key_file);
+        }
+        let key_id = self.key_id.clone().unwrap_or_default();
+        let (mut control_server, server) = {
+            let config = self.config.read()?;
+            (config.get_table("control-server")?, config.get_table("server")?)
+        };
+        //control server is usually issued by the same ca as the data server
+        if let Some(ca_root) = server.get("ca_root") {
+            control_server.entry("ca_root".to_string()).or_insert_with(|| ca_root.clone());
+        }
+        VerificationKey::from_control_server(self.key_type.clone(), &control_server, &key_id).await
+    }
+}
+
+
+impl SignCommand for CommandVerifyHandler {
+    type CommandValue = CommandVerify;
+
+    fn new(_signal: Arc<AtomicBool>, config: Arc<RwLock<Config>>, command: Self::CommandValue) -> Result<Self> {
+        let mut worker_threads = config.read()?.get_string("worker_threads")?.parse()?;
+        if worker_threads == 0 {
+            worker_threads = num_cpus::get() as usize;
+        }
+        Ok(CommandVerifyHandler{
+            worker_threads,
+            file_type: command.file_type,
+            key_type: command.key_type,
+            key_id: command.key_id,
+            key_file: command.key_file,
+            path: std::path::PathBuf::from(&command.path),
+            config: config.clone(),
+            detached: command.detached,
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.key_id.is_none() && self.key_file.is_none() {
+            return Err(error::Error::InvalidArgumentError("either key id or key file should be specified".to_string()))
+        }
+        FileHandlerFactory::get_handler(&self.file_type).validate_options(&self.get_sign_options())
+    }
+
+    //Verify process are described below.
+    //1. fetch all file candidates by walk through the specified path and filter by file extension.
+    //2. load public key or certificate from local file or control server
+    //3. extract the embedded or detached signature via file handler and verify it
+    //4. print the pass/fail report for each file
+    fn handle(&self) -> Result<bool> {
+        let files = collect_file_candidates(&self.path, &self.file_type)?;
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(self.worker_threads)
+            .enable_io()
+            .enable_time()
+            .build().unwrap();
+        info!("starting to verify {} files", files.len());
+        runtime.block_on(async {
+            let key = self.load_key().await?;
+            let handler = FileHandlerFactory::get_handler(&self.file_type);
+            let sign_options = self.get_sign_options();
+            let mut failed_files = 0;
+            for file in files.iter() {
+                match handler.verify(file, &key, &sign_options).await {
+                    Ok(_) => {
+                        println!("PASS\t{}", file.display());
+                    }
+                    Err(err) => {
+                        println!("FAIL\t{}\t{}", file.display(), err);
+                        failed_files += 1;
+                    }
+                }
+            }
+            info!("Successfully verified {} files failed {} files", files.len() - failed_files, failed_files);
+            Ok(failed_files == 0)
+        })
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::Path;
 use super::traits::FileHandler;
 use async_trait::async_trait;
 use crate::util::error::Result;
@@ -8,6 +8,7 @@ use uuid::Uuid;
 use std::collections::HashMap;
 use crate::util::error::Error;
 use crate::client::cmd::options;
+use crate::client::verifier::VerificationKey;
 
 
 const FILE_EXTENSION: &str = "asc";
@@ -38,13 +39,18 @@ impl FileHandler for CheckSumFileHandler {
     }
 
     /* when assemble checksum signature when only create another .asc file separately */
-    async fn assemble_data(&self, path: &PathBuf, data: Vec<Vec<u8>>, temp_dir: &PathBuf, _sign_options: &HashMap<String, String>) -> Result<(String, String)> {
+    async fn assemble_data(&self, path: &Path, data: Vec<Vec<u8>>, temp_dir: &Path, _sign_options: &HashMap<String, String>) -> Result<(String, String)> {
         let temp_file = temp_dir.join(Uuid::new_v4().to_string());
         //convert bytes into string
         let result = String::from_utf8_lossy(&data[0]);
         fs::write(temp_file.clone(), result.as_bytes()).await?;
         Ok((temp_file.as_path().display().to_string(),
-            format!("{}.{}", path.as_path().display(), FILE_EXTENSION)))
+            format!("{}.{}", path.display(), FILE_EXTENSION)))
+    }
+
+    async fn verify(&self, path: &Path, key: &VerificationKey, _sign_options: &HashMap<String, String>) -> Result<()> {
+        let signature = fs::read(format!("{}.{}", path.display(), FILE_EXTENSION)).await?;
+        key.verify_openpgp(&fs::read(path).await?, &signature)
     }
 }
 
@@ -0,0 +1,341 @@
+use std::path::Path;
+use super::traits::FileHandler;
+use async_trait::async_trait;
+use crate::util::error::Result;
+use tokio::fs;
+use uuid::Uuid;
+use openssl::sha::Sha256;
+
+use std::collections::HashMap;
+use crate::util::error::Error;
+use crate::client::cmd::options;
+use crate::client::sign_identity::KeyType;
+use crate::client::verifier::VerificationKey;
+
+// same extension with sbsign when detached signature required
+const FILE_EXTENSION: &str = "pk7";
+const DOS_MAGIC: &[u8] = b"MZ";
+const PE_MAGIC: &[u8] = b"PE\0\0";
+const PE_OFFSET_LOCATION: usize = 0x3c;
+const COFF_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const PE32_MAGIC: u16 = 0x10b;
+const PE32_PLUS_MAGIC: u16 = 0x20b;
+const CERTIFICATE_TABLE_INDEX: usize = 4;
+const DATA_DIRECTORY_SIZE: usize = 8;
+const WIN_CERT_HEADER_SIZE: usize = 8;
+const WIN_CERT_REVISION_2_0: u16 = 0x0200;
+const WIN_CERT_TYPE_PKCS_SIGNED_DATA: u16 = 0x0002;
+const CERTIFICATE_ALIGNMENT: usize = 8;
+
+// Reference https://learn.microsoft.com/en-us/windows/win32/debug/pe-format
+// NOTE: the image is normalized when parsing: existing certificate table is stripped and the image is padded to 8 bytes
+// so that the digest calculated at splitting is exactly the one of the image we assemble.
+struct PeImage {
+    data: Vec<u8>,
+    checksum_offset: usize,
+    certificate_directory_offset: usize,
+    size_of_headers: usize,
+    //pointer to raw data and size of raw data
+    sections: Vec<(usize, usize)>,
+    //the stripped certificate table, empty if image isn't signed
+    certificate_table: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    data.get(offset..offset + 2)
+        .map(|v| u16::from_le_bytes([v[0], v[1]]))
+        .ok_or_else(|| Error::FileNotSupportError("truncated pe image".to_string()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|v| u32::from_le_bytes([v[0], v[1], v[2], v[3]]))
+        .ok_or_else(|| Error::FileNotSupportError("truncated pe image".to_string()))
+}
+
+fn align_up(value: usize, alignment: usize) -> usize {
+    (value + alignment - 1) & !(alignment - 1)
+}
+
+impl PeImage {
+    fn parse(mut data: Vec<u8>) -> Result<Self> {
+        if !data.starts_with(DOS_MAGIC) {
+            return Err(Error::FileNotSupportError("missing dos header in efi image".to_string()));
+        }
+        let pe_offset = read_u32(&data, PE_OFFSET_LOCATION)? as usize;
+        if data.get(pe_offset..pe_offset + PE_MAGIC.len()) != Some(PE_MAGIC) {
+            return Err(Error::FileNotSupportError("missing pe signature in efi image".to_string()));
+        }
+        let coff_offset = pe_offset + PE_MAGIC.len();
+        let number_of_sections = read_u16(&data, coff_offset + 2)? as usize;
+        let size_of_optional_header = read_u16(&data, coff_offset + 16)? as usize;
+        let optional_offset = coff_offset + COFF_HEADER_SIZE;
+        let (rva_number_offset, directory_offset) = match read_u16(&data, optional_offset)? {
+            PE32_MAGIC => (optional_offset + 92, optional_offset + 96),
+            PE32_PLUS_MAGIC => (optional_offset + 108, optional_offset + 112),
+            magic => return Err(Error::FileNotSupportError(format!("unknown optional header magic {:#x}", magic))),
+        };
+        if (read_u32(&data, rva_number_offset)? as usize) <= CERTIFICATE_TABLE_INDEX {
+            return Err(Error::FileNotSupportError("certificate table entry missing in efi image".to_string()));
+        }
+        let certificate_directory_offset = directory_offset + CERTIFICATE_TABLE_INDEX * DATA_DIRECTORY_SIZE;
+        if certificate_directory_offset + DATA_DIRECTORY_SIZE > optional_offset + size_of_optional_header {
+            return Err(Error::FileNotSupportError("certificate table entry out of optional header".to_string()));
+        }
+        let size_of_headers = read_u32(&data, optional_offset + 60)? as usize;
+        if size_of_headers > data.len() || size_of_headers < certificate_directory_offset + DATA_DIRECTORY_SIZE {
+            return Err(Error::FileNotSupportError("invalid size of headers in efi image".to_string()));
+        }
+        let mut sections = Vec::with_capacity(number_of_sections);
+        let section_table = optional_offset + size_of_optional_header;
+        for index in 0..number_of_sections {
+            let header = section_table + index * SECTION_HEADER_SIZE;
+            let size = read_u32(&data, header + 16)? as usize;
+            let pointer = read_u32(&data, header + 20)? as usize;
+            if size == 0 {
+                continue;
+            }
+            if pointer + size > data.len() {
+                return Err(Error::FileNotSupportError(format!("section {} exceeds the efi image", index)));
+            }
+            sections.push((pointer, size));
+        }
+        sections.sort_by_key(|section| section.0);
+        //strip the certificate table, it must be located at the end of image
+        let certificate_address = read_u32(&data, certificate_directory_offset)? as usize;
+        let certificate_size = read_u32(&data, certificate_directory_offset + 4)? as usize;
+        let mut certificate_table = vec![];
+        if certificate_size != 0 {
+            if certificate_address + certificate_size != data.len() {
+                return Err(Error::FileNotSupportError("certificate table isn't located at the end of efi image".to_string()));
+            }
+            //the headers and sections must be kept intact after the certificate table is stripped
+            if certificate_address < size_of_headers {
+                return Err(Error::FileNotSupportError("certificate table overlaps the headers of efi image".to_string()));
+            }
+            if sections.iter().any(|(pointer, size)| pointer + size > certificate_address) {
+                return Err(Error::FileNotSupportError("certificate table overlaps the sections of efi image".to_string()));
+            }
+            certificate_table = data.split_off(certificate_address);
+        }
+        data[certificate_directory_offset..certificate_directory_offset + DATA_DIRECTORY_SIZE].fill(0);
+        data.resize(align_up(data.len(), CERTIFICATE_ALIGNMENT), 0);
+        Ok(Self {
+            data,
+            checksum_offset: optional_offset + 64,
+            certificate_directory_offset,
+            size_of_headers,
+            sections,
+            certificate_table,
+        })
+    }
+
+    //the pkcs7 signed data inside of the first WIN_CERTIFICATE structure
+    fn embedded_signature(&self) -> Result<&[u8]> {
+        if self.certificate_table.is_empty() {
+            return Err(Error::VerifyError("efi image isn't signed".to_string()));
+        }
+        let length = read_u32(&self.certificate_table, 0)? as usize;
+        if read_u16(&self.certificate_table, 6)? != WIN_CERT_TYPE_PKCS_SIGNED_DATA
+            || length < WIN_CERT_HEADER_SIZE || length > self.certificate_table.len() {
+            return Err(Error::VerifyError("unsupported certificate in efi image".to_string()));
+        }
+        Ok(&self.certificate_table[WIN_CERT_HEADER_SIZE..length])
+    }
+
+    //authenticode digest which excludes the checksum, certificate table entry and the certificate table
+    fn digest(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(&self.data[..self.checksum_offset]);
+        hasher.update(&self.data[self.checksum_offset + 4..self.certificate_directory_offset]);
+        hasher.update(&self.data[self.certificate_directory_offset + DATA_DIRECTORY_SIZE..self.size_of_headers]);
+        let mut sum_of_bytes_hashed = self.size_of_headers;
+        for (pointer, size) in self.sections.iter() {
+            hasher.update(&self.data[*pointer..*pointer + *size]);
+            sum_of_bytes_hashed += *size;
+        }
+        if self.data.len() > sum_of_bytes_hashed {
+            hasher.update(&self.data[sum_of_bytes_hashed..]);
+        }
+        hasher.finish().to_vec()
+    }
+
+    fn embed_signature(&self, signature: &[u8]) -> Vec<u8> {
+        let mut image = self.data.clone();
+        let certificate_address = image.len();
+        let length = WIN_CERT_HEADER_SIZE + signature.len();
+        let table_size = align_up(length, CERTIFICATE_ALIGNMENT);
+        //WIN_CERTIFICATE structure
+        image.extend_from_slice(&(length as u32).to_le_bytes());
+        image.extend_from_slice(&WIN_CERT_REVISION_2_0.to_le_bytes());
+        image.extend_from_slice(&WIN_CERT_TYPE_PKCS_SIGNED_DATA.to_le_bytes());
+        image.extend_from_slice(signature);
+        image.resize(certificate_address + table_size, 0);
+        let directory = self.certificate_directory_offset;
+        image[directory..directory + 4].copy_from_slice(&(certificate_address as u32).to_le_bytes());
+        image[directory + 4..directory + 8].copy_from_slice(&(table_size as u32).to_le_bytes());
+        let checksum = pe_checksum(&image, self.checksum_offset);
+        image[self.checksum_offset..self.checksum_offset + 4].copy_from_slice(&checksum.to_le_bytes());
+        image
+    }
+}
+
+fn pe_checksum(data: &[u8], checksum_offset: usize) -> u32 {
+    let mut sum: u64 = 0;
+    for (index, word) in data.chunks(2).enumerate() {
+        let offset = index * 2;
+        if offset == checksum_offset || offset == checksum_offset + 2 {
+            continue;
+        }
+        sum += u16::from_le_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u64;
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum = (sum & 0xffff) + (sum >> 16);
+    (sum as u32).wrapping_add(data.len() as u32)
+}
+
+#[derive(Clone)]
+pub struct EfiFileHandler {
+
+}
+
+impl EfiFileHandler {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+#[async_trait]
+impl FileHandler for EfiFileHandler {
+
+    fn validate_options(&self, sign_options: &HashMap<String, String>) -> Result<()> {
+        if let Some(key_type) = sign_options.get(options::KEY_TYPE) {
+            if key_type != KeyType::X509.to_string().as_str() {
+                return Err(Error::InvalidArgumentError("efi file only support x509 signature".to_string()))
+            }
+        }
+        Ok(())
+    }
+
+    //authenticode digest is already the only content sent to server
+    fn sign_with_digest(&self) -> bool {
+        false
+    }
+
+    //only the authenticode digest of image is sent to server
+    async fn split_data(&self, path: &Path, sign_options: &mut HashMap<String, String>) -> Result<Vec<Vec<u8>>> {
+        let image = PeImage::parse(fs::read(path).await?)?;
+        sign_options.insert(options::SIGN_TYPE.to_string(), options::AUTHENTICODE.to_string());
+        Ok(vec![image.digest()])
+    }
+
+    async fn assemble_data(&self, path: &Path, data: Vec<Vec<u8>>, temp_dir: &Path, sign_options: &HashMap<String, String>) -> Result<(String, String)> {
+        let temp_file = temp_dir.join(Uuid::new_v4().to_string());
+        if let Some(detached) = sign_options.get(options::DETACHED) {
+            if detached == "true" {
+                fs::write(temp_file.clone(), &data[0]).await?;
+                return Ok((temp_file.as_path().display().to_string(),
+                           format!("{}.{}", path.display(), FILE_EXTENSION)))
+            }
+        }
+        let image = PeImage::parse(fs::read(path).await?)?;
+        fs::write(temp_file.clone(), image.embed_signature(&data[0])).await?;
+        Ok((temp_file.as_path().display().to_string(), path.display().to_string()))
+    }
+
+    async fn verify(&self, path: &Path, key: &VerificationKey, sign_options: &HashMap<String, String>) -> Result<()> {
+        let image = PeImage::parse(fs::read(path).await?)?;
+        if let Some(detached) = sign_options.get(options::DETACHED) {
+            if detached == "true" {
+                let signature = fs::read(format!("{}.{}", path.display(), FILE_EXTENSION)).await?;
+                return key.verify_authenticode(&image.digest(), &signature)
+            }
+        }
+        key.verify_authenticode(&image.digest(), image.embedded_signature()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    //minimal PE32+ image with one section
+    fn generate_image() -> Vec<u8> {
+        let mut image = vec![0u8; 0x400];
+        image[..2].copy_from_slice(DOS_MAGIC);
+        image[PE_OFFSET_LOCATION..PE_OFFSET_LOCATION + 4].copy_from_slice(&0x40u32.to_le_bytes());
+        image[0x40..0x44].copy_from_slice(PE_MAGIC);
+        //coff header: one section, 240 bytes optional header
+        image[0x46..0x48].copy_from_slice(&1u16.to_le_bytes());
+        image[0x54..0x56].copy_from_slice(&240u16.to_le_bytes());
+        //optional header
+        let optional = 0x58;
+        image[optional..optional + 2].copy_from_slice(&PE32_PLUS_MAGIC.to_le_bytes());
+        image[optional + 60..optional + 64].copy_from_slice(&0x200u32.to_le_bytes());
+        image[optional + 108..optional + 112].copy_from_slice(&16u32.to_le_bytes());
+        //section header
+        let section = optional + 240;
+        image[section..section + 5].copy_from_slice(b".text");
+        image[section + 16..section + 20].copy_from_slice(&0x200u32.to_le_bytes());
+        image[section + 20..section + 24].copy_from_slice(&0x200u32.to_le_bytes());
+        image[0x200..].fill(0xcc);
+        image
+    }
+
+    #[test]
+    fn digest_excludes_checksum() {
+        let image = generate_image();
+        let digest = PeImage::parse(image.clone()).unwrap().digest();
+        let mut modified = image.clone();
+        modified[0x58 + 64] = 0xff;
+        assert_eq!(digest, PeImage::parse(modified).unwrap().digest());
+        let mut modified = image;
+        modified[0x300] = 0x00;
+        assert_ne!(digest, PeImage::parse(modified).unwrap().digest());
+    }
+
+    #[test]
+    fn embed_and_replace_signature() {
+        let image = PeImage::parse(generate_image()).unwrap();
+        let digest = image.digest();
+        let signed = image.embed_signature(&[1u8; 13]);
+        let directory = image.certificate_directory_offset;
+        assert_eq!(read_u32(&signed, directory).unwrap(), 0x400);
+        assert_eq!(read_u32(&signed, directory + 4).unwrap(), 24);
+        assert_eq!(read_u32(&signed, 0x400).unwrap(), 21);
+        assert_eq!(signed.len(), 0x400 + 24);
+        //signed image will have the same digest and signature will be replaced when signing again
+        let resigned = PeImage::parse(signed).unwrap();
+        assert_eq!(digest, resigned.digest());
+        assert_eq!(resigned.embed_signature(&[2u8; 13]).len(), 0x400 + 24);
+    }
+
+    #[test]
+    fn reject_invalid_image() {
+        assert!(PeImage::parse(vec![0u8; 0x400]).is_err());
+        let mut image = generate_image();
+        image[0x58] = 0x00;
+        assert!(PeImage::parse(image).is_err());
+    }
+
+    fn with_certificate_table(mut image: Vec<u8>, address: usize) -> Vec<u8> {
+        let directory = 0x58 + 112 + CERTIFICATE_TABLE_INDEX * DATA_DIRECTORY_SIZE;
+        let size = image.len() - address;
+        image[directory..directory + 4].copy_from_slice(&(address as u32).to_le_bytes());
+        image[directory + 4..directory + 8].copy_from_slice(&(size as u32).to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn reject_certificate_table_inside_headers() {
+        let image = with_certificate_table(generate_image(), 0x100);
+        assert!(PeImage::parse(image).is_err());
+    }
+
+    #[test]
+    fn reject_certificate_table_inside_section() {
+        let image = with_certificate_table(generate_image(), 0x300);
+        assert!(PeImage::parse(image).is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use super::rpm::RpmFileHandler;
 use super::checksum::CheckSumFileHandler;
 use super::kernel_module::KernelModuleFileHandler;
+use super::efi::EfiFileHandler;
 use crate::client::sign_identity::FileType;
 use super::traits::FileHandler;
 
@@ -18,6 +19,9 @@ impl FileHandlerFactory {
             },
             FileType::KernelModule => {
                 Box::new(KernelModuleFileHandler::new())
+            },
+            FileType::Efi => {
+                Box::new(EfiFileHandler::new())
             }
         }
     }
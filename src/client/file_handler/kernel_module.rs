@@ -1,9 +1,10 @@
-use std::path::PathBuf;
+use std::path::Path;
 use super::traits::FileHandler;
 use async_trait::async_trait;
 use crate::util::error::Result;
 
 use uuid::Uuid;
+use tokio::fs;
 use std::io::Write;
 use bincode::{config, Decode, Encode};
 use std::collections::HashMap;
@@ -11,11 +12,13 @@ use std::os::raw::{c_uchar, c_uint};
 use crate::client::cmd::options;
 use crate::client::sign_identity::KeyType;
 use crate::util::error::Error;
+use crate::client::verifier::VerificationKey;
 
 
 const FILE_EXTENSION: &str = "p7s";
 const PKEY_ID_PKCS7: c_uchar = 2;
 const MAGIC_NUMBER: &str = "~Module signature appended~\n";
+const MODULE_SIGNATURE_SIZE: usize = 12;
 
 // Reference https://git.kernel.org/pub/scm/linux/kernel/git/stable/linux.git/tree/scripts/sign-file.c
 #[derive(Encode, Decode, PartialEq, Debug)]
@@ -67,13 +70,32 @@ impl KernelModuleFileHandler {
         signed.write_all(&bincode::encode_to_vec(
             &sig_struct,
             config::standard()
-                .skip_fixed_array_length()
                 .with_fixed_int_encoding()
                 .with_big_endian(),
         )?)?;
         signed.write_all(MAGIC_NUMBER.as_bytes())?;
         Ok(())
     }
+
+    //split the module into the original content and the appended pkcs7 signature
+    pub fn split_inline_signature<'a>(&self, module: &'a [u8]) -> Result<(&'a [u8], &'a [u8])> {
+        let trailer = MODULE_SIGNATURE_SIZE + MAGIC_NUMBER.len();
+        if module.len() < trailer || !module.ends_with(MAGIC_NUMBER.as_bytes()) {
+            return Err(Error::VerifyError("module signature not found".to_string()));
+        }
+        let (sig_struct, _): (ModuleSignature, usize) = bincode::decode_from_slice(
+            &module[module.len() - trailer..module.len() - MAGIC_NUMBER.len()],
+            config::standard()
+                .with_fixed_int_encoding()
+                .with_big_endian(),
+        )?;
+        let signature_end = module.len() - trailer;
+        if sig_struct.id_type != PKEY_ID_PKCS7 || sig_struct.sig_len as usize > signature_end {
+            return Err(Error::VerifyError("invalid module signature".to_string()));
+        }
+        let signature_start = signature_end - sig_struct.sig_len as usize;
+        Ok((&module[..signature_start], &module[signature_start..signature_end]))
+    }
 }
 
 #[async_trait]
@@ -89,7 +111,7 @@ impl FileHandler for KernelModuleFileHandler {
     }
 
     /* when assemble checksum signature when only create another .asc file separately */
-    async fn assemble_data(&self, path: &PathBuf, data: Vec<Vec<u8>>, temp_dir: &PathBuf, sign_options: &HashMap<String, String>) -> Result<(String, String)> {
+    async fn assemble_data(&self, path: &Path, data: Vec<Vec<u8>>, temp_dir: &Path, sign_options: &HashMap<String, String>) -> Result<(String, String)> {
         let temp_file = temp_dir.join(Uuid::new_v4().to_string());
         //convert bytes into string
         if let Some(detached) = sign_options.get("detached") {
@@ -104,5 +126,44 @@ impl FileHandler for KernelModuleFileHandler {
                    path.display().to_string()))
 
     }
+
+    async fn verify(&self, path: &Path, key: &VerificationKey, sign_options: &HashMap<String, String>) -> Result<()> {
+        let module = fs::read(path).await?;
+        if let Some(detached) = sign_options.get(options::DETACHED) {
+            if detached == "true" {
+                let signature = fs::read(format!("{}.{}", path.display(), FILE_EXTENSION)).await?;
+                return key.verify_pkcs7(&module, &signature)
+            }
+        }
+        let (content, signature) = self.split_inline_signature(&module)?;
+        key.verify_pkcs7(content, signature)
+    }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_inline_signature() {
+        let handler = KernelModuleFileHandler::new();
+        let directory = std::env::temp_dir().join(format!("signatrust-module-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (module, signed) = (directory.join("module.ko"), directory.join("signed.ko"));
+        std::fs::write(&module, b"kernel module content").unwrap();
+        handler.append_inline_signature(
+            &module.display().to_string(), &signed.display().to_string(), b"pkcs7 signature").unwrap();
+        let signed = std::fs::read(&signed).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let (content, signature) = handler.split_inline_signature(&signed).unwrap();
+        assert_eq!((content, signature), (&b"kernel module content"[..], &b"pkcs7 signature"[..]));
+        assert!(handler.split_inline_signature(b"kernel module content").is_err());
+        //signature length exceeds the module
+        let mut invalid = signed.clone();
+        let length = invalid.len() - MAGIC_NUMBER.len() - 4;
+        invalid[length..length + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(handler.split_inline_signature(&invalid), Err(Error::VerifyError(_))));
+    }
+}
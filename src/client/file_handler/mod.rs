@@ -3,4 +3,5 @@ pub mod traits;
 pub mod factory;
 pub mod checksum;
 pub mod kernel_module;
+pub mod efi;
 pub mod sequential_cursor;
\ No newline at end of file
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::Path;
 use super::traits::FileHandler;
 use async_trait::async_trait;
 use crate::util::error::Result;
@@ -13,6 +13,7 @@ use sha1;
 use crate::client::cmd::options;
 use crate::client::sign_identity::KeyType;
 use crate::util::error::Error;
+use crate::client::verifier::VerificationKey;
 
 #[derive(Clone)]
 pub struct RpmFileHandler {
@@ -49,7 +50,7 @@ impl FileHandler for RpmFileHandler {
     //rpm has two sections need to be signed
     //1. header
     //2. header and content
-    async fn split_data(&self, path: &PathBuf, _sign_options: &mut HashMap<String, String>) -> Result<Vec<Vec<u8>>> {
+    async fn split_data(&self, path: &Path, _sign_options: &mut HashMap<String, String>) -> Result<Vec<Vec<u8>>> {
         let file = File::open(path)?;
         let package = RPMPackage::parse(&mut BufReader::new(file))?;
         let mut header_bytes = Vec::<u8>::with_capacity(1024);
@@ -61,7 +62,7 @@ impl FileHandler for RpmFileHandler {
         Ok(vec![header_bytes, header_and_content])
 
     }
-    async fn assemble_data(&self, path: &PathBuf, data: Vec<Vec<u8>>, temp_dir: &PathBuf, _sign_options: &HashMap<String, String>) -> Result<(String, String)> {
+    async fn assemble_data(&self, path: &Path, data: Vec<Vec<u8>>, temp_dir: &Path, _sign_options: &HashMap<String, String>) -> Result<(String, String)> {
         let temp_rpm = temp_dir.join(Uuid::new_v4().to_string());
         let file = File::open(path)?;
         let mut package = RPMPackage::parse(&mut BufReader::new(file))?;
@@ -109,5 +110,12 @@ impl FileHandler for RpmFileHandler {
         package.write(&mut output)?;
         Ok((temp_rpm.as_path().display().to_string(), format!("{}", path.display())))
     }
+
+    //verify both of the header signature and the header&payload signature
+    async fn verify(&self, path: &Path, key: &VerificationKey, _sign_options: &HashMap<String, String>) -> Result<()> {
+        let file = File::open(path)?;
+        let package = RPMPackage::parse(&mut BufReader::new(file))?;
+        key.verify_rpm(&package)
+    }
 }
 
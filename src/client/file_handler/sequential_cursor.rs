@@ -95,18 +95,15 @@ mod test {
         let c2 = vec![2u8; 17];
         let c3 = vec![3u8; 17];
 
-        let mut buf = Vec::<u8>::with_capacity(17 * 3);
-        unsafe {
-            buf.set_len(17 * 3);
-        }
+        let mut buf = [0u8; 17 * 3];
         let mut sq = SeqCursor::new(&[c1.as_slice(), c2.as_slice(), c3.as_slice()]);
 
         sq.seek(std::io::SeekFrom::Current(16)).unwrap();
-        sq.read(&mut buf[0..4]).unwrap();
+        sq.read_exact(&mut buf[0..4]).unwrap();
         assert_eq!(buf[0..4].to_vec(), vec![1u8, 2u8, 2u8, 2u8]);
 
         sq.seek(std::io::SeekFrom::Current(12)).unwrap();
-        sq.read(&mut buf[4..8]).unwrap();
+        sq.read_exact(&mut buf[4..8]).unwrap();
         assert_eq!(buf[4..8].to_vec(), vec![2u8, 2u8, 3u8, 3u8]);
     }
 
@@ -118,13 +115,13 @@ mod test {
 
         //read with a short buffer
         let mut buf = vec![0u8; 1];
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![1u8]);
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![2u8]);
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![3u8]);
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![4u8]);
     }
 
@@ -137,26 +134,26 @@ mod test {
         let mut buf = vec![0u8; 6];
         // without seek
         let mut sq = SeqCursor::new(&[c1.as_slice(), c2.as_slice(), c3.as_slice()]);
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![1u8, 1u8, 2u8, 2u8, 3u8, 3u8]);
 
         // seek with start
         let mut buf = vec![0u8; 5];
         sq.seek(SeekFrom::Start(1)).unwrap();
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![1u8, 2u8, 2u8, 3u8, 3u8]);
 
         //seek with current
         let mut buf = vec![0u8; 5];
         sq.seek(SeekFrom::Start(0)).unwrap();
         sq.seek(SeekFrom::Current(1)).unwrap();
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![1u8, 2u8, 2u8, 3u8, 3u8]);
 
         //seek with end
         let mut buf = vec![0u8; 3];
         sq.seek(SeekFrom::End(-3)).unwrap();
-        sq.read(&mut buf).unwrap();
+        sq.read_exact(&mut buf).unwrap();
         assert_eq!(buf.to_vec(), vec![2u8, 3u8, 3u8]);
     }
 }
@@ -1,8 +1,9 @@
 use std::collections::HashMap;
 use async_trait::async_trait;
-use std::path::PathBuf;
+use std::path::Path;
 use crate::util::error::Result;
 use tokio::fs;
+use crate::client::verifier::VerificationKey;
 
 #[async_trait]
 pub trait FileHandler: Send + Sync {
@@ -10,10 +11,16 @@ pub trait FileHandler: Send + Sync {
     fn validate_options(&self, _sign_options: &HashMap<String, String>) -> Result<()> {
         Ok(())
     }
-    async fn split_data(&self, path: &PathBuf, _sign_options: &mut HashMap<String, String>) -> Result<Vec<Vec<u8>>> {
+    //whether the split content can be hashed locally and signed via digest instead of being uploaded
+    fn sign_with_digest(&self) -> bool {
+        true
+    }
+    async fn split_data(&self, path: &Path, _sign_options: &mut HashMap<String, String>) -> Result<Vec<Vec<u8>>> {
         let content = fs::read(path).await?;
         Ok(vec![content])
     }
     //return the temporary file path and signature file name
-    async fn assemble_data(&self, path: &PathBuf, data: Vec<Vec<u8>>, temp_dir: &PathBuf, sign_options: &HashMap<String, String>) -> Result<(String, String)>;
+    async fn assemble_data(&self, path: &Path, data: Vec<Vec<u8>>, temp_dir: &Path, sign_options: &HashMap<String, String>) -> Result<(String, String)>;
+    //verify the embedded or detached signature of the file
+    async fn verify(&self, path: &Path, key: &VerificationKey, sign_options: &HashMap<String, String>) -> Result<()>;
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use tonic::transport::{Channel, ClientTlsConfig, Identity};
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
 use std::collections::HashMap;
 use config::Value;
 use crate::client::load_balancer::dns::DNSLoadBalancer;
@@ -16,7 +16,14 @@ impl ChannelFactory {
         let tls_cert = config.get("tls_cert").unwrap_or(&Value::default()).to_string();
         let tls_key = config.get("tls_key").unwrap_or(&Value::default()).to_string();
         let server_port = config.get("server_port").unwrap_or(&Value::default()).to_string();
-        if tls_cert.is_empty() || tls_key.is_empty()
+        let ca_root = config.get("ca_root").unwrap_or(&Value::default()).to_string();
+        if (tls_cert.is_empty() || tls_key.is_empty()) && !ca_root.is_empty() {
+            //client is authenticated via api token in this case
+            info!("tls client key and cert not configured, tls will be enabled without client certificate");
+            client_config = Some(ClientTlsConfig::new()
+                .ca_certificate(Certificate::from_pem(tokio::fs::read(ca_root).await?))
+                .domain_name(config.get("domain_name").unwrap_or(&Value::default()).to_string()));
+        } else if tls_cert.is_empty() || tls_key.is_empty()
         {
             info!("tls client key and cert not configured, tls will be disabled");
         } else {
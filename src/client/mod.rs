@@ -2,4 +2,5 @@ pub mod cmd;
 pub mod sign_identity;
 pub mod worker;
 pub mod file_handler;
-pub mod load_balancer;
\ No newline at end of file
+pub mod load_balancer;
+pub mod verifier;
\ No newline at end of file
@@ -5,11 +5,13 @@ use std::cell::{RefCell};
 use crate::util::error::Result;
 use std::collections::HashMap;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum FileType {
     RPM,
     CheckSum,
-    KernelModule
+    KernelModule,
+    Efi,
 }
 
 impl Display for FileType {
@@ -17,12 +19,14 @@ impl Display for FileType {
         match self {
             FileType::RPM => write!(f, "rpm"),
             FileType::CheckSum => write!(f, "checksum"),
-            FileType::KernelModule => write!(f, "ko")
+            FileType::KernelModule => write!(f, "ko"),
+            FileType::Efi => write!(f, "efi"),
         }
     }
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
 pub enum KeyType {
     PGP,
     X509,
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str::from_utf8;
+
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::X509;
+use pgp::composed::signed_key::SignedPublicKey;
+use pgp::composed::StandaloneSignature;
+use pgp::Deserializable;
+use rpm::RPMPackage;
+use rpm::signature::pgp::Verifier as RpmVerifier;
+use serde::Deserialize;
+
+use crate::client::sign_identity::KeyType;
+use crate::infra::sign_plugin::authenticode;
+use crate::util::error::{Error, Result};
+
+const ARMOR_HEADER: &[u8] = b"-----BEGIN";
+
+//the same structure as the control server's export key response
+#[derive(Deserialize)]
+struct ExportKey {
+    public_key: String,
+    certificate: String,
+}
+
+/// public key or certificate used to verify the signatures produced by signatrust
+pub struct VerificationKey {
+    key_type: KeyType,
+    public_key: Vec<u8>,
+    certificate: Vec<u8>,
+}
+
+impl VerificationKey {
+    //openpgp public key in armored format or x509 certificate in pem format
+    pub fn from_file(key_type: KeyType, path: &str) -> Result<Self> {
+        let content = std::fs::read(path)?;
+        match key_type {
+            KeyType::PGP => Ok(Self { key_type, public_key: content, certificate: vec![] }),
+            KeyType::X509 => Ok(Self { key_type, public_key: vec![], certificate: content }),
+        }
+    }
+
+    //export public key and certificate via control server's '/api/v1/keys/{id}/export' endpoint
+    pub async fn from_control_server(key_type: KeyType, config: &HashMap<String, config::Value>, key_id: &str) -> Result<Self> {
+        let address = config.get("address").unwrap_or(&config::Value::default()).to_string();
+        let token = config.get("token").unwrap_or(&config::Value::default()).to_string();
+        let response = Self::http_client(config).await?
+            .post(format!("{}/api/v1/keys/{}/export", address.trim_end_matches('/'), key_id))
+            .header("Authorization", token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(Error::HttpRequest(format!(
+                "failed to export key {} from control server, status {}", key_id, response.status())));
+        }
+        let exported: ExportKey = response.json().await?;
+        Ok(Self {
+            key_type,
+            public_key: exported.public_key.into_bytes(),
+            certificate: exported.certificate.into_bytes(),
+        })
+    }
+
+    //the control server certificate is verified against ca_root when configured, otherwise the system roots
+    async fn http_client(config: &HashMap<String, config::Value>) -> Result<reqwest::Client> {
+        let ca_root = config.get("ca_root").unwrap_or(&config::Value::default()).to_string();
+        let mut builder = reqwest::Client::builder();
+        if !ca_root.is_empty() {
+            builder = builder.add_root_certificate(reqwest::Certificate::from_pem(&tokio::fs::read(ca_root).await?)?);
+        }
+        Ok(builder.build()?)
+    }
+
+    //armored or binary openpgp signature
+    pub fn verify_openpgp(&self, content: &[u8], signature: &[u8]) -> Result<()> {
+        self.expect_key_type(KeyType::PGP)?;
+        let public = from_utf8(&self.public_key).map_err(|e| Error::KeyParseError(e.to_string()))?;
+        let (public_key, _) = SignedPublicKey::from_string(public).map_err(|e| Error::KeyParseError(e.to_string()))?;
+        let signature = if signature.starts_with(ARMOR_HEADER) {
+            let armored = from_utf8(signature).map_err(|e| Error::VerifyError(e.to_string()))?;
+            StandaloneSignature::from_string(armored)?.0
+        } else {
+            StandaloneSignature::from_bytes(Cursor::new(signature))?
+        };
+        signature.verify(&public_key, content).map_err(|e| Error::VerifyError(e.to_string()))
+    }
+
+    //rpm signature header which contains both of the header and the header&payload signatures
+    pub fn verify_rpm(&self, package: &RPMPackage) -> Result<()> {
+        self.expect_key_type(KeyType::PGP)?;
+        let verifier = RpmVerifier::load_from_asc_bytes(&self.public_key)?;
+        package.verify_signature(verifier).map_err(|e| Error::VerifyError(e.to_string()))
+    }
+
+    //detached pkcs7 signature without certificates
+    pub fn verify_pkcs7(&self, content: &[u8], signature: &[u8]) -> Result<()> {
+        self.expect_key_type(KeyType::X509)?;
+        let signature = Pkcs7::from_der(signature)?;
+        let mut certificates = Stack::new()?;
+        certificates.push(X509::from_pem(&self.certificate)?)?;
+        //the certificate is trusted directly, therefore the chain verification is skipped.
+        signature.verify(
+            &certificates,
+            &X509StoreBuilder::new()?.build(),
+            Some(content),
+            None,
+            Pkcs7Flags::NOVERIFY | Pkcs7Flags::NOINTERN | Pkcs7Flags::BINARY,
+        ).map_err(|e| Error::VerifyError(e.to_string()))
+    }
+
+    pub fn verify_authenticode(&self, image_digest: &[u8], signature: &[u8]) -> Result<()> {
+        self.expect_key_type(KeyType::X509)?;
+        let certificate = X509::from_pem(&self.certificate)?;
+        authenticode::verify(&certificate, signature, image_digest)
+    }
+
+    fn expect_key_type(&self, key_type: KeyType) -> Result<()> {
+        if self.key_type != key_type {
+            return Err(Error::InvalidArgumentError(format!(
+                "{} signature can't be verified with {} key", key_type, self.key_type)));
+        }
+        Ok(())
+    }
+}
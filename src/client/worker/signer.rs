@@ -10,64 +10,118 @@ pub mod signatrust {
 }
 
 use tonic::transport::Channel;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::Request;
 use signatrust::{
-    signatrust_client::SignatrustClient, SignStreamRequest,
+    signatrust_client::SignatrustClient, SignStreamRequest, SignDigestRequest,
 };
 
-use crate::util::error::Error;
+use crate::util::digest::{DigestAlgorithm, DigestState};
+use crate::util::error::{Error, Result};
 use std::io::{Cursor, Read};
+use std::collections::HashMap;
 
 pub struct RemoteSigner {
     client: SignatrustClient<Channel>,
     buffer_size: usize,
+    token: Option<MetadataValue<Ascii>>,
 }
 
 
 impl RemoteSigner {
 
-    pub fn new(channel: Channel, buffer_size: usize) -> Self {
+    pub fn new(channel: Channel, buffer_size: usize, token: Option<MetadataValue<Ascii>>) -> Self {
         Self {
             client: SignatrustClient::new(channel),
             buffer_size,
+            token,
+        }
+    }
+
+    //api token is used to identify the caller when client certificate is absent
+    fn build_request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(token) = &self.token {
+            request.metadata_mut().insert("authorization", token.clone());
+        }
+        request
+    }
+
+    async fn sign_stream(&mut self, key_type: String, key_id: String, options: HashMap<String, String>, sign_content: Vec<u8>) -> Result<Vec<u8>> {
+        let mut sign_segments: Vec<SignStreamRequest> = Vec::new();
+        let mut buffer = vec![0; self.buffer_size];
+        let mut cursor = Cursor::new(sign_content);
+        while let Ok(length) = cursor.read(&mut buffer) {
+            if length == 0 {
+                break
+            }
+            let content = buffer[0..length].to_vec();
+            sign_segments.push(SignStreamRequest{
+                data: content,
+                options: options.clone(),
+                key_type: key_type.clone(),
+                key_id: key_id.clone(),
+            });
+        }
+        let request = self.build_request(tokio_stream::iter(sign_segments));
+        let result = self.client.sign_stream(request).await;
+        match result {
+            Ok(result) => {
+                let data = result.into_inner();
+                if data.error.is_empty() {
+                    Ok(data.signature)
+                } else {
+                    Err(Error::RemoteSignError(data.error))
+                }
+            }
+            Err(err) => Err(Error::RemoteSignError(format!("{:?}", err)))
+        }
+    }
+
+    //only the hash state of content is sent to server
+    async fn sign_digest(&mut self, key_type: String, key_id: String, options: HashMap<String, String>, sign_content: Vec<u8>) -> Result<Vec<u8>> {
+        let mut digest = DigestState::new(DigestAlgorithm::Sha256);
+        digest.update(&sign_content);
+        let request = self.build_request(SignDigestRequest{
+            key_type,
+            key_id,
+            options,
+            hash_algorithm: digest.algorithm().to_string(),
+            hash_state: digest.state(),
+            hashed_length: digest.hashed_length(),
+            remainder: digest.remainder().to_vec(),
+        });
+        let result = self.client.sign_digest(request).await;
+        match result {
+            Ok(result) => {
+                let data = result.into_inner();
+                if data.error.is_empty() {
+                    Ok(data.signature)
+                } else {
+                    Err(Error::RemoteSignError(data.error))
+                }
+            }
+            Err(err) => Err(Error::RemoteSignError(format!("{:?}", err)))
         }
     }
 }
 
 #[async_trait]
 impl SignHandler for RemoteSigner {
-    async fn process(&mut self, _handler: Box<dyn FileHandler>, item: SignIdentity) -> SignIdentity {
+    async fn process(&mut self, handler: Box<dyn FileHandler>, item: SignIdentity) -> SignIdentity {
         let mut signed_content = Vec::new();
         let read_data = item.raw_content.borrow().clone();
         for sign_content in read_data.into_iter() {
-            let mut sign_segments: Vec<SignStreamRequest> = Vec::new();
-            let mut buffer = vec![0; self.buffer_size];
-            let mut cursor = Cursor::new(sign_content);
-            while let Ok(length) = cursor.read(&mut buffer) {
-                if length == 0 {
-                    break
-                }
-                let content = buffer[0..length].to_vec();
-                sign_segments.push(SignStreamRequest{
-                    data: content,
-                    options: item.sign_options.borrow().clone(),
-                    key_type: format!("{}", item.key_type),
-                    key_id: item.key_id.clone(),
-                });
-            }
-            let result = self.client.sign_stream(
-                tokio_stream::iter(sign_segments)).await;
+            let key_type = format!("{}", item.key_type);
+            let options = item.sign_options.borrow().clone();
+            let result = if handler.sign_with_digest() {
+                self.sign_digest(key_type, item.key_id.clone(), options, sign_content).await
+            } else {
+                self.sign_stream(key_type, item.key_id.clone(), options, sign_content).await
+            };
             match result {
-                Ok(result) => {
-                    let data = result.into_inner();
-                    if data.error.is_empty() {
-                        signed_content.push(data.signature);
-                    } else {
-                        *item.error.borrow_mut() = Err(Error::RemoteSignError(data.error))
-                    }
-                }
-                Err(err) => {
-                    *item.error.borrow_mut() = Err(Error::RemoteSignError(format!("{:?}", err)))
-                }
+                Ok(signature) => signed_content.push(signature),
+                Err(err) => *item.error.borrow_mut() = Err(err),
             }
         }
         debug!("successfully sign file {}", item.file_path.as_path().display());
@@ -77,4 +131,3 @@ impl SignHandler for RemoteSigner {
         item
     }
 }
-
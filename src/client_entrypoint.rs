@@ -2,7 +2,7 @@
 use std::env;
 use crate::util::error::{Result, Error};
 use clap::{Parser, Subcommand};
-use crate::client::cmd::add;
+use crate::client::cmd::{add, verify};
 use config::{Config, File};
 use std::sync::{Arc, atomic::AtomicBool, RwLock};
 use crate::client::cmd::traits::SignCommand;
@@ -39,6 +39,16 @@ pub struct App {
 enum Commands {
     #[command(about = "Create new signature for single file or all of the files in directory", long_about = None)]
     Add(add::CommandAdd),
+    #[command(about = "Verify the signature of single file or all of the files in directory", long_about = None)]
+    Verify(verify::CommandVerify),
+}
+
+fn run<T: SignCommand>(handler: T) -> Result<()> {
+    handler.validate().expect("failed to validate command option");
+    if !handler.handle().expect("failed to perform command") {
+        return Err(Error::PartialFailureError)
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -47,25 +57,20 @@ fn main() -> Result<()> {
     let app = App::parse();
     let path = app.config.unwrap_or(
         format!("{}/{}", env::current_dir().expect("current dir not found").display(), "client.toml"));
-    let mut client = Config::default();
-    client
-        .merge(File::with_name(path.as_str())).expect("load client configuration file");
+    let client = Config::builder()
+        .add_source(File::with_name(path.as_str()))
+        .build().expect("load client configuration file");
     let signal = Arc::new(AtomicBool::new(false));
     signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&signal)).expect("failed to register sigterm signal");
     signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&signal)).expect("failed to register sigint signal");
-    //construct handler
-    let command = match app.command {
+    //construct handler, handle and quit
+    match app.command {
         Some(Commands::Add(add_command)) => {
-            Some(add::CommandAddHandler::new(signal.clone(), Arc::new(RwLock::new(client)), add_command)?)
+            run(add::CommandAddHandler::new(signal.clone(), Arc::new(RwLock::new(client)), add_command)?)
         }
-        None => {None}
-    };
-    //handler and quit
-    if let Some(handler) = command {
-        handler.validate().expect("failed to validate command option");
-        if !handler.handle().expect("failed to perform command") {
-            return Err(Error::PartialFailureError)
+        Some(Commands::Verify(verify_command)) => {
+            run(verify::CommandVerifyHandler::new(signal.clone(), Arc::new(RwLock::new(client)), verify_command)?)
         }
+        None => {Ok(())}
     }
-    Ok(())
 }
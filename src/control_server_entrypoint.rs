@@ -27,6 +27,20 @@ pub struct App {
     help = "path of configuration file, 'config/server.toml' relative to working directory be used in default"
     )]
     config: Option<String>,
+    #[arg(long)]
+    #[arg(help = "re-encrypt all of the data keys with the latest cluster key and envelope format, then exit")]
+    reencrypt_keys: bool,
+    #[arg(long)]
+    #[arg(help = "apply the database migrations embedded in the binary, then exit")]
+    migrate_only: bool,
+    #[arg(long, value_name = "KEY_FILE")]
+    #[arg(help = "create the master key file for the file kms provider, the master key is sealed with the passphrase \
+    specified in SIGNATRUST_KMS_PASSPHRASE environment if present, then exit")]
+    create_kms_key_file: Option<String>,
+    #[arg(long, value_name = "KEY_FILE")]
+    #[arg(help = "append a new master key into the key file of the file kms provider, the previous keys are kept for \
+    decoding existing cluster keys, then exit")]
+    rotate_kms_key_file: Option<String>,
 }
 
 lazy_static! {
@@ -51,8 +65,22 @@ lazy_static! {
 async fn main() -> Result<()> {
     //prepare config and logger
     env_logger::init();
+    let app = App::parse();
+    if let Some(key_file) = app.create_kms_key_file {
+        return infra::kms::file::create_key_file(&key_file);
+    }
+    if let Some(key_file) = app.rotate_kms_key_file {
+        return infra::kms::file::rotate_key_file(&key_file);
+    }
+    if app.migrate_only {
+        let database = SERVERCONFIG.read()?.get_table("database")?;
+        return infra::database::pool::migrate_only(&database).await;
+    }
     //control server starts
     let control_server = presentation::server::control_server::ControlServer::new(SERVERCONFIG.clone()).await?;
+    if app.reencrypt_keys {
+        return control_server.reencrypt_keys().await;
+    }
     control_server.run().await?;
     Ok(())
 }
@@ -29,6 +29,9 @@ pub struct App {
         help = "path of configuration file, 'config/server.toml' relative to working directory be used in default"
     )]
     config: Option<String>,
+    #[arg(long)]
+    #[arg(help = "apply the database migrations embedded in the binary, then exit")]
+    migrate_only: bool,
 }
 
 lazy_static! {
@@ -53,6 +56,10 @@ lazy_static! {
 async fn main() -> Result<()> {
     //prepare config and logger
     env_logger::init();
+    if App::parse().migrate_only {
+        let database = SERVERCONFIG.read()?.get_table("database")?;
+        return infra::database::pool::migrate_only(&database).await;
+    }
     //data server starts
     let data_server: DataServer = DataServer::new(SERVERCONFIG.clone(), SIGNAL.clone()).await?;
     data_server.run().await?;
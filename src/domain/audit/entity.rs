@@ -0,0 +1,115 @@
+use crate::util::error::{Error, Result};
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuditAction {
+    Sign,
+    SignDigest,
+    Create,
+    Import,
+    Delete,
+    Enable,
+    Disable,
+    Renew,
+    Grant,
+    Revoke,
+}
+
+impl FromStr for AuditAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sign" => Ok(AuditAction::Sign),
+            "sign_digest" => Ok(AuditAction::SignDigest),
+            "create" => Ok(AuditAction::Create),
+            "import" => Ok(AuditAction::Import),
+            "delete" => Ok(AuditAction::Delete),
+            "enable" => Ok(AuditAction::Enable),
+            "disable" => Ok(AuditAction::Disable),
+            "renew" => Ok(AuditAction::Renew),
+            "grant" => Ok(AuditAction::Grant),
+            "revoke" => Ok(AuditAction::Revoke),
+            _ => Err(Error::UnsupportedTypeError(format!("unsupported audit action {}", s))),
+        }
+    }
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AuditAction::Sign => write!(f, "sign"),
+            AuditAction::SignDigest => write!(f, "sign_digest"),
+            AuditAction::Create => write!(f, "create"),
+            AuditAction::Import => write!(f, "import"),
+            AuditAction::Delete => write!(f, "delete"),
+            AuditAction::Enable => write!(f, "enable"),
+            AuditAction::Disable => write!(f, "disable"),
+            AuditAction::Renew => write!(f, "renew"),
+            AuditAction::Grant => write!(f, "grant"),
+            AuditAction::Revoke => write!(f, "revoke"),
+        }
+    }
+}
+
+//record of signing or key lifecycle action, key_id is 0 when the key can't be resolved
+#[derive(Debug, Clone)]
+pub struct SignRecord {
+    pub id: i32,
+    pub key_id: i32,
+    pub key_name: String,
+    pub key_type: String,
+    pub action: AuditAction,
+    //user email or the identity of client certificate
+    pub principal: String,
+    pub user_id: Option<i32>,
+    pub file_name: String,
+    //hex encoded sha256 digest of the signed content
+    pub digest: String,
+    pub detail: String,
+    pub succeed: bool,
+    pub error: String,
+    pub create_at: DateTime<Utc>,
+}
+
+impl SignRecord {
+    pub fn new(action: AuditAction, principal: String, user_id: Option<i32>) -> Self {
+        SignRecord {
+            id: 0,
+            key_id: 0,
+            key_name: "".to_string(),
+            key_type: "".to_string(),
+            action,
+            principal,
+            user_id,
+            file_name: "".to_string(),
+            digest: "".to_string(),
+            detail: "".to_string(),
+            succeed: true,
+            error: "".to_string(),
+            create_at: Utc::now(),
+        }
+    }
+
+    pub fn set_result<T>(&mut self, result: &Result<T>) {
+        if let Err(err) = result {
+            self.succeed = false;
+            self.error = err.to_string();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub key_id: Option<i32>,
+    pub principal: Option<String>,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    //only records of keys managed by the principal or performed by the principal are visible
+    pub visible_to: Option<String>,
+    pub limit: u32,
+    pub offset: u32,
+}
@@ -0,0 +1,10 @@
+use super::entity::{AuditFilter, SignRecord};
+use crate::util::error::Result;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    //the records are inserted with one statement
+    async fn create_batch(&self, records: Vec<SignRecord>) -> Result<()>;
+    async fn query(&self, filter: AuditFilter) -> Result<Vec<SignRecord>>;
+}
@@ -40,9 +40,14 @@ impl Display for ClusterKey {
 }
 
 impl ClusterKey {
-    pub fn new(data: Vec<u8>, algorithm: String, keep_in_days: i64) -> Result<Self> {
+    //the identity of the first key of the algorithm is unique per day, and the key rotated from the previous one is
+    //identified by its predecessor, so servers creating the key at the same time will end up with the same key
+    pub fn new(data: Vec<u8>, algorithm: String, keep_in_days: i64, previous: Option<i32>) -> Result<Self> {
         let now = Utc::now();
-        let identity = format!("{}-{}", algorithm, now.format("%d-%m-%Y"));
+        let identity = match previous {
+            Some(id) => format!("{}-after-{}", algorithm, id),
+            None => format!("{}-{}", algorithm, now.format("%d-%m-%Y")),
+        };
         Ok(ClusterKey {
             id: 0,
             data,
@@ -75,7 +80,7 @@ impl Default for SecClusterKey {
 
 
 impl SecClusterKey {
-    pub async fn load<K>(cluster_key: ClusterKey, kms_provider: &Box<K>) -> Result<SecClusterKey>
+    pub async fn load<K>(cluster_key: ClusterKey, kms_provider: &K) -> Result<SecClusterKey>
     where K: KMSProvider + ?Sized {
         Ok(Self {
             id: cluster_key.id,
@@ -1,5 +1,7 @@
 use crate::domain::datakey::traits::{ExtendableAttributes, Identity};
 use crate::util::error::{Error, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use chrono::{DateTime, Utc};
 use secstr::SecVec;
 use serde_json;
@@ -9,21 +11,25 @@ use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
 use crate::domain::encryption_engine::EncryptionEngine;
+use crate::domain::sign_plugin::ExternalKey;
+use std::sync::Arc;
 
+//fields of the key material which are encrypted separately
+pub const PRIVATE_KEY_FIELD: &str = "private_key";
+pub const PUBLIC_KEY_FIELD: &str = "public_key";
+pub const CERTIFICATE_FIELD: &str = "certificate";
+//options of importing existing key, they are carried within the attributes and replaced by the parsed ones
+pub const IMPORT_PASSPHRASE: &str = "import_passphrase";
+pub const IMPORT_FORMAT: &str = "import_format";
+pub const PKCS12_FORMAT: &str = "pkcs12";
 
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum KeyState {
     Enabled,
+    #[default]
     Disabled,
 }
 
-impl Default for KeyState {
-    fn default() -> Self {
-        KeyState::Disabled
-    }
-}
-
 impl FromStr for KeyState {
     type Err = Error;
 
@@ -72,6 +78,73 @@ impl Display for KeyType {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyRole {
+    Owner,
+    Admin,
+    Signer,
+}
+
+impl KeyRole {
+    //owner and admins can manage the key as well as sign with it
+    pub fn can_manage(&self) -> bool {
+        matches!(self, KeyRole::Owner | KeyRole::Admin)
+    }
+
+    //owner can grant admins and signers while admins can only grant signers
+    pub fn can_grant(&self, role: &KeyRole) -> bool {
+        match self {
+            KeyRole::Owner => *role != KeyRole::Owner,
+            KeyRole::Admin => *role == KeyRole::Signer,
+            KeyRole::Signer => false,
+        }
+    }
+}
+
+impl FromStr for KeyRole {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "owner" => Ok(KeyRole::Owner),
+            "admin" => Ok(KeyRole::Admin),
+            "signer" => Ok(KeyRole::Signer),
+            _ => Err(Error::UnsupportedTypeError(format!("unsupported data key role {}", s))),
+        }
+    }
+}
+
+impl Display for KeyRole {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            KeyRole::Owner => write!(f, "owner"),
+            KeyRole::Admin => write!(f, "admin"),
+            KeyRole::Signer => write!(f, "signer"),
+        }
+    }
+}
+
+//principal is the user email for control server requests or the identity of the client certificate
+//for data server requests
+#[derive(Debug, Clone)]
+pub struct KeyPermission {
+    pub id: i32,
+    pub key_id: i32,
+    pub principal: String,
+    pub role: KeyRole,
+}
+
+impl KeyPermission {
+    pub fn new(key_id: i32, principal: String, role: KeyRole) -> Self {
+        KeyPermission {
+            id: 0,
+            key_id,
+            principal,
+            role,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DataKey {
     pub id: i32,
@@ -87,7 +160,160 @@ pub struct DataKey {
     pub create_at: DateTime<Utc>,
     pub expire_at: DateTime<Utc>,
     pub soft_delete: bool,
-    pub key_state: KeyState
+    pub key_state: KeyState,
+    //refreshed by database whenever the state of key changes
+    pub update_at: DateTime<Utc>,
+}
+
+impl DataKey {
+    //the associated data binds the encrypted key material to the data key and field, so that the ciphertexts
+    //can't be swapped between the data keys or the fields without being detected
+    pub fn associated_data(&self, field: &str) -> Vec<u8> {
+        format!("{}\0{}\0{}", self.name, self.key_type, field).into_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeySortField {
+    #[default]
+    Id,
+    Name,
+    CreateAt,
+    ExpireAt,
+}
+
+impl FromStr for KeySortField {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "id" => Ok(KeySortField::Id),
+            "name" => Ok(KeySortField::Name),
+            "create_at" => Ok(KeySortField::CreateAt),
+            "expire_at" => Ok(KeySortField::ExpireAt),
+            _ => Err(Error::ParameterError(format!("unsupported data key sort field {}", s))),
+        }
+    }
+}
+
+impl Display for KeySortField {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            KeySortField::Id => write!(f, "id"),
+            KeySortField::Name => write!(f, "name"),
+            KeySortField::CreateAt => write!(f, "create_at"),
+            KeySortField::ExpireAt => write!(f, "expire_at"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl FromStr for SortOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "asc" => Ok(SortOrder::Asc),
+            "desc" => Ok(SortOrder::Desc),
+            _ => Err(Error::ParameterError(format!("unsupported sort order {}", s))),
+        }
+    }
+}
+
+impl Display for SortOrder {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            SortOrder::Asc => write!(f, "asc"),
+            SortOrder::Desc => write!(f, "desc"),
+        }
+    }
+}
+
+//position of the last key in the previous page, it's encoded into an opaque string for clients, the id breaks
+//the tie when the keys share the same value of the sort field. the cursor is only valid in the sorting it's created
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyCursor {
+    pub sort_by: KeySortField,
+    pub order: SortOrder,
+    pub id: i32,
+    pub value: String,
+}
+
+impl KeyCursor {
+    pub fn of(data_key: &DataKey, sort_by: KeySortField, order: SortOrder) -> Self {
+        KeyCursor {
+            sort_by,
+            order,
+            id: data_key.id,
+            value: match sort_by {
+                KeySortField::Id => String::new(),
+                KeySortField::Name => data_key.name.clone(),
+                KeySortField::CreateAt => data_key.create_at.to_rfc3339(),
+                KeySortField::ExpireAt => data_key.expire_at.to_rfc3339(),
+            },
+        }
+    }
+}
+
+impl FromStr for KeyCursor {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::ParameterError(format!("invalid cursor {}", s));
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(s).map_err(|_| invalid())?).map_err(|_| invalid())?;
+        let mut parts = decoded.splitn(4, ':');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(sort_by), Some(order), Some(id), Some(value)) => Ok(KeyCursor {
+                sort_by: KeySortField::from_str(sort_by)?,
+                order: SortOrder::from_str(order)?,
+                id: id.parse().map_err(|_| invalid())?,
+                value: value.to_string(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for KeyCursor {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", URL_SAFE_NO_PAD.encode(format!("{}:{}:{}:{}", self.sort_by, self.order, self.id, self.value)))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyFilter {
+    pub key_type: Option<KeyType>,
+    pub key_state: Option<KeyState>,
+    //email of the key owner
+    pub owner: Option<String>,
+    pub name_prefix: Option<String>,
+    //expiry window, the end is exclusive
+    pub expire_after: Option<DateTime<Utc>>,
+    pub expire_before: Option<DateTime<Utc>>,
+    //only keys which the principal has any permission of are visible
+    pub visible_to: Option<String>,
+    pub sort_by: KeySortField,
+    pub order: SortOrder,
+    //offset is always 0 when the cursor is specified
+    pub cursor: Option<KeyCursor>,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+//the key materials are not loaded for the listed keys
+#[derive(Debug)]
+pub struct KeyPage {
+    pub keys: Vec<DataKey>,
+    //total number of the keys matched without paging
+    pub total: i64,
+    //None if it's the last page
+    pub next_cursor: Option<KeyCursor>,
 }
 
 impl ExtendableAttributes for DataKey {
@@ -119,17 +345,60 @@ pub struct SecDataKey {
     pub private_key: SecVec<u8>,
     pub public_key: SecVec<u8>,
     pub certificate: SecVec<u8>,
-    pub identity: String
+    pub identity: String,
+    //private key is absent when it's held by external device
+    pub external_key: Option<Arc<dyn ExternalKey>>,
 }
 
 impl SecDataKey {
-    pub async fn load(data_key: &DataKey, engine: &Box<dyn EncryptionEngine>) -> Result<SecDataKey> {
+    pub async fn load(data_key: &DataKey, engine: &Arc<dyn EncryptionEngine>) -> Result<SecDataKey> {
         Ok(Self {
-            private_key: SecVec::new(engine.decode(data_key.private_key.clone()).await?),
-            public_key: SecVec::new(engine.decode(data_key.public_key.clone()).await?),
-            certificate: SecVec::new(engine.decode(data_key.certificate.clone()).await?),
+            private_key: SecVec::new(engine.decode(
+                data_key.private_key.clone(), &data_key.associated_data(PRIVATE_KEY_FIELD)).await?),
+            public_key: SecVec::new(engine.decode(
+                data_key.public_key.clone(), &data_key.associated_data(PUBLIC_KEY_FIELD)).await?),
+            certificate: SecVec::new(engine.decode(
+                data_key.certificate.clone(), &data_key.associated_data(CERTIFICATE_FIELD)).await?),
             identity: data_key.get_identity(),
+            external_key: None,
         })
     }
+
+    pub fn load_external(data_key: &DataKey, external_key: Arc<dyn ExternalKey>) -> SecDataKey {
+        Self {
+            private_key: SecVec::new(vec![]),
+            public_key: SecVec::new(data_key.public_key.clone()),
+            certificate: SecVec::new(data_key.certificate.clone()),
+            identity: data_key.get_identity(),
+            external_key: Some(external_key),
+        }
+    }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key_role() {
+        assert!(KeyRole::Owner.can_manage() && KeyRole::Admin.can_manage());
+        assert!(!KeyRole::Signer.can_manage());
+        for (role, grantable) in [
+            (KeyRole::Owner, [false, true, true]),
+            (KeyRole::Admin, [false, false, true]),
+            (KeyRole::Signer, [false, false, false]),
+        ] {
+            let granted = [KeyRole::Owner, KeyRole::Admin, KeyRole::Signer].map(|target| role.can_grant(&target));
+            assert_eq!(granted, grantable, "{}", role);
+        }
+    }
+
+    #[test]
+    fn test_key_cursor() {
+        let cursor = KeyCursor { sort_by: KeySortField::Name, order: SortOrder::Desc, id: 3, value: "key:a".to_string() };
+        assert_eq!(KeyCursor::from_str(&cursor.to_string()).unwrap(), cursor);
+        assert!(KeyCursor::from_str(&URL_SAFE_NO_PAD.encode("name:3:key")).is_err());
+        assert!(KeyCursor::from_str("not base64!").is_err());
+    }
+}
@@ -1,14 +1,33 @@
 use super::entity::DataKey;
 use crate::util::error::Result;
 use async_trait::async_trait;
-use crate::domain::datakey::entity::KeyState;
+use chrono::{DateTime, Utc};
+use crate::domain::datakey::entity::{KeyFilter, KeyPage, KeyPermission, KeyRole, KeyState};
 
 #[async_trait]
 pub trait Repository: Send + Sync {
     async fn create(&self, data_key: DataKey) -> Result<DataKey>;
     async fn get_all(&self) -> Result<Vec<DataKey>>;
+    async fn get_all_with_deleted(&self) -> Result<Vec<DataKey>>;
+    async fn get_by_principal(&self, principal: String) -> Result<Vec<DataKey>>;
+    //paged keys matched by the filter, the key materials are not queried
+    async fn get_by_filter(&self, filter: KeyFilter) -> Result<KeyPage>;
     async fn get_by_id(&self, id: i32) -> Result<DataKey>;
     async fn update_state(&self, id: i32, state: KeyState) -> Result<()>;
+    //update the key materials, attributes and expire time of the renewed key
+    async fn update_keys(&self, data_key: &DataKey) -> Result<()>;
+    //update the key materials only, used when re-encrypting keys
+    async fn update_encrypted_keys(&self, data_key: &DataKey) -> Result<()>;
+    async fn get_expired_enabled_keys(&self, now: DateTime<Utc>) -> Result<Vec<DataKey>>;
     async fn get_enabled_key_by_type_and_name(&self, key_type: String, name: String) -> Result<DataKey>;
     async fn delete_by_id(&self, id: i32) -> Result<()>;
+    //including the disabled and deleted keys, used for invalidating the cached keys. keys are returned without key
+    //materials in the order of (update_at, id) and only the ones after the (since, id) cursor are included
+    async fn get_updated_since(&self, since: DateTime<Utc>, id: i32) -> Result<Vec<DataKey>>;
+    async fn get_permissions(&self, key_id: i32) -> Result<Vec<KeyPermission>>;
+    //the most privileged role of the principal on the key, owner > admin > signer
+    async fn get_role(&self, key_id: i32, principal: &str) -> Result<Option<KeyRole>>;
+    //the update time of key is refreshed along with its permissions, so that the cached roles are invalidated
+    async fn create_permission(&self, permission: KeyPermission) -> Result<()>;
+    async fn delete_permission(&self, permission: KeyPermission) -> Result<()>;
 }
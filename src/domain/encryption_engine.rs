@@ -4,6 +4,9 @@ use crate::util::error::Result;
 #[async_trait]
 pub trait EncryptionEngine: Send + Sync {
     async fn initialize(&mut self) -> Result<()>;
-    async fn encode(&self, content: Vec<u8>) -> Result<Vec<u8>>;
-    async fn decode(&self, content: Vec<u8>) -> Result<Vec<u8>>;
+    //create the new cluster key when the latest one is expired and switch to the latest cluster key
+    async fn rotate(&self) -> Result<()>;
+    //the associated data is bound into the encrypted content, decode fails if it's different from the one used in encode
+    async fn encode(&self, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>>;
+    async fn decode(&self, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>>;
 }
\ No newline at end of file
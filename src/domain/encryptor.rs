@@ -2,24 +2,55 @@ use crate::util::error::{Error, Result};
 use std::fmt;
 use std::str::FromStr;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Algorithm {
+    //AES-256-GCM-SIV, the name is kept for the existing cluster keys
     Aes256GSM,
+    ChaCha20Poly1305,
+    Aes256GCM,
 }
 
 impl fmt::Display for Algorithm {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Algorithm::Aes256GSM => write!(f, "Aes256GSM"),
+            Algorithm::ChaCha20Poly1305 => write!(f, "ChaCha20Poly1305"),
+            Algorithm::Aes256GCM => write!(f, "Aes256GCM"),
+        }
+    }
+}
+
+impl Algorithm {
+    //identifier recorded in the envelope of encrypted data
+    pub fn id(&self) -> u8 {
+        match self {
+            Algorithm::Aes256GSM => 1,
+            Algorithm::ChaCha20Poly1305 => 2,
+            Algorithm::Aes256GCM => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(Algorithm::Aes256GSM),
+            2 => Ok(Algorithm::ChaCha20Poly1305),
+            3 => Ok(Algorithm::Aes256GCM),
+            _ => Err(Error::UnsupportedTypeError(format!(
+                "{} invalid encryption algorithm id",
+                id
+            ))),
         }
     }
 }
 
 impl FromStr for Algorithm {
     type Err = Error;
+    //both the configured name and the name recorded in cluster key are accepted
     fn from_str(s: &str) -> Result<Self> {
-        match s {
+        match s.to_lowercase().as_str() {
             "aes256gsm" => Ok(Algorithm::Aes256GSM),
+            "chacha20poly1305" => Ok(Algorithm::ChaCha20Poly1305),
+            "aes256gcm" => Ok(Algorithm::Aes256GCM),
             _ => Err(Error::UnsupportedTypeError(format!(
                 "{} invalid encryption algorithm type",
                 s
@@ -31,6 +62,7 @@ impl FromStr for Algorithm {
 pub trait Encryptor: Send + Sync {
     fn generate_key(&self) -> Vec<u8>;
     fn algorithm(&self) -> Algorithm;
-    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>) -> Result<Vec<u8>>;
-    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>) -> Result<Vec<u8>>;
+    //the associated data is authenticated but not encrypted, decryption fails if it's different
+    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>>;
+    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>>;
 }
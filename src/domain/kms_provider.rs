@@ -6,6 +6,9 @@ use std::str::FromStr;
 pub enum KMSType {
     HuaweiCloud,
     Dummy,
+    Vault,
+    Aws,
+    File,
 }
 
 impl FromStr for KMSType {
@@ -15,6 +18,9 @@ impl FromStr for KMSType {
         match s {
             "huaweicloud" => Ok(KMSType::HuaweiCloud),
             "dummy" => Ok(KMSType::Dummy),
+            "vault" => Ok(KMSType::Vault),
+            "aws" => Ok(KMSType::Aws),
+            "file" => Ok(KMSType::File),
             _ => Err(Error::UnsupportedTypeError(format!("{} kms type", s))),
         }
     }
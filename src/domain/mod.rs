@@ -1,7 +1,9 @@
+pub mod audit;
 pub mod clusterkey;
 pub mod datakey;
 pub mod user;
 pub mod token;
+pub mod timestamp;
 pub mod encryption_engine;
 pub mod encryptor;
 pub mod kms_provider;
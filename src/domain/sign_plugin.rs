@@ -1,6 +1,25 @@
 use crate::util::error::Result;
 use std::collections::HashMap;
+use std::sync::Arc;
 use crate::domain::datakey::entity::SecDataKey;
+use crate::util::digest::DigestState;
+use chrono::{DateTime, Utc};
+
+//sign options shared by the client, the servers and the x509 plugin
+pub const SIGN_TYPE: &str = "sign_type";
+pub const AUTHENTICODE: &str = "authenticode";
+pub const TIMESTAMP_TOKEN: &str = "timestamp_token";
+//whether to include the tsa certificate in the timestamp token
+pub const CERT_REQ: &str = "cert_req";
+
+/// Private key which is held by external device and never leaves it, i.e. the PKCS#11 token,
+/// only the public key and the raw signing operation are exposed.
+pub trait ExternalKey: Send + Sync {
+    //der encoded SubjectPublicKeyInfo
+    fn public_key(&self) -> Result<Vec<u8>>;
+    //RSA PKCS#1 v1.5 signature of the der encoded DigestInfo
+    fn sign_pkcs1(&self, digest_info: &[u8]) -> Result<Vec<u8>>;
+}
 
 pub trait SignPlugins: Send + Sync {
     fn new(db: &SecDataKey) -> Result<Self>
@@ -10,7 +29,16 @@ pub trait SignPlugins: Send + Sync {
         private_key: Option<Vec<u8>>,
         public_key: Option<Vec<u8>>,
         certificate: Option<Vec<u8>>,
-    ) -> HashMap<String, String>
+    ) -> Result<HashMap<String, String>>
+        where
+            Self: Sized;
+    //validate the existing keys and convert them into the same format as the generated ones.
+    fn import_keys(
+        private_key: Vec<u8>,
+        public_key: Vec<u8>,
+        certificate: Vec<u8>,
+        value: &HashMap<String, String>,
+    ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)>
         where
             Self: Sized;
     fn generate_keys(
@@ -18,5 +46,17 @@ pub trait SignPlugins: Send + Sync {
     ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)>
         where
             Self: Sized;
+    //generating public key and certificate for the key pair which is held by external device
+    fn generate_external_keys(
+        value: &HashMap<String, String>,
+        key: Arc<dyn ExternalKey>,
+    ) -> Result<(Vec<u8>, Vec<u8>)>
+        where
+            Self: Sized;
+    //re-issue the public key and certificate with the new expire time while the key pair is kept, the private key
+    //is returned as well since the openpgp self signatures are stored along with the secret key.
+    fn renew_keys(&self, expire_at: &DateTime<Utc>) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)>;
     fn sign(&self, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>>;
+    //sign with the digest of content which is calculated by client
+    fn sign_digest(&self, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>>;
 }
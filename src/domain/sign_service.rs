@@ -3,11 +3,13 @@ use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::domain::datakey::entity::DataKey;
+use crate::util::digest::DigestState;
 use async_trait::async_trait;
 
 #[derive(Debug)]
 pub enum SignBackendType {
     Memory,
+    Pkcs11,
 }
 
 impl FromStr for SignBackendType {
@@ -16,6 +18,7 @@ impl FromStr for SignBackendType {
     fn from_str(s: &str) -> Result<Self> {
         match s {
             "memory" => Ok(SignBackendType::Memory),
+            "pkcs11" => Ok(SignBackendType::Pkcs11),
             _ => Err(Error::UnsupportedTypeError(format!("{} sign backend type", s))),
         }
     }
@@ -24,6 +27,15 @@ impl FromStr for SignBackendType {
 #[async_trait]
 pub trait SignBackend: Send + Sync{
     async fn generate_keys(&self, data_key: &mut DataKey) -> Result<()>;
+    async fn import_keys(&self, data_key: &mut DataKey) -> Result<()>;
     async fn sign(&self, data_key: &DataKey, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>>;
+    async fn sign_digest(&self, data_key: &DataKey, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>>;
     async fn decode_public_keys(&self, data_key: &mut DataKey) -> Result<()>;
+    //re-issue the public key and certificate with the expire time of data key, the key pair is kept
+    async fn renew_keys(&self, data_key: &mut DataKey) -> Result<()>;
+    //re-encrypt the key materials with the latest cluster key, so that the old cluster keys can be retired
+    async fn reencrypt_keys(&self, data_key: &mut DataKey) -> Result<()>;
+    //drop everything cached in this process for the data key, invoked when the key is disabled or deleted here,
+    //the other servers invoke it once their periodical key refresh finds the key changed, see `start_key_refresh`
+    async fn invalidate(&self, data_key: &DataKey);
 }
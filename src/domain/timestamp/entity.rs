@@ -0,0 +1,241 @@
+use crate::util::der;
+use crate::util::error::Result;
+use chrono::{DateTime, SubsecRound, Utc};
+
+//hash algorithms accepted in the message imprint, in the form of (name, oid, digest length)
+const HASH_ALGORITHMS: &[(&str, &[u64], usize)] = &[
+    ("sha256", &[2, 16, 840, 1, 101, 3, 4, 2, 1], 32),
+    ("sha384", &[2, 16, 840, 1, 101, 3, 4, 2, 2], 48),
+    ("sha512", &[2, 16, 840, 1, 101, 3, 4, 2, 3], 64),
+];
+const TAG_EXTENSIONS: u8 = 0xa0;
+//PKIStatus
+const STATUS_GRANTED: u64 = 0;
+const STATUS_REJECTION: u64 = 2;
+
+/// PKIFailureInfo, the value is the bit position defined in RFC 3161
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureInfo {
+    BadAlg = 0,
+    BadRequest = 2,
+    BadDataFormat = 5,
+    UnacceptedPolicy = 15,
+    UnacceptedExtension = 16,
+    SystemFailure = 25,
+}
+
+/// TimeStampReq sent by client, only the fields required for issuing the token are kept
+#[derive(Debug, Clone)]
+pub struct TimestampRequest {
+    pub hash_algorithm: String,
+    //der encoded MessageImprint, which is copied into TSTInfo as it is
+    pub message_imprint: Vec<u8>,
+    pub hashed_message: Vec<u8>,
+    //der encoded TSAPolicyId and nonce
+    pub policy: Option<Vec<u8>>,
+    pub nonce: Option<Vec<u8>>,
+    pub cert_req: bool,
+}
+
+impl TimestampRequest {
+    pub fn from_der(request: &[u8]) -> std::result::Result<Self, FailureInfo> {
+        let bad_format = |_| FailureInfo::BadDataFormat;
+        let (_, request, rest) = der::next(request).map_err(bad_format)?;
+        if !rest.is_empty() {
+            return Err(FailureInfo::BadDataFormat);
+        }
+        let fields = der::children(der::content_of(request)).map_err(bad_format)?;
+        match fields.first() {
+            Some((der::TAG_INTEGER, version)) if der::content_of(version) == [1] => {}
+            _ => return Err(FailureInfo::BadDataFormat),
+        }
+        let message_imprint = match fields.get(1) {
+            Some((der::TAG_SEQUENCE, message_imprint)) => *message_imprint,
+            _ => return Err(FailureInfo::BadDataFormat),
+        };
+        let imprint = der::children(der::content_of(message_imprint)).map_err(bad_format)?;
+        if imprint.len() != 2 || imprint[0].0 != der::TAG_SEQUENCE || imprint[1].0 != der::TAG_OCTET_STRING {
+            return Err(FailureInfo::BadDataFormat);
+        }
+        let algorithm = der::children(der::content_of(imprint[0].1)).map_err(bad_format)?;
+        let (name, _, length) = HASH_ALGORITHMS
+            .iter()
+            .find(|(_, oid, _)| algorithm.first().map(|(_, element)| *element) == Some(der::oid(oid).as_slice()))
+            .ok_or(FailureInfo::BadAlg)?;
+        let hashed_message = der::content_of(imprint[1].1);
+        if hashed_message.len() != *length {
+            return Err(FailureInfo::BadDataFormat);
+        }
+        let mut result = TimestampRequest {
+            hash_algorithm: name.to_string(),
+            message_imprint: message_imprint.to_vec(),
+            hashed_message: hashed_message.to_vec(),
+            policy: None,
+            nonce: None,
+            cert_req: false,
+        };
+        for (tag, element) in fields.iter().skip(2) {
+            match *tag {
+                der::TAG_OID => result.policy = Some(element.to_vec()),
+                der::TAG_INTEGER => result.nonce = Some(element.to_vec()),
+                der::TAG_BOOLEAN => result.cert_req = der::content_of(element) != [0],
+                TAG_EXTENSIONS => return Err(FailureInfo::UnacceptedExtension),
+                _ => return Err(FailureInfo::BadDataFormat),
+            }
+        }
+        Ok(result)
+    }
+}
+
+//every issued timestamp token is recorded, the id is used as the serial number of the token
+#[derive(Debug, Clone)]
+pub struct TimestampRecord {
+    pub id: i64,
+    pub key_id: i32,
+    pub policy: String,
+    pub hash_algorithm: String,
+    //hex encoded hashed message and nonce
+    pub message_imprint: String,
+    pub nonce: Option<String>,
+    pub client_address: String,
+    pub gen_time: DateTime<Utc>,
+}
+
+impl TimestampRecord {
+    pub fn new(key_id: i32, policy: String, request: &TimestampRequest, client_address: String) -> Self {
+        TimestampRecord {
+            id: 0,
+            key_id,
+            policy,
+            hash_algorithm: request.hash_algorithm.clone(),
+            message_imprint: hex::encode(&request.hashed_message),
+            nonce: request.nonce.as_ref().map(|nonce| hex::encode(der::content_of(nonce))),
+            client_address,
+            //genTime is encoded in seconds, the same precision as database
+            gen_time: Utc::now().trunc_subsecs(0),
+        }
+    }
+
+    /// TSTInfo which will be signed by TSA, the accuracy is one second
+    pub fn tst_info(&self, request: &TimestampRequest) -> Result<Vec<u8>> {
+        let mut fields = vec![
+            der::small_integer(1),
+            der::oid(&der::parse_oid(&self.policy)?),
+            request.message_imprint.clone(),
+            der::small_integer(self.id as u64),
+            der::tlv(der::TAG_GENERALIZED_TIME, self.gen_time.format("%Y%m%d%H%M%SZ").to_string().as_bytes()),
+            der::sequence(&[der::small_integer(1)]),
+        ];
+        if let Some(nonce) = &request.nonce {
+            fields.push(nonce.clone());
+        }
+        Ok(der::sequence(&fields))
+    }
+}
+
+/// TimeStampResp returned to client
+pub enum TimestampResponse {
+    Granted(Vec<u8>),
+    Rejected(FailureInfo, String),
+}
+
+impl TimestampResponse {
+    pub fn to_der(&self) -> Vec<u8> {
+        match self {
+            TimestampResponse::Granted(token) => der::sequence(&[
+                der::sequence(&[der::small_integer(STATUS_GRANTED)]),
+                token.clone(),
+            ]),
+            TimestampResponse::Rejected(failure, text) => {
+                //named bit list, the trailing zero bits are removed
+                let bit = *failure as usize;
+                let mut bits = vec![0u8; bit / 8 + 2];
+                bits[0] = 7 - (bit % 8) as u8;
+                bits[bit / 8 + 1] = 0x80 >> (bit % 8);
+                der::sequence(&[der::sequence(&[
+                    der::small_integer(STATUS_REJECTION),
+                    der::sequence(&[der::tlv(der::TAG_UTF8_STRING, text.as_bytes())]),
+                    der::tlv(der::TAG_BIT_STRING, &bits),
+                ])])
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+
+    //TimeStampReq with sha256 message imprint, the optional fields are appended as they are
+    fn request(hashed_message: &[u8], optional: &[Vec<u8>]) -> Vec<u8> {
+        let mut fields = vec![
+            der::small_integer(1),
+            der::sequence(&[der::algorithm_identifier(OID_SHA256), der::octet_string(hashed_message)]),
+        ];
+        fields.extend_from_slice(optional);
+        der::sequence(&fields)
+    }
+
+    #[test]
+    fn test_request_from_der() {
+        let nonce = der::integer(&[0x01, 0x02, 0x03]);
+        let policy = der::oid(&[1, 2, 3, 4, 1]);
+        let parsed = TimestampRequest::from_der(
+            &request(&[7; 32], &[policy.clone(), nonce.clone(), der::boolean(true)])).unwrap();
+        assert_eq!(parsed.hash_algorithm, "sha256");
+        assert_eq!(parsed.hashed_message, vec![7; 32]);
+        assert_eq!(parsed.policy, Some(policy));
+        assert_eq!(parsed.nonce, Some(nonce));
+        assert!(parsed.cert_req);
+
+        assert_eq!(TimestampRequest::from_der(&request(&[7; 20], &[])).unwrap_err(), FailureInfo::BadDataFormat);
+        assert_eq!(TimestampRequest::from_der(&request(&[7; 32], &[der::context(0, true, &[])])).unwrap_err(),
+                   FailureInfo::UnacceptedExtension);
+        let mut trailing = request(&[7; 32], &[]);
+        trailing.push(0);
+        assert_eq!(TimestampRequest::from_der(&trailing).unwrap_err(), FailureInfo::BadDataFormat);
+        let sha1 = der::sequence(&[
+            der::small_integer(1),
+            der::sequence(&[der::algorithm_identifier(&[1, 3, 14, 3, 2, 26]), der::octet_string(&[7; 20])]),
+        ]);
+        assert_eq!(TimestampRequest::from_der(&sha1).unwrap_err(), FailureInfo::BadAlg);
+    }
+
+    #[test]
+    fn test_tst_info() {
+        let nonce = der::integer(&[0x01, 0x02, 0x03]);
+        let parsed = TimestampRequest::from_der(&request(&[7; 32], std::slice::from_ref(&nonce))).unwrap();
+        let mut record = TimestampRecord::new(1, "1.2.3.4.1".to_string(), &parsed, "127.0.0.1".to_string());
+        record.id = 300;
+        assert_eq!(record.message_imprint, hex::encode([7; 32]));
+        assert_eq!(record.nonce, Some("010203".to_string()));
+        let tst_info = record.tst_info(&parsed).unwrap();
+        let (_, tst_info, rest) = der::next(&tst_info).unwrap();
+        assert!(rest.is_empty());
+        let fields = der::children(der::content_of(tst_info)).unwrap();
+        //version, policy, messageImprint, serialNumber, genTime, accuracy, nonce
+        assert_eq!(fields.len(), 7);
+        assert_eq!(fields[1].1, der::oid(&[1, 2, 3, 4, 1]).as_slice());
+        assert_eq!(fields[2].1, parsed.message_imprint.as_slice());
+        assert_eq!(der::content_of(fields[3].1), [0x01, 0x2c]);
+        assert_eq!(der::content_of(fields[4].1), record.gen_time.format("%Y%m%d%H%M%SZ").to_string().as_bytes());
+        assert_eq!(fields[6].1, nonce.as_slice());
+    }
+
+    #[test]
+    fn test_response_to_der() {
+        let granted = TimestampResponse::Granted(der::sequence(&[der::null()])).to_der();
+        assert_eq!(granted, der::sequence(&[der::sequence(&[der::small_integer(0)]), der::sequence(&[der::null()])]));
+
+        let rejected = TimestampResponse::Rejected(FailureInfo::SystemFailure, "failed".to_string()).to_der();
+        let (_, response, _) = der::next(&rejected).unwrap();
+        let (_, status_info, _) = der::next(der::content_of(response)).unwrap();
+        let status_info = der::children(der::content_of(status_info)).unwrap();
+        assert_eq!(der::content_of(status_info[0].1), [2]);
+        assert_eq!(status_info[1].1, der::sequence(&[der::tlv(der::TAG_UTF8_STRING, b"failed")]).as_slice());
+        //bit 25 with 6 unused bits in the last byte
+        assert_eq!(der::content_of(status_info[2].1), [6, 0, 0, 0, 0x40]);
+    }
+}
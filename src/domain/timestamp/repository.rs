@@ -0,0 +1,9 @@
+use super::entity::TimestampRecord;
+use crate::util::error::Result;
+use async_trait::async_trait;
+
+#[async_trait]
+pub trait Repository: Send + Sync {
+    //the id of created record is used as the serial number of timestamp token
+    async fn create(&self, record: TimestampRecord) -> Result<TimestampRecord>;
+}
@@ -0,0 +1,124 @@
+use crate::infra::database::pool::DbPool;
+use crate::util::error::{Error, Result};
+use sqlx::any::{AnyArguments, AnyKind};
+use sqlx::query::Query;
+use sqlx::{Any, Row};
+
+static INSERT_IGNORE: &str = "INSERT IGNORE INTO";
+static CURRENT_TIMESTAMP: &str = "CURRENT_TIMESTAMP";
+//sqlite stores datetime as text, the current timestamp has to be in the same format of bound datetime values,
+//otherwise they can't be compared
+static SQLITE_CURRENT_TIMESTAMP: &str = "strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now')";
+
+/// Repositories write their statements in the MySQL flavor, i.e. `?` placeholders, backtick quoted identifiers
+/// and `INSERT IGNORE`, this module rewrites them for the database behind the pool.
+pub fn translate(kind: AnyKind, statement: &str) -> String {
+    match kind {
+        AnyKind::Postgres => to_postgres(statement),
+        AnyKind::Sqlite => to_sqlite(statement),
+        _ => statement.to_string(),
+    }
+}
+
+pub fn sql(pool: &DbPool, statement: &str) -> String {
+    translate(pool.any_kind(), statement)
+}
+
+//postgres doesn't report the last insert id, the id column needs to be returned explicitly
+pub fn insert_sql(pool: &DbPool, statement: &str) -> String {
+    let mut translated = sql(pool, statement);
+    if pool.any_kind() == AnyKind::Postgres {
+        translated.push_str(" RETURNING CAST(id AS BIGINT)");
+    }
+    translated
+}
+
+//execute the statement built from `insert_sql` and return the id of the inserted row
+pub async fn insert<'q>(pool: &DbPool, query: Query<'q, Any, AnyArguments<'q>>) -> Result<i64> {
+    match pool.any_kind() {
+        AnyKind::Postgres => Ok(query.fetch_one(pool).await?.try_get(0)?),
+        _ => query.execute(pool).await?.last_insert_id().ok_or_else(
+            || Error::DatabaseError("failed to get the id of inserted record".to_string())),
+    }
+}
+
+//the segments of statement outside of the quoted string literals are rewritten, the literals are kept as they are
+fn rewrite_outside_literals<F>(statement: &str, mut rewrite: F) -> String
+    where F: FnMut(&str) -> String {
+    let mut result = String::with_capacity(statement.len() + 16);
+    for (index, segment) in statement.split('\'').enumerate() {
+        if index > 0 {
+            result.push('\'');
+        }
+        //the segments at odd positions are inside the literals
+        if index % 2 == 1 {
+            result.push_str(segment);
+        } else {
+            result.push_str(&rewrite(segment));
+        }
+    }
+    result
+}
+
+fn to_postgres(statement: &str) -> String {
+    let (statement, ignore) = match statement.strip_prefix(INSERT_IGNORE) {
+        Some(remain) => (format!("INSERT INTO{}", remain), true),
+        None => (statement.to_string(), false),
+    };
+    let mut index = 0;
+    let mut result = rewrite_outside_literals(&statement, |segment| {
+        let mut rewritten = String::with_capacity(segment.len() + 8);
+        for c in segment.chars() {
+            match c {
+                '?' => {
+                    index += 1;
+                    rewritten.push_str(&format!("${}", index));
+                }
+                '`' => rewritten.push('"'),
+                _ => rewritten.push(c),
+            }
+        }
+        rewritten
+    });
+    if ignore {
+        result.push_str(" ON CONFLICT DO NOTHING");
+    }
+    result
+}
+
+fn to_sqlite(statement: &str) -> String {
+    let statement = match statement.strip_prefix(INSERT_IGNORE) {
+        Some(remain) => format!("INSERT OR IGNORE INTO{}", remain),
+        None => statement.to_string(),
+    };
+    rewrite_outside_literals(&statement, |segment| segment.replace(CURRENT_TIMESTAMP, SQLITE_CURRENT_TIMESTAMP))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate(AnyKind::Postgres, "SELECT * FROM `user` WHERE email = ? AND role IN ('owner?', 'admin') LIMIT ?"),
+            "SELECT * FROM \"user\" WHERE email = $1 AND role IN ('owner?', 'admin') LIMIT $2");
+        assert_eq!(
+            translate(AnyKind::Postgres, "INSERT IGNORE INTO data_key_permission(key_id, principal, role) VALUES (?, ?, ?)"),
+            "INSERT INTO data_key_permission(key_id, principal, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING");
+        let statement = "INSERT IGNORE INTO `user`(email) VALUES (?)";
+        assert_eq!(translate(AnyKind::Sqlite, statement), "INSERT OR IGNORE INTO `user`(email) VALUES (?)");
+        assert_eq!(translate(AnyKind::MySql, statement), statement);
+    }
+
+    #[test]
+    fn test_literals_untouched() {
+        let statement = "UPDATE data_key SET update_at = CURRENT_TIMESTAMP, description = 'CURRENT_TIMESTAMP ? `x`' WHERE id = ?";
+        assert_eq!(
+            translate(AnyKind::Sqlite, statement),
+            format!("UPDATE data_key SET update_at = {}, description = 'CURRENT_TIMESTAMP ? `x`' WHERE id = ?", SQLITE_CURRENT_TIMESTAMP));
+        assert_eq!(
+            translate(AnyKind::Postgres, statement),
+            "UPDATE data_key SET update_at = CURRENT_TIMESTAMP, description = 'CURRENT_TIMESTAMP ? `x`' WHERE id = $1");
+    }
+}
@@ -1,2 +1,3 @@
+pub mod dialect;
 pub mod model;
-pub mod pool;
+pub mod pool;
\ No newline at end of file
@@ -0,0 +1,64 @@
+use crate::domain::audit::entity::{AuditAction, SignRecord};
+use crate::util::error::Error;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use std::str::FromStr;
+
+#[derive(Debug, FromRow)]
+pub(super) struct SignRecordDTO {
+    pub id: i32,
+    pub key_id: i32,
+    pub key_name: String,
+    pub key_type: String,
+    pub action: String,
+    pub principal: String,
+    pub user_id: Option<i32>,
+    pub file_name: String,
+    pub digest: String,
+    pub detail: String,
+    pub succeed: bool,
+    pub error: String,
+    pub create_at: DateTime<Utc>,
+}
+
+impl TryFrom<SignRecordDTO> for SignRecord {
+    type Error = Error;
+
+    fn try_from(dto: SignRecordDTO) -> std::result::Result<Self, Self::Error> {
+        Ok(SignRecord {
+            id: dto.id,
+            key_id: dto.key_id,
+            key_name: dto.key_name,
+            key_type: dto.key_type,
+            action: AuditAction::from_str(&dto.action)?,
+            principal: dto.principal,
+            user_id: dto.user_id,
+            file_name: dto.file_name,
+            digest: dto.digest,
+            detail: dto.detail,
+            succeed: dto.succeed,
+            error: dto.error,
+            create_at: dto.create_at,
+        })
+    }
+}
+
+impl From<SignRecord> for SignRecordDTO {
+    fn from(record: SignRecord) -> Self {
+        SignRecordDTO {
+            id: record.id,
+            key_id: record.key_id,
+            key_name: record.key_name,
+            key_type: record.key_type,
+            action: record.action.to_string(),
+            principal: record.principal,
+            user_id: record.user_id,
+            file_name: record.file_name,
+            digest: record.digest,
+            detail: record.detail,
+            succeed: record.succeed,
+            error: record.error,
+            create_at: record.create_at,
+        }
+    }
+}
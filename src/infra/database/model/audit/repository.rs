@@ -0,0 +1,100 @@
+use super::dto::SignRecordDTO;
+
+use crate::infra::database::dialect::sql;
+use crate::infra::database::pool::DbPool;
+use crate::domain::audit::entity::{AuditFilter, SignRecord};
+use crate::domain::audit::repository::Repository;
+use crate::util::error::Result;
+use async_trait::async_trait;
+use std::boxed::Box;
+
+#[derive(Clone)]
+pub struct SignRecordRepository {
+    db_pool: DbPool,
+}
+
+impl SignRecordRepository {
+    pub fn new(db_pool: DbPool) -> Self {
+        Self {
+            db_pool,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for SignRecordRepository {
+    async fn create_batch(&self, records: Vec<SignRecord>) -> Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let values = vec!["(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"; records.len()].join(", ");
+        let statement = sql(&self.db_pool, &format!("INSERT INTO sign_record(key_id, key_name, key_type, action, principal, user_id, file_name, digest, detail, succeed, error, create_at) VALUES {}", values));
+        let mut query = sqlx::query(&statement);
+        for record in records.into_iter() {
+            let dto = SignRecordDTO::from(record);
+            query = query
+                .bind(dto.key_id)
+                .bind(dto.key_name)
+                .bind(dto.key_type)
+                .bind(dto.action)
+                .bind(dto.principal)
+                .bind(dto.user_id)
+                .bind(dto.file_name)
+                .bind(dto.digest)
+                .bind(dto.detail)
+                .bind(dto.succeed)
+                .bind(dto.error)
+                .bind(dto.create_at);
+        }
+        query.execute(&self.db_pool).await?;
+        Ok(())
+    }
+
+    async fn query(&self, filter: AuditFilter) -> Result<Vec<SignRecord>> {
+        //placeholders are pushed and bound in the same order
+        let mut statement = String::from("SELECT * FROM sign_record WHERE 1 = 1");
+        if filter.key_id.is_some() {
+            statement.push_str(" AND key_id = ?");
+        }
+        if filter.principal.is_some() {
+            statement.push_str(" AND principal = ?");
+        }
+        if filter.start.is_some() {
+            statement.push_str(" AND create_at >= ?");
+        }
+        if filter.end.is_some() {
+            statement.push_str(" AND create_at < ?");
+        }
+        if filter.visible_to.is_some() {
+            statement.push_str(" AND (principal = ? OR key_id IN (SELECT key_id FROM data_key_permission WHERE principal = ? AND role IN ('owner', 'admin')))");
+        }
+        statement.push_str(" ORDER BY create_at DESC, id DESC LIMIT ? OFFSET ?");
+        let statement = sql(&self.db_pool, &statement);
+        let mut query = sqlx::query_as::<_, SignRecordDTO>(&statement);
+        if let Some(key_id) = filter.key_id {
+            query = query.bind(key_id);
+        }
+        if let Some(principal) = filter.principal {
+            query = query.bind(principal);
+        }
+        if let Some(start) = filter.start {
+            query = query.bind(start);
+        }
+        if let Some(end) = filter.end {
+            query = query.bind(end);
+        }
+        if let Some(visible_to) = filter.visible_to {
+            query = query.bind(visible_to.clone()).bind(visible_to);
+        }
+        let dtos: Vec<SignRecordDTO> = query
+            .bind(filter.limit as i64)
+            .bind(filter.offset as i64)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(SignRecord::try_from(dto)?);
+        }
+        Ok(results)
+    }
+}
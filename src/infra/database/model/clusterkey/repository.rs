@@ -1,5 +1,6 @@
 use super::dto::ClusterKeyDTO;
 
+use crate::infra::database::dialect::sql;
 use crate::infra::database::pool::DbPool;
 
 use crate::domain::clusterkey::entity::ClusterKey;
@@ -26,7 +27,7 @@ impl ClusterKeyRepository {
 impl Repository for ClusterKeyRepository {
     async fn create(&self, cluster_key: ClusterKey) -> Result<()> {
         let dto = ClusterKeyDTO::from(cluster_key);
-        let _ : Option<ClusterKeyDTO> = sqlx::query_as("INSERT IGNORE INTO cluster_key(data, algorithm, identity, create_at, expire_at) VALUES (?, ?, ?, ?, ?)")
+        let _ : Option<ClusterKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "INSERT IGNORE INTO cluster_key(data, algorithm, identity, create_at, expire_at) VALUES (?, ?, ?, ?, ?)"))
             .bind(&dto.data)
             .bind(&dto.algorithm)
             .bind(&dto.identity)
@@ -38,9 +39,10 @@ impl Repository for ClusterKeyRepository {
     }
 
     async fn get_latest(&self, algorithm: &str) -> Result<Option<ClusterKey>> {
-        let latest: Option<ClusterKeyDTO> = sqlx::query_as(
+        let latest: Option<ClusterKeyDTO> = sqlx::query_as(&sql(
+            &self.db_pool,
             "SELECT * FROM cluster_key WHERE algorithm = ? ORDER BY id DESC LIMIT 1",
-        )
+        ))
         .bind(algorithm)
         .fetch_optional(&self.db_pool)
         .await?;
@@ -51,7 +53,7 @@ impl Repository for ClusterKeyRepository {
     }
 
     async fn get_by_id(&self, id: i32) -> Result<ClusterKey> {
-        let selected: ClusterKeyDTO = sqlx::query_as("SELECT * FROM cluster_key WHERE id = ?")
+        let selected: ClusterKeyDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM cluster_key WHERE id = ?"))
             .bind(id)
             .fetch_one(&self.db_pool)
             .await?;
@@ -59,7 +61,7 @@ impl Repository for ClusterKeyRepository {
     }
 
     async fn delete_by_id(&self, id: i32) -> Result<()> {
-        let _: Option<ClusterKeyDTO> = sqlx::query_as("DELETE FROM cluster_key where id = ?")
+        let _: Option<ClusterKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "DELETE FROM cluster_key where id = ?"))
             .bind(id)
             .fetch_optional(&self.db_pool)
             .await?;
@@ -1,7 +1,7 @@
 
 
 
-use crate::domain::datakey::entity::{DataKey, KeyState};
+use crate::domain::datakey::entity::{DataKey, KeyPermission, KeyRole, KeyState};
 use crate::domain::datakey::entity::KeyType;
 use crate::domain::datakey::traits::ExtendableAttributes;
 use crate::util::error::{Error};
@@ -31,7 +31,8 @@ pub(super) struct DataKeyDTO {
     pub create_at: DateTime<Utc>,
     pub expire_at: DateTime<Utc>,
     pub soft_delete: bool,
-    pub key_state: String
+    pub key_state: String,
+    pub update_at: DateTime<Utc>,
 }
 
 
@@ -54,6 +55,7 @@ impl TryFrom<DataKeyDTO> for DataKey {
             expire_at: dto.expire_at,
             soft_delete: dto.soft_delete,
             key_state: KeyState::from_str(&dto.key_state)?,
+            update_at: dto.update_at,
         })
     }
 }
@@ -83,6 +85,39 @@ impl TryFrom<DataKey> for DataKeyDTO {
             expire_at: data_key.expire_at,
             soft_delete: data_key.soft_delete,
             key_state: data_key.key_state.to_string(),
+            update_at: data_key.update_at,
         })
     }
 }
+
+#[derive(Debug, FromRow)]
+pub(super) struct KeyPermissionDTO {
+    pub id: i32,
+    pub key_id: i32,
+    pub principal: String,
+    pub role: String,
+}
+
+impl TryFrom<KeyPermissionDTO> for KeyPermission {
+    type Error = Error;
+
+    fn try_from(dto: KeyPermissionDTO) -> std::result::Result<Self, Self::Error> {
+        Ok(KeyPermission {
+            id: dto.id,
+            key_id: dto.key_id,
+            principal: dto.principal,
+            role: KeyRole::from_str(&dto.role)?,
+        })
+    }
+}
+
+impl From<KeyPermission> for KeyPermissionDTO {
+    fn from(permission: KeyPermission) -> Self {
+        KeyPermissionDTO {
+            id: permission.id,
+            key_id: permission.key_id,
+            principal: permission.principal,
+            role: permission.role.to_string(),
+        }
+    }
+}
@@ -1,13 +1,90 @@
-use super::dto::DataKeyDTO;
+use super::dto::{DataKeyDTO, KeyPermissionDTO};
 
 
+use crate::infra::database::dialect::{insert, insert_sql, sql};
 use crate::infra::database::pool::DbPool;
 
-use crate::domain::datakey::entity::{DataKey, KeyState};
+use crate::domain::datakey::entity::{DataKey, KeyCursor, KeyFilter, KeyPage, KeyPermission, KeyRole, KeySortField, KeyState, SortOrder};
 use crate::domain::datakey::repository::Repository;
-use crate::util::error::{Result};
+use crate::util::error::{Error, Result};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::any::AnyArguments;
+use sqlx::query::QueryAs;
+use sqlx::Any;
 use std::boxed::Box;
+use std::str::FromStr;
+
+//the key materials are never loaded when listing keys
+static LIST_COLUMNS: &str = "id, name, description, `user`, email, attributes, key_type, '' AS private_key, \
+    '' AS public_key, '' AS certificate, create_at, expire_at, soft_delete, key_state, update_at";
+//wildcards in the name prefix are escaped with it
+static LIKE_ESCAPE: char = '!';
+
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        if c == LIKE_ESCAPE || c == '%' || c == '_' {
+            pattern.push(LIKE_ESCAPE);
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+//placeholders are pushed here and bound in `bind_conditions` in the same order
+fn filter_conditions(filter: &KeyFilter) -> String {
+    let mut conditions = String::from(" WHERE soft_delete = ?");
+    if filter.key_type.is_some() {
+        conditions.push_str(" AND key_type = ?");
+    }
+    if filter.key_state.is_some() {
+        conditions.push_str(" AND key_state = ?");
+    }
+    if filter.owner.is_some() {
+        conditions.push_str(" AND id IN (SELECT key_id FROM data_key_permission WHERE principal = ? AND role = 'owner')");
+    }
+    if filter.name_prefix.is_some() {
+        conditions.push_str(&format!(" AND name LIKE ? ESCAPE '{}'", LIKE_ESCAPE));
+    }
+    if filter.expire_after.is_some() {
+        conditions.push_str(" AND expire_at >= ?");
+    }
+    if filter.expire_before.is_some() {
+        conditions.push_str(" AND expire_at < ?");
+    }
+    if filter.visible_to.is_some() {
+        conditions.push_str(" AND id IN (SELECT key_id FROM data_key_permission WHERE principal = ?)");
+    }
+    conditions
+}
+
+fn bind_conditions<'q, O>(mut query: QueryAs<'q, Any, O, AnyArguments<'q>>, filter: &KeyFilter) -> QueryAs<'q, Any, O, AnyArguments<'q>> {
+    query = query.bind(false);
+    if let Some(key_type) = &filter.key_type {
+        query = query.bind(key_type.to_string());
+    }
+    if let Some(key_state) = &filter.key_state {
+        query = query.bind(key_state.to_string());
+    }
+    if let Some(owner) = &filter.owner {
+        query = query.bind(owner.clone());
+    }
+    if let Some(name_prefix) = &filter.name_prefix {
+        query = query.bind(like_prefix(name_prefix));
+    }
+    if let Some(expire_after) = filter.expire_after {
+        query = query.bind(expire_after);
+    }
+    if let Some(expire_before) = filter.expire_before {
+        query = query.bind(expire_before);
+    }
+    if let Some(visible_to) = &filter.visible_to {
+        query = query.bind(visible_to.clone());
+    }
+    query
+}
 
 
 
@@ -22,13 +99,22 @@ impl DataKeyRepository {
             db_pool,
         }
     }
+
+    //picked up by the key refresh of every server, see `get_updated_since`
+    async fn touch(&self, id: i32) -> Result<()> {
+        let _ = sqlx::query(&sql(&self.db_pool, "UPDATE data_key SET update_at = CURRENT_TIMESTAMP WHERE id = ?"))
+            .bind(id)
+            .execute(&self.db_pool)
+            .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl Repository for DataKeyRepository {
     async fn create(&self, data_key: DataKey) -> Result<DataKey> {
         let dto = DataKeyDTO::try_from(data_key)?;
-        let record : u64 = sqlx::query("INSERT INTO data_key(name, description, user, email, attributes, key_type, private_key, public_key, certificate, create_at, expire_at, key_state, soft_delete) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+        let record = insert(&self.db_pool, sqlx::query(&insert_sql(&self.db_pool, "INSERT INTO data_key(name, description, `user`, email, attributes, key_type, private_key, public_key, certificate, create_at, expire_at, key_state, soft_delete) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"))
             .bind(&dto.name)
             .bind(&dto.description)
             .bind(&dto.user)
@@ -41,14 +127,36 @@ impl Repository for DataKeyRepository {
             .bind(dto.create_at)
             .bind(dto.expire_at)
             .bind(dto.key_state)
-            .bind(dto.soft_delete)
-            .execute(&self.db_pool)
-            .await?.last_insert_id();
+            .bind(dto.soft_delete)).await?;
         return self.get_by_id(record as i32).await
     }
 
     async fn get_all(&self) -> Result<Vec<DataKey>> {
-        let dtos: Vec<DataKeyDTO> = sqlx::query_as("SELECT * FROM data_key WHERE soft_delete = ?")
+        let dtos: Vec<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key WHERE soft_delete = ?"))
+            .bind(false)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(DataKey::try_from(dto)?);
+        }
+        Ok(results)
+    }
+
+    async fn get_all_with_deleted(&self) -> Result<Vec<DataKey>> {
+        let dtos: Vec<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key"))
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(DataKey::try_from(dto)?);
+        }
+        Ok(results)
+    }
+
+    async fn get_by_principal(&self, principal: String) -> Result<Vec<DataKey>> {
+        let dtos: Vec<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT DISTINCT data_key.* FROM data_key INNER JOIN data_key_permission ON data_key.id = data_key_permission.key_id WHERE data_key_permission.principal = ? AND data_key.soft_delete = ?"))
+            .bind(principal)
             .bind(false)
             .fetch_all(&self.db_pool)
             .await?;
@@ -59,8 +167,74 @@ impl Repository for DataKeyRepository {
         Ok(results)
     }
 
+    async fn get_by_filter(&self, filter: KeyFilter) -> Result<KeyPage> {
+        let conditions = filter_conditions(&filter);
+        let total: (i64,) = bind_conditions(
+            sqlx::query_as(&sql(&self.db_pool, &format!("SELECT COUNT(*) FROM data_key{}", conditions))), &filter)
+            .fetch_one(&self.db_pool)
+            .await?;
+        let column = filter.sort_by.to_string();
+        let (direction, comparator) = match filter.order {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        //keys after the cursor in the order of (sort field, id)
+        let mut statement = format!("SELECT {} FROM data_key{}", LIST_COLUMNS, conditions);
+        if let Some(cursor) = &filter.cursor {
+            if cursor.sort_by == KeySortField::Id {
+                statement.push_str(&format!(" AND id {} ?", comparator));
+            } else {
+                statement.push_str(&format!(" AND ({0} {1} ? OR ({0} = ? AND id {1} ?))", column, comparator));
+            }
+        }
+        if filter.sort_by == KeySortField::Id {
+            statement.push_str(&format!(" ORDER BY id {}", direction));
+        } else {
+            statement.push_str(&format!(" ORDER BY {} {}, id {}", column, direction, direction));
+        }
+        //one more key is queried to tell whether there is a next page
+        statement.push_str(" LIMIT ? OFFSET ?");
+        let statement = sql(&self.db_pool, &statement);
+        let mut query = bind_conditions(sqlx::query_as::<_, DataKeyDTO>(&statement), &filter);
+        let mut offset = filter.offset;
+        if let Some(cursor) = &filter.cursor {
+            offset = 0;
+            match cursor.sort_by {
+                KeySortField::Id => {}
+                KeySortField::Name => {
+                    query = query.bind(cursor.value.clone()).bind(cursor.value.clone());
+                }
+                KeySortField::CreateAt | KeySortField::ExpireAt => {
+                    let value = cursor.value.parse::<DateTime<Utc>>().map_err(
+                        |_| Error::ParameterError(format!("invalid cursor value {}", cursor.value)))?;
+                    query = query.bind(value).bind(value);
+                }
+            }
+            query = query.bind(cursor.id);
+        }
+        let dtos: Vec<DataKeyDTO> = query
+            .bind(filter.limit as i64 + 1)
+            .bind(offset as i64)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut keys = vec![];
+        for dto in dtos.into_iter() {
+            keys.push(DataKey::try_from(dto)?);
+        }
+        let mut next_cursor = None;
+        if keys.len() > filter.limit as usize {
+            keys.truncate(filter.limit as usize);
+            next_cursor = keys.last().map(|key| KeyCursor::of(key, filter.sort_by, filter.order));
+        }
+        Ok(KeyPage {
+            keys,
+            total: total.0,
+            next_cursor,
+        })
+    }
+
     async fn get_by_id(&self, id: i32) -> Result<DataKey> {
-        let dto: DataKeyDTO = sqlx::query_as("SELECT * FROM data_key WHERE id = ? AND soft_delete = ?")
+        let dto: DataKeyDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key WHERE id = ? AND soft_delete = ?"))
             .bind(id)
             .bind(false)
             .fetch_one(&self.db_pool)
@@ -69,7 +243,7 @@ impl Repository for DataKeyRepository {
     }
 
     async fn update_state(&self, id: i32, state: KeyState) -> Result<()> {
-        let _: Option<DataKeyDTO>  = sqlx::query_as("UPDATE data_key SET key_state = ? WHERE id = ? AND soft_delete = ?")
+        let _: Option<DataKeyDTO>  = sqlx::query_as(&sql(&self.db_pool, "UPDATE data_key SET key_state = ?, update_at = CURRENT_TIMESTAMP WHERE id = ? AND soft_delete = ?"))
             .bind(state.to_string())
             .bind(id)
             .bind(false)
@@ -78,8 +252,49 @@ impl Repository for DataKeyRepository {
         Ok(())
     }
 
+    async fn update_keys(&self, data_key: &DataKey) -> Result<()> {
+        let dto = DataKeyDTO::try_from(data_key.clone())?;
+        let _: Option<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "UPDATE data_key SET attributes = ?, private_key = ?, public_key = ?, certificate = ?, expire_at = ?, update_at = CURRENT_TIMESTAMP WHERE id = ? AND soft_delete = ?"))
+            .bind(dto.attributes)
+            .bind(dto.private_key)
+            .bind(dto.public_key)
+            .bind(dto.certificate)
+            .bind(dto.expire_at)
+            .bind(dto.id)
+            .bind(false)
+            .fetch_optional(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn update_encrypted_keys(&self, data_key: &DataKey) -> Result<()> {
+        let dto = DataKeyDTO::try_from(data_key.clone())?;
+        let _: Option<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "UPDATE data_key SET private_key = ?, public_key = ?, certificate = ?, update_at = CURRENT_TIMESTAMP WHERE id = ?"))
+            .bind(dto.private_key)
+            .bind(dto.public_key)
+            .bind(dto.certificate)
+            .bind(dto.id)
+            .fetch_optional(&self.db_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_expired_enabled_keys(&self, now: DateTime<Utc>) -> Result<Vec<DataKey>> {
+        let dtos: Vec<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key WHERE key_state = ? AND soft_delete = ? AND expire_at <= ?"))
+            .bind(KeyState::Enabled.to_string())
+            .bind(false)
+            .bind(now)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(DataKey::try_from(dto)?);
+        }
+        Ok(results)
+    }
+
     async fn get_enabled_key_by_type_and_name(&self, key_type: String, name: String) -> Result<DataKey> {
-        let dto: DataKeyDTO = sqlx::query_as("SELECT * FROM data_key WHERE name = ? AND key_type = ? AND key_state = ? AND soft_delete = ?")
+        let dto: DataKeyDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key WHERE name = ? AND key_type = ? AND key_state = ? AND soft_delete = ?"))
             .bind(name)
             .bind(key_type)
             .bind(KeyState::Enabled.to_string())
@@ -90,11 +305,187 @@ impl Repository for DataKeyRepository {
     }
 
     async fn delete_by_id(&self, id: i32) -> Result<()> {
-        let _: Option<DataKeyDTO> = sqlx::query_as("UPDATE data_key SET soft_delete = ? WHERE id = ?")
+        let _: Option<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, "UPDATE data_key SET soft_delete = ?, update_at = CURRENT_TIMESTAMP WHERE id = ?"))
             .bind(true)
             .bind(id)
             .fetch_optional(&self.db_pool)
             .await?;
         Ok(())
     }
+
+    async fn get_updated_since(&self, since: DateTime<Utc>, id: i32) -> Result<Vec<DataKey>> {
+        let statement = format!("SELECT {} FROM data_key WHERE update_at > ? OR (update_at = ? AND id > ?) ORDER BY update_at, id", LIST_COLUMNS);
+        let dtos: Vec<DataKeyDTO> = sqlx::query_as(&sql(&self.db_pool, &statement))
+            .bind(since)
+            .bind(since)
+            .bind(id)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(DataKey::try_from(dto)?);
+        }
+        Ok(results)
+    }
+
+    async fn get_permissions(&self, key_id: i32) -> Result<Vec<KeyPermission>> {
+        let dtos: Vec<KeyPermissionDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM data_key_permission WHERE key_id = ?"))
+            .bind(key_id)
+            .fetch_all(&self.db_pool)
+            .await?;
+        let mut results = vec![];
+        for dto in dtos.into_iter() {
+            results.push(KeyPermission::try_from(dto)?);
+        }
+        Ok(results)
+    }
+
+    async fn get_role(&self, key_id: i32, principal: &str) -> Result<Option<KeyRole>> {
+        let role: Option<String> = sqlx::query_scalar(&sql(&self.db_pool, "SELECT role FROM data_key_permission WHERE key_id = ? AND principal = ? \
+            ORDER BY CASE role WHEN 'owner' THEN 0 WHEN 'admin' THEN 1 ELSE 2 END LIMIT 1"))
+            .bind(key_id)
+            .bind(principal)
+            .fetch_optional(&self.db_pool)
+            .await?;
+        role.map(|role| KeyRole::from_str(&role)).transpose()
+    }
+
+    async fn create_permission(&self, permission: KeyPermission) -> Result<()> {
+        let dto = KeyPermissionDTO::from(permission);
+        let key_id = dto.key_id;
+        let _ = sqlx::query(&sql(&self.db_pool, "INSERT IGNORE INTO data_key_permission(key_id, principal, role) VALUES (?, ?, ?)"))
+            .bind(dto.key_id)
+            .bind(dto.principal)
+            .bind(dto.role)
+            .execute(&self.db_pool)
+            .await?;
+        self.touch(key_id).await
+    }
+
+    async fn delete_permission(&self, permission: KeyPermission) -> Result<()> {
+        let dto = KeyPermissionDTO::from(permission);
+        let key_id = dto.key_id;
+        let _ = sqlx::query(&sql(&self.db_pool, "DELETE FROM data_key_permission WHERE key_id = ? AND principal = ? AND role = ?"))
+            .bind(dto.key_id)
+            .bind(dto.principal)
+            .bind(dto.role)
+            .execute(&self.db_pool)
+            .await?;
+        self.touch(key_id).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::datakey::entity::KeyRole;
+    use crate::infra::database::pool::{check_schema_version, connect, migrate};
+    use crate::util::test_support::{data_key, memory_pool};
+    use chrono::Duration;
+    use std::env;
+    use crate::domain::audit::entity::{AuditAction, AuditFilter, SignRecord};
+    use crate::domain::audit::repository::Repository as AuditRepository;
+    use crate::infra::database::model::audit::repository::SignRecordRepository;
+
+    #[tokio::test]
+    async fn test_get_by_filter() {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool);
+        for (name, days) in [("key_a", 10), ("key_b", 20), ("keyc", 30)] {
+            let key = repository.create(DataKey { expire_at: Utc::now() + Duration::days(days), ..data_key(name) }).await.unwrap();
+            repository.create_permission(KeyPermission::new(key.id, "signatrust@example.com".to_string(), KeyRole::Owner)).await.unwrap();
+        }
+        let mut filter = KeyFilter {
+            visible_to: Some("signatrust@example.com".to_string()),
+            sort_by: KeySortField::Name,
+            order: SortOrder::Desc,
+            limit: 2,
+            ..Default::default()
+        };
+        let page = repository.get_by_filter(filter.clone()).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.keys.iter().map(|k| k.name.as_str()).collect::<Vec<&str>>(), vec!["keyc", "key_b"]);
+        assert!(page.keys.iter().all(|k| k.private_key.is_empty() && k.public_key.is_empty()));
+        filter.cursor = page.next_cursor;
+        let page = repository.get_by_filter(filter.clone()).await.unwrap();
+        assert_eq!(page.keys.iter().map(|k| k.name.as_str()).collect::<Vec<&str>>(), vec!["key_a"]);
+        assert!(page.next_cursor.is_none());
+
+        //underscore in the prefix isn't a wildcard
+        let page = repository.get_by_filter(KeyFilter {
+            name_prefix: Some("key_".to_string()),
+            expire_before: Some(Utc::now() + Duration::days(15)),
+            owner: Some("signatrust@example.com".to_string()),
+            limit: 10,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.keys[0].name, "key_a");
+    }
+
+    #[tokio::test]
+    async fn test_get_role() {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool);
+        let key = repository.create(data_key("key_a")).await.unwrap();
+        let created = repository.get_by_id(key.id).await.unwrap().update_at;
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        for role in [KeyRole::Signer, KeyRole::Owner, KeyRole::Admin] {
+            repository.create_permission(KeyPermission::new(key.id, "signatrust@example.com".to_string(), role)).await.unwrap();
+        }
+        assert_eq!(repository.get_role(key.id, "signatrust@example.com").await.unwrap(), Some(KeyRole::Owner));
+        assert_eq!(repository.get_role(key.id, "other@example.com").await.unwrap(), None);
+        //the key is refreshed along with its permissions
+        assert!(repository.get_by_id(key.id).await.unwrap().update_at > created);
+    }
+
+    //the statements translated for postgres run against a real server, for instance
+    //SIGNATRUST_TEST_POSTGRES_URL=postgres://postgres@127.0.0.1:5432/signatrust, they are skipped otherwise
+    #[tokio::test]
+    async fn test_postgres_repository() {
+        let url = match env::var("SIGNATRUST_TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => return,
+        };
+        let pool = connect(&url, 2).await.expect("connect to postgres");
+        migrate(&pool).await.expect("migrate postgres");
+        check_schema_version(&pool).await.expect("schema is up to date");
+        let repository = DataKeyRepository::new(pool.clone());
+        //the database could be reused by the previous runs
+        let name = format!("postgres-key-{}-{}", std::process::id(), Utc::now().timestamp_millis());
+        let principal = format!("{}@example.com", name);
+        let key = repository.create(data_key(&name)).await.unwrap();
+        assert_eq!(repository.get_by_id(key.id).await.unwrap().private_key, vec![1, 2, 3]);
+
+        //duplicated permissions are ignored by ON CONFLICT DO NOTHING
+        for _ in 0..2 {
+            repository.create_permission(KeyPermission::new(key.id, principal.clone(), KeyRole::Signer)).await.unwrap();
+        }
+        assert_eq!(repository.get_permissions(key.id).await.unwrap().len(), 1);
+        let page = repository.get_by_filter(KeyFilter {
+            visible_to: Some(principal.clone()),
+            name_prefix: Some(name.clone()),
+            limit: 10,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(page.keys.iter().map(|k| k.id).collect::<Vec<i32>>(), vec![key.id]);
+
+        let before = Utc::now() - Duration::seconds(1);
+        repository.update_state(key.id, KeyState::Disabled).await.unwrap();
+        assert!(repository.get_updated_since(before, 0).await.unwrap().iter().any(|k| k.id == key.id));
+        assert!(repository.get_enabled_key_by_type_and_name("pgp".to_string(), name.clone()).await.is_err());
+        repository.delete_permission(KeyPermission::new(key.id, principal.clone(), KeyRole::Signer)).await.unwrap();
+        assert!(repository.get_permissions(key.id).await.unwrap().is_empty());
+
+        //the placeholders of the batch insert are numbered across the records
+        let audit_repository = SignRecordRepository::new(pool);
+        let record = SignRecord::new(AuditAction::Sign, principal.clone(), None);
+        audit_repository.create_batch(vec![record.clone(), record]).await.unwrap();
+        let records = audit_repository.query(AuditFilter {
+            principal: Some(principal.clone()),
+            limit: 10,
+            ..Default::default()
+        }).await.unwrap();
+        assert_eq!(records.len(), 2);
+    }
 }
@@ -1,4 +1,6 @@
+pub mod audit;
 pub mod clusterkey;
 pub mod datakey;
 pub mod user;
-pub mod token;
\ No newline at end of file
+pub mod token;
+pub mod timestamp;
\ No newline at end of file
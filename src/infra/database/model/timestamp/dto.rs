@@ -0,0 +1,45 @@
+use crate::domain::timestamp::entity::TimestampRecord;
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+
+#[derive(Debug, FromRow)]
+pub(super) struct TimestampRecordDTO {
+    pub id: i64,
+    pub key_id: i32,
+    pub policy: String,
+    pub hash_algorithm: String,
+    pub message_imprint: String,
+    pub nonce: Option<String>,
+    pub client_address: String,
+    pub gen_time: DateTime<Utc>,
+}
+
+impl From<TimestampRecordDTO> for TimestampRecord {
+    fn from(dto: TimestampRecordDTO) -> Self {
+        TimestampRecord {
+            id: dto.id,
+            key_id: dto.key_id,
+            policy: dto.policy,
+            hash_algorithm: dto.hash_algorithm,
+            message_imprint: dto.message_imprint,
+            nonce: dto.nonce,
+            client_address: dto.client_address,
+            gen_time: dto.gen_time,
+        }
+    }
+}
+
+impl From<TimestampRecord> for TimestampRecordDTO {
+    fn from(record: TimestampRecord) -> Self {
+        TimestampRecordDTO {
+            id: record.id,
+            key_id: record.key_id,
+            policy: record.policy,
+            hash_algorithm: record.hash_algorithm,
+            message_imprint: record.message_imprint,
+            nonce: record.nonce,
+            client_address: record.client_address,
+            gen_time: record.gen_time,
+        }
+    }
+}
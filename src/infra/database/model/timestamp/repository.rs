@@ -0,0 +1,41 @@
+use super::dto::TimestampRecordDTO;
+
+use crate::infra::database::dialect::{insert, insert_sql};
+use crate::infra::database::pool::DbPool;
+use crate::domain::timestamp::entity::TimestampRecord;
+use crate::domain::timestamp::repository::Repository;
+use crate::util::error::Result;
+use async_trait::async_trait;
+use std::boxed::Box;
+
+#[derive(Clone)]
+pub struct TimestampRecordRepository {
+    db_pool: DbPool,
+}
+
+impl TimestampRecordRepository {
+    pub fn new(db_pool: DbPool) -> Self {
+        Self {
+            db_pool,
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for TimestampRecordRepository {
+    async fn create(&self, record: TimestampRecord) -> Result<TimestampRecord> {
+        let dto = TimestampRecordDTO::from(record);
+        let id = insert(&self.db_pool, sqlx::query(&insert_sql(&self.db_pool, "INSERT INTO timestamp_record(key_id, policy, hash_algorithm, message_imprint, nonce, client_address, gen_time) VALUES (?, ?, ?, ?, ?, ?, ?)"))
+            .bind(dto.key_id)
+            .bind(&dto.policy)
+            .bind(&dto.hash_algorithm)
+            .bind(&dto.message_imprint)
+            .bind(&dto.nonce)
+            .bind(&dto.client_address)
+            .bind(dto.gen_time)).await?;
+        Ok(TimestampRecord::from(TimestampRecordDTO {
+            id,
+            ..dto
+        }))
+    }
+}
@@ -1,3 +1,4 @@
+use crate::infra::database::dialect::{insert, insert_sql, sql};
 use crate::infra::database::pool::DbPool;
 use crate::domain::token::entity::{Token};
 use crate::domain::token::repository::Repository;
@@ -26,17 +27,15 @@ impl Repository for TokenRepository {
 
     async fn create(&self, token: &Token) -> Result<Token> {
         let dto = TokenDTO::encrypt(token).await?;
-        let record : u64 = sqlx::query("INSERT INTO token(user_id, token, expire_at) VALUES (?, ?, ?)")
-            .bind(&dto.user_id)
+        let record = insert(&self.db_pool, sqlx::query(&insert_sql(&self.db_pool, "INSERT INTO token(user_id, token, expire_at) VALUES (?, ?, ?)"))
+            .bind(dto.user_id)
             .bind(&dto.token)
-            .bind(&dto.expire_at)
-            .execute(&self.db_pool)
-            .await?.last_insert_id();
+            .bind(dto.expire_at)).await?;
         self.get_token_by_id(record as i32).await
     }
 
     async fn get_token_by_id(&self, id: i32) -> Result<Token> {
-        let selected: TokenDTO = sqlx::query_as("SELECT * FROM token WHERE id = ?")
+        let selected: TokenDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM token WHERE id = ?"))
             .bind(id)
             .fetch_one(&self.db_pool)
             .await?;
@@ -44,7 +43,7 @@ impl Repository for TokenRepository {
     }
 
     async fn get_token_by_value(&self, token: &str) -> Result<Token> {
-        let selected: TokenDTO = sqlx::query_as("SELECT * FROM token WHERE token = ?")
+        let selected: TokenDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM token WHERE token = ?"))
             .bind(token)
             .fetch_one(&self.db_pool)
             .await?;
@@ -52,7 +51,7 @@ impl Repository for TokenRepository {
     }
 
     async fn delete_by_id(&self, id: i32) -> Result<()> {
-        let _: Option<TokenDTO> = sqlx::query_as("DELETE FROM token where id = ?")
+        let _: Option<TokenDTO> = sqlx::query_as(&sql(&self.db_pool, "DELETE FROM token where id = ?"))
             .bind(id)
             .fetch_optional(&self.db_pool)
             .await?;
@@ -60,7 +59,7 @@ impl Repository for TokenRepository {
     }
 
     async fn get_token_by_user_id(&self, id: i32) -> Result<Vec<Token>> {
-        let dtos: Vec<TokenDTO> = sqlx::query_as("SELECT * FROM token WHERE user_id = ?")
+        let dtos: Vec<TokenDTO> = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM token WHERE user_id = ?"))
             .bind(id)
             .fetch_all(&self.db_pool)
             .await?;
@@ -1,5 +1,6 @@
 use super::dto::UserDTO;
 
+use crate::infra::database::dialect::{insert, insert_sql, sql};
 use crate::infra::database::pool::DbPool;
 use crate::domain::user::entity::User;
 use crate::domain::user::repository::Repository;
@@ -31,17 +32,15 @@ impl Repository for UserRepository {
             }
             Err(_err) => {
                 let dto = UserDTO::encrypt(user).await?;
-                let record : u64 = sqlx::query("INSERT INTO user(email) VALUES (?)")
-                    .bind(&dto.email)
-                    .execute(&self.db_pool)
-                    .await?.last_insert_id();
+                let record = insert(&self.db_pool, sqlx::query(&insert_sql(&self.db_pool, "INSERT INTO `user`(email) VALUES (?)"))
+                    .bind(&dto.email)).await?;
                 self.get_by_id(record as i32).await
             }
         }
     }
 
     async fn get_by_id(&self, id: i32) -> Result<User> {
-        let selected: UserDTO = sqlx::query_as("SELECT * FROM user WHERE id = ?")
+        let selected: UserDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM `user` WHERE id = ?"))
             .bind(id)
             .fetch_one(&self.db_pool)
             .await?;
@@ -49,7 +48,7 @@ impl Repository for UserRepository {
     }
 
     async fn get_by_email(&self, email: &str) -> Result<User> {
-        let selected: UserDTO = sqlx::query_as("SELECT * FROM user WHERE email = ?")
+        let selected: UserDTO = sqlx::query_as(&sql(&self.db_pool, "SELECT * FROM `user` WHERE email = ?"))
             .bind(email)
             .fetch_one(&self.db_pool)
             .await?;
@@ -57,7 +56,7 @@ impl Repository for UserRepository {
     }
 
     async fn delete_by_id(&self, id: i32) -> Result<()> {
-        let _: Option<UserDTO> = sqlx::query_as("DELETE FROM user where id = ?")
+        let _: Option<UserDTO> = sqlx::query_as(&sql(&self.db_pool, "DELETE FROM `user` where id = ?"))
             .bind(id)
             .fetch_optional(&self.db_pool)
             .await?;
@@ -1,16 +1,48 @@
 use crate::util::error;
 use config::Value;
 use once_cell::sync::OnceCell;
-use sqlx::mysql::{MySql, MySqlPoolOptions};
-use sqlx::pool::Pool;
+use sqlx::any::{AnyConnection, AnyKind, AnyPool, AnyPoolOptions};
+use sqlx::migrate::{Migrate, Migrator};
 use std::collections::HashMap;
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
 
 use crate::util::error::{Error, Result};
-pub type DbPool = Pool<MySql>;
+pub type DbPool = AnyPool;
 
 static DB_POOL: OnceCell<DbPool> = OnceCell::new();
+//migrations are embedded into binary, so that no external tool is required for deployment
+static MYSQL_MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
 
 pub async fn create_pool(config: &HashMap<String, Value>) -> Result<()> {
+    let pool = connect_with_config(config).await?;
+    //the embedded sqlite database is always migrated at startup
+    let auto_migrate = match config.get("auto_migrate") {
+        Some(value) => value.clone().into_bool()?,
+        None => pool.any_kind() == AnyKind::Sqlite,
+    };
+    if auto_migrate {
+        migrate(&pool).await?;
+    }
+    check_schema_version(&pool).await?;
+    DB_POOL.set(pool).expect("db pool configured");
+    ping().await?;
+    Ok(())
+}
+
+//apply the migrations regardless of the auto_migrate switch, used for the controlled upgrades
+pub async fn migrate_only(config: &HashMap<String, Value>) -> Result<()> {
+    let pool = connect_with_config(config).await?;
+    migrate(&pool).await?;
+    check_schema_version(&pool).await?;
+    info!("Database migrated successfully");
+    Ok(())
+}
+
+async fn connect_with_config(config: &HashMap<String, Value>) -> Result<DbPool> {
     let max_connections: u32 = config
         .get("max_connection")
         .expect("max connection should configured")
@@ -32,23 +64,114 @@ pub async fn create_pool(config: &HashMap<String, Value>) -> Result<()> {
             db_connection
         )));
     }
-    let pool = MySqlPoolOptions::new()
+    connect(&db_connection, max_connections).await
+}
+
+//the database backend is selected by the scheme of connection url, i.e. mysql://, postgres:// or sqlite://
+pub async fn connect(db_connection: &str, max_connections: u32) -> Result<DbPool> {
+    let kind = AnyKind::from_str(db_connection)?;
+    info!("Connecting to {:?} database", kind);
+    let pool = AnyPoolOptions::new()
         .max_connections(max_connections)
-        .connect(db_connection.as_str())
+        .connect(db_connection)
         .await
         .map_err(Error::from)?;
-    DB_POOL.set(pool).expect("db pool configured");
-    ping().await?;
+    Ok(pool)
+}
+
+fn get_migrator(kind: AnyKind) -> &'static Migrator {
+    match kind {
+        AnyKind::Postgres => &POSTGRES_MIGRATOR,
+        AnyKind::Sqlite => &SQLITE_MIGRATOR,
+        _ => &MYSQL_MIGRATOR,
+    }
+}
+
+fn latest_version(migrator: &Migrator) -> i64 {
+    migrator.iter().map(|m| m.version).max().unwrap_or(0)
+}
+
+//the migrator holds the advisory lock of database (GET_LOCK in MySQL and pg_advisory_lock in PostgreSQL)
+//while migrating, the data servers which start at the same time will wait for the lock instead of racing.
+//sqlite has no advisory lock, the servers sharing the database file are serialized by the file lock instead
+pub async fn migrate(pool: &DbPool) -> Result<()> {
+    let migrator = get_migrator(pool.any_kind());
+    let _lock = match pool.any_kind() {
+        AnyKind::Sqlite => lock_sqlite_file(pool).await?,
+        _ => None,
+    };
+    info!("Migrating database schema to version {}", latest_version(migrator));
+    migrator.run(pool).await?;
     Ok(())
 }
 
+//the exclusive flock is released when the returned file is closed, it doesn't interfere with the fcntl locks
+//used by sqlite itself. the in memory database is private to its connection and needs no lock
+async fn lock_sqlite_file(pool: &DbPool) -> Result<Option<File>> {
+    let path: String = sqlx::query_scalar("SELECT file FROM pragma_database_list WHERE name = 'main'")
+        .fetch_one(pool)
+        .await?;
+    if path.is_empty() {
+        return Ok(None);
+    }
+    let file = File::open(&path)?;
+    let file = tokio::task::spawn_blocking(move || {
+        match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } {
+            0 => Ok(file),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }).await.map_err(|e| Error::DatabaseError(e.to_string()))??;
+    Ok(Some(file))
+}
+
+//refuse to start when the schema is newer than the binary or was partially migrated, since the queries may
+//not work on it
+pub async fn check_schema_version(pool: &DbPool) -> Result<()> {
+    let migrator = get_migrator(pool.any_kind());
+    let supported = latest_version(migrator);
+    let mut conn = pool.acquire().await?;
+    //the schema is left untouched when auto_migrate is disabled, a database never migrated has no version at all
+    let applied = if migrations_table_exists(&mut conn).await? {
+        if let Some(version) = conn.dirty_version().await? {
+            return Err(Error::DatabaseError(format!(
+                "database schema version {} is partially applied, please fix it before starting", version)));
+        }
+        conn.list_applied_migrations().await?
+    } else {
+        vec![]
+    };
+    let current = applied.iter().map(|m| m.version).max().unwrap_or(0);
+    if current > supported {
+        return Err(Error::DatabaseError(format!(
+            "database schema version {} is newer than version {} supported by this binary", current, supported)));
+    }
+    let pending = migrator
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration() && !applied.iter().any(|a| a.version == m.version))
+        .count();
+    if pending > 0 {
+        warn!("{} database migrations are pending, enable auto_migrate or run with --migrate-only to apply them", pending);
+    }
+    Ok(())
+}
+
+async fn migrations_table_exists(conn: &mut AnyConnection) -> Result<bool> {
+    let query = match conn.kind() {
+        AnyKind::Sqlite => "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        AnyKind::Postgres => "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = '_sqlx_migrations'",
+        _ => "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = '_sqlx_migrations'",
+    };
+    let count: i64 = sqlx::query_scalar(query).fetch_one(conn).await?;
+    Ok(count > 0)
+}
+
 pub fn get_db_pool() -> Result<DbPool> {
-    return match DB_POOL.get() {
+    match DB_POOL.get() {
         None => Err(error::Error::DatabaseError(
             "failed to get database pool".to_string(),
         )),
         Some(pool) => Ok(pool.clone()),
-    };
+    }
 }
 
 pub async fn ping() -> Result<()> {
@@ -66,3 +189,74 @@ pub async fn ping() -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::user::entity::User;
+    use crate::domain::user::repository::Repository;
+    use crate::infra::database::model::user::repository::UserRepository;
+    use crate::util::test_support::memory_pool;
+
+    #[tokio::test]
+    async fn test_sqlite_pool_migrated() {
+        let pool = memory_pool().await;
+        check_schema_version(&pool).await.expect("schema is up to date");
+        let repository = UserRepository::new(pool);
+        let user = repository.create(&User::new("signatrust@example.com".to_string()).unwrap()).await.unwrap();
+        assert_eq!(repository.get_by_email("signatrust@example.com").await.unwrap().id, user.id);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_file_migrated_concurrently() {
+        let dir = std::env::temp_dir().join(format!("signatrust-sqlite-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = format!("sqlite://{}?mode=rwc", dir.join("signatrust.db").display());
+        //every server owns a pool of its own, none of them fails on the migrations applied by the others
+        let migrations = (0..4).map(|_| {
+            let url = url.clone();
+            tokio::spawn(async move {
+                let pool = connect(&url, 2).await?;
+                migrate(&pool).await?;
+                check_schema_version(&pool).await
+            })
+        }).collect::<Vec<_>>();
+        for migration in migrations {
+            migration.await.unwrap().expect("migrate sqlite file");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    //the ddl is written for every database, a migration missing in any of them fails the startup check there
+    #[test]
+    fn test_migrations_of_every_database() {
+        let migrations = |migrator: &Migrator| migrator
+            .iter()
+            .map(|m| (m.version, m.description.to_string(), m.migration_type.is_down_migration()))
+            .collect::<Vec<_>>();
+        assert_eq!(migrations(&POSTGRES_MIGRATOR), migrations(&MYSQL_MIGRATOR));
+        assert_eq!(migrations(&SQLITE_MIGRATOR), migrations(&MYSQL_MIGRATOR));
+    }
+
+    #[tokio::test]
+    async fn test_check_leaves_schema_untouched() {
+        let pool = connect("sqlite::memory:", 1).await.expect("connect to sqlite in memory");
+        check_schema_version(&pool).await.expect("database never migrated is accepted");
+        assert!(!migrations_table_exists(&mut pool.acquire().await.unwrap()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_refuse_newer_schema() {
+        let pool = memory_pool().await;
+        sqlx::query("INSERT INTO _sqlx_migrations(version, description, success, checksum, execution_time) VALUES (?, ?, ?, ?, ?)")
+            .bind(i64::MAX)
+            .bind("from newer binary")
+            .bind(true)
+            .bind(vec![0u8])
+            .bind(0i64)
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(check_schema_version(&pool).await.is_err());
+    }
+}
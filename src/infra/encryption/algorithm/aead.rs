@@ -0,0 +1,42 @@
+use crate::util::error::{Error, Result};
+use aes_gcm_siv::aead::{consts::U12, Aead, AeadCore, KeyInit, OsRng, Payload};
+use generic_array::GenericArray;
+
+pub const NONCE_LENGTH: usize = 12;
+
+pub fn generate_key<C: KeyInit>() -> Vec<u8> {
+    C::generate_key(&mut OsRng).as_slice().to_vec()
+}
+
+//the random nonce is prepended to the encrypted content
+pub fn encrypt<C>(key: &[u8], content: &[u8], aad: &[u8]) -> Result<Vec<u8>>
+where
+    C: KeyInit + Aead + AeadCore<NonceSize = U12>
+{
+    let cipher = C::new_from_slice(key).map_err(|e| Error::EncodeError(e.to_string()))?;
+    let nonce = C::generate_nonce(&mut OsRng);
+    let encrypt_msg = cipher
+        .encrypt(&nonce, Payload { msg: content, aad })
+        .map_err(|e| Error::EncodeError(e.to_string()))?;
+    let mut encrypted = Vec::with_capacity(NONCE_LENGTH + encrypt_msg.len());
+    encrypted.extend_from_slice(&nonce);
+    encrypted.extend(encrypt_msg);
+    Ok(encrypted)
+}
+
+pub fn decrypt<C>(key: &[u8], content: &[u8], aad: &[u8]) -> Result<Vec<u8>>
+where
+    C: KeyInit + Aead + AeadCore<NonceSize = U12>
+{
+    if content.len() <= NONCE_LENGTH {
+        return Err(Error::EncodeError(
+            "failed to decode cluster key due to incorrect length".to_string(),
+        ));
+    }
+    let cipher = C::new_from_slice(key).map_err(|e| Error::EncodeError(e.to_string()))?;
+    let nonce = GenericArray::from_slice(&content[..NONCE_LENGTH]);
+    cipher
+        .decrypt(nonce, Payload { msg: &content[NONCE_LENGTH..], aad })
+        .map_err(|_| Error::EncodeError(
+            "failed to decrypt data, either the key or the associated data doesn't match".to_string()))
+}
@@ -1,56 +1,48 @@
 use crate::domain::encryptor::{Algorithm, Encryptor};
-use crate::util::error::Error;
-use aes_gcm_siv::{
-    aead::{Aead, KeyInit, OsRng},
-    Aes256GcmSiv,
-};
-use generic_array::GenericArray;
-use rand::{thread_rng, Rng};
-
-pub const NONCE_LENGTH: usize = 12;
+use crate::infra::encryption::algorithm::aead;
+use crate::util::error::Result;
+use aes_gcm::Aes256Gcm;
+use aes_gcm_siv::Aes256GcmSiv;
 
+/// AES-256-GCM-SIV, it's configured with the name of `aes256gsm`.
 #[derive(Default)]
-pub struct Aes256GcmEncryptor {}
+pub struct Aes256GcmSivEncryptor {}
+
+impl Encryptor for Aes256GcmSivEncryptor {
+    fn generate_key(&self) -> Vec<u8> {
+        aead::generate_key::<Aes256GcmSiv>()
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Aes256GSM
+    }
 
-impl Aes256GcmEncryptor {
-    fn generate_nonce_bytes(&self) -> [u8; NONCE_LENGTH] {
-        thread_rng().gen::<[u8; NONCE_LENGTH]>()
+    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::encrypt::<Aes256GcmSiv>(&key, &content, aad)
+    }
+
+    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::decrypt::<Aes256GcmSiv>(&key, &content, aad)
     }
 }
 
+#[derive(Default)]
+pub struct Aes256GcmEncryptor {}
+
 impl Encryptor for Aes256GcmEncryptor {
     fn generate_key(&self) -> Vec<u8> {
-        Aes256GcmSiv::generate_key(&mut OsRng).as_slice().to_vec()
+        aead::generate_key::<Aes256Gcm>()
     }
 
     fn algorithm(&self) -> Algorithm {
-        Algorithm::Aes256GSM
+        Algorithm::Aes256GCM
+    }
+
+    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::encrypt::<Aes256Gcm>(&key, &content, aad)
     }
 
-    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>) -> crate::util::error::Result<Vec<u8>> {
-        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&key));
-        let random = self.generate_nonce_bytes();
-        let nonce = GenericArray::from_slice(&random);
-        let encrypt_msg = cipher
-            .encrypt(nonce, content.as_slice())
-            .map_err(|e| Error::EncodeError(e.to_string()))?;
-        let mut encrypted = Vec::new();
-        encrypted.extend_from_slice(&random);
-        encrypted.extend(encrypt_msg);
-        Ok(encrypted)
-    }
-
-    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>) -> crate::util::error::Result<Vec<u8>> {
-        if key.len() <= NONCE_LENGTH {
-            return Err(Error::EncodeError(
-                "failed to decode cluster key due to incorrect length".to_string(),
-            ));
-        }
-        let cipher = Aes256GcmSiv::new(GenericArray::from_slice(&key));
-        let nonce = GenericArray::from_slice(&content[..NONCE_LENGTH]);
-        let decrypted = cipher
-            .decrypt(nonce, &content[NONCE_LENGTH..])
-            .map_err(|e| Error::EncodeError(e.to_string()))?;
-        Ok(decrypted)
+    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::decrypt::<Aes256Gcm>(&key, &content, aad)
     }
 }
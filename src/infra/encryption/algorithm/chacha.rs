@@ -0,0 +1,25 @@
+use crate::domain::encryptor::{Algorithm, Encryptor};
+use crate::infra::encryption::algorithm::aead;
+use crate::util::error::Result;
+use chacha20poly1305::ChaCha20Poly1305;
+
+#[derive(Default)]
+pub struct ChaCha20Poly1305Encryptor {}
+
+impl Encryptor for ChaCha20Poly1305Encryptor {
+    fn generate_key(&self) -> Vec<u8> {
+        aead::generate_key::<ChaCha20Poly1305>()
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ChaCha20Poly1305
+    }
+
+    fn encrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::encrypt::<ChaCha20Poly1305>(&key, &content, aad)
+    }
+
+    fn decrypt(&self, key: Vec<u8>, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
+        aead::decrypt::<ChaCha20Poly1305>(&key, &content, aad)
+    }
+}
@@ -1,4 +1,5 @@
-use crate::infra::encryption::algorithm::aes::Aes256GcmEncryptor;
+use crate::infra::encryption::algorithm::aes::{Aes256GcmEncryptor, Aes256GcmSivEncryptor};
+use crate::infra::encryption::algorithm::chacha::ChaCha20Poly1305Encryptor;
 use crate::domain::encryptor::{Algorithm, Encryptor};
 use crate::util::error::{Result};
 use std::str::FromStr;
@@ -10,8 +11,14 @@ impl AlgorithmFactory {
     pub fn new_algorithm(algo: &str) -> Result<Box<dyn Encryptor>> {
         let algorithm = Algorithm::from_str(algo)?;
         info!("encryption algorithm configured with {:?}", algorithm);
+        Ok(AlgorithmFactory::new_encryptor(algorithm))
+    }
+
+    pub fn new_encryptor(algorithm: Algorithm) -> Box<dyn Encryptor> {
         match algorithm {
-            Algorithm::Aes256GSM => Ok(Box::new(Aes256GcmEncryptor::default())),
+            Algorithm::Aes256GSM => Box::new(Aes256GcmSivEncryptor::default()),
+            Algorithm::ChaCha20Poly1305 => Box::new(ChaCha20Poly1305Encryptor::default()),
+            Algorithm::Aes256GCM => Box::new(Aes256GcmEncryptor::default()),
         }
     }
 }
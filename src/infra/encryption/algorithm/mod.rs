@@ -1,2 +1,4 @@
+pub mod aead;
 pub mod aes;
+pub mod chacha;
 pub mod factory;
@@ -1,17 +1,28 @@
 
-use crate::domain::encryptor::Encryptor;
+use crate::domain::encryptor::{Algorithm, Encryptor};
+use crate::infra::encryption::algorithm::factory::AlgorithmFactory;
 use crate::domain::encryption_engine::EncryptionEngine;
 use crate::domain::clusterkey::entity::{ClusterKey, SecClusterKey};
 use crate::domain::clusterkey::repository::Repository as ClusterKeyRepository;
+use crate::util::cache::TTLCache;
 use crate::util::error::{Error, Result};
 use crate::util::key;
+use crate::util::metrics;
 use async_trait::async_trait;
 use config::Value;
 use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use chrono::Utc;
 
 use crate::domain::kms_provider::KMSProvider;
 
+//size of the cluster key id in the legacy format
 pub const KEY_SIZE: usize = 2;
+pub const ENVELOPE_MAGIC: [u8; 2] = [0xFF, 0x53];
+pub const ENVELOPE_VERSION: u8 = 1;
+const ENVELOPE_HEADER_SIZE: usize = 8;
 
 pub struct EncryptionEngineWithClusterKey<C, K, E>
 where
@@ -24,17 +35,35 @@ where
     kms_provider: Box<K>,
     encryptor: Box<E>,
     keep_in_days: i64,
-    latest_cluster_key: Box<SecClusterKey>,
+    //the legacy format isn't bound with the associated data of caller, reject it once all of the data have been
+    //re-encrypted into the envelope format
+    reject_legacy_format: bool,
+    //switched to the new cluster key when rotated
+    latest_cluster_key: RwLock<Arc<SecClusterKey>>,
+    //decrypted cluster keys, avoid asking kms to decrypt the same cluster key on every decode
+    cluster_keys: TTLCache<i32, Arc<SecClusterKey>>,
 }
 
 /// considering we have rotated cluster key for safety concern
-/// we need append cluster key id to the encrypt data, for example
+/// we need wrap the encrypted data into envelope with the cluster key id, for example
 /// encrypted data 1 in hex string
+/// FF53, 01, 01, 0000000A, AB13......BF46, A237.....BA13CC46
+/// |-magic-|-version-|-algorithm-|-key id-|--nonce--|---encrypted data--|
+/// 1. magic and version: identify the envelope format, fixed size
+/// 2. algorithm: the id of encryption algorithm, fixed size
+/// 3. key id: is the cluster key used for encryption, 32 bits unsigned integer
+/// 4. nonce: the random bytes used for encryption. fixed size
+/// 5. encrypted data: the encrypted content
+///
+/// the whole header followed by the associated data specified by caller is used as the associated data of
+/// encryption, so neither the algorithm and key id nor the owner of the data can be tampered.
+///
+/// the data encrypted in the legacy format only starts with the 2 bytes key id and is encrypted without
+/// associated data, it can still be decoded and is migrated into the envelope format when the data keys are
+/// re-encrypted. since the data in legacy format can be swapped between keys unnoticed, it's rejected when
+/// `reject_legacy_format` is enabled after the migration.
 /// 000A, AB13......BF46, A237.....BA13CC46
 /// |-key id-|--nonce--|---encrypted data--|
-/// 1. key id: is the cluster key used for encryption, fixed size
-/// 2. nonce: the random bytes used for encryption. fixed size
-/// 3. encrypted data: the encrypted content
 impl<C, K, E> EncryptionEngineWithClusterKey<C, K, E>
 where
     C: ClusterKeyRepository,
@@ -46,6 +75,15 @@ where
         encryptor: Box<E>,
         config: &HashMap<String, Value>,
         kms_provider: Box<K>) -> Result<Self> {
+        let cache_ttl: u64 = config
+            .get("cluster_key_cache_ttl")
+            .map(|ttl| ttl.to_string())
+            .unwrap_or_else(|| "3600".to_string())
+            .parse()?;
+        let reject_legacy_format = match config.get("reject_legacy_format") {
+            Some(value) => value.clone().into_bool()?,
+            None => false,
+        };
         Ok(EncryptionEngineWithClusterKey {
             cluster_repository,
             encryptor,
@@ -54,25 +92,53 @@ where
                 .expect("encryption engine should configured")
                 .to_string()
                 .parse()?,
-            latest_cluster_key: Box::new(SecClusterKey::default()),
+            reject_legacy_format,
+            latest_cluster_key: RwLock::new(Arc::new(SecClusterKey::default())),
+            cluster_keys: TTLCache::new(Duration::from_secs(cache_ttl)),
             kms_provider,
 
         })
     }
-    fn append_cluster_key_hex(&self, data: &mut Vec<u8>) -> Vec<u8> {
-        let mut result = vec![];
-        result.append(&mut key::decode_hex_string_to_u8(&format!(
-            "{:04X}",
-            self.latest_cluster_key.id
-        )));
-        result.append(data);
-        result
+    //the encryptor is picked per cluster key, the data encrypted before the algorithm changed is still decodable
+    async fn get_used_sec_cluster_key(&self, cluster_id: i32) -> Result<(Arc<SecClusterKey>, Box<dyn Encryptor>)> {
+        let cluster_key = self.cluster_keys.get_or_load(cluster_id, || async {
+            Ok::<_, Error>(Arc::new(self.load_cluster_key(self.cluster_repository.get_by_id(cluster_id).await?).await?))
+        }).await?;
+        let encryptor = AlgorithmFactory::new_encryptor(Algorithm::from_str(&cluster_key.algorithm)?);
+        Ok((cluster_key, encryptor))
+    }
+
+    async fn load_cluster_key(&self, cluster_key: ClusterKey) -> Result<SecClusterKey> {
+        metrics::CLUSTER_KEY_DECRYPTS.inc();
+        SecClusterKey::load(cluster_key, self.kms_provider.as_ref()).await
     }
 
-    async fn get_used_sec_cluster_key(&self, data: &[u8]) -> Result<SecClusterKey> {
-        //convert the cluster back and obtain from database, hard code here.
-        let cluster_id: i32 = (data[0] as i32) * 256 + data[1] as i32;
-        SecClusterKey::load( self.cluster_repository.get_by_id(cluster_id).await?, &self.kms_provider).await
+    //servers rotating the same previous key at the same time will end up with the same key
+    async fn create_cluster_key(&self, previous: Option<&ClusterKey>) -> Result<ClusterKey> {
+        let algorithm = self.encryptor.algorithm().to_string();
+        let cluster_key = ClusterKey::new(
+            self.kms_provider.encode(
+                key::encode_u8_to_hex_string(&self.encryptor.generate_key()))
+                .await?.as_bytes().to_vec(),
+            algorithm.clone(),
+            self.keep_in_days,
+            previous.map(|key| key.id),
+        )?;
+        //insert is ignored when the key has been created by other servers
+        self.cluster_repository.create(cluster_key).await?;
+        match self.cluster_repository.get_latest(&algorithm).await? {
+            None => Err(Error::ConfigError(
+                "can't find latest cluster key from database".to_string(),
+            )),
+            //the insert is ignored for other reason, for instance the identity is occupied by an expired key
+            Some(cluster) if cluster.expire_at <= Utc::now() => Err(Error::ClusterError(format!(
+                "latest cluster key {} is still expired after rotation", cluster))),
+            Some(cluster) => Ok(cluster),
+        }
+    }
+
+    fn get_latest_cluster_key(&self) -> Result<Arc<SecClusterKey>> {
+        Ok(self.latest_cluster_key.read()?.clone())
     }
 }
 
@@ -85,54 +151,250 @@ where
 {
     async fn initialize(&mut self) -> Result<()> {
         //generate new symmetric keys when there is no db record
-        let key = self
+        self.rotate().await?;
+        info!("current cluster key is: {}", self.get_latest_cluster_key()?);
+        Ok(())
+    }
+
+    async fn rotate(&self) -> Result<()> {
+        let latest = match self
             .cluster_repository
             .get_latest(&self.encryptor.algorithm().to_string())
-            .await?;
-        match key {
-            Some(k) => *self.latest_cluster_key = SecClusterKey::load(k, &self.kms_provider).await?,
-            None => {
-                let cluster_key = ClusterKey::new(
-                        self.kms_provider.encode(
-                            key::encode_u8_to_hex_string(&self.encryptor.generate_key()))
-                        .await?.as_bytes().to_vec(),
-                    self.encryptor.algorithm().to_string(),
-                    self.keep_in_days,
-                )?;
-                //insert when no records
-                self.cluster_repository.create(cluster_key).await?;
-                match self
-                    .cluster_repository
-                    .get_latest(&self.encryptor.algorithm().to_string())
-                    .await?
-                {
-                    None => {
-                        return Err(Error::ConfigError(
-                            "can't find latest cluster key from database".to_string(),
-                        ))
-                    }
-                    Some(cluster) => *self.latest_cluster_key = SecClusterKey::load(cluster, &self.kms_provider).await?,
-                }
+            .await? {
+            Some(key) if key.expire_at > Utc::now() => key,
+            Some(key) => {
+                info!("cluster key {} expired at {}, rotating", key, key.expire_at);
+                self.create_cluster_key(Some(&key)).await?
             }
+            None => self.create_cluster_key(None).await?,
+        };
+        if latest.id == self.get_latest_cluster_key()?.id {
+            return Ok(());
         }
-        info!("current cluster key is: {}", self.latest_cluster_key);
+        let cluster_key = Arc::new(self.load_cluster_key(latest).await?);
+        self.cluster_keys.insert(cluster_key.id, cluster_key.clone());
+        *self.latest_cluster_key.write()? = cluster_key.clone();
+        info!("switched to cluster key: {}", cluster_key);
         Ok(())
     }
-    async fn encode(&self, content: Vec<u8>) -> Result<Vec<u8>> {
+
+    async fn encode(&self, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
         //always use latest cluster key to encode data
-        let mut secret = self
+        let cluster_key = self.get_latest_cluster_key()?;
+        let mut envelope = envelope_header(&self.encryptor.algorithm(), cluster_key.id)?;
+        let secret = self
             .encryptor
-            .encrypt(self.latest_cluster_key.data.unsecure().to_owned(), content)?;
-        Ok(self.append_cluster_key_hex(&mut secret))
+            .encrypt(cluster_key.data.unsecure().to_owned(), content, &[envelope.as_slice(), aad].concat())?;
+        envelope.extend(secret);
+        Ok(envelope)
     }
 
-    async fn decode(&self, content: Vec<u8>) -> Result<Vec<u8>> {
+    async fn decode(&self, content: Vec<u8>, aad: &[u8]) -> Result<Vec<u8>> {
         //1. obtain cluster key id from content
         //2. use cluster key to decrypt data
-        let sec_cluster_key = self.get_used_sec_cluster_key(&content[0..KEY_SIZE]).await?;
-        self.encryptor.decrypt(
+        let envelope = parse_envelope(&content)?;
+        if envelope.algorithm.is_none() && self.reject_legacy_format {
+            return Err(Error::EncodeError(
+                "data encrypted in legacy format is rejected, it needs to be re-encrypted".to_string()));
+        }
+        let cluster_id = envelope.cluster_id;
+        let (sec_cluster_key, encryptor) = self.get_used_sec_cluster_key(cluster_id).await?;
+        if let Some(algorithm) = envelope.algorithm {
+            if algorithm != encryptor.algorithm() {
+                return Err(Error::EncodeError(format!(
+                    "data encrypted with {} while cluster key {} is used for {}",
+                    algorithm, cluster_id, sec_cluster_key.algorithm)));
+            }
+        }
+        encryptor.decrypt(
             sec_cluster_key.data.unsecure().to_owned(),
-            content[KEY_SIZE..].to_vec(),
+            envelope.secret.to_vec(),
+            &envelope.associated_data(aad),
         )
     }
 }
+
+fn envelope_header(algorithm: &Algorithm, cluster_id: i32) -> Result<Vec<u8>> {
+    let cluster_id = u32::try_from(cluster_id)
+        .map_err(|_| Error::EncodeError(format!("invalid cluster key id {}", cluster_id)))?;
+    let mut result = Vec::with_capacity(ENVELOPE_HEADER_SIZE);
+    result.extend_from_slice(&ENVELOPE_MAGIC);
+    result.push(ENVELOPE_VERSION);
+    result.push(algorithm.id());
+    result.extend_from_slice(&cluster_id.to_be_bytes());
+    Ok(result)
+}
+
+struct Envelope<'a> {
+    cluster_id: i32,
+    //None for the legacy format
+    algorithm: Option<Algorithm>,
+    //empty for the legacy format
+    header: &'a [u8],
+    secret: &'a [u8],
+}
+
+impl Envelope<'_> {
+    fn associated_data(&self, aad: &[u8]) -> Vec<u8> {
+        match self.algorithm {
+            Some(_) => [self.header, aad].concat(),
+            None => vec![],
+        }
+    }
+}
+
+//the legacy format is assumed when the envelope header is absent, the legacy key ids never reach the magic
+//since they are far smaller.
+fn parse_envelope(content: &[u8]) -> Result<Envelope<'_>> {
+    if content.len() >= ENVELOPE_HEADER_SIZE && content[0..2] == ENVELOPE_MAGIC && content[2] == ENVELOPE_VERSION {
+        let cluster_id = u32::from_be_bytes([content[4], content[5], content[6], content[7]]);
+        let cluster_id = i32::try_from(cluster_id)
+            .map_err(|_| Error::EncodeError(format!("invalid cluster key id {}", cluster_id)))?;
+        let (header, secret) = content.split_at(ENVELOPE_HEADER_SIZE);
+        return Ok(Envelope {
+            cluster_id,
+            algorithm: Some(Algorithm::from_id(content[3])?),
+            header,
+            secret,
+        });
+    }
+    if content.len() < KEY_SIZE {
+        return Err(Error::EncodeError("encrypted data is too short".to_string()));
+    }
+    let cluster_id: i32 = (content[0] as i32) * 256 + content[1] as i32;
+    Ok(Envelope {
+        cluster_id,
+        algorithm: None,
+        header: &[],
+        secret: &content[KEY_SIZE..],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::infra::database::model::clusterkey::repository::ClusterKeyRepository as DBClusterKeyRepository;
+    use crate::util::test_support::memory_pool;
+    use crate::infra::kms::dummy::DummyKMS;
+
+    type TestEngine = EncryptionEngineWithClusterKey<DBClusterKeyRepository, DummyKMS, dyn Encryptor>;
+
+    async fn engine(reject_legacy_format: bool) -> TestEngine {
+        engine_with_keys(reject_legacy_format, vec![]).await
+    }
+
+    async fn engine_with_keys(reject_legacy_format: bool, cluster_keys: Vec<ClusterKey>) -> TestEngine {
+        let pool = memory_pool().await;
+        for cluster_key in cluster_keys {
+            DBClusterKeyRepository::new(pool.clone()).create(cluster_key).await.unwrap();
+        }
+        let mut config = HashMap::new();
+        config.insert("keep_in_days".to_string(), Value::from(180));
+        config.insert("reject_legacy_format".to_string(), Value::from(reject_legacy_format));
+        let mut engine = EncryptionEngineWithClusterKey::new(
+            DBClusterKeyRepository::new(pool),
+            AlgorithmFactory::new_encryptor(Algorithm::Aes256GSM),
+            &config,
+            Box::new(DummyKMS::new(&config).unwrap())).unwrap();
+        engine.initialize().await.unwrap();
+        engine
+    }
+
+    //encrypt in the legacy format, which is bound with neither header nor the associated data of caller
+    fn legacy_encode(engine: &TestEngine, content: Vec<u8>) -> Vec<u8> {
+        let cluster_key = engine.get_latest_cluster_key().unwrap();
+        let mut result = vec![(cluster_key.id / 256) as u8, (cluster_key.id % 256) as u8];
+        result.extend(engine.encryptor.encrypt(cluster_key.data.unsecure().to_owned(), content, &[]).unwrap());
+        result
+    }
+
+    #[tokio::test]
+    async fn test_reject_swapped_data() {
+        let engine = engine(true).await;
+        let encoded = engine.encode(vec![1, 2, 3], b"key-a").await.unwrap();
+        assert_eq!(engine.decode(encoded.clone(), b"key-a").await.unwrap(), vec![1, 2, 3]);
+        assert!(engine.decode(encoded, b"key-b").await.is_err());
+        //the legacy data can't be verified against its owner, it's rejected in whichever row
+        let legacy = legacy_encode(&engine, vec![1, 2, 3]);
+        assert!(engine.decode(legacy.clone(), b"key-a").await.is_err());
+        assert!(engine.decode(legacy, b"key-b").await.is_err());
+    }
+
+    fn expired_cluster_key() -> ClusterKey {
+        let data = key::encode_u8_to_hex_string(&AlgorithmFactory::new_encryptor(Algorithm::Aes256GSM).generate_key());
+        let mut cluster_key = ClusterKey::new(data.as_bytes().to_vec(), Algorithm::Aes256GSM.to_string(), 180, None).unwrap();
+        cluster_key.expire_at = Utc::now() - chrono::Duration::days(1);
+        cluster_key
+    }
+
+    #[tokio::test]
+    async fn test_rotate_expired_key_on_the_same_day() {
+        //the expired key is created today, its successor must not be ignored for the same identity
+        let engine = engine_with_keys(false, vec![expired_cluster_key()]).await;
+        let latest = engine.get_latest_cluster_key().unwrap();
+        assert_eq!(latest.id, 2);
+        assert_eq!(latest.identity, format!("{}-after-1", Algorithm::Aes256GSM));
+        engine.rotate().await.unwrap();
+        assert_eq!(engine.get_latest_cluster_key().unwrap().id, 2);
+        let encoded = engine.encode(vec![1, 2, 3], b"key-a").await.unwrap();
+        assert_eq!(engine.decode(encoded, b"key-a").await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_keeps_valid_key() {
+        let engine = engine(false).await;
+        let latest = engine.get_latest_cluster_key().unwrap();
+        engine.rotate().await.unwrap();
+        assert_eq!(engine.get_latest_cluster_key().unwrap().id, latest.id);
+        //the valid cluster key is kept in database and used for encryption
+        let encoded = engine.encode(vec![1, 2, 3], b"key-a").await.unwrap();
+        assert_eq!(engine.cluster_repository.get_latest(&Algorithm::Aes256GSM.to_string()).await.unwrap().unwrap().id, latest.id);
+        assert_eq!(engine.decode(encoded, b"key-a").await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_decode_legacy_format() {
+        let engine = engine(false).await;
+        let legacy = legacy_encode(&engine, vec![1, 2, 3]);
+        assert_eq!(engine.decode(legacy, b"key-a").await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let mut envelope = envelope_header(&Algorithm::Aes256GSM, 70000).unwrap();
+        assert_eq!(envelope, [0xFF, 0x53, 1, 1, 0x00, 0x01, 0x11, 0x70]);
+        envelope.extend([1, 2, 3]);
+        let parsed = parse_envelope(&envelope).unwrap();
+        assert_eq!(parsed.cluster_id, 70000);
+        assert!(matches!(parsed.algorithm, Some(Algorithm::Aes256GSM)));
+        assert_eq!(parsed.associated_data(b"aad"), [&envelope[..ENVELOPE_HEADER_SIZE], b"aad"].concat());
+        assert_eq!(parsed.secret, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_header_and_caller_bound_as_associated_data() {
+        for algorithm in [Algorithm::Aes256GSM, Algorithm::ChaCha20Poly1305, Algorithm::Aes256GCM] {
+            let encryptor = AlgorithmFactory::new_encryptor(algorithm);
+            let key = encryptor.generate_key();
+            let mut envelope = envelope_header(&algorithm, 1).unwrap();
+            envelope.extend(encryptor.encrypt(key.clone(), vec![1, 2, 3], &[envelope.as_slice(), b"key-a"].concat()).unwrap());
+            let parsed = parse_envelope(&envelope).unwrap();
+            assert_eq!(encryptor.decrypt(key.clone(), parsed.secret.to_vec(), &parsed.associated_data(b"key-a")).unwrap(), vec![1, 2, 3]);
+            assert!(encryptor.decrypt(key.clone(), parsed.secret.to_vec(), &parsed.associated_data(b"key-b")).is_err());
+            //the data can't be decrypted once the algorithm in header is changed
+            envelope[3] = 0x7F;
+            let (header, secret) = envelope.split_at(ENVELOPE_HEADER_SIZE);
+            assert!(encryptor.decrypt(key, secret.to_vec(), &[header, b"key-a"].concat()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_legacy_format() {
+        let parsed = parse_envelope(&[0x00, 0x0A, 4, 5, 6]).unwrap();
+        assert_eq!(parsed.cluster_id, 10);
+        assert!(parsed.algorithm.is_none());
+        assert!(parsed.associated_data(b"aad").is_empty());
+        assert_eq!(parsed.secret, &[4, 5, 6]);
+        assert!(parse_envelope(&[0x01]).is_err());
+    }
+}
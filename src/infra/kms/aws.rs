@@ -0,0 +1,245 @@
+use crate::domain::kms_provider::KMSProvider;
+use crate::util::error::{Error, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use config::Value;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sha::sha256;
+use openssl::sign::Signer;
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+use url::Url;
+use secstr::*;
+
+static SERVICE: &str = "kms";
+static ALGORITHM: &str = "AWS4-HMAC-SHA256";
+static CONTENT_TYPE: &str = "application/x-amz-json-1.1";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EncryptResponse {
+    ciphertext_blob: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DecryptResponse {
+    plaintext: String,
+}
+
+/// AWS KMS wraps and unwraps the cluster key with the KMS Encrypt and Decrypt APIs, requests are
+/// signed with Signature Version 4, the endpoint is configurable so that LocalStack or moto can be used.
+/// Reference: https://docs.aws.amazon.com/IAM/latest/UserGuide/create-signed-request.html
+pub struct AwsKMS {
+    key_id: String,
+    region: String,
+    endpoint: Url,
+    access_key_id: String,
+    secret_access_key: SecUtf8,
+    session_token: SecUtf8,
+    client: Client,
+}
+
+impl AwsKMS {
+    pub fn new(config: &HashMap<String, Value>) -> Result<AwsKMS> {
+        //values in config take precedence over the standard AWS environment variables
+        let get = |name: &str, envs: &[&str]| {
+            config
+                .get(name)
+                .map(|value| value.to_string())
+                .filter(|value| !value.is_empty())
+                .or_else(|| envs.iter().find_map(|e| env::var(e).ok()))
+                .unwrap_or_default()
+        };
+        let region = get("region", &["AWS_REGION", "AWS_DEFAULT_REGION"]);
+        let key_id = get("key_id", &[]);
+        if region.is_empty() || key_id.is_empty() {
+            return Err(Error::ConfigError("aws kms requires both 'region' and 'key_id'".to_string()));
+        }
+        let mut endpoint = get("endpoint", &["AWS_ENDPOINT_URL_KMS", "AWS_ENDPOINT_URL"]);
+        if endpoint.is_empty() {
+            endpoint = format!("https://kms.{}.amazonaws.com", region);
+        }
+        let timeout = config.get("timeout").map(|t| t.to_string()).unwrap_or_else(|| "10".to_string());
+        Ok(AwsKMS {
+            key_id,
+            region,
+            endpoint: Url::parse(&endpoint)?,
+            access_key_id: get("access_key_id", &["AWS_ACCESS_KEY_ID"]),
+            secret_access_key: SecUtf8::from(get("secret_access_key", &["AWS_SECRET_ACCESS_KEY"])),
+            session_token: SecUtf8::from(get("session_token", &["AWS_SESSION_TOKEN"])),
+            client: Client::builder().timeout(Duration::from_secs(timeout.parse()?)).build()?,
+        })
+    }
+
+    fn host(&self) -> Result<String> {
+        let host = self.endpoint.host_str().ok_or_else(
+            || Error::ConfigError(format!("invalid aws kms endpoint {}", self.endpoint)))?;
+        Ok(match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        })
+    }
+
+    async fn do_request<T: for<'de> Deserialize<'de>>(&self, action: &str, body: serde_json::Value) -> Result<T> {
+        let payload = serde_json::to_vec(&body)?;
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let target = format!("TrentService.{}", action);
+        let mut headers = vec![
+            ("content-type".to_string(), CONTENT_TYPE.to_string()),
+            ("host".to_string(), self.host()?),
+            ("x-amz-date".to_string(), amz_date.clone()),
+            ("x-amz-target".to_string(), target.clone()),
+        ];
+        if !self.session_token.unsecure().is_empty() {
+            headers.push(("x-amz-security-token".to_string(), self.session_token.unsecure().to_string()));
+        }
+        let authorization = authorization(
+            &self.access_key_id, self.secret_access_key.unsecure(), &self.region, SERVICE, &amz_date,
+            "POST", self.endpoint.path(), "", &mut headers, &payload)?;
+        let mut request = self.client.post(self.endpoint.clone())
+            .header("Content-Type", CONTENT_TYPE)
+            .header("X-Amz-Date", amz_date)
+            .header("X-Amz-Target", target)
+            .header("Authorization", authorization);
+        if !self.session_token.unsecure().is_empty() {
+            request = request.header("X-Amz-Security-Token", self.session_token.unsecure());
+        }
+        let res = request.body(payload).send().await?;
+        if res.status() != StatusCode::OK {
+            return Err(Error::KMSInvokeError(format!(
+                "failed to {} data in aws kms, result {} {}",
+                action.to_lowercase(),
+                res.status(),
+                res.text().await.unwrap_or_default()
+            )));
+        }
+        Ok(res.json::<T>().await?)
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+//build the SigV4 authorization header, the header names must be lowercase and are sorted in place
+#[allow(clippy::too_many_arguments)]
+fn authorization(access_key_id: &str, secret_access_key: &str, region: &str, service: &str, amz_date: &str,
+                 method: &str, path: &str, query: &str, headers: &mut [(String, String)], payload: &[u8]) -> Result<String> {
+    headers.sort();
+    let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v.trim())).collect();
+    let signed_headers = headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<&str>>().join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, path, query, canonical_headers, signed_headers, hex::encode(sha256(payload)));
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM, amz_date, scope, hex::encode(sha256(canonical_request.as_bytes())));
+    let mut key = hmac(format!("AWS4{}", secret_access_key).as_bytes(), date.as_bytes())?;
+    for part in [region, service, "aws4_request"] {
+        key = hmac(&key, part.as_bytes())?;
+    }
+    Ok(format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, access_key_id, scope, signed_headers, hex::encode(hmac(&key, string_to_sign.as_bytes())?)))
+}
+
+#[async_trait]
+impl KMSProvider for AwsKMS {
+    async fn encode(&self, content: String) -> Result<String> {
+        let encrypted: EncryptResponse = self.do_request("Encrypt", json!({
+            "KeyId": self.key_id,
+            "Plaintext": STANDARD.encode(content.as_bytes()),
+        })).await?;
+        Ok(encrypted.ciphertext_blob)
+    }
+
+    async fn decode(&self, content: String) -> Result<String> {
+        let decrypted: DecryptResponse = self.do_request("Decrypt", json!({
+            "KeyId": self.key_id,
+            "CiphertextBlob": content,
+        })).await?;
+        let plaintext = STANDARD
+            .decode(decrypted.plaintext)
+            .map_err(|e| Error::KMSInvokeError(format!("invalid plaintext returned by aws kms: {}", e)))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_support::serve;
+
+    #[test]
+    fn test_signature_v4() {
+        //example from the AWS signature version 4 documentation
+        let mut headers = vec![
+            ("host".to_string(), "iam.amazonaws.com".to_string()),
+            ("content-type".to_string(), "application/x-www-form-urlencoded; charset=utf-8".to_string()),
+            ("x-amz-date".to_string(), "20150830T123600Z".to_string()),
+        ];
+        let authorization = authorization(
+            "AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "us-east-1", "iam", "20150830T123600Z",
+            "GET", "/", "Action=ListUsers&Version=2010-05-08", &mut headers, b"").unwrap();
+        assert_eq!(authorization, "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+            SignedHeaders=content-type;host;x-amz-date, \
+            Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7");
+    }
+
+    #[tokio::test]
+    async fn test_encrypt_decrypt() {
+        let address = serve(3, "application/json", |request| {
+            //the stand-in verifies the signature with the same secret key
+            let authorization_header = request.headers.get("authorization").unwrap();
+            let signed_headers = authorization_header.split("SignedHeaders=").nth(1).unwrap().split(',').next().unwrap();
+            let mut headers: Vec<(String, String)> = signed_headers.split(';')
+                .map(|name| (name.to_string(), request.headers.get(name).cloned().unwrap_or_default()))
+                .collect();
+            let expected = authorization(
+                "access", "secret", "eu-west-1", SERVICE, request.headers.get("x-amz-date").unwrap(),
+                &request.method, &request.path, "", &mut headers, &request.body).unwrap();
+            if &expected != authorization_header || request.headers.get("x-amz-security-token").map(|t| t.as_str()) != Some("session") {
+                return (400, json!({"__type": "InvalidSignatureException"}).to_string());
+            }
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            assert_eq!(body["KeyId"], "alias/signatrust");
+            match request.headers.get("x-amz-target").unwrap().as_str() {
+                "TrentService.Encrypt" => {
+                    let plaintext = STANDARD.decode(body["Plaintext"].as_str().unwrap()).unwrap();
+                    (200, json!({"CiphertextBlob": STANDARD.encode([b"wrapped:".as_slice(), &plaintext].concat())}).to_string())
+                }
+                "TrentService.Decrypt" => {
+                    let ciphertext = STANDARD.decode(body["CiphertextBlob"].as_str().unwrap()).unwrap();
+                    match ciphertext.strip_prefix(b"wrapped:") {
+                        Some(plaintext) => (200, json!({"Plaintext": STANDARD.encode(plaintext)}).to_string()),
+                        None => (400, json!({"__type": "InvalidCiphertextException"}).to_string()),
+                    }
+                }
+                _ => (400, json!({"__type": "UnknownOperationException"}).to_string()),
+            }
+        });
+        let config = HashMap::from([
+            ("endpoint", address.as_str()), ("region", "eu-west-1"), ("key_id", "alias/signatrust"),
+            ("access_key_id", "access"), ("secret_access_key", "secret"), ("session_token", "session"),
+        ].map(|(key, value)| (key.to_string(), Value::from(value))));
+        let kms = AwsKMS::new(&config).unwrap();
+
+        let ciphertext = kms.encode("cluster key".to_string()).await.unwrap();
+        assert_ne!(ciphertext, STANDARD.encode("cluster key"));
+        assert_eq!(kms.decode(ciphertext).await.unwrap(), "cluster key");
+        assert!(matches!(kms.decode(STANDARD.encode("invalid")).await, Err(Error::KMSInvokeError(_))));
+    }
+}
@@ -1,5 +1,9 @@
 use crate::infra::kms::huaweicloud::HuaweiCloudKMS;
 use crate::infra::kms::dummy::DummyKMS;
+use crate::infra::kms::vault::VaultKMS;
+use crate::infra::kms::aws::AwsKMS;
+use crate::infra::kms::file::FileKMS;
+use crate::infra::kms::instrumented::InstrumentedKMS;
 use crate::domain::kms_provider::{KMSProvider, KMSType};
 use crate::util::error::{Result};
 use config::Value;
@@ -19,9 +23,13 @@ impl KMSProviderFactory {
                 .as_str(),
         )?;
         info!("kms provider configured with {:?}", kms_type);
-        match kms_type {
-            KMSType::HuaweiCloud => Ok(Box::new(HuaweiCloudKMS::new(config)?)),
-            KMSType::Dummy => Ok(Box::new(DummyKMS::new(config)?)),
-        }
+        let provider: Box<dyn KMSProvider> = match kms_type {
+            KMSType::HuaweiCloud => Box::new(HuaweiCloudKMS::new(config)?),
+            KMSType::Dummy => Box::new(DummyKMS::new(config)?),
+            KMSType::Vault => Box::new(VaultKMS::new(config)?),
+            KMSType::Aws => Box::new(AwsKMS::new(config)?),
+            KMSType::File => Box::new(FileKMS::new(config)?),
+        };
+        Ok(Box::new(InstrumentedKMS::new(format!("{:?}", kms_type).to_lowercase(), provider)))
     }
 }
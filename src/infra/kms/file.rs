@@ -0,0 +1,377 @@
+use crate::domain::kms_provider::KMSProvider;
+use crate::util::error::{Error, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use config::Value;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::RwLock;
+use std::time::SystemTime;
+use uuid::Uuid;
+use secstr::*;
+
+pub const PASSPHRASE_ENV: &str = "SIGNATRUST_KMS_PASSPHRASE";
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const SALT_LENGTH: usize = 16;
+//additional authenticated data used for sealing the master keys and the cluster keys
+const MASTER_KEY_AAD: &str = "signatrust-file-kms-master-key";
+const CONTENT_AAD: &str = "signatrust-file-kms";
+
+//argon2id parameters used for new key files, they are recorded in the file for deriving later
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_MEMORY_KIB: u32 = 65536;
+const ARGON2_LANES: u32 = 4;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KdfParams {
+    algorithm: String,
+    salt: String,
+    iterations: u32,
+    memory_kib: u32,
+    lanes: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct MasterKeyEntry {
+    id: u32,
+    create_at: DateTime<Utc>,
+    //raw master key if kdf is absent, otherwise the master key sealed by the passphrase derived key
+    key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    kdf: Option<KdfParams>,
+    keys: Vec<MasterKeyEntry>,
+}
+
+fn seal(key: &[u8], content: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand_bytes(&mut nonce)?;
+    let mut tag = [0u8; TAG_LENGTH];
+    let encrypted = encrypt_aead(Cipher::aes_256_gcm(), key, Some(&nonce), aad, content, &mut tag)?;
+    Ok([nonce.as_slice(), &encrypted, &tag].concat())
+}
+
+fn open(key: &[u8], content: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if content.len() < NONCE_LENGTH + TAG_LENGTH {
+        return Err(Error::KMSInvokeError("sealed content is too short".to_string()));
+    }
+    let (nonce, rest) = content.split_at(NONCE_LENGTH);
+    let (encrypted, tag) = rest.split_at(rest.len() - TAG_LENGTH);
+    decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), aad, encrypted, tag)
+        .map_err(|_| Error::KMSInvokeError("failed to open sealed content, wrong key or passphrase".to_string()))
+}
+
+fn decode_base64(content: &str) -> Result<Vec<u8>> {
+    STANDARD.decode(content).map_err(|e| Error::KMSInvokeError(format!("invalid base64 content: {}", e)))
+}
+
+impl KdfParams {
+    fn new() -> Result<Self> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rand_bytes(&mut salt)?;
+        Ok(KdfParams {
+            algorithm: "argon2id".to_string(),
+            salt: STANDARD.encode(salt),
+            iterations: ARGON2_ITERATIONS,
+            memory_kib: ARGON2_MEMORY_KIB,
+            lanes: ARGON2_LANES,
+        })
+    }
+
+    fn derive(&self, passphrase: &SecUtf8) -> Result<SecVec<u8>> {
+        if self.algorithm != "argon2id" {
+            return Err(Error::UnsupportedTypeError(format!("{} key derivation function", self.algorithm)));
+        }
+        let params = Params::new(self.memory_kib, self.iterations, self.lanes, Some(KEY_LENGTH))
+            .map_err(|e| Error::ConfigError(format!("invalid argon2id parameters: {}", e)))?;
+        let mut key = vec![0u8; KEY_LENGTH];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.unsecure().as_bytes(), &decode_base64(&self.salt)?, &mut key)
+            .map_err(|e| Error::KMSInvokeError(format!("failed to derive key from passphrase: {}", e)))?;
+        Ok(SecVec::new(key))
+    }
+}
+
+impl KeyFile {
+    //the key file may contain the master key in plain text, reject it if it's accessible to other users
+    fn load(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        if metadata.mode() & 0o077 != 0 {
+            return Err(Error::ConfigError(format!(
+                "permissions {:o} of kms key file {} are too open, expect 0600", metadata.mode() & 0o777, path.display())));
+        }
+        if metadata.uid() != unsafe { libc::geteuid() } {
+            return Err(Error::ConfigError(format!(
+                "kms key file {} is not owned by the current user", path.display())));
+        }
+        Ok(serde_json::from_slice(&fs::read(path)?)?)
+    }
+
+    //write into a temporary file with 0600 permission first, then replace the original one atomically,
+    //the temporary file is named uniquely so that the one left by an interrupted save won't block others
+    fn save(&self, path: &Path) -> Result<()> {
+        let temp = path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+        let result = fs::OpenOptions::new().write(true).create_new(true).mode(0o600).open(&temp)
+            .and_then(|mut file| {
+                file.write_all(&serde_json::to_vec_pretty(self)?)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp, path));
+        if let Err(e) = result {
+            let _ = fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn wrapping_key(&self, passphrase: &Option<SecUtf8>) -> Result<Option<SecVec<u8>>> {
+        match (&self.kdf, passphrase) {
+            (None, _) => Ok(None),
+            (Some(kdf), Some(passphrase)) => Ok(Some(kdf.derive(passphrase)?)),
+            (Some(_), None) => Err(Error::ConfigError(format!(
+                "kms key file is protected by passphrase, please specify it via {}", PASSPHRASE_ENV))),
+        }
+    }
+
+    fn add_key(&mut self, passphrase: &Option<SecUtf8>) -> Result<u32> {
+        let mut key = vec![0u8; KEY_LENGTH];
+        rand_bytes(&mut key)?;
+        let id = self.keys.iter().map(|k| k.id).max().unwrap_or(0) + 1;
+        let key = match self.wrapping_key(passphrase)? {
+            Some(wrapping) => seal(wrapping.unsecure(), &key, MASTER_KEY_AAD.as_bytes())?,
+            None => key,
+        };
+        self.keys.push(MasterKeyEntry {
+            id,
+            create_at: Utc::now(),
+            key: STANDARD.encode(key),
+        });
+        Ok(id)
+    }
+
+    fn master_keys(&self, passphrase: &Option<SecUtf8>) -> Result<HashMap<u32, SecVec<u8>>> {
+        let wrapping = self.wrapping_key(passphrase)?;
+        let mut keys = HashMap::new();
+        for entry in self.keys.iter() {
+            let key = decode_base64(&entry.key)?;
+            let key = match &wrapping {
+                Some(wrapping) => open(wrapping.unsecure(), &key, MASTER_KEY_AAD.as_bytes())?,
+                None => key,
+            };
+            if key.len() != KEY_LENGTH {
+                return Err(Error::ConfigError(format!("invalid length of master key {} in kms key file", entry.id)));
+            }
+            keys.insert(entry.id, SecVec::new(key));
+        }
+        Ok(keys)
+    }
+}
+
+fn passphrase_from_env() -> Option<SecUtf8> {
+    env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()).map(SecUtf8::from)
+}
+
+/// Create the master key file for file kms, the master key is sealed with the argon2id derived key when
+/// the passphrase is specified in environment, otherwise the key file itself must be kept safely.
+pub fn create_key_file(path: &str) -> Result<()> {
+    create_key_file_with(Path::new(path), &passphrase_from_env())
+}
+
+fn create_key_file_with(path: &Path, passphrase: &Option<SecUtf8>) -> Result<()> {
+    if path.exists() {
+        return Err(Error::ParameterError(format!("kms key file {} already exists", path.display())));
+    }
+    let mut key_file = KeyFile {
+        version: 1,
+        kdf: match passphrase {
+            Some(_) => Some(KdfParams::new()?),
+            None => None,
+        },
+        keys: vec![],
+    };
+    let id = key_file.add_key(passphrase)?;
+    key_file.save(path)?;
+    info!("kms key file {} created with master key {}", path.display(), id);
+    Ok(())
+}
+
+/// Append a new master key into the key file, the new key is used for encoding while the previous keys
+/// are kept for decoding the cluster keys which are encoded before. Running servers reload the key file
+/// once it's modified, the new key is used since the next cluster key is created.
+pub fn rotate_key_file(path: &str) -> Result<()> {
+    rotate_key_file_with(Path::new(path), &passphrase_from_env())
+}
+
+fn rotate_key_file_with(path: &Path, passphrase: &Option<SecUtf8>) -> Result<()> {
+    let mut key_file = KeyFile::load(path)?;
+    //make sure the passphrase is correct before appending the new key
+    key_file.master_keys(passphrase)?;
+    let id = key_file.add_key(passphrase)?;
+    key_file.save(path)?;
+    info!("kms key file {} rotated, master key {} will be used for new cluster keys", path.display(), id);
+    Ok(())
+}
+
+/// File KMS wraps the cluster key with the master keys stored in the local key file, it's used for the
+/// deployments without cloud KMS, the encoded content is in the format of `<master key id>:<base64 content>`.
+pub struct FileKMS {
+    key_file: String,
+    passphrase: Option<SecUtf8>,
+    keys: RwLock<HashMap<u32, SecVec<u8>>>,
+    //inode and modification time of the loaded key file, the file is replaced by a new one when it's saved
+    modified: RwLock<Option<(u64, SystemTime)>>,
+}
+
+impl FileKMS {
+    pub fn new(config: &HashMap<String, Value>) -> Result<FileKMS> {
+        Self::with_passphrase(config, passphrase_from_env())
+    }
+
+    fn with_passphrase(config: &HashMap<String, Value>, passphrase: Option<SecUtf8>) -> Result<FileKMS> {
+        let key_file = config.get("key_file").map(|k| k.to_string()).unwrap_or_default();
+        if key_file.is_empty() {
+            return Err(Error::ConfigError("file kms requires 'key_file'".to_string()));
+        }
+        let kms = FileKMS {
+            key_file,
+            passphrase,
+            keys: RwLock::new(HashMap::new()),
+            modified: RwLock::new(None),
+        };
+        kms.reload()?;
+        if kms.keys.read()?.is_empty() {
+            return Err(Error::ConfigError(format!("no master key found in kms key file {}", kms.key_file)));
+        }
+        Ok(kms)
+    }
+
+    fn modified_time(&self) -> Option<(u64, SystemTime)> {
+        fs::metadata(&self.key_file).and_then(|metadata| Ok((metadata.ino(), metadata.modified()?))).ok()
+    }
+
+    //reload the key file in case it's rotated by other instance
+    fn reload(&self) -> Result<()> {
+        let modified = self.modified_time();
+        let keys = KeyFile::load(Path::new(&self.key_file))?.master_keys(&self.passphrase)?;
+        *self.keys.write()? = keys;
+        *self.modified.write()? = modified;
+        Ok(())
+    }
+
+    //the latest master key is used for encoding, reload the key file once it's rotated
+    fn reload_if_modified(&self) -> Result<()> {
+        let modified = self.modified_time();
+        if modified.is_some() && modified != *self.modified.read()? {
+            info!("kms key file {} is modified, reload master keys", self.key_file);
+            self.reload()?;
+        }
+        Ok(())
+    }
+
+    fn get_key(&self, id: u32) -> Result<Option<SecVec<u8>>> {
+        Ok(self.keys.read()?.get(&id).cloned())
+    }
+}
+
+#[async_trait]
+impl KMSProvider for FileKMS {
+    async fn encode(&self, content: String) -> Result<String> {
+        self.reload_if_modified()?;
+        let (id, key) = {
+            let keys = self.keys.read()?;
+            let (id, key) = keys.iter().max_by_key(|(id, _)| **id).ok_or_else(
+                || Error::KMSInvokeError("no master key available".to_string()))?;
+            (*id, key.clone())
+        };
+        let aad = format!("{}:{}", CONTENT_AAD, id);
+        Ok(format!("{}:{}", id, STANDARD.encode(seal(key.unsecure(), content.as_bytes(), aad.as_bytes())?)))
+    }
+
+    async fn decode(&self, content: String) -> Result<String> {
+        let (id, encoded) = content.split_once(':').ok_or_else(
+            || Error::KMSInvokeError("invalid content encoded by file kms".to_string()))?;
+        let id: u32 = id.parse().map_err(|_| Error::KMSInvokeError(format!("invalid master key id {}", id)))?;
+        let key = match self.get_key(id)? {
+            Some(key) => key,
+            None => {
+                self.reload()?;
+                self.get_key(id)?.ok_or_else(
+                    || Error::KMSInvokeError(format!("master key {} not found in kms key file", id)))?
+            }
+        };
+        let aad = format!("{}:{}", CONTENT_AAD, id);
+        Ok(String::from_utf8(open(key.unsecure(), &decode_base64(encoded)?, aad.as_bytes())?)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_master_key_sealed_with_passphrase() {
+        let passphrase = Some(SecUtf8::from("passphrase"));
+        //cheap parameters are recorded in the file, the unoptimized argon2 is slow in test
+        let mut kdf = KdfParams::new().unwrap();
+        kdf.memory_kib = 1024;
+        kdf.iterations = 1;
+        let mut key_file = KeyFile {
+            version: 1,
+            kdf: Some(kdf),
+            keys: vec![],
+        };
+        key_file.add_key(&passphrase).unwrap();
+        key_file.add_key(&passphrase).unwrap();
+        let keys = key_file.master_keys(&passphrase).unwrap();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains_key(&2));
+        assert!(key_file.master_keys(&Some(SecUtf8::from("wrong"))).is_err());
+        assert!(key_file.master_keys(&None).is_err());
+    }
+
+    //removed along with the key files even if the test fails
+    struct TempDir(std::path::PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key_file() {
+        let directory = TempDir(env::temp_dir().join(format!("signatrust-file-kms-{}", Uuid::new_v4())));
+        fs::create_dir_all(&directory.0).unwrap();
+        let path = directory.0.join("kms-key.json");
+        //temporary file left by an interrupted save
+        fs::write(directory.0.join("kms-key.tmp"), b"").unwrap();
+        //key file without passphrase, regardless of the environment
+        create_key_file_with(&path, &None).unwrap();
+        let config = HashMap::from([("key_file".to_string(), Value::from(path.to_str().unwrap()))]);
+        let kms = FileKMS::with_passphrase(&config, None).unwrap();
+        let before = kms.encode("cluster key".to_string()).await.unwrap();
+
+        rotate_key_file_with(&path, &None).unwrap();
+        let after = kms.encode("cluster key".to_string()).await.unwrap();
+        assert_eq!(fs::read_dir(&directory.0).unwrap().count(), 2);
+
+        assert!(before.starts_with("1:") && after.starts_with("2:"));
+        assert_eq!(kms.decode(before).await.unwrap(), "cluster key");
+        assert_eq!(kms.decode(after).await.unwrap(), "cluster key");
+    }
+}
@@ -0,0 +1,34 @@
+use crate::domain::kms_provider::KMSProvider;
+use crate::util::error::Result;
+use crate::util::metrics;
+use async_trait::async_trait;
+
+//count the invocations of the wrapped kms provider
+pub struct InstrumentedKMS {
+    provider: String,
+    inner: Box<dyn KMSProvider>,
+}
+
+impl InstrumentedKMS {
+    pub fn new(provider: String, inner: Box<dyn KMSProvider>) -> Self {
+        Self {
+            provider,
+            inner,
+        }
+    }
+}
+
+#[async_trait]
+impl KMSProvider for InstrumentedKMS {
+    async fn encode(&self, content: String) -> Result<String> {
+        let result = self.inner.encode(content).await;
+        metrics::KMS_REQUESTS.with_label_values(&[&self.provider, "encode", metrics::result_label(&result)]).inc();
+        result
+    }
+
+    async fn decode(&self, content: String) -> Result<String> {
+        let result = self.inner.decode(content).await;
+        metrics::KMS_REQUESTS.with_label_values(&[&self.provider, "decode", metrics::result_label(&result)]).inc();
+        result
+    }
+}
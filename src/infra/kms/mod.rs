@@ -1,3 +1,7 @@
 pub mod factory;
 pub mod huaweicloud;
 pub mod dummy;
+pub mod vault;
+pub mod aws;
+pub mod file;
+pub mod instrumented;
@@ -0,0 +1,309 @@
+use crate::domain::kms_provider::KMSProvider;
+use crate::util::error::{Error, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use config::Value;
+use reqwest::{Certificate, Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use secstr::*;
+
+static TOKEN_HEADER: &str = "X-Vault-Token";
+static NAMESPACE_HEADER: &str = "X-Vault-Namespace";
+
+#[derive(Debug, PartialEq)]
+enum AuthMethod {
+    Token,
+    AppRole,
+}
+
+impl FromStr for AuthMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "" | "token" => Ok(AuthMethod::Token),
+            "approle" => Ok(AuthMethod::AppRole),
+            _ => Err(Error::UnsupportedTypeError(format!("{} vault auth method", s))),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthInfo {
+    client_token: String,
+    lease_duration: u64,
+    renewable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthResponse {
+    auth: AuthInfo,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenLookup {
+    ttl: u64,
+    renewable: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataResponse<T> {
+    data: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct EncryptData {
+    ciphertext: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DecryptData {
+    plaintext: String,
+}
+
+struct VaultToken {
+    token: SecUtf8,
+    //None if the token never expires
+    renew_at: Option<Instant>,
+    renewable: bool,
+}
+
+impl VaultToken {
+    //renew the token when less than one third of the lease is left
+    fn new(token: SecUtf8, lease_duration: u64, renewable: bool) -> Self {
+        VaultToken {
+            token,
+            renew_at: match lease_duration {
+                0 => None,
+                lease => Some(Instant::now() + Duration::from_secs(lease * 2 / 3)),
+            },
+            renewable,
+        }
+    }
+
+    fn need_renew(&self) -> bool {
+        matches!(self.renew_at, Some(renew_at) if Instant::now() >= renew_at)
+    }
+}
+
+/// HashiCorp Vault KMS wraps and unwraps the cluster key with the transit secrets engine, the client
+/// authenticates with either the static token or AppRole, and the token is renewed before it expires.
+pub struct VaultKMS {
+    address: String,
+    mount_path: String,
+    key_name: String,
+    namespace: String,
+    auth_method: AuthMethod,
+    token: SecUtf8,
+    approle_path: String,
+    role_id: String,
+    secret_id: SecUtf8,
+    token_cache: Mutex<Option<VaultToken>>,
+    client: Client,
+}
+
+impl VaultKMS {
+    pub fn new(config: &HashMap<String, Value>) -> Result<VaultKMS> {
+        let get = |name: &str, default: &str| {
+            config
+                .get(name)
+                .map(|value| value.to_string())
+                .unwrap_or_else(|| default.to_string())
+        };
+        let mut builder = Client::builder().timeout(Duration::from_secs(get("timeout", "10").parse()?));
+        let ca_file = get("ca_file", "");
+        if !ca_file.is_empty() {
+            builder = builder.add_root_certificate(Certificate::from_pem(&fs::read(&ca_file)?)?);
+        }
+        let kms = VaultKMS {
+            address: get("address", "").trim_end_matches('/').to_string(),
+            mount_path: get("mount_path", "transit"),
+            key_name: get("key_name", ""),
+            namespace: get("namespace", ""),
+            auth_method: AuthMethod::from_str(&get("auth_method", "token"))?,
+            token: SecUtf8::from(get("token", "")),
+            approle_path: get("approle_path", "approle"),
+            role_id: get("role_id", ""),
+            secret_id: SecUtf8::from(get("secret_id", "")),
+            token_cache: Mutex::new(None),
+            client: builder.build()?,
+        };
+        if kms.address.is_empty() || kms.key_name.is_empty() {
+            return Err(Error::ConfigError("vault kms requires both 'address' and 'key_name'".to_string()));
+        }
+        Ok(kms)
+    }
+
+    fn request(&self, builder: RequestBuilder) -> RequestBuilder {
+        if self.namespace.is_empty() {
+            builder
+        } else {
+            builder.header(NAMESPACE_HEADER, &self.namespace)
+        }
+    }
+
+    async fn send<T: for<'de> Deserialize<'de>>(&self, builder: RequestBuilder, action: &str) -> Result<T> {
+        let res = self.request(builder).send().await?;
+        if res.status() != StatusCode::OK {
+            return Err(Error::KMSInvokeError(format!(
+                "failed to {} in vault, result {} {}",
+                action,
+                res.status(),
+                res.text().await.unwrap_or_default()
+            )));
+        }
+        Ok(res.json::<T>().await?)
+    }
+
+    async fn login(&self) -> Result<VaultToken> {
+        match self.auth_method {
+            AuthMethod::Token => {
+                let lookup: DataResponse<TokenLookup> = self.send(
+                    self.client
+                        .get(format!("{}/v1/auth/token/lookup-self", self.address))
+                        .header(TOKEN_HEADER, self.token.unsecure()),
+                    "lookup token").await?;
+                Ok(VaultToken::new(self.token.clone(), lookup.data.ttl, lookup.data.renewable))
+            }
+            AuthMethod::AppRole => {
+                let login: AuthResponse = self.send(
+                    self.client
+                        .post(format!("{}/v1/auth/{}/login", self.address, self.approle_path))
+                        .json(&json!({
+                            "role_id": self.role_id,
+                            "secret_id": self.secret_id.unsecure(),
+                        })),
+                    "login with approle").await?;
+                Ok(VaultToken::new(SecUtf8::from(login.auth.client_token), login.auth.lease_duration, login.auth.renewable))
+            }
+        }
+    }
+
+    async fn renew(&self, token: &SecUtf8) -> Result<VaultToken> {
+        let renewed: AuthResponse = self.send(
+            self.client
+                .post(format!("{}/v1/auth/token/renew-self", self.address))
+                .header(TOKEN_HEADER, token.unsecure())
+                .json(&json!({})),
+            "renew token").await?;
+        Ok(VaultToken::new(SecUtf8::from(renewed.auth.client_token), renewed.auth.lease_duration, renewed.auth.renewable))
+    }
+
+    //the renewable token is renewed in advance, login again when renewal fails or the token isn't renewable
+    async fn get_token(&self) -> Result<SecUtf8> {
+        let mut cache = self.token_cache.lock().await;
+        if let Some(token) = cache.as_ref() {
+            if !token.need_renew() {
+                return Ok(token.token.clone());
+            }
+            if token.renewable {
+                match self.renew(&token.token).await {
+                    Ok(renewed) => {
+                        let result = renewed.token.clone();
+                        *cache = Some(renewed);
+                        return Ok(result);
+                    }
+                    Err(e) => warn!("failed to renew vault token, login again: {}", e),
+                }
+            }
+        }
+        let token = self.login().await?;
+        let result = token.token.clone();
+        *cache = Some(token);
+        Ok(result)
+    }
+
+    async fn do_request<T: for<'de> Deserialize<'de>>(&self, operation: &str, body: &serde_json::Value) -> Result<T> {
+        let url = format!("{}/v1/{}/{}/{}", self.address, self.mount_path, operation, self.key_name);
+        let mut res = self
+            .request(self.client.post(&url).header(TOKEN_HEADER, self.get_token().await?.unsecure()).json(body))
+            .send()
+            .await?;
+        if res.status() == StatusCode::FORBIDDEN {
+            //token might be revoked or expired, login again
+            *self.token_cache.lock().await = None;
+            res = self
+                .request(self.client.post(&url).header(TOKEN_HEADER, self.get_token().await?.unsecure()).json(body))
+                .send()
+                .await?;
+        }
+        if res.status() != StatusCode::OK {
+            return Err(Error::KMSInvokeError(format!(
+                "unable to {} data in vault transit, result {} {}",
+                operation,
+                res.status(),
+                res.text().await.unwrap_or_default()
+            )));
+        }
+        Ok(res.json::<DataResponse<T>>().await?.data)
+    }
+}
+
+#[async_trait]
+impl KMSProvider for VaultKMS {
+    async fn encode(&self, content: String) -> Result<String> {
+        let encrypted: EncryptData = self.do_request("encrypt", &json!({
+            "plaintext": STANDARD.encode(content.as_bytes()),
+        })).await?;
+        Ok(encrypted.ciphertext)
+    }
+
+    async fn decode(&self, content: String) -> Result<String> {
+        let decrypted: DecryptData = self.do_request("decrypt", &json!({
+            "ciphertext": content,
+        })).await?;
+        let plaintext = STANDARD
+            .decode(decrypted.plaintext)
+            .map_err(|e| Error::KMSInvokeError(format!("invalid plaintext returned by vault: {}", e)))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_support::serve;
+
+    #[tokio::test]
+    async fn test_transit_with_approle() {
+        let mut logins = 0;
+        let address = serve(5, "application/json", move |request| {
+            assert_eq!((request.method.as_str(), request.headers.get("x-vault-namespace").unwrap().as_str()), ("POST", "signatrust"));
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            let token = request.headers.get("x-vault-token").cloned().unwrap_or_default();
+            match request.path.as_str() {
+                "/v1/auth/approle/login" => {
+                    assert_eq!((body["role_id"].as_str(), body["secret_id"].as_str()), (Some("role"), Some("secret")));
+                    logins += 1;
+                    (200, json!({"auth": {"client_token": format!("token-{}", logins), "lease_duration": 3600, "renewable": true}}).to_string())
+                }
+                "/v1/transit/encrypt/signatrust" if token == "token-1" => {
+                    (200, json!({"data": {"ciphertext": format!("vault:v1:{}", body["plaintext"].as_str().unwrap())}}).to_string())
+                }
+                //the first token is revoked before decrypting
+                "/v1/transit/decrypt/signatrust" if token == "token-2" => {
+                    let ciphertext = body["ciphertext"].as_str().unwrap();
+                    (200, json!({"data": {"plaintext": ciphertext.strip_prefix("vault:v1:").unwrap()}}).to_string())
+                }
+                _ => (403, json!({"errors": ["permission denied"]}).to_string()),
+            }
+        });
+        let config = HashMap::from([
+            ("address", address.as_str()), ("key_name", "signatrust"), ("namespace", "signatrust"),
+            ("auth_method", "approle"), ("role_id", "role"), ("secret_id", "secret"),
+        ].map(|(key, value)| (key.to_string(), Value::from(value))));
+        let kms = VaultKMS::new(&config).unwrap();
+
+        let ciphertext = kms.encode("cluster key".to_string()).await.unwrap();
+        assert!(ciphertext.starts_with("vault:v1:"));
+        assert_eq!(kms.decode(ciphertext).await.unwrap(), "cluster key");
+        assert!(VaultKMS::new(&HashMap::from([("address".to_string(), Value::from(address))])).is_err());
+    }
+}
@@ -3,3 +3,4 @@ pub mod database;
 pub mod kms;
 pub mod sign_plugin;
 pub mod sign_backend;
+pub mod timestamp;
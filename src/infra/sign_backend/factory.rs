@@ -5,6 +5,8 @@ use std::sync::{Arc, RwLock};
 use config::{Config};
 use crate::infra::database::pool::DbPool;
 use crate::infra::sign_backend::memory::backend::MemorySignBackend;
+use crate::infra::sign_backend::pkcs11::backend::Pkcs11SignBackend;
+use crate::infra::sign_backend::timestamp::TimestampSignBackend;
 
 pub struct SignBackendFactory {}
 
@@ -14,8 +16,10 @@ impl SignBackendFactory {
             config.read()?.get_string("sign-backend.type")?.as_str(),
         )?;
         info!("sign backend configured with plugin {:?}", engine_type);
-        match engine_type {
-            SignBackendType::Memory => Ok(Box::new(MemorySignBackend::new(config, db_pool).await?)),
-        }
+        let backend: Box<dyn SignBackend> = match engine_type {
+            SignBackendType::Memory => Box::new(MemorySignBackend::new(config.clone(), db_pool).await?),
+            SignBackendType::Pkcs11 => Box::new(Pkcs11SignBackend::new(config.clone())?),
+        };
+        Ok(Box::new(TimestampSignBackend::new(config, backend)?))
     }
 }
@@ -14,16 +14,24 @@ use crate::infra::encryption::engine::{EncryptionEngineWithClusterKey};
 use crate::domain::encryption_engine::EncryptionEngine;
 use crate::domain::datakey::entity::SecDataKey;
 use crate::infra::sign_plugin::signers::Signers;
-use crate::domain::datakey::entity::DataKey;
+use crate::domain::datakey::entity::{DataKey, CERTIFICATE_FIELD, PRIVATE_KEY_FIELD, PUBLIC_KEY_FIELD};
 use crate::util::error::Result;
+use crate::util::digest::DigestState;
 use async_trait::async_trait;
 use crate::infra::encryption::algorithm::factory::AlgorithmFactory;
+use crate::domain::sign_plugin::SignPlugins;
+use crate::util::cache::TTLCache;
+use crate::util::metrics;
+use std::time::Duration;
+use tokio::time::interval;
 
 
 /// Memory Sign Backend will perform all sensitive operations directly in host memory.
 pub struct MemorySignBackend {
     server_config: Arc<RwLock<Config>>,
-    engine: Box<dyn EncryptionEngine>
+    engine: Arc<dyn EncryptionEngine>,
+    //sign plugins loaded with the decrypted keys, indexed by data key id
+    plugins: TTLCache<i32, Arc<dyn SignPlugins>>,
 }
 
 impl MemorySignBackend {
@@ -32,6 +40,7 @@ impl MemorySignBackend {
     /// 2. initialize the cluster repo
     /// 2. initialize the encryption engine including the cluster key
     /// 3. initialize the signing plugins
+    /// 4. start rotating the cluster key periodically
     pub async fn new(server_config: Arc<RwLock<Config>>, db_pool: DbPool) -> Result<MemorySignBackend> {
         //initialize the kms backend
         let kms_provider = factory::KMSProviderFactory::new_provider(
@@ -53,32 +62,190 @@ impl MemorySignBackend {
             kms_provider
         )?;
         engine.initialize().await?;
+        let cache_ttl: u64 = server_config
+            .read()?
+            .get_string("sign-backend.plugin_cache_ttl")
+            .unwrap_or_else(|_| "600".to_string())
+            .parse()?;
+        let rotate_interval: u64 = engine_config
+            .get("rotate_check_interval")
+            .map(|interval| interval.to_string())
+            .unwrap_or_else(|| "3600".to_string())
+            .parse()?;
+        let engine: Arc<dyn EncryptionEngine> = Arc::new(engine);
+        MemorySignBackend::start_rotation(engine.clone(), Duration::from_secs(rotate_interval));
 
         Ok(MemorySignBackend {
             server_config,
-            engine: Box::new(engine),
+            engine,
+            plugins: TTLCache::new(Duration::from_secs(cache_ttl)),
         })
     }
+
+    //every server checks the expiration of cluster key and switches to the new one created by any server
+    fn start_rotation(engine: Arc<dyn EncryptionEngine>, period: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = interval(period);
+            //the first tick completes immediately while the engine has just been initialized
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Err(err) = engine.rotate().await {
+                    error!("failed to rotate cluster key: {}", err);
+                }
+            }
+        });
+    }
+
+    //decrypting the keys involves the kms requests, the loaded plugin is reused until it's expired or invalidated,
+    //the concurrent requests of a key missing in cache share a single loading
+    async fn get_plugin(&self, data_key: &DataKey) -> Result<Arc<dyn SignPlugins>> {
+        if let Some(plugin) = self.plugins.get(&data_key.id) {
+            metrics::SIGN_PLUGIN_CACHE.with_label_values(&["hit"]).inc();
+            return Ok(plugin);
+        }
+        metrics::SIGN_PLUGIN_CACHE.with_label_values(&["miss"]).inc();
+        self.plugins.get_or_load(data_key.id, || async {
+            let sec_key = SecDataKey::load(data_key, &self.engine).await?;
+            let plugin: Arc<dyn SignPlugins> = Arc::from(Signers::load_from_data_key(&data_key.key_type, &sec_key)?);
+            Ok(plugin)
+        }).await
+    }
+
+    //every field is encrypted with the associated data of its own, see `DataKey::associated_data`
+    async fn encode_keys(&self, data_key: &mut DataKey, private_key: Vec<u8>, public_key: Vec<u8>, certificate: Vec<u8>) -> Result<()> {
+        data_key.private_key = self.engine.encode(private_key, &data_key.associated_data(PRIVATE_KEY_FIELD)).await?;
+        data_key.public_key = self.engine.encode(public_key, &data_key.associated_data(PUBLIC_KEY_FIELD)).await?;
+        data_key.certificate = self.engine.encode(certificate, &data_key.associated_data(CERTIFICATE_FIELD)).await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl SignBackend for MemorySignBackend {
     async fn generate_keys(&self, data_key: &mut DataKey) -> Result<()> {
         let (private_key, public_key, certificate) = Signers::generate_keys(&data_key.key_type, &data_key.attributes)?;
-        data_key.private_key = self.engine.encode(private_key).await?;
-        data_key.public_key = self.engine.encode(public_key).await?;
-        data_key.certificate = self.engine.encode(certificate).await?;
+        self.encode_keys(data_key, private_key, public_key, certificate).await?;
+        Ok(())
+    }
+
+    async fn import_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        let (private_key, public_key, certificate, attributes) = Signers::import_keys(
+            &data_key.key_type,
+            data_key.private_key.clone(),
+            data_key.public_key.clone(),
+            data_key.certificate.clone(),
+            &data_key.attributes,
+        )?;
+        if let Some(create_at) = attributes.get("create_at") {
+            data_key.create_at = create_at.parse()?;
+        }
+        if let Some(expire_at) = attributes.get("expire_at") {
+            data_key.expire_at = expire_at.parse()?;
+        }
+        data_key.attributes = attributes;
+        self.encode_keys(data_key, private_key, public_key, certificate).await?;
         Ok(())
     }
 
     async fn sign(&self, data_key: &DataKey, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>> {
-        let sec_key = SecDataKey::load(data_key, &self.engine).await?;
-        Signers::load_from_data_key(&data_key.key_type, &sec_key)?.sign(content, options)
+        self.get_plugin(data_key).await?.sign(content, options)
+    }
+
+    async fn sign_digest(&self, data_key: &DataKey, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        self.get_plugin(data_key).await?.sign_digest(digest, options)
     }
 
     async fn decode_public_keys(&self, data_key: &mut DataKey) -> Result<()> {
-        data_key.public_key = self.engine.decode(data_key.public_key.clone()).await?;
-        data_key.certificate = self.engine.decode(data_key.certificate.clone()).await?;
+        data_key.public_key = self.engine.decode(
+            data_key.public_key.clone(), &data_key.associated_data(PUBLIC_KEY_FIELD)).await?;
+        data_key.certificate = self.engine.decode(
+            data_key.certificate.clone(), &data_key.associated_data(CERTIFICATE_FIELD)).await?;
+        Ok(())
+    }
+
+    async fn renew_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        let sec_key = SecDataKey::load(data_key, &self.engine).await?;
+        let (private_key, public_key, certificate) = Signers::load_from_data_key(
+            &data_key.key_type, &sec_key)?.renew_keys(&data_key.expire_at)?;
+        self.encode_keys(data_key, private_key, public_key, certificate).await?;
+        self.plugins.remove(&data_key.id);
+        Ok(())
+    }
+
+    async fn reencrypt_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        let sec_key = SecDataKey::load(data_key, &self.engine).await?;
+        self.encode_keys(
+            data_key,
+            sec_key.private_key.unsecure().to_vec(),
+            sec_key.public_key.unsecure().to_vec(),
+            sec_key.certificate.unsecure().to_vec()).await?;
+        self.plugins.remove(&data_key.id);
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn invalidate(&self, data_key: &DataKey) {
+        self.plugins.remove(&data_key.id);
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::application::datakey::DBKeyService;
+    use crate::domain::clusterkey::entity::SecClusterKey;
+    use crate::domain::clusterkey::repository::Repository as ClusterKeyRepository;
+    use crate::domain::datakey::repository::Repository as DataKeyRepository;
+    use crate::domain::encryptor::Algorithm;
+    use crate::infra::database::model::audit::repository::SignRecordRepository;
+    use crate::infra::database::model::datakey::repository::DataKeyRepository as DBDataKeyRepository;
+    use crate::infra::encryption::engine::ENVELOPE_MAGIC;
+    use crate::infra::kms::dummy::DummyKMS;
+    use crate::util::key;
+    use crate::util::test_support::{data_key, memory_pool};
+    use config::Value;
+
+    //encrypted by the previous release, the content is prefixed with the `{:04X}` cluster key id only
+    async fn legacy_encode(pool: &DbPool, content: &[u8]) -> Vec<u8> {
+        let cluster_key = repository::ClusterKeyRepository::new(pool.clone())
+            .get_latest(&Algorithm::Aes256GSM.to_string()).await.unwrap().unwrap();
+        let cluster_key = SecClusterKey::load(cluster_key, &DummyKMS {}).await.unwrap();
+        let mut result = key::decode_hex_string_to_u8(&format!("{:04X}", cluster_key.id));
+        result.extend(AlgorithmFactory::new_encryptor(Algorithm::Aes256GSM)
+            .encrypt(cluster_key.data.unsecure().to_vec(), content.to_vec(), &[]).unwrap());
+        result
+    }
+
+    #[tokio::test]
+    async fn test_reencrypt_legacy_keys() {
+        let pool = memory_pool().await;
+        let config = HashMap::from([("keep_in_days".to_string(), Value::from(180))]);
+        let mut engine = EncryptionEngineWithClusterKey::new(
+            repository::ClusterKeyRepository::new(pool.clone()),
+            AlgorithmFactory::new_encryptor(Algorithm::Aes256GSM),
+            &config,
+            Box::new(DummyKMS::new(&config).unwrap())).unwrap();
+        engine.initialize().await.unwrap();
+        let backend = Arc::new(MemorySignBackend {
+            server_config: Arc::new(RwLock::new(Config::default())),
+            engine: Arc::new(engine),
+            plugins: TTLCache::new(Duration::from_secs(600)),
+        });
+        let repository = DBDataKeyRepository::new(pool.clone());
+        let mut legacy = data_key("legacy-key");
+        legacy.private_key = legacy_encode(&pool, b"private").await;
+        legacy.public_key = legacy_encode(&pool, b"public").await;
+        legacy.certificate = legacy_encode(&pool, b"").await;
+        let legacy = repository.create(legacy).await.unwrap();
+
+        let service = DBKeyService::new(repository.clone(), backend.clone(), SignRecordRepository::new(pool));
+        assert_eq!(service.reencrypt_keys().await.unwrap(), 1);
+        let stored = repository.get_by_id(legacy.id).await.unwrap();
+        for field in [&stored.private_key, &stored.public_key, &stored.certificate] {
+            assert!(field.starts_with(&ENVELOPE_MAGIC));
+        }
+        let sec_key = SecDataKey::load(&stored, &backend.engine).await.unwrap();
+        assert_eq!(sec_key.private_key.unsecure(), b"private");
+        assert_eq!(sec_key.public_key.unsecure(), b"public");
+        assert!(sec_key.certificate.unsecure().is_empty());
+    }
+}
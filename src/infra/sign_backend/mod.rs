@@ -1,2 +1,4 @@
 pub mod factory;
 pub mod memory;
+pub mod pkcs11;
+pub mod timestamp;
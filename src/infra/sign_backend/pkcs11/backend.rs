@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use config::Config;
+use uuid::Uuid;
+
+use crate::domain::datakey::entity::{DataKey, SecDataKey};
+use crate::domain::sign_service::SignBackend;
+use crate::infra::sign_backend::pkcs11::token::{Pkcs11Key, Pkcs11Token};
+use crate::infra::sign_plugin::signers::Signers;
+use crate::util::digest::DigestState;
+use crate::util::error::{Error, Result};
+
+//the user pin is read from the environment if present, so that it doesn't have to be kept in configuration file
+pub const PIN_ENV: &str = "SIGNATRUST_PKCS11_PIN";
+
+/// PKCS#11 Sign Backend will generate and keep the private keys inside the PKCS#11 token, only the label
+/// of key pair is stored in database, the raw signing operation is performed by the token while the
+/// OpenPGP and CMS signatures are still assembled in host memory.
+pub struct Pkcs11SignBackend {
+    token: Arc<Pkcs11Token>,
+}
+
+impl Pkcs11SignBackend {
+    /// initialize process
+    /// 1. load the vendor's PKCS#11 module
+    /// 2. locate the token by label and login with user pin
+    pub fn new(server_config: Arc<RwLock<Config>>) -> Result<Pkcs11SignBackend> {
+        let config = server_config.read()?.get_table("pkcs11")?;
+        let get = |name: &str| config.get(name).map(|value| value.to_string()).filter(|value| !value.is_empty());
+        let pin = match env::var(PIN_ENV).ok().filter(|pin| !pin.is_empty()) {
+            Some(pin) => pin,
+            None => get("pin").ok_or_else(|| Error::ConfigError(
+                format!("pkcs11 user pin should be configured or specified via {}", PIN_ENV)))?,
+        };
+        let token = Pkcs11Token::new(
+            &get("library").ok_or_else(|| Error::ConfigError("pkcs11 library should be configured".to_string()))?,
+            &get("token_label").ok_or_else(|| Error::ConfigError("pkcs11 token label should be configured".to_string()))?,
+            &pin,
+        )?;
+        Ok(Pkcs11SignBackend {
+            token: Arc::new(token),
+        })
+    }
+
+    fn load_key(&self, data_key: &DataKey) -> Result<SecDataKey> {
+        let label = String::from_utf8(data_key.private_key.clone())
+            .map_err(|e| Error::KeyParseError(e.to_string()))?;
+        Ok(SecDataKey::load_external(data_key, Arc::new(Pkcs11Key::new(self.token.clone(), label))))
+    }
+}
+
+#[async_trait]
+impl SignBackend for Pkcs11SignBackend {
+    async fn generate_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        if data_key.attributes.get("key_type").map(|t| t.as_str()) != Some("rsa") {
+            return Err(Error::ParameterError(format!(
+                "pkcs11 sign backend only support rsa keys, {} key is requested",
+                data_key.attributes.get("key_type").cloned().unwrap_or_default())));
+        }
+        let bits = data_key.attributes.get("key_length").cloned().unwrap_or_default().parse()?;
+        let label = Uuid::new_v4().to_string();
+        self.token.generate_rsa_key_pair(&label, bits)?;
+        let key = Arc::new(Pkcs11Key::new(self.token.clone(), label.clone()));
+        match Signers::generate_external_keys(&data_key.key_type, &data_key.attributes, key) {
+            Ok((public_key, certificate)) => {
+                data_key.private_key = label.into_bytes();
+                data_key.public_key = public_key;
+                data_key.certificate = certificate;
+                Ok(())
+            }
+            Err(e) => {
+                self.token.destroy_key_pair(&label)?;
+                Err(e)
+            }
+        }
+    }
+
+    async fn import_keys(&self, _data_key: &mut DataKey) -> Result<()> {
+        Err(Error::UnsupportedTypeError("importing keys into pkcs11 sign backend".to_string()))
+    }
+
+    async fn sign(&self, data_key: &DataKey, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        Signers::load_from_data_key(&data_key.key_type, &self.load_key(data_key)?)?.sign(content, options)
+    }
+
+    async fn sign_digest(&self, data_key: &DataKey, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        Signers::load_from_data_key(&data_key.key_type, &self.load_key(data_key)?)?.sign_digest(digest, options)
+    }
+
+    //public key and certificate are not encrypted in pkcs11 sign backend
+    async fn decode_public_keys(&self, _data_key: &mut DataKey) -> Result<()> {
+        Ok(())
+    }
+
+    //the label of key pair stored as private key is unchanged
+    async fn renew_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        let (_, public_key, certificate) = Signers::load_from_data_key(
+            &data_key.key_type, &self.load_key(data_key)?)?.renew_keys(&data_key.expire_at)?;
+        data_key.public_key = public_key;
+        data_key.certificate = certificate;
+        Ok(())
+    }
+
+    //nothing is encrypted with cluster key in pkcs11 sign backend
+    async fn reencrypt_keys(&self, _data_key: &mut DataKey) -> Result<()> {
+        Ok(())
+    }
+
+    //nothing is cached, the private key never leaves the token
+    async fn invalidate(&self, _data_key: &DataKey) {}
+}
@@ -0,0 +1,234 @@
+//! Minimal subset of the PKCS#11 v2.40 C interface used by the pkcs11 sign backend.
+//! Reference: http://docs.oasis-open.org/pkcs11/pkcs11-base/v2.40/os/pkcs11-base-v2.40-os.html
+#![allow(non_camel_case_types, non_snake_case)]
+
+use std::os::raw::{c_uchar, c_ulong, c_void};
+
+pub type CK_BYTE = c_uchar;
+pub type CK_BBOOL = CK_BYTE;
+pub type CK_ULONG = c_ulong;
+pub type CK_RV = CK_ULONG;
+pub type CK_FLAGS = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_OBJECT_HANDLE = CK_ULONG;
+pub type CK_OBJECT_CLASS = CK_ULONG;
+pub type CK_KEY_TYPE = CK_ULONG;
+pub type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub type CK_MECHANISM_TYPE = CK_ULONG;
+pub type CK_USER_TYPE = CK_ULONG;
+
+pub const CK_TRUE: CK_BBOOL = 1;
+pub const CK_FALSE: CK_BBOOL = 0;
+
+pub const CKR_OK: CK_RV = 0x0;
+pub const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x100;
+pub const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x191;
+
+pub const CKF_OS_LOCKING_OK: CK_FLAGS = 0x2;
+pub const CKF_RW_SESSION: CK_FLAGS = 0x2;
+pub const CKF_SERIAL_SESSION: CK_FLAGS = 0x4;
+
+pub const CKU_USER: CK_USER_TYPE = 1;
+
+pub const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 0x2;
+pub const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 0x3;
+
+pub const CKK_RSA: CK_KEY_TYPE = 0x0;
+
+pub const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x0;
+pub const CKA_TOKEN: CK_ATTRIBUTE_TYPE = 0x1;
+pub const CKA_PRIVATE: CK_ATTRIBUTE_TYPE = 0x2;
+pub const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x3;
+pub const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x100;
+pub const CKA_ID: CK_ATTRIBUTE_TYPE = 0x102;
+pub const CKA_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x103;
+pub const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x108;
+pub const CKA_VERIFY: CK_ATTRIBUTE_TYPE = 0x10a;
+pub const CKA_MODULUS: CK_ATTRIBUTE_TYPE = 0x120;
+pub const CKA_MODULUS_BITS: CK_ATTRIBUTE_TYPE = 0x121;
+pub const CKA_PUBLIC_EXPONENT: CK_ATTRIBUTE_TYPE = 0x122;
+pub const CKA_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x162;
+
+pub const CKM_RSA_PKCS_KEY_PAIR_GEN: CK_MECHANISM_TYPE = 0x0;
+pub const CKM_RSA_PKCS: CK_MECHANISM_TYPE = 0x1;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CK_VERSION {
+    pub major: CK_BYTE,
+    pub minor: CK_BYTE,
+}
+
+#[repr(C)]
+pub struct CK_TOKEN_INFO {
+    pub label: [CK_BYTE; 32],
+    pub manufacturerID: [CK_BYTE; 32],
+    pub model: [CK_BYTE; 16],
+    pub serialNumber: [CK_BYTE; 16],
+    pub flags: CK_FLAGS,
+    pub ulMaxSessionCount: CK_ULONG,
+    pub ulSessionCount: CK_ULONG,
+    pub ulMaxRwSessionCount: CK_ULONG,
+    pub ulRwSessionCount: CK_ULONG,
+    pub ulMaxPinLen: CK_ULONG,
+    pub ulMinPinLen: CK_ULONG,
+    pub ulTotalPublicMemory: CK_ULONG,
+    pub ulFreePublicMemory: CK_ULONG,
+    pub ulTotalPrivateMemory: CK_ULONG,
+    pub ulFreePrivateMemory: CK_ULONG,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+    pub utcTime: [CK_BYTE; 16],
+}
+
+#[repr(C)]
+pub struct CK_ATTRIBUTE {
+    pub type_: CK_ATTRIBUTE_TYPE,
+    pub pValue: *mut c_void,
+    pub ulValueLen: CK_ULONG,
+}
+
+#[repr(C)]
+pub struct CK_MECHANISM {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub pParameter: *mut c_void,
+    pub ulParameterLen: CK_ULONG,
+}
+
+#[repr(C)]
+pub struct CK_C_INITIALIZE_ARGS {
+    pub CreateMutex: *mut c_void,
+    pub DestroyMutex: *mut c_void,
+    pub LockMutex: *mut c_void,
+    pub UnlockMutex: *mut c_void,
+    pub flags: CK_FLAGS,
+    pub pReserved: *mut c_void,
+}
+
+type Unused = Option<unsafe extern "C" fn()>;
+
+/// CK_FUNCTION_LIST, only the functions used are typed, the others are kept as placeholders
+/// to preserve the layout.
+#[repr(C)]
+pub struct CK_FUNCTION_LIST {
+    pub version: CK_VERSION,
+    pub C_Initialize: Option<unsafe extern "C" fn(pInitArgs: *mut c_void) -> CK_RV>,
+    pub C_Finalize: Option<unsafe extern "C" fn(pReserved: *mut c_void) -> CK_RV>,
+    pub C_GetInfo: Unused,
+    pub C_GetFunctionList: Unused,
+    pub C_GetSlotList: Option<unsafe extern "C" fn(
+        tokenPresent: CK_BBOOL,
+        pSlotList: *mut CK_SLOT_ID,
+        pulCount: *mut CK_ULONG,
+    ) -> CK_RV>,
+    pub C_GetSlotInfo: Unused,
+    pub C_GetTokenInfo: Option<unsafe extern "C" fn(slotID: CK_SLOT_ID, pInfo: *mut CK_TOKEN_INFO) -> CK_RV>,
+    pub C_GetMechanismList: Unused,
+    pub C_GetMechanismInfo: Unused,
+    pub C_InitToken: Unused,
+    pub C_InitPIN: Unused,
+    pub C_SetPIN: Unused,
+    pub C_OpenSession: Option<unsafe extern "C" fn(
+        slotID: CK_SLOT_ID,
+        flags: CK_FLAGS,
+        pApplication: *mut c_void,
+        Notify: *mut c_void,
+        phSession: *mut CK_SESSION_HANDLE,
+    ) -> CK_RV>,
+    pub C_CloseSession: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CloseAllSessions: Unused,
+    pub C_GetSessionInfo: Unused,
+    pub C_GetOperationState: Unused,
+    pub C_SetOperationState: Unused,
+    pub C_Login: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        userType: CK_USER_TYPE,
+        pPin: *const CK_BYTE,
+        ulPinLen: CK_ULONG,
+    ) -> CK_RV>,
+    pub C_Logout: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CreateObject: Unused,
+    pub C_CopyObject: Unused,
+    pub C_DestroyObject: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE, hObject: CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_GetObjectSize: Unused,
+    pub C_GetAttributeValue: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        hObject: CK_OBJECT_HANDLE,
+        pTemplate: *mut CK_ATTRIBUTE,
+        ulCount: CK_ULONG,
+    ) -> CK_RV>,
+    pub C_SetAttributeValue: Unused,
+    pub C_FindObjectsInit: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        pTemplate: *mut CK_ATTRIBUTE,
+        ulCount: CK_ULONG,
+    ) -> CK_RV>,
+    pub C_FindObjects: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        phObject: *mut CK_OBJECT_HANDLE,
+        ulMaxObjectCount: CK_ULONG,
+        pulObjectCount: *mut CK_ULONG,
+    ) -> CK_RV>,
+    pub C_FindObjectsFinal: Option<unsafe extern "C" fn(hSession: CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_EncryptInit: Unused,
+    pub C_Encrypt: Unused,
+    pub C_EncryptUpdate: Unused,
+    pub C_EncryptFinal: Unused,
+    pub C_DecryptInit: Unused,
+    pub C_Decrypt: Unused,
+    pub C_DecryptUpdate: Unused,
+    pub C_DecryptFinal: Unused,
+    pub C_DigestInit: Unused,
+    pub C_Digest: Unused,
+    pub C_DigestUpdate: Unused,
+    pub C_DigestKey: Unused,
+    pub C_DigestFinal: Unused,
+    pub C_SignInit: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        pMechanism: *mut CK_MECHANISM,
+        hKey: CK_OBJECT_HANDLE,
+    ) -> CK_RV>,
+    pub C_Sign: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        pData: *const CK_BYTE,
+        ulDataLen: CK_ULONG,
+        pSignature: *mut CK_BYTE,
+        pulSignatureLen: *mut CK_ULONG,
+    ) -> CK_RV>,
+    pub C_SignUpdate: Unused,
+    pub C_SignFinal: Unused,
+    pub C_SignRecoverInit: Unused,
+    pub C_SignRecover: Unused,
+    pub C_VerifyInit: Unused,
+    pub C_Verify: Unused,
+    pub C_VerifyUpdate: Unused,
+    pub C_VerifyFinal: Unused,
+    pub C_VerifyRecoverInit: Unused,
+    pub C_VerifyRecover: Unused,
+    pub C_DigestEncryptUpdate: Unused,
+    pub C_DecryptDigestUpdate: Unused,
+    pub C_SignEncryptUpdate: Unused,
+    pub C_DecryptVerifyUpdate: Unused,
+    pub C_GenerateKey: Unused,
+    pub C_GenerateKeyPair: Option<unsafe extern "C" fn(
+        hSession: CK_SESSION_HANDLE,
+        pMechanism: *mut CK_MECHANISM,
+        pPublicKeyTemplate: *mut CK_ATTRIBUTE,
+        ulPublicKeyAttributeCount: CK_ULONG,
+        pPrivateKeyTemplate: *mut CK_ATTRIBUTE,
+        ulPrivateKeyAttributeCount: CK_ULONG,
+        phPublicKey: *mut CK_OBJECT_HANDLE,
+        phPrivateKey: *mut CK_OBJECT_HANDLE,
+    ) -> CK_RV>,
+    pub C_WrapKey: Unused,
+    pub C_UnwrapKey: Unused,
+    pub C_DeriveKey: Unused,
+    pub C_SeedRandom: Unused,
+    pub C_GenerateRandom: Unused,
+    pub C_GetFunctionStatus: Unused,
+    pub C_CancelFunction: Unused,
+    pub C_WaitForSlotEvent: Unused,
+}
+
+pub type CK_C_GetFunctionList = unsafe extern "C" fn(ppFunctionList: *mut *const CK_FUNCTION_LIST) -> CK_RV;
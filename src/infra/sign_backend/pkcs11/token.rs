@@ -0,0 +1,389 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::Arc;
+
+use openssl::bn::BigNum;
+use openssl::pkey::PKey;
+use openssl::rsa::Rsa;
+
+use super::ffi::*;
+use crate::domain::sign_plugin::ExternalKey;
+use crate::util::error::{Error, Result};
+
+const RSA_PUBLIC_EXPONENT: [u8; 3] = [0x01, 0x00, 0x01];
+
+fn check(rv: CK_RV, operation: &str) -> Result<()> {
+    if rv != CKR_OK {
+        return Err(Error::PKCS11InvokeError(format!("{} failed with error code {:#x}", operation, rv)));
+    }
+    Ok(())
+}
+
+fn function<T>(function: Option<T>, name: &str) -> Result<T> {
+    function.ok_or_else(|| Error::PKCS11InvokeError(format!("{} is not provided by module", name)))
+}
+
+//attribute template, the values are kept alive until the template is dropped
+#[derive(Default)]
+struct Template {
+    values: Vec<(CK_ATTRIBUTE_TYPE, Vec<u8>)>,
+}
+
+impl Template {
+    fn bool(mut self, attribute: CK_ATTRIBUTE_TYPE, value: bool) -> Self {
+        self.values.push((attribute, vec![if value { CK_TRUE } else { CK_FALSE }]));
+        self
+    }
+
+    fn ulong(mut self, attribute: CK_ATTRIBUTE_TYPE, value: CK_ULONG) -> Self {
+        self.values.push((attribute, value.to_ne_bytes().to_vec()));
+        self
+    }
+
+    fn bytes(mut self, attribute: CK_ATTRIBUTE_TYPE, value: &[u8]) -> Self {
+        self.values.push((attribute, value.to_vec()));
+        self
+    }
+
+    fn attributes(&mut self) -> Vec<CK_ATTRIBUTE> {
+        self.values
+            .iter_mut()
+            .map(|(attribute, value)| CK_ATTRIBUTE {
+                type_: *attribute,
+                pValue: value.as_mut_ptr() as *mut c_void,
+                ulValueLen: value.len() as CK_ULONG,
+            })
+            .collect()
+    }
+}
+
+struct Session<'a> {
+    token: &'a Pkcs11Token,
+    handle: CK_SESSION_HANDLE,
+}
+
+impl<'a> Drop for Session<'a> {
+    fn drop(&mut self) {
+        if let Some(close) = self.token.functions().C_CloseSession {
+            unsafe { close(self.handle) };
+        }
+    }
+}
+
+/// PKCS#11 token loaded from the vendor's module, the keys inside the token are located by their labels.
+pub struct Pkcs11Token {
+    library: *mut c_void,
+    functions: *const CK_FUNCTION_LIST,
+    slot: CK_SLOT_ID,
+    //the login state is shared by all of the sessions, keep one session open to stay logged in
+    login_session: Option<CK_SESSION_HANDLE>,
+    //the module is only finalized by the token which initialized it, it may have been initialized by other token
+    //or library in the same process
+    initialized: bool,
+}
+
+//the module is initialized with CKF_OS_LOCKING_OK, and every operation uses its own session
+unsafe impl Send for Pkcs11Token {}
+unsafe impl Sync for Pkcs11Token {}
+
+impl Pkcs11Token {
+    pub fn new(library: &str, token_label: &str, pin: &str) -> Result<Self> {
+        let path = CString::new(library).map_err(|e| Error::ConfigError(e.to_string()))?;
+        let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(Error::PKCS11InvokeError(format!("failed to load module {}: {}", library, dl_error())));
+        }
+        let mut token = Self {
+            library: handle,
+            functions: ptr::null(),
+            slot: 0,
+            login_session: None,
+            initialized: false,
+        };
+        let symbol = unsafe { libc::dlsym(handle, c"C_GetFunctionList".as_ptr()) };
+        if symbol.is_null() {
+            return Err(Error::PKCS11InvokeError(format!("invalid module {}: {}", library, dl_error())));
+        }
+        let get_function_list: CK_C_GetFunctionList = unsafe { std::mem::transmute(symbol) };
+        check(unsafe { get_function_list(&mut token.functions) }, "C_GetFunctionList")?;
+        if token.functions.is_null() {
+            return Err(Error::PKCS11InvokeError(format!("invalid module {}: empty function list", library)));
+        }
+        let mut arguments = CK_C_INITIALIZE_ARGS {
+            CreateMutex: ptr::null_mut(),
+            DestroyMutex: ptr::null_mut(),
+            LockMutex: ptr::null_mut(),
+            UnlockMutex: ptr::null_mut(),
+            flags: CKF_OS_LOCKING_OK,
+            pReserved: ptr::null_mut(),
+        };
+        let initialize = function(token.functions().C_Initialize, "C_Initialize")?;
+        let rv = unsafe { initialize(&mut arguments as *mut CK_C_INITIALIZE_ARGS as *mut c_void) };
+        if rv != CKR_CRYPTOKI_ALREADY_INITIALIZED {
+            check(rv, "C_Initialize")?;
+            token.initialized = true;
+        }
+        token.slot = token.find_slot(token_label)?;
+        let session = token.open_session()?;
+        let login = function(token.functions().C_Login, "C_Login")?;
+        let rv = unsafe { login(session.handle, CKU_USER, pin.as_ptr(), pin.len() as CK_ULONG) };
+        if rv != CKR_USER_ALREADY_LOGGED_IN {
+            check(rv, "C_Login")?;
+        }
+        let handle = session.handle;
+        std::mem::forget(session);
+        token.login_session = Some(handle);
+        Ok(token)
+    }
+
+    fn functions(&self) -> &CK_FUNCTION_LIST {
+        unsafe { &*self.functions }
+    }
+
+    fn find_slot(&self, token_label: &str) -> Result<CK_SLOT_ID> {
+        let get_slot_list = function(self.functions().C_GetSlotList, "C_GetSlotList")?;
+        let get_token_info = function(self.functions().C_GetTokenInfo, "C_GetTokenInfo")?;
+        let mut count: CK_ULONG = 0;
+        check(unsafe { get_slot_list(CK_TRUE, ptr::null_mut(), &mut count) }, "C_GetSlotList")?;
+        let mut slots = vec![0; count as usize];
+        check(unsafe { get_slot_list(CK_TRUE, slots.as_mut_ptr(), &mut count) }, "C_GetSlotList")?;
+        slots.truncate(count as usize);
+        for slot in slots {
+            let mut info: CK_TOKEN_INFO = unsafe { std::mem::zeroed() };
+            check(unsafe { get_token_info(slot, &mut info) }, "C_GetTokenInfo")?;
+            //token label is padded with blank characters
+            if String::from_utf8_lossy(&info.label).trim_end() == token_label {
+                return Ok(slot);
+            }
+        }
+        Err(Error::PKCS11InvokeError(format!("token {} not found", token_label)))
+    }
+
+    fn open_session(&self) -> Result<Session<'_>> {
+        let open_session = function(self.functions().C_OpenSession, "C_OpenSession")?;
+        let mut handle: CK_SESSION_HANDLE = 0;
+        check(unsafe {
+            open_session(self.slot, CKF_SERIAL_SESSION | CKF_RW_SESSION, ptr::null_mut(), ptr::null_mut(), &mut handle)
+        }, "C_OpenSession")?;
+        Ok(Session { token: self, handle })
+    }
+
+    fn find_object(&self, session: &Session, class: CK_OBJECT_CLASS, label: &str) -> Result<CK_OBJECT_HANDLE> {
+        let find_init = function(self.functions().C_FindObjectsInit, "C_FindObjectsInit")?;
+        let find = function(self.functions().C_FindObjects, "C_FindObjects")?;
+        let find_final = function(self.functions().C_FindObjectsFinal, "C_FindObjectsFinal")?;
+        let mut template = Template::default()
+            .ulong(CKA_CLASS, class)
+            .bytes(CKA_LABEL, label.as_bytes());
+        let mut attributes = template.attributes();
+        check(unsafe { find_init(session.handle, attributes.as_mut_ptr(), attributes.len() as CK_ULONG) }, "C_FindObjectsInit")?;
+        let mut object: CK_OBJECT_HANDLE = 0;
+        let mut count: CK_ULONG = 0;
+        let rv = unsafe { find(session.handle, &mut object, 1, &mut count) };
+        check(unsafe { find_final(session.handle) }, "C_FindObjectsFinal")?;
+        check(rv, "C_FindObjects")?;
+        if count == 0 {
+            return Err(Error::PKCS11InvokeError(format!("key {} not found in token", label)));
+        }
+        Ok(object)
+    }
+
+    fn attribute(&self, session: &Session, object: CK_OBJECT_HANDLE, attribute: CK_ATTRIBUTE_TYPE) -> Result<Vec<u8>> {
+        let get_attribute = function(self.functions().C_GetAttributeValue, "C_GetAttributeValue")?;
+        let mut template = CK_ATTRIBUTE {
+            type_: attribute,
+            pValue: ptr::null_mut(),
+            ulValueLen: 0,
+        };
+        check(unsafe { get_attribute(session.handle, object, &mut template, 1) }, "C_GetAttributeValue")?;
+        let mut value = vec![0u8; template.ulValueLen as usize];
+        template.pValue = value.as_mut_ptr() as *mut c_void;
+        check(unsafe { get_attribute(session.handle, object, &mut template, 1) }, "C_GetAttributeValue")?;
+        value.truncate(template.ulValueLen as usize);
+        Ok(value)
+    }
+
+    /// generate rsa key pair inside the token, the private key is sensitive and can't be extracted
+    pub fn generate_rsa_key_pair(&self, label: &str, bits: CK_ULONG) -> Result<()> {
+        let generate = function(self.functions().C_GenerateKeyPair, "C_GenerateKeyPair")?;
+        let session = self.open_session()?;
+        let mut public_template = Template::default()
+            .ulong(CKA_CLASS, CKO_PUBLIC_KEY)
+            .ulong(CKA_KEY_TYPE, CKK_RSA)
+            .bool(CKA_TOKEN, true)
+            .bytes(CKA_LABEL, label.as_bytes())
+            .bytes(CKA_ID, label.as_bytes())
+            .bool(CKA_VERIFY, true)
+            .ulong(CKA_MODULUS_BITS, bits)
+            .bytes(CKA_PUBLIC_EXPONENT, &RSA_PUBLIC_EXPONENT);
+        let mut private_template = Template::default()
+            .ulong(CKA_CLASS, CKO_PRIVATE_KEY)
+            .ulong(CKA_KEY_TYPE, CKK_RSA)
+            .bool(CKA_TOKEN, true)
+            .bool(CKA_PRIVATE, true)
+            .bytes(CKA_LABEL, label.as_bytes())
+            .bytes(CKA_ID, label.as_bytes())
+            .bool(CKA_SIGN, true)
+            .bool(CKA_SENSITIVE, true)
+            .bool(CKA_EXTRACTABLE, false);
+        let mut public_attributes = public_template.attributes();
+        let mut private_attributes = private_template.attributes();
+        let mut mechanism = CK_MECHANISM {
+            mechanism: CKM_RSA_PKCS_KEY_PAIR_GEN,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        let (mut public_key, mut private_key): (CK_OBJECT_HANDLE, CK_OBJECT_HANDLE) = (0, 0);
+        check(unsafe {
+            generate(
+                session.handle,
+                &mut mechanism,
+                public_attributes.as_mut_ptr(),
+                public_attributes.len() as CK_ULONG,
+                private_attributes.as_mut_ptr(),
+                private_attributes.len() as CK_ULONG,
+                &mut public_key,
+                &mut private_key,
+            )
+        }, "C_GenerateKeyPair")
+    }
+
+    /// remove both of the public and private keys from the token
+    pub fn destroy_key_pair(&self, label: &str) -> Result<()> {
+        let destroy = function(self.functions().C_DestroyObject, "C_DestroyObject")?;
+        let session = self.open_session()?;
+        for class in [CKO_PUBLIC_KEY, CKO_PRIVATE_KEY] {
+            if let Ok(object) = self.find_object(&session, class, label) {
+                check(unsafe { destroy(session.handle, object) }, "C_DestroyObject")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// der encoded SubjectPublicKeyInfo of the rsa public key
+    pub fn public_key(&self, label: &str) -> Result<Vec<u8>> {
+        let session = self.open_session()?;
+        let object = self.find_object(&session, CKO_PUBLIC_KEY, label)?;
+        let modulus = self.attribute(&session, object, CKA_MODULUS)?;
+        let exponent = self.attribute(&session, object, CKA_PUBLIC_EXPONENT)?;
+        let rsa = Rsa::from_public_components(BigNum::from_slice(&modulus)?, BigNum::from_slice(&exponent)?)?;
+        Ok(PKey::from_rsa(rsa)?.public_key_to_der()?)
+    }
+
+    /// raw RSA PKCS#1 v1.5 signature, the data is expected to be the der encoded DigestInfo
+    pub fn sign(&self, label: &str, data: &[u8]) -> Result<Vec<u8>> {
+        let sign_init = function(self.functions().C_SignInit, "C_SignInit")?;
+        let sign = function(self.functions().C_Sign, "C_Sign")?;
+        let session = self.open_session()?;
+        let object = self.find_object(&session, CKO_PRIVATE_KEY, label)?;
+        let mut mechanism = CK_MECHANISM {
+            mechanism: CKM_RSA_PKCS,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        check(unsafe { sign_init(session.handle, &mut mechanism, object) }, "C_SignInit")?;
+        let mut length: CK_ULONG = 0;
+        check(unsafe { sign(session.handle, data.as_ptr(), data.len() as CK_ULONG, ptr::null_mut(), &mut length) }, "C_Sign")?;
+        let mut signature = vec![0u8; length as usize];
+        check(unsafe {
+            sign(session.handle, data.as_ptr(), data.len() as CK_ULONG, signature.as_mut_ptr(), &mut length)
+        }, "C_Sign")?;
+        signature.truncate(length as usize);
+        Ok(signature)
+    }
+}
+
+impl Drop for Pkcs11Token {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.functions.is_null() {
+                if let (Some(session), Some(close)) = (self.login_session, self.functions().C_CloseSession) {
+                    close(session);
+                }
+                if let (true, Some(finalize)) = (self.initialized, self.functions().C_Finalize) {
+                    finalize(ptr::null_mut());
+                }
+            }
+            libc::dlclose(self.library);
+        }
+    }
+}
+
+fn dl_error() -> String {
+    let error = unsafe { libc::dlerror() };
+    if error.is_null() {
+        return "unknown error".to_string();
+    }
+    unsafe { CStr::from_ptr(error) }.to_string_lossy().to_string()
+}
+
+/// key pair held by the PKCS#11 token
+pub struct Pkcs11Key {
+    token: Arc<Pkcs11Token>,
+    label: String,
+}
+
+impl Pkcs11Key {
+    pub fn new(token: Arc<Pkcs11Token>, label: String) -> Self {
+        Self { token, label }
+    }
+}
+
+impl ExternalKey for Pkcs11Key {
+    fn public_key(&self) -> Result<Vec<u8>> {
+        self.token.public_key(&self.label)
+    }
+
+    fn sign_pkcs1(&self, digest_info: &[u8]) -> Result<Vec<u8>> {
+        self.token.sign(&self.label, digest_info)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::infra::sign_plugin::pkcs7::{digest_info, OID_SHA256};
+    use openssl::hash::{hash, MessageDigest};
+    use openssl::sign::Verifier;
+    use std::env;
+
+    //the tests run against an initialized SoftHSM2 token, for instance
+    //softhsm2-util --init-token --free --label signatrust --so-pin 12345678 --pin 123456
+    //and they are skipped unless SIGNATRUST_TEST_PKCS11_LIBRARY is specified
+    fn softhsm() -> Option<(String, String, String)> {
+        let library = env::var("SIGNATRUST_TEST_PKCS11_LIBRARY").ok()?;
+        Some((
+            library,
+            env::var("SIGNATRUST_TEST_PKCS11_TOKEN").unwrap_or_else(|_| "signatrust".to_string()),
+            env::var("SIGNATRUST_TEST_PKCS11_PIN").unwrap_or_else(|_| "123456".to_string()),
+        ))
+    }
+
+    //module initialization is process wide, the cases are performed sequentially in one test
+    #[test]
+    fn test_softhsm_token() {
+        let (library, label, pin) = match softhsm() {
+            Some(config) => config,
+            None => return,
+        };
+        //the module initialized by the failed token is finalized on its error path
+        assert!(Pkcs11Token::new(&library, "signatrust-missing-token", &pin).is_err());
+        let token = Pkcs11Token::new(&library, &label, &pin).unwrap();
+        assert!(token.initialized);
+        //the module initialized by the first token is kept after the other token is dropped
+        let other = Pkcs11Token::new(&library, &label, &pin).unwrap();
+        assert!(!other.initialized);
+        drop(other);
+
+        let key_label = format!("signatrust-test-{}", std::process::id());
+        token.generate_rsa_key_pair(&key_label, 2048).unwrap();
+        let public_key = PKey::public_key_from_der(&token.public_key(&key_label).unwrap()).unwrap();
+        let digest = hash(MessageDigest::sha256(), b"content").unwrap();
+        let signature = token.sign(&key_label, &digest_info(OID_SHA256, &digest)).unwrap();
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+        assert!(verifier.verify_oneshot(&signature, b"content").unwrap());
+        token.destroy_key_pair(&key_label).unwrap();
+        assert!(token.public_key(&key_label).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use config::Config;
+
+use crate::domain::datakey::entity::{DataKey, KeyType};
+use crate::domain::sign_service::SignBackend;
+use crate::infra::sign_plugin::pkcs7;
+use crate::domain::sign_plugin::{AUTHENTICODE, SIGN_TYPE};
+use crate::infra::timestamp::client::{TimestampClient, OID_SPC_RFC3161, OID_TIMESTAMP_TOKEN};
+use crate::util::digest::DigestState;
+use crate::util::error::{Error, Result};
+
+pub const TIMESTAMP: &str = "timestamp";
+
+/// Timestamp Sign Backend wraps the configured sign backend, when the `timestamp` sign option is
+/// specified, the RFC 3161 timestamp token of the x509 signature value is acquired from the configured
+/// TSA and embedded into the signature as unsigned attribute.
+pub struct TimestampSignBackend {
+    inner: Box<dyn SignBackend>,
+    client: Option<TimestampClient>,
+}
+
+impl TimestampSignBackend {
+    pub fn new(server_config: Arc<RwLock<Config>>, inner: Box<dyn SignBackend>) -> Result<TimestampSignBackend> {
+        let url = server_config.read()?.get_string("timestamp.tsa_url").unwrap_or_default();
+        let client = if url.is_empty() {
+            None
+        } else {
+            let timeout = server_config.read()?.get_string("timestamp.timeout").unwrap_or_else(|_| "10".to_string());
+            info!("rfc3161 timestamp authority configured with {}", url);
+            Some(TimestampClient::new(url, Duration::from_secs(timeout.parse()?))?)
+        };
+        Ok(TimestampSignBackend {
+            inner,
+            client,
+        })
+    }
+
+    //validate the timestamp option before signing, None if timestamp is not required
+    fn get_client(&self, data_key: &DataKey, options: &HashMap<String, String>) -> Result<Option<&TimestampClient>> {
+        if options.get(TIMESTAMP).map(|t| t.as_str()) != Some("true") {
+            return Ok(None);
+        }
+        if !matches!(data_key.key_type, KeyType::X509) {
+            return Err(Error::ParameterError(format!("timestamp is not supported for {} signature", data_key.key_type)));
+        }
+        match &self.client {
+            Some(client) => Ok(Some(client)),
+            None => Err(Error::TimestampError("timestamp authority is not configured on data server".to_string())),
+        }
+    }
+
+    async fn timestamp(&self, client: &TimestampClient, signature: Vec<u8>, options: &HashMap<String, String>) -> Result<Vec<u8>> {
+        let token = client.request_token(&pkcs7::signature_value(&signature)?).await?;
+        let oid = match options.get(SIGN_TYPE) {
+            Some(sign_type) if sign_type == AUTHENTICODE => OID_SPC_RFC3161,
+            _ => OID_TIMESTAMP_TOKEN,
+        };
+        pkcs7::add_unsigned_attribute(&signature, pkcs7::attribute(oid, token))
+    }
+}
+
+#[async_trait]
+impl SignBackend for TimestampSignBackend {
+    async fn generate_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        self.inner.generate_keys(data_key).await
+    }
+
+    async fn import_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        self.inner.import_keys(data_key).await
+    }
+
+    async fn sign(&self, data_key: &DataKey, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        let client = self.get_client(data_key, &options)?;
+        let signature = self.inner.sign(data_key, content, options.clone()).await?;
+        match client {
+            Some(client) => self.timestamp(client, signature, &options).await,
+            None => Ok(signature),
+        }
+    }
+
+    async fn sign_digest(&self, data_key: &DataKey, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        let client = self.get_client(data_key, &options)?;
+        let signature = self.inner.sign_digest(data_key, digest, options.clone()).await?;
+        match client {
+            Some(client) => self.timestamp(client, signature, &options).await,
+            None => Ok(signature),
+        }
+    }
+
+    async fn decode_public_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        self.inner.decode_public_keys(data_key).await
+    }
+
+    async fn renew_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        self.inner.renew_keys(data_key).await
+    }
+
+    async fn reencrypt_keys(&self, data_key: &mut DataKey) -> Result<()> {
+        self.inner.reencrypt_keys(data_key).await
+    }
+
+    async fn invalidate(&self, data_key: &DataKey) {
+        self.inner.invalidate(data_key).await
+    }
+}
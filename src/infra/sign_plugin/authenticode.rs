@@ -0,0 +1,173 @@
+use openssl::hash::{hash, MessageDigest};
+use openssl::sign::Verifier;
+use openssl::x509::X509Ref;
+
+use crate::infra::sign_plugin::pkcs7::{self, SigningKey, OID_CONTENT_TYPE, OID_MESSAGE_DIGEST, OID_SHA256};
+use crate::util::der;
+use crate::util::error::{Error, Result};
+
+const OID_SPC_INDIRECT_DATA: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 4];
+const OID_SPC_SP_OPUS_INFO: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 12];
+const OID_SPC_PE_IMAGE_DATA: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 2, 1, 15];
+const SHA256_DIGEST_LENGTH: usize = 32;
+// the same obsolete file link which sbsign and signtool put into the SpcPeImageData
+const OBSOLETE_LINK: &str = "<<<Obsolete>>>";
+
+/// Authenticode signature for PE/COFF images, the digest of the image is calculated on client side
+/// and wrapped into SpcIndirectDataContent, the result is a PKCS#7 SignedData which can be embedded
+/// into the certificate table of the image directly.
+/// Reference: https://download.microsoft.com/download/9/c/5/9c5b2167-8017-4bae-9fde-d599bac8184a/Authenticode_PE.docx
+pub struct AuthenticodeSigner<'a> {
+    certificate: &'a X509Ref,
+    private_key: SigningKey<'a>,
+}
+
+impl<'a> AuthenticodeSigner<'a> {
+    pub fn new(certificate: &'a X509Ref, private_key: SigningKey<'a>) -> Self {
+        Self {
+            certificate,
+            private_key,
+        }
+    }
+
+    pub fn sign(&self, image_digest: &[u8]) -> Result<Vec<u8>> {
+        if image_digest.len() != SHA256_DIGEST_LENGTH {
+            return Err(Error::ParameterError(format!(
+                "authenticode requires sha256 image digest, got {} bytes",
+                image_digest.len()
+            )));
+        }
+        let indirect_data = self.spc_indirect_data_content(image_digest);
+        //NOTE: the message digest of authenticode only covers the content of SpcIndirectDataContent
+        let message_digest = hash(MessageDigest::sha256(), der::content_of(&indirect_data))?;
+        let attributes = vec![
+            pkcs7::attribute(OID_CONTENT_TYPE, der::oid(OID_SPC_INDIRECT_DATA)),
+            pkcs7::attribute(OID_SPC_SP_OPUS_INFO, der::sequence(&[])),
+            pkcs7::attribute(OID_MESSAGE_DIGEST, der::octet_string(&message_digest)),
+        ];
+        let signature = self.private_key.sign_sha256_digest(
+            &hash(MessageDigest::sha256(), &der::set(&attributes))?)?;
+        let signer_info = der::sequence(&[
+            der::small_integer(1),
+            pkcs7::issuer_and_serial(self.certificate)?,
+            der::algorithm_identifier(OID_SHA256),
+            der::context_set(0, &attributes),
+            self.private_key.signature_algorithm()?,
+            der::octet_string(&signature),
+        ]);
+        Ok(pkcs7::signed_data(
+            1,
+            der::algorithm_identifier(OID_SHA256),
+            der::sequence(&[
+                der::oid(OID_SPC_INDIRECT_DATA),
+                der::context(0, true, &indirect_data),
+            ]),
+            &[self.certificate.to_der()?],
+            signer_info,
+        ))
+    }
+
+    fn spc_indirect_data_content(&self, image_digest: &[u8]) -> Vec<u8> {
+        let obsolete: Vec<u8> = OBSOLETE_LINK
+            .encode_utf16()
+            .flat_map(|c| c.to_be_bytes())
+            .collect();
+        //SpcPeImageData ::= SEQUENCE { flags BIT STRING, file [0] EXPLICIT SpcLink }
+        let pe_image_data = der::sequence(&[
+            der::tlv(der::TAG_BIT_STRING, &[0]),
+            der::context(0, true, &der::context(2, true, &der::context(0, false, &obsolete))),
+        ]);
+        der::sequence(&[
+            der::sequence(&[der::oid(OID_SPC_PE_IMAGE_DATA), pe_image_data]),
+            der::sequence(&[
+                der::algorithm_identifier(OID_SHA256),
+                der::octet_string(image_digest),
+            ]),
+        ])
+    }
+}
+
+/// verify the authenticode signature against the image digest and the signer's certificate
+pub fn verify(certificate: &X509Ref, signature: &[u8], image_digest: &[u8]) -> Result<()> {
+    let invalid = |reason: &str| Error::VerifyError(format!("invalid authenticode signature, {}", reason));
+    let (_, content_info, _) = der::next(signature)?;
+    let content_info = der::children(der::content_of(content_info))?;
+    if content_info.len() != 2 || content_info[0].1 != der::oid(pkcs7::OID_SIGNED_DATA).as_slice() {
+        return Err(invalid("not a signed data"));
+    }
+    let signed_data = der::children(der::content_of(der::content_of(content_info[1].1)))?;
+    //version, digest algorithms, content info, [certificates], [crls], signer infos
+    let (encapsulated, signer_infos) = match (signed_data.get(2), signed_data.last()) {
+        (Some(encapsulated), Some(signer_infos)) if signed_data.len() >= 4 => (encapsulated.1, signer_infos.1),
+        _ => return Err(invalid("incomplete signed data")),
+    };
+    let encapsulated = der::children(der::content_of(encapsulated))?;
+    if encapsulated.len() != 2 || encapsulated[0].1 != der::oid(OID_SPC_INDIRECT_DATA).as_slice() {
+        return Err(invalid("not a spc indirect data content"));
+    }
+    let indirect_data = der::content_of(encapsulated[1].1);
+    let digest_info = der::children(der::content_of(indirect_data))?;
+    let embedded_digest = match digest_info.get(1) {
+        Some((_, digest_info)) => der::children(der::content_of(digest_info))?
+            .get(1).map(|digest| der::content_of(digest.1)).ok_or_else(|| invalid("missing image digest"))?,
+        None => return Err(invalid("missing image digest")),
+    };
+    if embedded_digest != image_digest {
+        return Err(Error::VerifyError("image digest mismatched".to_string()));
+    }
+    let signer_infos = der::children(der::content_of(signer_infos))?;
+    let signer_info = der::children(der::content_of(signer_infos.first().ok_or_else(|| invalid("missing signer info"))?.1))?;
+    if signer_info.len() < 6 || signer_info[1].1 != pkcs7::issuer_and_serial(certificate)?.as_slice() {
+        return Err(Error::VerifyError("image isn't signed by the certificate".to_string()));
+    }
+    //version, signer identifier, digest algorithm, signed attributes, signature algorithm, signature
+    let (attributes_tag, attributes) = signer_info[3];
+    if attributes_tag != der::TAG_CONTEXT_CONSTRUCTED {
+        return Err(invalid("missing signed attributes"));
+    }
+    let message_digest = pkcs7::attribute(
+        OID_MESSAGE_DIGEST,
+        der::octet_string(&hash(MessageDigest::sha256(), der::content_of(indirect_data))?));
+    let digest_matched = der::children(der::content_of(attributes))?
+        .iter()
+        .any(|(_, attribute)| *attribute == message_digest.as_slice());
+    if !digest_matched {
+        return Err(Error::VerifyError("message digest mismatched".to_string()));
+    }
+    //signed attributes are signed in the form of SET OF instead of the implicit tag
+    let mut signed_attributes = attributes.to_vec();
+    signed_attributes[0] = der::TAG_SET;
+    let public_key = certificate.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key)?;
+    if !verifier.verify_oneshot(der::content_of(signer_info[5].1), &signed_attributes)? {
+        return Err(Error::VerifyError("signature mismatched".to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_support::{certificate, rsa_key};
+
+    #[test]
+    fn test_sign_and_verify() {
+        let private_key = rsa_key();
+        let certificate = certificate("signatrust efi", &private_key, vec![]);
+        let image_digest = hash(MessageDigest::sha256(), b"pe image").unwrap().to_vec();
+        let signer = AuthenticodeSigner::new(&certificate, SigningKey::Memory(&private_key));
+        assert!(signer.sign(&image_digest[..20]).is_err());
+        let signature = signer.sign(&image_digest).unwrap();
+        verify(&certificate, &signature, &image_digest).unwrap();
+
+        let other_digest = hash(MessageDigest::sha256(), b"other image").unwrap().to_vec();
+        assert!(matches!(verify(&certificate, &signature, &other_digest), Err(Error::VerifyError(_))));
+        let other = self::certificate("other efi", &rsa_key(), vec![]);
+        assert!(verify(&other, &signature, &image_digest).is_err());
+        //flip the last byte of the signature value
+        let mut tampered = signature.clone();
+        *tampered.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(verify(&certificate, &tampered, &image_digest), Err(Error::VerifyError(_))));
+        assert!(verify(&certificate, &[0x30, 0x00], &image_digest).is_err());
+    }
+}
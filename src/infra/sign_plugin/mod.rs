@@ -1,3 +1,6 @@
 pub mod openpgp;
 pub mod x509;
 pub mod signers;
+pub mod authenticode;
+pub mod pkcs7;
+pub mod timestamp_token;
@@ -1,15 +1,17 @@
-use crate::domain::sign_plugin::SignPlugins;
+use crate::domain::sign_plugin::{ExternalKey, SignPlugins};
+use crate::infra::sign_plugin::pkcs7;
 
 use crate::util::error::{Error, Result};
-use chrono::{DateTime, Utc};
-use pgp::composed::signed_key::{SignedSecretKey, SignedPublicKey};
-use pgp::composed::{key::SecretKeyParamsBuilder, KeyType};
-use pgp::crypto::{hash::HashAlgorithm, sym::SymmetricKeyAlgorithm};
+use chrono::{DateTime, SubsecRound, TimeZone, Utc};
+use pgp::composed::signed_key::{SignedKeyDetails, SignedSecretKey, SignedSecretSubKey, SignedPublicKey, SignedPublicSubKey};
+use pgp::composed::{key::{KeyDetails, SecretKeyParamsBuilder}, KeyType};
+use pgp::crypto::{hash::{HashAlgorithm, Hasher}, public_key::PublicKeyAlgorithm, sym::SymmetricKeyAlgorithm};
 use pgp::packet::SignatureConfig;
 use pgp::packet::*;
 
-use pgp::types::KeyTrait;
-use pgp::types::{CompressionAlgorithm, SecretKeyTrait};
+use pgp::types::{KeyId, KeyTrait, KeyVersion, Mpi, PublicKeyTrait, SecretKeyRepr};
+use pgp::types::{CompressionAlgorithm, PublicParams, SecretKeyTrait, SecretParams};
+use pgp::ser::Serialize;
 use pgp::Deserializable;
 use serde::Deserialize;
 use smallvec::*;
@@ -18,13 +20,20 @@ use std::collections::HashMap;
 
 use std::io::{Cursor};
 use std::str::from_utf8;
+use std::sync::Arc;
+
+use openssl::pkey::PKey;
+use rand::{CryptoRng, Rng};
 
 
 use validator::{Validate, ValidationError};
 use pgp::composed::StandaloneSignature;
-use crate::domain::datakey::entity::SecDataKey;
+use crate::domain::datakey::entity::{SecDataKey, IMPORT_PASSPHRASE};
+use crate::util::digest::{DigestAlgorithm, DigestState};
 
 const DETACHED_SIGNATURE: &str = "detached";
+//the max datetime supported by database, used for keys without expiration time
+const NEVER_EXPIRE: &str = "9999-12-31T23:59:59+00:00";
 
 #[derive(Debug, Validate, Deserialize)]
 pub struct PgpKeyGenerationParameter {
@@ -44,14 +53,14 @@ pub struct PgpKeyGenerationParameter {
 
 impl PgpKeyGenerationParameter {
     pub fn get_key(&self) -> Result<KeyType> {
-        return match self.key_type.as_str() {
+        match self.key_type.as_str() {
             "rsa" => Ok(KeyType::Rsa(self.key_length.parse::<u32>()?)),
             "ecdh" => Ok(KeyType::ECDH),
             "eddsa" => Ok(KeyType::EdDSA),
             _ => Err(Error::ParameterError(
                 "invalid key type for openpgp".to_string(),
             )),
-        };
+        }
     }
 
     pub fn get_user_id(&self) -> String {
@@ -60,14 +69,14 @@ impl PgpKeyGenerationParameter {
 }
 
 fn validate_key_type(key_type: &str) -> std::result::Result<(), ValidationError> {
-    if !vec!["rsa", "ecdh", "eddsa"].contains(&key_type) {
+    if !["rsa", "ecdh", "eddsa"].contains(&key_type) {
         return Err(ValidationError::new("invalid key type"));
     }
     Ok(())
 }
 
 fn validate_key_size(key_size: &str) -> std::result::Result<(), ValidationError> {
-    if !vec!["2048", "3072", "4096"].contains(&key_size) {
+    if !["2048", "3072", "4096"].contains(&key_size) {
         return Err(ValidationError::new("invalid key size"));
     }
     Ok(())
@@ -88,12 +97,276 @@ fn validate_utc_time(expire: &str) -> std::result::Result<(), ValidationError> {
     Ok(())
 }
 
+fn parse_secret_key(private_key: &[u8]) -> Result<SignedSecretKey> {
+    let private = from_utf8(private_key).map_err(|e| Error::ParameterError(e.to_string()))?;
+    let (secret_key, _) = SignedSecretKey::from_string(private)
+        .map_err(|e| Error::ParameterError(format!("invalid openpgp secret key: {}", e)))?;
+    Ok(secret_key)
+}
+
+fn parse_public_key(public_key: &[u8]) -> Result<SignedPublicKey> {
+    let public = from_utf8(public_key).map_err(|e| Error::ParameterError(e.to_string()))?;
+    let (public_key, _) = SignedPublicKey::from_string(public)
+        .map_err(|e| Error::ParameterError(format!("invalid openpgp public key: {}", e)))?;
+    Ok(public_key)
+}
+
+//serialize the key packet with plain secret params, the encrypted params are unlocked with passphrase.
+fn plain_secret_key_body(
+    public_key: impl Serialize,
+    algorithm: PublicKeyAlgorithm,
+    secret_params: &SecretParams,
+    passphrase: &str,
+) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    public_key.to_writer(&mut body)?;
+    match secret_params {
+        SecretParams::Plain(plain) => plain.to_writer(&mut body)?,
+        SecretParams::Encrypted(encrypted) => encrypted
+            .unlock(|| passphrase.to_string(), algorithm)
+            .map_err(|_| Error::ParameterError("failed to unlock openpgp secret key with passphrase".to_string()))?
+            .to_writer(&mut body)?,
+    }
+    Ok(body)
+}
+
+//keys are always unlocked with empty password when signing, the passphrase protection of primary key
+//and subkeys is removed when importing, the signatures are kept since the public part is unchanged.
+fn remove_passphrase(secret_key: &SignedSecretKey, passphrase: &str) -> Result<SignedSecretKey> {
+    let primary = &secret_key.primary_key;
+    let primary_key = SecretKey::from_slice(primary.packet_version(), &plain_secret_key_body(
+        primary.public_key(), primary.algorithm(), primary.secret_params(), passphrase)?)?;
+    let mut secret_subkeys = Vec::new();
+    for subkey in secret_key.secret_subkeys.iter() {
+        let key = &subkey.key;
+        secret_subkeys.push(SignedSecretSubKey::new(
+            SecretSubkey::from_slice(key.packet_version(), &plain_secret_key_body(
+                key.public_key(), key.algorithm(), key.secret_params(), passphrase)?)?,
+            subkey.signatures.clone()));
+    }
+    Ok(SignedSecretKey::new(
+        primary_key, secret_key.details.clone(), secret_key.public_subkeys.clone(), secret_subkeys))
+}
+
+//extract the public key from secret key, all of the existing self signatures are kept.
+fn public_key_of(secret_key: &SignedSecretKey) -> SignedPublicKey {
+    let mut public_subkeys = secret_key.public_subkeys.clone();
+    public_subkeys.extend(secret_key.secret_subkeys.iter().map(
+        |subkey| SignedPublicSubKey::new(subkey.key.public_key(), subkey.signatures.clone())));
+    SignedPublicKey::new(secret_key.primary_key.public_key(), secret_key.details.clone(), public_subkeys)
+}
+
+//key expiration time is the number of seconds after the key creation time
+fn key_expiration(created_at: &DateTime<Utc>, expire_at: &DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let expiration = (*expire_at - *created_at).num_seconds();
+    if expiration <= 0 || expiration > u32::MAX as i64 {
+        return Err(Error::ParameterError(format!("invalid openpgp expiration time {}", expire_at)));
+    }
+    Ok(Utc.timestamp_opt(expiration, 0).unwrap())
+}
+
+//signature config based on the latest self signature, the other subpackets are kept while the creation
+//and expiration time are replaced.
+fn renewed_signature_config(
+    signatures: &[Signature],
+    default_type: SignatureType,
+    expiration: DateTime<Utc>,
+    secret_key: &impl SecretKeyTrait,
+) -> Result<SignatureConfig> {
+    let key_id = secret_key.key_id();
+    let latest = signatures
+        .iter()
+        .filter(|signature| signature.issuer() == Some(&key_id))
+        .max_by_key(|signature| signature.created().cloned());
+    let typ = latest.map(|signature| signature.typ()).unwrap_or(default_type);
+    let mut hashed_subpackets: Vec<Subpacket> = latest
+        .map(|signature| signature.config.hashed_subpackets.iter()
+            .filter(|subpacket| !matches!(subpacket, Subpacket::SignatureCreationTime(_) | Subpacket::KeyExpirationTime(_)))
+            .cloned()
+            .collect())
+        .unwrap_or_default();
+    hashed_subpackets.push(Subpacket::SignatureCreationTime(Utc::now().trunc_subsecs(0)));
+    hashed_subpackets.push(Subpacket::KeyExpirationTime(expiration));
+    Ok(SignatureConfigBuilder::default()
+        .typ(typ)
+        .pub_alg(secret_key.algorithm())
+        .hash_alg(HashAlgorithm::SHA2_256)
+        .hashed_subpackets(hashed_subpackets)
+        .unhashed_subpackets(vec![Subpacket::Issuer(key_id)])
+        .build()?)
+}
+
+//re-issue the self signatures of user ids with the new key expiration time, the other subpackets of the latest
+//self signature are kept and the certifications made by other keys are untouched.
+fn renew_self_signatures(
+    details: &SignedKeyDetails,
+    created_at: &DateTime<Utc>,
+    expire_at: &DateTime<Utc>,
+    secret_key: &impl SecretKeyTrait,
+) -> Result<SignedKeyDetails> {
+    let expiration = key_expiration(created_at, expire_at)?;
+    let key_id = secret_key.key_id();
+    let mut details = details.clone();
+    for user in details.users.iter_mut() {
+        let signature = renewed_signature_config(&user.signatures, SignatureType::CertGeneric, expiration, secret_key)?
+            .sign_certificate(secret_key, String::new, user.id.tag(), &user.id)?;
+        user.signatures.retain(|signature| signature.issuer() != Some(&key_id));
+        user.signatures.push(signature);
+    }
+    Ok(details)
+}
+
+//re-issue the binding signatures of subkeys so that they expire along with the primary key, the embedded
+//primary key binding signature of signing subkeys is kept since it doesn't cover the binding signature.
+fn renew_binding_signatures(
+    subkeys: &[SignedPublicSubKey],
+    expire_at: &DateTime<Utc>,
+    secret_key: &impl SecretKeyTrait,
+) -> Result<Vec<SignedPublicSubKey>> {
+    let key_id = secret_key.key_id();
+    let mut renewed = Vec::new();
+    for subkey in subkeys.iter() {
+        let expiration = key_expiration(subkey.key.created_at(), expire_at)?;
+        let signature = renewed_signature_config(&subkey.signatures, SignatureType::SubkeyBinding, expiration, secret_key)?
+            .sign_key_binding(secret_key, String::new, &subkey.key)?;
+        let mut signatures: Vec<Signature> = subkey.signatures.iter()
+            .filter(|signature| signature.issuer() != Some(&key_id))
+            .cloned()
+            .collect();
+        signatures.push(signature);
+        renewed.push(SignedPublicSubKey::new(subkey.key.clone(), signatures));
+    }
+    Ok(renewed)
+}
+
+//user id is in the format of 'name (comment) <email>'
+fn split_user_id(user_id: &str) -> (String, String) {
+    match (user_id.find('<'), user_id.rfind('>')) {
+        (Some(start), Some(end)) if start < end => (
+            user_id[..start].trim().to_string(),
+            user_id[start + 1..end].trim().to_string(),
+        ),
+        _ => (user_id.trim().to_string(), String::new()),
+    }
+}
+
+fn bits_of(value: &[u8]) -> usize {
+    match value.first() {
+        Some(first) => value.len() * 8 - first.leading_zeros() as usize,
+        None => 0,
+    }
+}
+
+//object identifiers of the hash algorithms used in DigestInfo
+fn hash_algorithm_oid(hash: HashAlgorithm) -> pgp::errors::Result<&'static [u64]> {
+    match hash {
+        HashAlgorithm::SHA1 => Ok(&[1, 3, 14, 3, 2, 26]),
+        HashAlgorithm::SHA2_256 => Ok(&[2, 16, 840, 1, 101, 3, 4, 2, 1]),
+        HashAlgorithm::SHA2_384 => Ok(&[2, 16, 840, 1, 101, 3, 4, 2, 2]),
+        HashAlgorithm::SHA2_512 => Ok(&[2, 16, 840, 1, 101, 3, 4, 2, 3]),
+        HashAlgorithm::SHA2_224 => Ok(&[2, 16, 840, 1, 101, 3, 4, 2, 4]),
+        _ => Err(pgp::errors::Error::Unimplemented(format!("{:?} for external key", hash))),
+    }
+}
+
+/// OpenPGP rsa key whose secret part is held by external device, the signature is created by
+/// the external device while the packets are still assembled by pgp.
+#[derive(Clone)]
+pub struct ExternalSecretKey {
+    public_key: PublicKey,
+    external_key: Arc<dyn ExternalKey>,
+}
+
+impl ExternalSecretKey {
+    pub fn new(public_key: PublicKey, external_key: Arc<dyn ExternalKey>) -> Self {
+        Self {
+            public_key,
+            external_key,
+        }
+    }
+}
+
+impl std::fmt::Debug for ExternalSecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExternalSecretKey").field("public_key", &self.public_key).finish()
+    }
+}
+
+impl KeyTrait for ExternalSecretKey {
+    fn fingerprint(&self) -> Vec<u8> {
+        self.public_key.fingerprint()
+    }
+
+    fn key_id(&self) -> KeyId {
+        self.public_key.key_id()
+    }
+
+    fn algorithm(&self) -> PublicKeyAlgorithm {
+        self.public_key.algorithm()
+    }
+}
+
+impl PublicKeyTrait for ExternalSecretKey {
+    fn verify_signature(&self, hash: HashAlgorithm, data: &[u8], sig: &[Mpi]) -> pgp::errors::Result<()> {
+        self.public_key.verify_signature(hash, data, sig)
+    }
+
+    fn encrypt<R: CryptoRng + Rng>(&self, rng: &mut R, plain: &[u8]) -> pgp::errors::Result<Vec<Mpi>> {
+        self.public_key.encrypt(rng, plain)
+    }
+
+    fn to_writer_old(&self, writer: &mut impl std::io::Write) -> pgp::errors::Result<()> {
+        self.public_key.to_writer_old(writer)
+    }
+}
+
+impl SecretKeyTrait for ExternalSecretKey {
+    type PublicKey = PublicKey;
+
+    fn unlock<F, G>(&self, _pw: F, _work: G) -> pgp::errors::Result<()>
+        where
+            F: FnOnce() -> String,
+            G: FnOnce(&SecretKeyRepr) -> pgp::errors::Result<()>,
+    {
+        Err(pgp::errors::Error::Message("external key can't be unlocked".to_string()))
+    }
+
+    fn create_signature<F>(&self, _key_pw: F, hash: HashAlgorithm, data: &[u8]) -> pgp::errors::Result<Vec<Mpi>>
+        where
+            F: FnOnce() -> String,
+    {
+        let signature = self.external_key
+            .sign_pkcs1(&pkcs7::digest_info(hash_algorithm_oid(hash)?, data))
+            .map_err(|e| pgp::errors::Error::Message(e.to_string()))?;
+        Ok(vec![Mpi::from_raw(signature)])
+    }
+
+    fn public_key(&self) -> Self::PublicKey {
+        self.public_key.clone()
+    }
+}
+
+enum PgpSecretKey {
+    Memory(Box<SignedSecretKey>),
+    External(ExternalSecretKey),
+}
+
 pub struct OpenPGPPlugin {
-    secret_key: SignedSecretKey,
+    secret_key: PgpSecretKey,
     public_key: SignedPublicKey,
     identity: String,
 }
 
+impl Hasher for DigestState {
+    fn update(&mut self, data: &[u8]) {
+        DigestState::update(self, data)
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.finalize()
+    }
+}
+
 impl OpenPGPPlugin {
     pub fn attributes_validate(attr: &HashMap<String, String>) -> Result<PgpKeyGenerationParameter> {
         let parameter: PgpKeyGenerationParameter =
@@ -103,16 +376,55 @@ impl OpenPGPPlugin {
             Err(e) => Err(Error::ParameterError(format!("{:?}", e))),
         }
     }
+
+    fn signature_config(&self) -> SignatureConfig {
+        let now = Utc::now();
+        SignatureConfig {
+            version: SignatureVersion::V4,
+            typ: SignatureType::Binary,
+            pub_alg: self.public_key.primary_key.algorithm(),
+            hash_alg: HashAlgorithm::SHA2_256,
+            issuer: Some(self.public_key.key_id()),
+            created: Some(now),
+            unhashed_subpackets: vec![],
+            hashed_subpackets: vec![
+                Subpacket::SignatureCreationTime(now),
+                Subpacket::Issuer(self.public_key.key_id()),
+            ],
+        }
+    }
+
+    fn serialize_signature(&self, signature_packet: Signature, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        //detached signature
+        if let Some(detached) = options.get(DETACHED_SIGNATURE) {
+            if detached == "true" {
+                let standard_signature = StandaloneSignature::new(signature_packet);
+                return Ok(standard_signature.to_armored_bytes(None)?)
+            }
+        }
+        let mut signature_bytes = Vec::with_capacity(1024);
+        let mut cursor = Cursor::new(&mut signature_bytes);
+        write_packet(&mut cursor, &signature_packet)
+            .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
+        Ok(signature_bytes)
+    }
 }
 
 impl SignPlugins for OpenPGPPlugin {
     fn new(db: &SecDataKey) -> Result<Self> {
-        let private = from_utf8(&db.private_key.unsecure()).map_err(|e| Error::KeyParseError(e.to_string()))?;
-        let (secret_key, _) =
-            SignedSecretKey::from_string(private).map_err(|e| Error::KeyParseError(e.to_string()))?;
-        let public = from_utf8(&db.public_key.unsecure()).map_err(|e| Error::KeyParseError(e.to_string()))?;
+        let public = from_utf8(db.public_key.unsecure()).map_err(|e| Error::KeyParseError(e.to_string()))?;
         let (public_key, _) =
             SignedPublicKey::from_string(public).map_err(|e| Error::KeyParseError(e.to_string()))?;
+        let secret_key = match &db.external_key {
+            Some(external_key) => PgpSecretKey::External(
+                ExternalSecretKey::new(public_key.primary_key.clone(), external_key.clone())),
+            None => {
+                let private = from_utf8(db.private_key.unsecure()).map_err(|e| Error::KeyParseError(e.to_string()))?;
+                let (secret_key, _) =
+                    SignedSecretKey::from_string(private).map_err(|e| Error::KeyParseError(e.to_string()))?;
+                PgpSecretKey::Memory(Box::new(secret_key))
+            }
+        };
         Ok(Self {
             secret_key,
             public_key,
@@ -121,11 +433,69 @@ impl SignPlugins for OpenPGPPlugin {
     }
 
     fn parse_attributes(
-        _private_key: Option<Vec<u8>>,
-        _public_key: Option<Vec<u8>>,
+        private_key: Option<Vec<u8>>,
+        public_key: Option<Vec<u8>>,
         _certificate: Option<Vec<u8>>,
-    ) -> HashMap<String, String> {
-        todo!()
+    ) -> Result<HashMap<String, String>> {
+        let public_key = match (public_key, private_key) {
+            (Some(public_key), _) if !public_key.is_empty() => parse_public_key(&public_key)?,
+            (_, Some(private_key)) if !private_key.is_empty() => public_key_of(&parse_secret_key(&private_key)?),
+            _ => return Err(Error::ParameterError("openpgp key is required to parse attributes".to_string())),
+        };
+        let mut attributes = HashMap::new();
+        let users = &public_key.details.users;
+        if let Some(user) = users.iter().find(|user| user.is_primary()).or_else(|| users.first()) {
+            let (name, email) = split_user_id(user.id.id());
+            attributes.insert("name".to_string(), name);
+            attributes.insert("email".to_string(), email);
+        }
+        let (key_type, key_length) = match public_key.primary_key.public_params() {
+            PublicParams::RSA { n, .. } => ("rsa", Some(bits_of(n.as_bytes()))),
+            PublicParams::DSA { p, .. } => ("dsa", Some(bits_of(p.as_bytes()))),
+            PublicParams::Elgamal { p, .. } => ("elgamal", Some(bits_of(p.as_bytes()))),
+            PublicParams::ECDSA { .. } => ("ecdsa", None),
+            PublicParams::ECDH { .. } => ("ecdh", None),
+            PublicParams::EdDSA { .. } => ("eddsa", None),
+        };
+        attributes.insert("key_type".to_string(), key_type.to_string());
+        if let Some(key_length) = key_length {
+            attributes.insert("key_length".to_string(), key_length.to_string());
+        }
+        attributes.insert("create_at".to_string(), public_key.primary_key.created_at().to_rfc3339());
+        attributes.insert("expire_at".to_string(), match public_key.expires_at() {
+            Some(expire_at) => expire_at.to_rfc3339(),
+            None => NEVER_EXPIRE.to_string(),
+        });
+        Ok(attributes)
+    }
+
+    fn import_keys(
+        private_key: Vec<u8>,
+        public_key: Vec<u8>,
+        _certificate: Vec<u8>,
+        value: &HashMap<String, String>,
+    ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let secret_key = parse_secret_key(&private_key)?;
+        secret_key.verify().map_err(|e| Error::ParameterError(format!("invalid openpgp secret key: {}", e)))?;
+        if !secret_key.is_signing_key() {
+            return Err(Error::ParameterError("openpgp primary key is not capable of signing".to_string()));
+        }
+        let secret_key = remove_passphrase(
+            &secret_key, value.get(IMPORT_PASSPHRASE).map(|p| p.as_str()).unwrap_or_default())?;
+        let public_key = if public_key.is_empty() {
+            public_key_of(&secret_key)
+        } else {
+            let public_key = parse_public_key(&public_key)?;
+            if public_key.fingerprint() != secret_key.fingerprint() {
+                return Err(Error::ParameterError("openpgp public key doesn't match the secret key".to_string()));
+            }
+            public_key
+        };
+        Ok((
+            secret_key.to_armored_bytes(None)?,
+            public_key.to_armored_bytes(None)?,
+            vec![],
+        ))
     }
 
     fn generate_keys(
@@ -159,39 +529,180 @@ impl SignPlugins for OpenPGPPlugin {
         ))
     }
 
+    fn generate_external_keys(
+        value: &HashMap<String, String>,
+        key: Arc<dyn ExternalKey>,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let parameter = OpenPGPPlugin::attributes_validate(value)?;
+        let rsa = PKey::public_key_from_der(&key.public_key()?)?.rsa()?;
+        let create_at: DateTime<Utc> = parameter.create_at.parse()?;
+        let primary_key = PublicKey::new(
+            Default::default(),
+            KeyVersion::V4,
+            PublicKeyAlgorithm::RSA,
+            create_at.trunc_subsecs(0),
+            None,
+            PublicParams::RSA {
+                n: Mpi::from_raw(rsa.n().to_vec()),
+                e: Mpi::from_raw(rsa.e().to_vec()),
+            },
+        )?;
+        let secret_key = ExternalSecretKey::new(primary_key.clone(), key);
+        let mut key_flags = KeyFlags::default();
+        key_flags.set_sign(true);
+        let details = KeyDetails::new(
+            UserId::from_str(Default::default(), &parameter.get_user_id()),
+            vec![],
+            vec![],
+            key_flags,
+            smallvec![SymmetricKeyAlgorithm::AES256,],
+            smallvec![HashAlgorithm::SHA2_256,],
+            smallvec![CompressionAlgorithm::ZLIB,],
+            None,
+        ).sign(&secret_key, String::new)?;
+        let signed_public_key = SignedPublicKey::new(primary_key, details, vec![]);
+        Ok((signed_public_key.to_armored_bytes(None)?, vec![]))
+    }
+
+    fn renew_keys(&self, expire_at: &DateTime<Utc>) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let primary_key = &self.public_key.primary_key;
+        let details = match &self.secret_key {
+            PgpSecretKey::Memory(secret_key) => renew_self_signatures(
+                &self.public_key.details, primary_key.created_at(), expire_at, secret_key.as_ref()),
+            PgpSecretKey::External(secret_key) => renew_self_signatures(
+                &self.public_key.details, primary_key.created_at(), expire_at, secret_key),
+        }?;
+        match &self.secret_key {
+            PgpSecretKey::Memory(secret_key) => {
+                let signing_key = secret_key.as_ref();
+                let public_subkeys = renew_binding_signatures(&self.public_key.public_subkeys, expire_at, signing_key)?;
+                let public_key = SignedPublicKey::new(primary_key.clone(), details.clone(), public_subkeys.clone());
+                //secret subkeys share the renewed binding signatures with their public parts
+                let mut secret_subkeys = Vec::new();
+                for subkey in secret_key.secret_subkeys.iter() {
+                    let renewed = public_subkeys.iter()
+                        .find(|public_subkey| public_subkey.key.fingerprint() == subkey.key.fingerprint())
+                        .map(|public_subkey| public_subkey.signatures.clone())
+                        .ok_or_else(|| Error::KeyParseError("openpgp subkey missing in public key".to_string()))?;
+                    secret_subkeys.push(SignedSecretSubKey::new(subkey.key.clone(), renewed));
+                }
+                let secret_key = SignedSecretKey::new(
+                    secret_key.primary_key.clone(), details,
+                    renew_binding_signatures(&secret_key.public_subkeys, expire_at, signing_key)?, secret_subkeys);
+                Ok((secret_key.to_armored_bytes(None)?, public_key.to_armored_bytes(None)?, vec![]))
+            }
+            PgpSecretKey::External(secret_key) => {
+                let public_subkeys = renew_binding_signatures(&self.public_key.public_subkeys, expire_at, secret_key)?;
+                let public_key = SignedPublicKey::new(primary_key.clone(), details, public_subkeys);
+                Ok((vec![], public_key.to_armored_bytes(None)?, vec![]))
+            }
+        }
+    }
+
     fn sign(&self, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>> {
         let passwd_fn = String::new;
-        let now = Utc::now();
-        let sig_cfg = SignatureConfig {
-            version: SignatureVersion::V4,
-            typ: SignatureType::Binary,
-            pub_alg: self.public_key.primary_key.algorithm(),
-            hash_alg: HashAlgorithm::SHA2_256,
-            issuer: Some(self.secret_key.key_id()),
-            created: Some(now),
-            unhashed_subpackets: vec![],
-            hashed_subpackets: vec![
-                Subpacket::SignatureCreationTime(now),
-                Subpacket::Issuer(self.secret_key.key_id()),
-            ],
-        };
         let read_cursor = Cursor::new(content);
-        let signature_packet = sig_cfg
-            .sign(&self.secret_key, passwd_fn, read_cursor)
-            .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
-
+        let signature_packet = match &self.secret_key {
+            PgpSecretKey::Memory(secret_key) => self.signature_config().sign(secret_key.as_ref(), passwd_fn, read_cursor),
+            PgpSecretKey::External(secret_key) => self.signature_config().sign(secret_key, passwd_fn, read_cursor),
+        }.map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
+        self.serialize_signature(signature_packet, options)
+    }
 
-        //detached signature
-        if let Some(detached) = options.get(DETACHED_SIGNATURE) {
-            if detached == "true" {
-                let standard_signature = StandaloneSignature::new(signature_packet);
-                return Ok(standard_signature.to_armored_bytes(None)?)
-            }
+    fn sign_digest(&self, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        if digest.algorithm() != DigestAlgorithm::Sha256 {
+            return Err(Error::ParameterError(format!("openpgp doesn't support {} digest", digest.algorithm())));
         }
-        let mut signature_bytes = Vec::with_capacity(1024);
-        let mut cursor = Cursor::new(&mut signature_bytes);
-        write_packet(&mut cursor, &signature_packet)
+        let passwd_fn = String::new;
+        let sig_cfg = self.signature_config();
+        //resume from the content digest and finalize it with the openpgp signature trailer
+        let mut hasher = Box::new(digest);
+        let length = sig_cfg.hash_signature_data(&mut *hasher)
             .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
-        Ok(signature_bytes)
+        Hasher::update(&mut *hasher, &sig_cfg.trailer(length));
+        let hash = hasher.finish();
+        let signature = match &self.secret_key {
+            PgpSecretKey::Memory(secret_key) => secret_key.create_signature(passwd_fn, sig_cfg.hash_alg, &hash),
+            PgpSecretKey::External(secret_key) => secret_key.create_signature(passwd_fn, sig_cfg.hash_alg, &hash),
+        }.map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
+        let signature_packet = Signature::from_config(sig_cfg, [hash[0], hash[1]], signature);
+        self.serialize_signature(signature_packet, options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pgp::composed::key::SubkeyParamsBuilder;
+    use secstr::SecVec;
+
+    //eddsa primary key with an ecdh subkey
+    fn generate_key(passphrase: Option<&str>) -> Vec<u8> {
+        let passphrase = passphrase.map(|p| p.to_string());
+        let subkey = SubkeyParamsBuilder::default()
+            .key_type(KeyType::ECDH)
+            .can_encrypt(true)
+            .passphrase(passphrase.clone())
+            .build().unwrap();
+        let params = SecretKeyParamsBuilder::default()
+            .key_type(KeyType::EdDSA)
+            .can_create_certificates(false)
+            .can_sign(true)
+            .primary_user_id("signatrust <signatrust@example.com>".to_string())
+            .passphrase(passphrase.clone())
+            .subkey(subkey)
+            .expiration(Some(core::time::Duration::from_secs(3600)))
+            .build().unwrap();
+        params.generate().unwrap().sign(|| passphrase.unwrap_or_default()).unwrap().to_armored_bytes(None).unwrap()
+    }
+
+    #[test]
+    fn test_import_keys_with_passphrase() {
+        let private_key = generate_key(Some("signatrust"));
+        let options = |passphrase: &str| HashMap::from([(IMPORT_PASSPHRASE.to_string(), passphrase.to_string())]);
+        assert!(OpenPGPPlugin::import_keys(private_key.clone(), vec![], vec![], &HashMap::new()).is_err());
+        assert!(OpenPGPPlugin::import_keys(private_key.clone(), vec![], vec![], &options("wrong")).is_err());
+
+        let (secret_key, public_key, _) = OpenPGPPlugin::import_keys(
+            private_key.clone(), vec![], vec![], &options("signatrust")).unwrap();
+        let (secret_key, public_key) = (parse_secret_key(&secret_key).unwrap(), parse_public_key(&public_key).unwrap());
+        assert_eq!(secret_key.fingerprint(), parse_secret_key(&private_key).unwrap().fingerprint());
+        assert!(matches!(secret_key.primary_key.secret_params(), SecretParams::Plain(_)));
+        assert!(secret_key.secret_subkeys.iter().all(|subkey| matches!(subkey.key.secret_params(), SecretParams::Plain(_))));
+        secret_key.verify().unwrap();
+        //imported key can be used with the empty password
+        let digest = openssl::sha::sha256(b"signatrust");
+        let signature = secret_key.create_signature(String::new, HashAlgorithm::SHA2_256, &digest).unwrap();
+        public_key.verify_signature(HashAlgorithm::SHA2_256, &digest, &signature).unwrap();
+    }
+
+    #[test]
+    fn test_renew_keys() {
+        let (secret_key, public_key, _) = OpenPGPPlugin::import_keys(
+            generate_key(None), vec![], vec![], &HashMap::new()).unwrap();
+        let plugin = OpenPGPPlugin::new(&SecDataKey {
+            private_key: SecVec::new(secret_key),
+            public_key: SecVec::new(public_key),
+            certificate: SecVec::new(vec![]),
+            identity: "renew-key".to_string(),
+            external_key: None,
+        }).unwrap();
+        let expire_at = (Utc::now() + chrono::Duration::days(365)).trunc_subsecs(0);
+        let (secret_key, public_key, _) = plugin.renew_keys(&expire_at).unwrap();
+
+        let attributes = OpenPGPPlugin::parse_attributes(None, Some(public_key.clone()), None).unwrap();
+        assert_eq!(attributes.get("expire_at").unwrap(), &expire_at.to_rfc3339());
+        let attributes = OpenPGPPlugin::parse_attributes(Some(secret_key.clone()), None, None).unwrap();
+        assert_eq!(attributes.get("expire_at").unwrap(), &expire_at.to_rfc3339());
+        let (public_key, secret_key) = (parse_public_key(&public_key).unwrap(), parse_secret_key(&secret_key).unwrap());
+        public_key.verify().unwrap();
+        secret_key.verify().unwrap();
+        //subkeys expire along with the primary key
+        let subkey = &public_key.public_subkeys[0];
+        assert_eq!(subkey.signatures.len(), 1);
+        let expiration = *subkey.signatures[0].key_expiration_time().unwrap() - Utc.timestamp_opt(0, 0).unwrap();
+        assert_eq!(*subkey.key.created_at() + expiration, expire_at);
+        assert_eq!(secret_key.secret_subkeys[0].signatures, subkey.signatures);
+        assert!(plugin.renew_keys(&(Utc::now() - chrono::Duration::days(1))).is_err());
     }
 }
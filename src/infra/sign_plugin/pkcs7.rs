@@ -0,0 +1,186 @@
+use openssl::md::Md;
+use openssl::pkey::{Id, PKeyRef, Private};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::x509::X509Ref;
+
+use crate::domain::sign_plugin::ExternalKey;
+use crate::util::der;
+use crate::util::error::{Error, Result};
+
+pub const OID_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 1];
+pub const OID_SIGNED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 2];
+pub const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+pub const OID_CONTENT_TYPE: &[u64] = &[1, 2, 840, 113549, 1, 9, 3];
+pub const OID_MESSAGE_DIGEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 4];
+pub const OID_TST_INFO: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 1, 4];
+const OID_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+const OID_DSA_WITH_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 3, 2];
+const OID_SHA256_WITH_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 11];
+const TAG_UNSIGNED_ATTRIBUTES: u8 = 0xa1;
+
+/// private key used for signing, either loaded into host memory or held by external device
+#[derive(Clone, Copy)]
+pub enum SigningKey<'a> {
+    Memory(&'a PKeyRef<Private>),
+    External(&'a dyn ExternalKey),
+}
+
+impl<'a> SigningKey<'a> {
+    /// signature algorithm identifier used in SignerInfo
+    pub fn signature_algorithm(&self) -> Result<Vec<u8>> {
+        match self {
+            SigningKey::Memory(private_key) => match private_key.id() {
+                Id::RSA => Ok(der::algorithm_identifier(OID_RSA_ENCRYPTION)),
+                Id::DSA => Ok(der::sequence(&[der::oid(OID_DSA_WITH_SHA256)])),
+                _ => Err(Error::UnsupportedTypeError(
+                    "pkcs7 signature only support rsa and dsa keys".to_string(),
+                )),
+            },
+            //external keys are always rsa keys
+            SigningKey::External(_) => Ok(der::algorithm_identifier(OID_RSA_ENCRYPTION)),
+        }
+    }
+
+    /// signature algorithm identifier used in certificate, the digest is always sha256
+    pub fn certificate_signature_algorithm(&self) -> Result<Vec<u8>> {
+        match self {
+            SigningKey::Memory(private_key) => match private_key.id() {
+                Id::RSA => Ok(der::algorithm_identifier(OID_SHA256_WITH_RSA_ENCRYPTION)),
+                Id::DSA => Ok(der::sequence(&[der::oid(OID_DSA_WITH_SHA256)])),
+                _ => Err(Error::UnsupportedTypeError(
+                    "certificate signature only support rsa and dsa keys".to_string(),
+                )),
+            },
+            SigningKey::External(_) => Ok(der::algorithm_identifier(OID_SHA256_WITH_RSA_ENCRYPTION)),
+        }
+    }
+
+    /// sign the sha256 digest directly, the same result as signing the content with sha256
+    pub fn sign_sha256_digest(&self, digest: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            SigningKey::Memory(private_key) => {
+                let mut context = PkeyCtx::new(private_key)?;
+                context.sign_init()?;
+                context.set_signature_md(Md::sha256())?;
+                let mut signature = vec![];
+                context.sign_to_vec(digest, &mut signature)?;
+                Ok(signature)
+            }
+            SigningKey::External(key) => key.sign_pkcs1(&digest_info(OID_SHA256, digest)),
+        }
+    }
+}
+
+/// DigestInfo which is signed in RSA PKCS#1 v1.5 signature
+pub fn digest_info(digest_algorithm: &[u64], digest: &[u8]) -> Vec<u8> {
+    der::sequence(&[
+        der::algorithm_identifier(digest_algorithm),
+        der::octet_string(digest),
+    ])
+}
+
+/// SignerIdentifier in the form of IssuerAndSerialNumber
+pub fn issuer_and_serial(certificate: &X509Ref) -> Result<Vec<u8>> {
+    Ok(der::sequence(&[
+        certificate.issuer_name().to_der()?,
+        der::integer(&certificate.serial_number().to_bn()?.to_vec()),
+    ]))
+}
+
+/// wrap the SignedData into ContentInfo, version 3 is required when the content type is not id-data
+pub fn signed_data(
+    version: u64,
+    digest_algorithm: Vec<u8>,
+    encapsulated_content: Vec<u8>,
+    certificates: &[Vec<u8>],
+    signer_info: Vec<u8>,
+) -> Vec<u8> {
+    let mut fields = vec![
+        der::small_integer(version),
+        der::set(&[digest_algorithm]),
+        encapsulated_content,
+    ];
+    if !certificates.is_empty() {
+        fields.push(der::context_set(0, certificates));
+    }
+    fields.push(der::set(&[signer_info]));
+    der::sequence(&[
+        der::oid(OID_SIGNED_DATA),
+        der::context(0, true, &der::sequence(&fields)),
+    ])
+}
+
+/// Attribute with single value, used in both signed and unsigned attributes
+pub fn attribute(oid: &[u64], value: Vec<u8>) -> Vec<u8> {
+    der::sequence(&[der::oid(oid), der::set(&[value])])
+}
+
+/// signature value of the first SignerInfo inside of the ContentInfo
+pub fn signature_value(content_info: &[u8]) -> Result<Vec<u8>> {
+    let fields = signed_data_fields(content_info)?;
+    let signer_info = first_signer_info(&fields)?;
+    //the signature is the only OCTET STRING among SignerInfo fields
+    match signer_info.iter().find(|(tag, _)| *tag == der::TAG_OCTET_STRING) {
+        Some((_, signature)) => Ok(der::content_of(signature).to_vec()),
+        None => Err(Error::EncodeError("missing signature in pkcs7 signer info".to_string())),
+    }
+}
+
+/// append the attribute into `unsignedAttrs [1] IMPLICIT SET OF Attribute` of the first SignerInfo,
+/// the signature stays valid since unsigned attributes are not covered by it.
+pub fn add_unsigned_attribute(content_info: &[u8], attribute: Vec<u8>) -> Result<Vec<u8>> {
+    let mut fields = signed_data_fields(content_info)?;
+    let mut signer_info: Vec<Vec<u8>> = first_signer_info(&fields)?
+        .into_iter()
+        .map(|(_, element)| element.to_vec())
+        .collect();
+    let mut attributes = vec![attribute];
+    if let Some(last) = signer_info.last() {
+        if last[0] == TAG_UNSIGNED_ATTRIBUTES {
+            let existing = signer_info.pop().unwrap_or_default();
+            for (_, element) in der::children(der::content_of(&existing))? {
+                attributes.push(element.to_vec());
+            }
+        }
+    }
+    signer_info.push(der::context_set(1, &attributes));
+    //the first SignerInfo is replaced while the others are kept as they are
+    let signer_infos = fields.pop().unwrap_or_default();
+    let mut updated = vec![der::sequence(&signer_info)];
+    for (_, element) in der::children(der::content_of(signer_infos))?.into_iter().skip(1) {
+        updated.push(element.to_vec());
+    }
+    let mut fields: Vec<Vec<u8>> = fields.into_iter().map(|field| field.to_vec()).collect();
+    fields.push(der::set(&updated));
+    Ok(der::sequence(&[
+        der::oid(OID_SIGNED_DATA),
+        der::context(0, true, &der::sequence(&fields)),
+    ]))
+}
+
+//all of the SignedData fields inside of ContentInfo, the last one is signerInfos
+fn signed_data_fields(content_info: &[u8]) -> Result<Vec<&[u8]>> {
+    let invalid = || Error::EncodeError("invalid pkcs7 signed data".to_string());
+    let (_, content_info, _) = der::next(content_info)?;
+    let content_info = der::children(der::content_of(content_info))?;
+    if content_info.len() != 2 || content_info[0].1 != der::oid(OID_SIGNED_DATA).as_slice() {
+        return Err(invalid());
+    }
+    let (_, signed_data, _) = der::next(der::content_of(content_info[1].1))?;
+    let fields: Vec<&[u8]> = der::children(der::content_of(signed_data))?
+        .into_iter()
+        .map(|(_, element)| element)
+        .collect();
+    match fields.last() {
+        Some(signer_infos) if fields.len() >= 4 && signer_infos[0] == der::TAG_SET => Ok(fields),
+        _ => Err(invalid()),
+    }
+}
+
+fn first_signer_info<'a>(fields: &[&'a [u8]]) -> Result<Vec<(u8, &'a [u8])>> {
+    let signer_infos = der::children(der::content_of(fields.last().copied().unwrap_or_default()))?;
+    match signer_infos.first() {
+        Some((_, signer_info)) => der::children(der::content_of(signer_info)),
+        None => Err(Error::EncodeError("missing pkcs7 signer info".to_string())),
+    }
+}
@@ -1,12 +1,16 @@
-use crate::domain::sign_plugin::SignPlugins;
+use crate::domain::sign_plugin::{ExternalKey, SignPlugins};
 use crate::infra::sign_plugin::openpgp::OpenPGPPlugin;
 use crate::infra::sign_plugin::x509::X509Plugin;
 use crate::domain::datakey::entity::{KeyType};
 use crate::util::error::Result;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use crate::domain::datakey::entity::SecDataKey;
 
+//private key, public key, certificate and the attributes parsed from them
+type ImportedKeys = (Vec<u8>, Vec<u8>, Vec<u8>, HashMap<String, String>);
+
 pub struct Signers {}
 
 impl Signers {
@@ -29,4 +33,37 @@ impl Signers {
             KeyType::X509 => X509Plugin::generate_keys(value),
         }
     }
+
+    //generating the public key and certificate for the key pair held by external device.
+    pub fn generate_external_keys(
+        key_type: &KeyType,
+        value: &HashMap<String, String>,
+        key: Arc<dyn ExternalKey>,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        match key_type {
+            KeyType::OpenPGP => OpenPGPPlugin::generate_external_keys(value, key),
+            KeyType::X509 => X509Plugin::generate_external_keys(value, key),
+        }
+    }
+
+    //importing existing key, the key is normalized and the attributes are parsed from the key itself.
+    pub fn import_keys(
+        key_type: &KeyType,
+        private_key: Vec<u8>,
+        public_key: Vec<u8>,
+        certificate: Vec<u8>,
+        value: &HashMap<String, String>,
+    ) -> Result<ImportedKeys> {
+        let (private_key, public_key, certificate) = match key_type {
+            KeyType::OpenPGP => OpenPGPPlugin::import_keys(private_key, public_key, certificate, value)?,
+            KeyType::X509 => X509Plugin::import_keys(private_key, public_key, certificate, value)?,
+        };
+        let attributes = match key_type {
+            KeyType::OpenPGP => OpenPGPPlugin::parse_attributes(
+                Some(private_key.clone()), Some(public_key.clone()), None)?,
+            KeyType::X509 => X509Plugin::parse_attributes(
+                Some(private_key.clone()), Some(public_key.clone()), Some(certificate.clone()))?,
+        };
+        Ok((private_key, public_key, certificate, attributes))
+    }
 }
@@ -0,0 +1,166 @@
+use openssl::hash::{hash, MessageDigest};
+use openssl::x509::X509Ref;
+
+use crate::infra::sign_plugin::pkcs7::{self, SigningKey, OID_CONTENT_TYPE, OID_MESSAGE_DIGEST, OID_SHA256, OID_TST_INFO};
+use crate::util::der;
+use crate::util::error::{Error, Result};
+
+const OID_SIGNING_CERTIFICATE_V2: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 2, 47];
+const OID_EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+const OID_TIMESTAMPING: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 3, 8];
+const TAG_EXTENSIONS: u8 = 0xa3;
+
+/// RFC 3161 TimeStampToken, the TSTInfo built by TSA is wrapped into SignedData with the ESS
+/// signingCertificateV2 attribute which binds the token to the TSA certificate.
+/// Reference: https://www.rfc-editor.org/rfc/rfc3161 and https://www.rfc-editor.org/rfc/rfc5816
+pub struct TimestampTokenSigner<'a> {
+    certificate: &'a X509Ref,
+    private_key: SigningKey<'a>,
+}
+
+impl<'a> TimestampTokenSigner<'a> {
+    pub fn new(certificate: &'a X509Ref, private_key: SigningKey<'a>) -> Self {
+        Self {
+            certificate,
+            private_key,
+        }
+    }
+
+    pub fn sign(&self, tst_info: &[u8], include_certificate: bool) -> Result<Vec<u8>> {
+        if !has_timestamping_usage(self.certificate)? {
+            return Err(Error::ParameterError(
+                "timestamp token requires certificate with the only critical extended key usage timeStamping".to_string()));
+        }
+        let certificate = self.certificate.to_der()?;
+        //ESSCertIDv2 with the default sha256 hash algorithm
+        let signing_certificate = der::sequence(&[der::sequence(&[der::sequence(&[
+            der::octet_string(&hash(MessageDigest::sha256(), &certificate)?),
+        ])])]);
+        let attributes = vec![
+            pkcs7::attribute(OID_CONTENT_TYPE, der::oid(OID_TST_INFO)),
+            pkcs7::attribute(OID_MESSAGE_DIGEST, der::octet_string(&hash(MessageDigest::sha256(), tst_info)?)),
+            pkcs7::attribute(OID_SIGNING_CERTIFICATE_V2, signing_certificate),
+        ];
+        let signature = self.private_key.sign_sha256_digest(
+            &hash(MessageDigest::sha256(), &der::set(&attributes))?)?;
+        let signer_info = der::sequence(&[
+            der::small_integer(1),
+            pkcs7::issuer_and_serial(self.certificate)?,
+            der::algorithm_identifier(OID_SHA256),
+            der::context_set(0, &attributes),
+            self.private_key.signature_algorithm()?,
+            der::octet_string(&signature),
+        ]);
+        let certificates = if include_certificate { vec![certificate] } else { vec![] };
+        Ok(pkcs7::signed_data(
+            3,
+            der::algorithm_identifier(OID_SHA256),
+            der::sequence(&[
+                der::oid(OID_TST_INFO),
+                der::context(0, true, &der::octet_string(tst_info)),
+            ]),
+            &certificates,
+            signer_info,
+        ))
+    }
+}
+
+//RFC 3161 requires the TSA certificate contains the critical extended key usage with only timeStamping
+fn has_timestamping_usage(certificate: &X509Ref) -> Result<bool> {
+    let certificate = certificate.to_der()?;
+    let (_, certificate, _) = der::next(&certificate)?;
+    let (_, tbs_certificate, _) = der::next(der::content_of(certificate))?;
+    let extensions = match der::children(der::content_of(tbs_certificate))?
+        .into_iter()
+        .find(|(tag, _)| *tag == TAG_EXTENSIONS) {
+        Some((_, extensions)) => extensions,
+        None => return Ok(false),
+    };
+    let (_, extensions, _) = der::next(der::content_of(extensions))?;
+    for (_, extension) in der::children(der::content_of(extensions))? {
+        //Extension ::= SEQUENCE { extnID, critical BOOLEAN DEFAULT FALSE, extnValue OCTET STRING }
+        let fields = der::children(der::content_of(extension))?;
+        if fields.len() != 3 || fields[0].1 != der::oid(OID_EXTENDED_KEY_USAGE).as_slice() {
+            continue;
+        }
+        if fields[1].1 != der::boolean(true).as_slice() {
+            return Ok(false);
+        }
+        let (_, usages, _) = der::next(der::content_of(fields[2].1))?;
+        let usages = der::children(der::content_of(usages))?;
+        return Ok(usages.len() == 1 && usages[0].1 == der::oid(OID_TIMESTAMPING).as_slice());
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::timestamp::entity::{TimestampRecord, TimestampRequest, TimestampResponse};
+    use crate::infra::timestamp::client::parse_response;
+    use crate::util::test_support;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::extension::ExtendedKeyUsage;
+    use openssl::cms::{CMSOptions, CmsContentInfo};
+    use openssl::x509::store::X509StoreBuilder;
+    use openssl::x509::{X509, X509PurposeId};
+
+    fn certificate(extended_key_usage: Option<ExtendedKeyUsage>) -> (X509, PKey<Private>) {
+        let private_key = test_support::rsa_key();
+        let extensions = extended_key_usage.into_iter().map(|usage| usage.build().unwrap()).collect();
+        (test_support::certificate("signatrust tsa", &private_key, extensions), private_key)
+    }
+
+    fn timestamping() -> ExtendedKeyUsage {
+        let mut usage = ExtendedKeyUsage::new();
+        usage.critical().time_stamping();
+        usage
+    }
+
+    #[test]
+    fn test_has_timestamping_usage() {
+        assert!(has_timestamping_usage(&certificate(Some(timestamping())).0).unwrap());
+        assert!(!has_timestamping_usage(&certificate(None).0).unwrap());
+        let mut not_critical = ExtendedKeyUsage::new();
+        not_critical.time_stamping();
+        assert!(!has_timestamping_usage(&certificate(Some(not_critical)).0).unwrap());
+        let mut code_signing = timestamping();
+        code_signing.code_signing();
+        let (certificate, private_key) = certificate(Some(code_signing));
+        assert!(!has_timestamping_usage(&certificate).unwrap());
+        assert!(TimestampTokenSigner::new(&certificate, SigningKey::Memory(&private_key)).sign(&[], true).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_token_round_trip() {
+        let (certificate, private_key) = certificate(Some(timestamping()));
+        let hashed_message = hash(MessageDigest::sha256(), b"signature").unwrap().to_vec();
+        let message_imprint = der::sequence(&[der::algorithm_identifier(OID_SHA256), der::octet_string(&hashed_message)]);
+        let nonce = der::integer(&[0x5a, 0x01, 0x02, 0x03]);
+        let request = der::sequence(&[der::small_integer(1), message_imprint.clone(), nonce.clone(), der::boolean(true)]);
+
+        let parsed = TimestampRequest::from_der(&request).unwrap();
+        let mut record = TimestampRecord::new(1, "1.2.3.4.1".to_string(), &parsed, "127.0.0.1".to_string());
+        record.id = 42;
+        let tst_info = record.tst_info(&parsed).unwrap();
+        let token = TimestampTokenSigner::new(&certificate, SigningKey::Memory(&private_key))
+            .sign(&tst_info, parsed.cert_req).unwrap();
+        let response = TimestampResponse::Granted(token.clone()).to_der();
+        assert_eq!(parse_response(&response, &message_imprint, &nonce).unwrap(), token);
+
+        //the embedded tsa certificate is verified for the timestamping purpose along with the signature
+        let verify = |token: &[u8]| {
+            let mut store = X509StoreBuilder::new().unwrap();
+            store.add_cert(certificate.clone()).unwrap();
+            store.set_purpose(X509PurposeId::TIMESTAMP_SIGN).unwrap();
+            let mut content = vec![];
+            CmsContentInfo::from_der(token)
+                .and_then(|mut cms| cms.verify(None, Some(&store.build()), None, Some(&mut content), CMSOptions::empty()))
+                .map(|_| content)
+        };
+        assert_eq!(verify(&token).unwrap(), tst_info);
+        let mut tampered = token.clone();
+        *tampered.last_mut().unwrap() ^= 0xFF;
+        assert!(verify(&tampered).is_err());
+    }
+}
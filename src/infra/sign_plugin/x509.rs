@@ -1,20 +1,42 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
-use openssl::asn1::Asn1Time;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
 use openssl::cms::{CmsContentInfo, CMSOptions};
 use openssl::dsa::Dsa;
-use openssl::hash::MessageDigest;
-use openssl::pkey::{PKey, Private};
+use openssl::hash::{hash, MessageDigest};
+use openssl::nid::Nid;
+use openssl::pkcs12::Pkcs12;
+use openssl::pkey::{Id, PKey, Private};
 use openssl::rsa::Rsa;
 use openssl::x509;
+use openssl::x509::extension::ExtendedKeyUsage;
+use rand::RngCore;
 use secstr::SecVec;
 use serde::Deserialize;
 
 use validator::{Validate, ValidationError};
-use crate::domain::datakey::entity::SecDataKey;
+use crate::domain::datakey::entity::{SecDataKey, IMPORT_FORMAT, IMPORT_PASSPHRASE, PKCS12_FORMAT};
 use crate::util::error::{Error, Result};
-use crate::domain::sign_plugin::SignPlugins;
+use crate::domain::sign_plugin::{ExternalKey, SignPlugins};
+use crate::infra::sign_plugin::authenticode::AuthenticodeSigner;
+use crate::infra::sign_plugin::timestamp_token::TimestampTokenSigner;
+use crate::infra::sign_plugin::pkcs7::{self, SigningKey, OID_DATA, OID_SHA256};
+use crate::util::der;
+use crate::util::digest::{DigestAlgorithm, DigestState};
+
+use crate::domain::sign_plugin::{AUTHENTICODE, CERT_REQ, SIGN_TYPE, TIMESTAMP_TOKEN};
+
+lazy_static! {
+    //openssl can't sign with the external key, the draft certificate is signed with this key and then re-signed,
+    //the key is generated once since the draft signature is always dropped
+    static ref DRAFT_SIGNING_KEY: PKey<Private> = Rsa::generate(2048)
+        .and_then(PKey::from_rsa)
+        .expect("generate signing key of draft certificate");
+}
 
 #[derive(Debug, Validate, Deserialize)]
 pub struct X509KeyGenerationParameter {
@@ -38,17 +60,20 @@ pub struct X509KeyGenerationParameter {
     create_at: String,
     #[validate(custom(function= "validate_utc_time", message="invalid x509 attribute 'expire_at'"))]
     expire_at: String,
+    #[serde(default)]
+    #[validate(custom(function = "validate_x509_key_usage", message="invalid x509 attribute 'extended_key_usage'"))]
+    extended_key_usage: Option<String>,
 }
 
 impl X509KeyGenerationParameter {
     pub fn get_key(&self) -> Result<PKey<Private>> {
-        return match self.key_type.as_str() {
+        match self.key_type.as_str() {
             "rsa" => Ok(PKey::from_rsa(Rsa::generate(self.key_length.parse()?)?)?),
             "dsa" => Ok(PKey::from_dsa(Dsa::generate(self.key_length.parse()?)?)?),
             _ => Err(Error::ParameterError(
                 "invalid key type for x509".to_string(),
             )),
-        };
+        }
     }
 
     pub fn get_subject_name(&self) -> Result<x509::X509Name> {
@@ -61,17 +86,33 @@ impl X509KeyGenerationParameter {
         x509_name.append_entry_by_text("C", &self.country_name)?;
         Ok(x509_name.build())
     }
+
+    //timeStamping is marked as critical which is required by RFC 3161
+    pub fn get_extended_key_usage(&self) -> Result<Option<x509::X509Extension>> {
+        match self.extended_key_usage.as_deref() {
+            Some("codeSigning") => Ok(Some(ExtendedKeyUsage::new().code_signing().build()?)),
+            Some("timeStamping") => Ok(Some(ExtendedKeyUsage::new().critical().time_stamping().build()?)),
+            _ => Ok(None),
+        }
+    }
 }
 
 fn validate_x509_key_type(key_type: &str) -> std::result::Result<(), ValidationError> {
-    if !vec!["rsa", "dsa"].contains(&key_type) {
+    if !["rsa", "dsa"].contains(&key_type) {
         return Err(ValidationError::new("invalid key type"));
     }
     Ok(())
 }
 
+fn validate_x509_key_usage(key_usage: &str) -> std::result::Result<(), ValidationError> {
+    if !["codeSigning", "timeStamping"].contains(&key_usage) {
+        return Err(ValidationError::new("invalid extended key usage"));
+    }
+    Ok(())
+}
+
 fn validate_x509_key_size(key_size: &str) -> std::result::Result<(), ValidationError> {
-    if !vec!["2048", "3072", "4096"].contains(&key_size) {
+    if !["2048", "3072", "4096"].contains(&key_size) {
         return Err(ValidationError::new("invalid key size"));
     }
     Ok(())
@@ -98,11 +139,28 @@ fn days_in_duration(time: &str) -> Result<i64> {
     Ok((end - start).num_days())
 }
 
+fn asn1_time_to_utc(time: &Asn1TimeRef) -> Result<DateTime<Utc>> {
+    let diff = Asn1Time::from_unix(0)?.diff(time)?;
+    Utc.timestamp_opt(diff.days as i64 * 86400 + diff.secs as i64, 0)
+        .single()
+        .ok_or_else(|| Error::ConvertError(format!("invalid asn1 time {}", time)))
+}
+
+//validity is encoded as UTCTime before 2050 and GeneralizedTime afterwards, see RFC 5280 section 4.1.2.5
+fn validity_time(time: &DateTime<Utc>) -> Vec<u8> {
+    if time.year() < 2050 {
+        der::tlv(der::TAG_UTC_TIME, time.format("%y%m%d%H%M%SZ").to_string().as_bytes())
+    } else {
+        der::tlv(der::TAG_GENERALIZED_TIME, time.format("%Y%m%d%H%M%SZ").to_string().as_bytes())
+    }
+}
+
 pub struct X509Plugin {
     private_key: SecVec<u8>,
     public_key: SecVec<u8>,
     certificate: SecVec<u8>,
     identity: String,
+    external_key: Option<Arc<dyn ExternalKey>>,
 }
 
 impl X509Plugin {
@@ -114,6 +172,45 @@ impl X509Plugin {
             Err(e) => Err(Error::ParameterError(format!("{:?}", e))),
         }
     }
+
+    //the same structure as the detached cms signature, without certificates and signed attributes
+    fn detached_signature(&self, certificate: &x509::X509, signing_key: SigningKey, digest: &[u8]) -> Result<Vec<u8>> {
+        let signature = signing_key.sign_sha256_digest(digest)
+            .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
+        let digest_algorithm = der::sequence(&[der::oid(OID_SHA256)]);
+        let signer_info = der::sequence(&[
+            der::small_integer(1),
+            pkcs7::issuer_and_serial(certificate)?,
+            digest_algorithm.clone(),
+            signing_key.signature_algorithm()?,
+            der::octet_string(&signature),
+        ]);
+        Ok(pkcs7::signed_data(
+            1,
+            digest_algorithm,
+            der::sequence(&[der::oid(OID_DATA)]),
+            &[],
+            signer_info,
+        ))
+    }
+
+    //decode the base64 encoded pkcs12 bundle into pem encoded private key and certificate
+    fn decode_pkcs12(content: &[u8], password: &str) -> Result<(Vec<u8>, Vec<u8>)> {
+        let content = STANDARD.decode(String::from_utf8_lossy(content).trim()).map_err(
+            |e| Error::ParameterError(format!("invalid base64 encoded pkcs12 bundle: {}", e)))?;
+        let bundle = Pkcs12::from_der(&content)
+            .and_then(|pkcs12| pkcs12.parse2(password))
+            .map_err(|e| Error::ParameterError(format!("invalid pkcs12 bundle: {}", e)))?;
+        match (bundle.pkey, bundle.cert) {
+            (Some(private_key), Some(certificate)) => Ok((
+                private_key.private_key_to_pem_pkcs8()?,
+                certificate.to_pem()?,
+            )),
+            _ => Err(Error::ParameterError(
+                "pkcs12 bundle should contain both private key and certificate".to_string(),
+            )),
+        }
+    }
 }
 
 impl SignPlugins for X509Plugin {
@@ -123,15 +220,81 @@ impl SignPlugins for X509Plugin {
             public_key: db.public_key.clone(),
             certificate: db.certificate.clone(),
             identity: db.identity.clone(),
+            external_key: db.external_key.clone(),
         })
     }
 
     fn parse_attributes(
         _private_key: Option<Vec<u8>>,
         _public_key: Option<Vec<u8>>,
-        _certificate: Option<Vec<u8>>,
-    ) -> HashMap<String, String> {
-        todo!()
+        certificate: Option<Vec<u8>>,
+    ) -> Result<HashMap<String, String>> {
+        let certificate = x509::X509::from_pem(&certificate.unwrap_or_default())
+            .map_err(|e| Error::ParameterError(format!("invalid x509 certificate: {}", e)))?;
+        let mut attributes = HashMap::new();
+        for (nid, name) in [
+            (Nid::COMMONNAME, "common_name"),
+            (Nid::ORGANIZATIONALUNITNAME, "organizational_unit"),
+            (Nid::ORGANIZATIONNAME, "organization"),
+            (Nid::LOCALITYNAME, "locality"),
+            (Nid::STATEORPROVINCENAME, "province_name"),
+            (Nid::COUNTRYNAME, "country_name"),
+        ] {
+            if let Some(entry) = certificate.subject_name().entries_by_nid(nid).next() {
+                attributes.insert(name.to_string(), String::from_utf8_lossy(entry.data().as_slice()).to_string());
+            }
+        }
+        let public_key = certificate.public_key()?;
+        let key_type = match public_key.id() {
+            Id::RSA => "rsa",
+            Id::DSA => "dsa",
+            Id::EC => "ec",
+            _ => return Err(Error::UnsupportedTypeError(format!("x509 key type {:?}", public_key.id()))),
+        };
+        attributes.insert("key_type".to_string(), key_type.to_string());
+        attributes.insert("key_length".to_string(), public_key.bits().to_string());
+        attributes.insert("create_at".to_string(), asn1_time_to_utc(certificate.not_before())?.to_rfc3339());
+        attributes.insert("expire_at".to_string(), asn1_time_to_utc(certificate.not_after())?.to_rfc3339());
+        Ok(attributes)
+    }
+
+    fn import_keys(
+        private_key: Vec<u8>,
+        public_key: Vec<u8>,
+        certificate: Vec<u8>,
+        value: &HashMap<String, String>,
+    ) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let passphrase = value.get(IMPORT_PASSPHRASE).map(|p| p.as_str()).unwrap_or_default();
+        let (private_key, certificate) = match value.get(IMPORT_FORMAT) {
+            Some(format) if format == PKCS12_FORMAT => X509Plugin::decode_pkcs12(&private_key, passphrase)?,
+            _ => (private_key, certificate),
+        };
+        let private_key = if passphrase.is_empty() {
+            PKey::private_key_from_pem(&private_key)
+        } else {
+            PKey::private_key_from_pem_passphrase(&private_key, passphrase.as_bytes())
+        }.map_err(|e| Error::ParameterError(format!("invalid x509 private key: {}", e)))?;
+        //pkcs7 signer info only supports rsa and dsa keys
+        if private_key.id() != Id::RSA && private_key.id() != Id::DSA {
+            return Err(Error::ParameterError("only rsa and dsa x509 keys can be imported".to_string()));
+        }
+        let certificate = x509::X509::from_pem(&certificate)
+            .map_err(|e| Error::ParameterError(format!("invalid x509 certificate: {}", e)))?;
+        if !certificate.public_key()?.public_eq(&private_key) {
+            return Err(Error::ParameterError("x509 certificate doesn't match the private key".to_string()));
+        }
+        if !public_key.is_empty() {
+            let public_key = PKey::public_key_from_pem(&public_key)
+                .map_err(|e| Error::ParameterError(format!("invalid x509 public key: {}", e)))?;
+            if !public_key.public_eq(&private_key) {
+                return Err(Error::ParameterError("x509 public key doesn't match the private key".to_string()));
+            }
+        }
+        Ok((
+            private_key.private_key_to_pem_pkcs8()?,
+            private_key.public_key_to_pem()?,
+            certificate.to_pem()?,
+        ))
     }
 
     fn generate_keys(
@@ -147,6 +310,9 @@ impl SignPlugins for X509Plugin {
         generator.set_version(2)?;
         generator.set_not_before(Asn1Time::days_from_now(days_in_duration(&parameter.create_at)? as u32)?.as_ref())?;
         generator.set_not_after(Asn1Time::days_from_now(days_in_duration(&parameter.expire_at)? as u32)?.as_ref())?;
+        if let Some(extended_key_usage) = parameter.get_extended_key_usage()? {
+            generator.append_extension(extended_key_usage)?;
+        }
         generator.sign(keys.as_ref(), hash)?;
         let cert = generator.build();
         Ok((
@@ -156,21 +322,258 @@ impl SignPlugins for X509Plugin {
         ))
     }
 
-    fn sign(&self, content: Vec<u8>, _options: HashMap<String, String>) -> Result<Vec<u8>> {
-        let private_key = PKey::private_key_from_pem(self.private_key.unsecure())?;
+    fn generate_external_keys(
+        value: &HashMap<String, String>,
+        key: Arc<dyn ExternalKey>,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let parameter = X509Plugin::attributes_validate(value)?;
+        let public_key = PKey::public_key_from_der(&key.public_key()?)?;
+        let mut generator = x509::X509Builder::new()?;
+        generator.set_subject_name(parameter.get_subject_name()?.as_ref())?;
+        generator.set_issuer_name(parameter.get_subject_name()?.as_ref())?;
+        generator.set_pubkey(public_key.as_ref())?;
+        generator.set_version(2)?;
+        generator.set_not_before(Asn1Time::days_from_now(days_in_duration(&parameter.create_at)? as u32)?.as_ref())?;
+        generator.set_not_after(Asn1Time::days_from_now(days_in_duration(&parameter.expire_at)? as u32)?.as_ref())?;
+        if let Some(extended_key_usage) = parameter.get_extended_key_usage()? {
+            generator.append_extension(extended_key_usage)?;
+        }
+        //the tbsCertificate of draft is re-signed by the external key with the same signature algorithm
+        generator.sign(DRAFT_SIGNING_KEY.as_ref(), MessageDigest::sha256())?;
+        let draft = generator.build().to_der()?;
+        let (_, draft, _) = der::next(&draft)?;
+        let fields = der::children(der::content_of(draft))?;
+        if fields.len() != 3 {
+            return Err(Error::X509InvokeError("unexpected certificate structure".to_string()));
+        }
+        let signature = key.sign_pkcs1(&pkcs7::digest_info(
+            OID_SHA256, &hash(MessageDigest::sha256(), fields[0].1)?))?;
+        let mut signature_value = vec![0];
+        signature_value.extend(signature);
+        let cert = x509::X509::from_der(&der::sequence(&[
+            fields[0].1.to_vec(),
+            fields[1].1.to_vec(),
+            der::tlv(der::TAG_BIT_STRING, &signature_value),
+        ]))?;
+        Ok((public_key.public_key_to_pem()?, cert.to_pem()?))
+    }
+
+    //the tbsCertificate is re-signed with the new serial number and validity, subject and extensions are kept.
+    //certificates issued by other CA can't be renewed here, the re-issued certificate should be imported instead.
+    fn renew_keys(&self, expire_at: &DateTime<Utc>) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+        let certificate = x509::X509::from_pem(self.certificate.unsecure())?;
+        if certificate.issuer_name().to_der()? != certificate.subject_name().to_der()? {
+            return Err(Error::ParameterError("only the self-signed x509 certificate can be renewed".to_string()));
+        }
+        let private_key;
+        let signing_key = match &self.external_key {
+            Some(key) => SigningKey::External(key.as_ref()),
+            None => {
+                private_key = PKey::private_key_from_pem(self.private_key.unsecure())?;
+                SigningKey::Memory(&private_key)
+            }
+        };
+        let content = certificate.to_der()?;
+        let (_, content, _) = der::next(&content)?;
+        let fields = der::children(der::content_of(content))?;
+        if fields.len() != 3 {
+            return Err(Error::X509InvokeError("unexpected certificate structure".to_string()));
+        }
+        let mut tbs_fields: Vec<Vec<u8>> = der::children(der::content_of(fields[0].1))?
+            .into_iter()
+            .map(|(_, field)| field.to_vec())
+            .collect();
+        //version, serialNumber, signature, issuer, validity, subject, subjectPublicKeyInfo and extensions
+        let offset = match tbs_fields.first() {
+            Some(version) if version[0] == der::TAG_CONTEXT_CONSTRUCTED => 1,
+            _ => 0,
+        };
+        if tbs_fields.len() < offset + 6 {
+            return Err(Error::X509InvokeError("unexpected certificate structure".to_string()));
+        }
+        let mut serial = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut serial);
+        serial[0] &= 0x7f;
+        let signature_algorithm = signing_key.certificate_signature_algorithm()?;
+        tbs_fields[offset] = der::integer(&serial);
+        tbs_fields[offset + 1] = signature_algorithm.clone();
+        tbs_fields[offset + 3] = der::sequence(&[validity_time(&Utc::now()), validity_time(expire_at)]);
+        let tbs_certificate = der::sequence(&tbs_fields);
+        let signature = signing_key.sign_sha256_digest(&hash(MessageDigest::sha256(), &tbs_certificate)?)
+            .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()))?;
+        let mut signature_value = vec![0];
+        signature_value.extend(signature);
+        let renewed = x509::X509::from_der(&der::sequence(&[
+            tbs_certificate,
+            signature_algorithm,
+            der::tlv(der::TAG_BIT_STRING, &signature_value),
+        ]))?;
+        Ok((
+            self.private_key.unsecure().to_vec(),
+            self.public_key.unsecure().to_vec(),
+            renewed.to_pem()?,
+        ))
+    }
+
+    fn sign(&self, content: Vec<u8>, options: HashMap<String, String>) -> Result<Vec<u8>> {
         let certificate = x509::X509::from_pem(self.certificate.unsecure())?;
-        //cms option reference: https://man.openbsd.org/CMS_sign.3
-        let cms_signature = CmsContentInfo::sign(
-            Some(&certificate),
-            Some(&private_key),
-            None,
-            Some(&content),
-            CMSOptions::DETACHED
-                | CMSOptions::CMS_NOCERTS
-                | CMSOptions::BINARY
-                | CMSOptions::NOSMIMECAP
-                | CMSOptions::NOATTR,
-        )?;
-        Ok(cms_signature.to_der()?)
+        let private_key;
+        let signing_key = match &self.external_key {
+            Some(key) => SigningKey::External(key.as_ref()),
+            None => {
+                private_key = PKey::private_key_from_pem(self.private_key.unsecure())?;
+                SigningKey::Memory(&private_key)
+            }
+        };
+        //efi image, the content is the authenticode digest of image calculated by client
+        if let Some(sign_type) = options.get(SIGN_TYPE) {
+            if sign_type == AUTHENTICODE {
+                return AuthenticodeSigner::new(&certificate, signing_key)
+                    .sign(&content)
+                    .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()));
+            }
+            //timestamp token issued by built-in tsa, the content is the der encoded TSTInfo
+            if sign_type == TIMESTAMP_TOKEN {
+                let include_certificate = options.get(CERT_REQ).map(|c| c.as_str()) == Some("true");
+                return TimestampTokenSigner::new(&certificate, signing_key)
+                    .sign(&content, include_certificate)
+                    .map_err(|e| Error::SignError(self.identity.clone(), e.to_string()));
+            }
+        }
+        match signing_key {
+            //cms option reference: https://man.openbsd.org/CMS_sign.3
+            SigningKey::Memory(private_key) => {
+                let cms_signature = CmsContentInfo::sign(
+                    Some(&certificate),
+                    Some(private_key),
+                    None,
+                    Some(&content),
+                    CMSOptions::DETACHED
+                        | CMSOptions::CMS_NOCERTS
+                        | CMSOptions::BINARY
+                        | CMSOptions::NOSMIMECAP
+                        | CMSOptions::NOATTR,
+                )?;
+                Ok(cms_signature.to_der()?)
+            }
+            SigningKey::External(_) => self.detached_signature(
+                &certificate, signing_key, &hash(MessageDigest::sha256(), &content)?),
+        }
+    }
+
+    fn sign_digest(&self, digest: DigestState, options: HashMap<String, String>) -> Result<Vec<u8>> {
+        if let Some(sign_type) = options.get(SIGN_TYPE) {
+            if sign_type == AUTHENTICODE {
+                return Err(Error::ParameterError("authenticode signature requires the image digest as content".to_string()));
+            }
+            if sign_type == TIMESTAMP_TOKEN {
+                return Err(Error::ParameterError("timestamp token requires the tst info as content".to_string()));
+            }
+        }
+        if digest.algorithm() != DigestAlgorithm::Sha256 {
+            return Err(Error::ParameterError(format!("x509 doesn't support {} digest", digest.algorithm())));
+        }
+        let certificate = x509::X509::from_pem(self.certificate.unsecure())?;
+        match &self.external_key {
+            Some(key) => self.detached_signature(&certificate, SigningKey::External(key.as_ref()), &digest.finalize()),
+            None => {
+                let private_key = PKey::private_key_from_pem(self.private_key.unsecure())?;
+                self.detached_signature(&certificate, SigningKey::Memory(&private_key), &digest.finalize())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_support;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::symm::Cipher;
+    use openssl::x509::X509;
+    use secstr::SecVec;
+
+    fn certificate(private_key: &PKey<Private>) -> X509 {
+        test_support::certificate("signatrust import", private_key, vec![])
+    }
+
+    fn options(format: Option<&str>, passphrase: &str) -> HashMap<String, String> {
+        let mut options = HashMap::from([(IMPORT_PASSPHRASE.to_string(), passphrase.to_string())]);
+        if let Some(format) = format {
+            options.insert(IMPORT_FORMAT.to_string(), format.to_string());
+        }
+        options
+    }
+
+    #[test]
+    fn test_import_keys() {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let certificate = certificate(&private_key);
+        let pem = private_key.private_key_to_pem_pkcs8().unwrap();
+        let (imported, public_key, imported_certificate) = X509Plugin::import_keys(
+            pem.clone(), vec![], certificate.to_pem().unwrap(), &HashMap::new()).unwrap();
+        assert_eq!((imported, imported_certificate.clone()), (pem.clone(), certificate.to_pem().unwrap()));
+        assert_eq!(public_key, private_key.public_key_to_pem().unwrap());
+        let attributes = X509Plugin::parse_attributes(None, None, Some(imported_certificate)).unwrap();
+        assert_eq!(attributes.get("common_name").unwrap(), "signatrust import");
+
+        //passphrase protected pem
+        let encrypted = private_key.private_key_to_pem_pkcs8_passphrase(Cipher::aes_256_cbc(), b"signatrust").unwrap();
+        assert!(X509Plugin::import_keys(encrypted.clone(), vec![], certificate.to_pem().unwrap(), &options(None, "wrong")).is_err());
+        let (imported, _, _) = X509Plugin::import_keys(
+            encrypted, vec![], certificate.to_pem().unwrap(), &options(None, "signatrust")).unwrap();
+        assert_eq!(imported, pem);
+
+        //certificate of another key
+        let other = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        assert!(X509Plugin::import_keys(pem, vec![], self::certificate(&other).to_pem().unwrap(), &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_import_pkcs12() {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let certificate = certificate(&private_key);
+        let bundle = Pkcs12::builder().name("signatrust").pkey(&private_key).cert(&certificate)
+            .build2("signatrust").unwrap().to_der().unwrap();
+        let bundle = STANDARD.encode(bundle).into_bytes();
+        assert!(X509Plugin::import_keys(bundle.clone(), vec![], vec![], &options(Some(PKCS12_FORMAT), "wrong")).is_err());
+        let (imported, _, imported_certificate) = X509Plugin::import_keys(
+            bundle, vec![], vec![], &options(Some(PKCS12_FORMAT), "signatrust")).unwrap();
+        assert_eq!(imported, private_key.private_key_to_pem_pkcs8().unwrap());
+        assert_eq!(imported_certificate, certificate.to_pem().unwrap());
+        assert!(X509Plugin::import_keys(b"invalid".to_vec(), vec![], vec![], &options(Some(PKCS12_FORMAT), "")).is_err());
+    }
+
+    #[test]
+    fn test_import_ec_key_rejected() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let certificate = certificate(&private_key);
+        let result = X509Plugin::import_keys(
+            private_key.private_key_to_pem_pkcs8().unwrap(), vec![], certificate.to_pem().unwrap(), &HashMap::new());
+        assert!(matches!(result, Err(Error::ParameterError(_))));
+    }
+
+    #[test]
+    fn test_renew_keys() {
+        let private_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let certificate = certificate(&private_key);
+        let plugin = X509Plugin::new(&SecDataKey {
+            private_key: SecVec::new(private_key.private_key_to_pem_pkcs8().unwrap()),
+            public_key: SecVec::new(private_key.public_key_to_pem().unwrap()),
+            certificate: SecVec::new(certificate.to_pem().unwrap()),
+            identity: "renew-key".to_string(),
+            external_key: None,
+        }).unwrap();
+        let expire_at = Utc::now() + chrono::Duration::days(365);
+        let (_, _, renewed) = plugin.renew_keys(&expire_at).unwrap();
+
+        let renewed = X509::from_pem(&renewed).unwrap();
+        assert!(renewed.verify(&private_key).unwrap());
+        assert!(renewed.public_key().unwrap().public_eq(&private_key));
+        assert_eq!(renewed.subject_name().to_der().unwrap(), certificate.subject_name().to_der().unwrap());
+        assert_ne!(renewed.serial_number().to_bn().unwrap(), certificate.serial_number().to_bn().unwrap());
+        let not_after = Asn1Time::from_unix(expire_at.timestamp()).unwrap();
+        assert_eq!(renewed.not_after().compare(&not_after).unwrap(), std::cmp::Ordering::Equal);
     }
 }
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use openssl::hash::{hash, MessageDigest};
+use rand::RngCore;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+
+use crate::infra::sign_plugin::pkcs7::{OID_SHA256, OID_SIGNED_DATA, OID_TST_INFO};
+use crate::util::der;
+use crate::util::error::{Error, Result};
+
+/// id-aa-timeStampToken, unsigned attribute of the cms signature
+pub const OID_TIMESTAMP_TOKEN: &[u64] = &[1, 2, 840, 113549, 1, 9, 16, 2, 14];
+/// SPC_RFC3161_OBJID, unsigned attribute of the authenticode signature
+pub const OID_SPC_RFC3161: &[u64] = &[1, 3, 6, 1, 4, 1, 311, 3, 3, 1];
+const TIMESTAMP_QUERY: &str = "application/timestamp-query";
+//PKIStatus granted and grantedWithMods
+const STATUS_GRANTED: &[&[u8]] = &[&[0x00], &[0x01]];
+
+/// RFC 3161 client which requests the timestamp token of the signature value from remote TSA
+pub struct TimestampClient {
+    url: String,
+    client: Client,
+}
+
+impl TimestampClient {
+    pub fn new(url: String, timeout: Duration) -> Result<Self> {
+        Ok(Self {
+            url,
+            client: Client::builder().timeout(timeout).build()?,
+        })
+    }
+
+    //return the der encoded TimeStampToken, the ContentInfo signed by TSA
+    pub async fn request_token(&self, signature: &[u8]) -> Result<Vec<u8>> {
+        let message_imprint = der::sequence(&[
+            der::algorithm_identifier(OID_SHA256),
+            der::octet_string(&hash(MessageDigest::sha256(), signature)?),
+        ]);
+        let mut nonce = [0u8; 8];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let nonce = der::integer(&nonce);
+        let request = der::sequence(&[
+            der::small_integer(1),
+            message_imprint.clone(),
+            nonce.clone(),
+            der::boolean(true),
+        ]);
+        let response = self.client
+            .post(&self.url)
+            .header(CONTENT_TYPE, TIMESTAMP_QUERY)
+            .body(request)
+            .send()
+            .await
+            .map_err(|e| Error::TimestampError(format!("failed to request tsa {}: {}", self.url, e)))?;
+        if !response.status().is_success() {
+            return Err(Error::TimestampError(format!("tsa {} responded with http status {}", self.url, response.status())));
+        }
+        let content = response
+            .bytes()
+            .await
+            .map_err(|e| Error::TimestampError(format!("failed to read response of tsa {}: {}", self.url, e)))?;
+        parse_response(&content, &message_imprint, &nonce)
+    }
+}
+
+//TimeStampResp ::= SEQUENCE { status PKIStatusInfo, timeStampToken TimeStampToken OPTIONAL }
+pub(crate) fn parse_response(response: &[u8], message_imprint: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+    let invalid = |e: Error| Error::TimestampError(format!("invalid timestamp response: {}", e));
+    let (_, response, _) = der::next(response).map_err(invalid)?;
+    let fields = der::children(der::content_of(response)).map_err(invalid)?;
+    let status_info = match fields.first() {
+        Some((der::TAG_SEQUENCE, status_info)) => der::children(der::content_of(status_info)).map_err(invalid)?,
+        _ => return Err(invalid(Error::EncodeError("missing status".to_string()))),
+    };
+    let status = match status_info.first() {
+        Some((der::TAG_INTEGER, status)) => der::content_of(status),
+        _ => return Err(invalid(Error::EncodeError("missing status".to_string()))),
+    };
+    if !STATUS_GRANTED.contains(&status) {
+        return Err(Error::TimestampError(format!(
+            "request rejected by tsa with status {}{}", hex::encode(status), status_text(&status_info))));
+    }
+    let token = match fields.get(1) {
+        Some((der::TAG_SEQUENCE, token)) => token,
+        _ => return Err(invalid(Error::EncodeError("missing timestamp token".to_string()))),
+    };
+    verify_tst_info(token, message_imprint, nonce).map_err(invalid)?;
+    Ok(token.to_vec())
+}
+
+//the optional PKIFreeText returned by TSA when request is rejected
+fn status_text(status_info: &[(u8, &[u8])]) -> String {
+    let mut text = vec![];
+    if let Some((der::TAG_SEQUENCE, free_text)) = status_info.get(1) {
+        for (tag, element) in der::children(der::content_of(free_text)).unwrap_or_default() {
+            if tag == der::TAG_UTF8_STRING {
+                text.push(String::from_utf8_lossy(der::content_of(element)).to_string());
+            }
+        }
+    }
+    if text.is_empty() {
+        return "".to_string();
+    }
+    format!(", {}", text.join(" "))
+}
+
+//make sure the token is issued for the very signature and request
+fn verify_tst_info(token: &[u8], message_imprint: &[u8], nonce: &[u8]) -> Result<()> {
+    let invalid = |message: &str| Error::EncodeError(message.to_string());
+    let content_info = der::children(der::content_of(token))?;
+    if content_info.len() != 2 || content_info[0].1 != der::oid(OID_SIGNED_DATA).as_slice() {
+        return Err(invalid("timestamp token is not signed data"));
+    }
+    let (_, signed_data, _) = der::next(der::content_of(content_info[1].1))?;
+    let signed_data = der::children(der::content_of(signed_data))?;
+    let encapsulated = der::children(der::content_of(signed_data.get(2).ok_or_else(|| invalid("missing tst info"))?.1))?;
+    if encapsulated.len() != 2 || encapsulated[0].1 != der::oid(OID_TST_INFO).as_slice() {
+        return Err(invalid("missing tst info"));
+    }
+    let (_, tst_info, _) = der::next(der::content_of(encapsulated[1].1))?;
+    let (_, tst_info, _) = der::next(der::content_of(tst_info))?;
+    let tst_info = der::children(der::content_of(tst_info))?;
+    //version, policy, messageImprint, serialNumber, genTime, accuracy, ordering, nonce
+    if tst_info.get(2).map(|(_, element)| *element) != Some(message_imprint) {
+        return Err(invalid("message imprint mismatched"));
+    }
+    match tst_info.iter().skip(5).find(|(tag, _)| *tag == der::TAG_INTEGER) {
+        Some((_, value)) if *value == nonce => Ok(()),
+        _ => Err(invalid("nonce mismatched")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::test_support::serve;
+
+    //local TSA stand-in which answers a single request with the response built from the request
+    fn serve_once<F>(build_response: F) -> String
+        where F: FnOnce(&[u8]) -> Vec<u8> + Send + 'static {
+        let mut build_response = Some(build_response);
+        let address = serve(1, "application/timestamp-reply", move |request| {
+            (200, build_response.take().unwrap()(&request.body))
+        });
+        format!("{}/tsa", address)
+    }
+
+    //unsigned token is enough since the TSA signature is verified by consumers of the signature
+    fn granted_response(request: &[u8]) -> Vec<u8> {
+        let (_, request, _) = der::next(request).unwrap();
+        let fields = der::children(der::content_of(request)).unwrap();
+        let tst_info = der::sequence(&[
+            der::small_integer(1),
+            der::oid(&[1, 2, 3, 4]),
+            fields[1].1.to_vec(),
+            der::small_integer(42),
+            der::tlv(0x18, b"20230101000000Z"),
+            fields[2].1.to_vec(),
+        ]);
+        let token = der::sequence(&[
+            der::oid(OID_SIGNED_DATA),
+            der::context(0, true, &der::sequence(&[
+                der::small_integer(3),
+                der::set(&[]),
+                der::sequence(&[der::oid(OID_TST_INFO), der::context(0, true, &der::octet_string(&tst_info))]),
+                der::set(&[]),
+            ])),
+        ]);
+        der::sequence(&[der::sequence(&[der::small_integer(0)]), token])
+    }
+
+    #[test]
+    fn test_request_token_from_tsa() {
+        let url = serve_once(granted_response);
+        let client = TimestampClient::new(url, Duration::from_secs(5)).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let token = runtime.block_on(client.request_token(b"signature")).unwrap();
+        assert_eq!(der::children(der::content_of(&token)).unwrap()[0].1, der::oid(OID_SIGNED_DATA).as_slice());
+    }
+
+    #[test]
+    fn test_request_rejected_by_tsa() {
+        let url = serve_once(|_| der::sequence(&[der::sequence(&[
+            der::small_integer(2),
+            der::sequence(&[der::tlv(der::TAG_UTF8_STRING, b"bad algorithm")]),
+        ])]));
+        let client = TimestampClient::new(url, Duration::from_secs(5)).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let error = runtime.block_on(client.request_token(b"signature")).unwrap_err();
+        assert_eq!(error.to_string(), "failed to acquire rfc3161 timestamp: request rejected by tsa with status 02, bad algorithm");
+    }
+}
@@ -0,0 +1,25 @@
+use actix_web::{
+    HttpResponse, Responder, Result, web, Scope
+};
+use validator::Validate;
+
+use crate::application::audit::AuditService;
+use crate::domain::audit::entity::AuditFilter;
+use crate::presentation::handler::control::model::audit::dto::{AuditQueryDTO, SignRecordDTO};
+use crate::util::error::Error;
+use super::model::user::dto::UserIdentity;
+
+
+async fn list_audits(user: UserIdentity, audit_service: web::Data<dyn AuditService>, query: web::Query<AuditQueryDTO>) -> Result<impl Responder, Error> {
+    query.validate()?;
+    let records = audit_service.get_records(&user, AuditFilter::try_from(query.into_inner())?).await?;
+    Ok(HttpResponse::Ok().json(records.into_iter().map(SignRecordDTO::from).collect::<Vec<SignRecordDTO>>()))
+}
+
+
+pub fn get_scope() -> Scope {
+    web::scope("/audits")
+        .service(
+            web::resource("/")
+                .route(web::get().to(list_audits)))
+}
@@ -3,55 +3,77 @@ use actix_web::{
 };
 
 
-use crate::presentation::handler::control::model::datakey::dto::{DataKeyDTO, ExportKey};
+use crate::domain::datakey::entity::KeyFilter;
+use crate::presentation::handler::control::model::datakey::dto::{DataKeyDTO, ExportKey, ImportDataKeyDTO, ListKeyQueryDTO, PagedDataKeyDTO, PermissionDTO, RenewDataKeyDTO};
 use crate::util::error::Error;
 use validator::Validate;
 use crate::application::datakey::KeyService;
 use super::model::user::dto::UserIdentity;
 
 
-async fn create_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, datakey: web::Json<DataKeyDTO>,) -> Result<impl Responder, Error> {
+async fn create_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, datakey: web::Json<DataKeyDTO>,) -> Result<impl Responder, Error> {
     datakey.validate()?;
-    Ok(HttpResponse::Created().json(DataKeyDTO::try_from(key_service.into_inner().create(datakey.0).await?)?))
+    Ok(HttpResponse::Created().json(DataKeyDTO::try_from(key_service.into_inner().create(&user, datakey.0).await?)?))
 }
 
-async fn list_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>) -> Result<impl Responder, Error> {
-    let keys = key_service.into_inner().get_all().await?;
-    let mut results = vec![];
-    for k in keys {
-        results.push(DataKeyDTO::try_from(k)?)
-    }
-    Ok(HttpResponse::Ok().json(results))
+async fn list_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, query: web::Query<ListKeyQueryDTO>) -> Result<impl Responder, Error> {
+    query.validate()?;
+    let filter = KeyFilter::try_from(query.into_inner())?;
+    let page = key_service.into_inner().get_all(&user, filter.clone()).await?;
+    Ok(HttpResponse::Ok().json(PagedDataKeyDTO::new(page, &filter)?))
 }
 
-async fn show_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
-    let key = key_service.into_inner().get_one(id.parse::<i32>()?).await?;
+async fn show_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    let key = key_service.into_inner().get_one(&user, id.parse::<i32>()?).await?;
     Ok(HttpResponse::Ok().json(DataKeyDTO::try_from(key)?))
 }
 
-async fn delete_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
-    key_service.into_inner().delete_one(id.parse::<i32>()?).await?;
+async fn delete_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    key_service.into_inner().delete_one(&user, id.parse::<i32>()?).await?;
     Ok(HttpResponse::Ok())
 }
 
-async fn export_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
-    Ok(HttpResponse::Ok().json(ExportKey::try_from(key_service.export_one(id.parse::<i32>()?).await?)?))
+async fn export_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    Ok(HttpResponse::Ok().json(ExportKey::try_from(key_service.export_one(&user, id.parse::<i32>()?).await?)?))
 }
 
-async fn enable_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
-    key_service.enable(id.parse::<i32>()?).await?;
+async fn enable_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    key_service.enable(&user, id.parse::<i32>()?).await?;
     Ok(HttpResponse::Ok())
 }
 
-async fn disable_data_key(_user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
-    key_service.disable(id.parse::<i32>()?).await?;
+async fn disable_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    key_service.disable(&user, id.parse::<i32>()?).await?;
     Ok(HttpResponse::Ok())
 }
 
-async fn import_data_key(_user: UserIdentity) -> Result<impl Responder, Error> {
+async fn renew_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>, renew: web::Json<RenewDataKeyDTO>) -> Result<impl Responder, Error> {
+    renew.validate()?;
+    let key = key_service.renew(&user, id.parse::<i32>()?, renew.expire_at.parse()?).await?;
+    Ok(HttpResponse::Ok().json(DataKeyDTO::try_from(key)?))
+}
+
+async fn import_data_key(user: UserIdentity, key_service: web::Data<dyn KeyService>, datakey: web::Json<ImportDataKeyDTO>,) -> Result<impl Responder, Error> {
+    datakey.validate()?;
+    Ok(HttpResponse::Created().json(DataKeyDTO::try_from(key_service.into_inner().import(&user, datakey.0).await?)?))
+}
+
+async fn list_permissions(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>) -> Result<impl Responder, Error> {
+    let permissions = key_service.get_permissions(&user, id.parse::<i32>()?).await?;
+    Ok(HttpResponse::Ok().json(permissions.into_iter().map(PermissionDTO::from).collect::<Vec<PermissionDTO>>()))
+}
+
+async fn grant_permission(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>, permission: web::Json<PermissionDTO>) -> Result<impl Responder, Error> {
+    permission.validate()?;
+    key_service.grant_permission(&user, permission.0.into_permission(id.parse::<i32>()?)?).await?;
     Ok(HttpResponse::Ok())
 }
 
+async fn revoke_permission(user: UserIdentity, key_service: web::Data<dyn KeyService>, id: web::Path<String>, permission: web::Json<PermissionDTO>) -> Result<impl Responder, Error> {
+    permission.validate()?;
+    key_service.revoke_permission(&user, permission.0.into_permission(id.parse::<i32>()?)?).await?;
+    Ok(HttpResponse::Ok())
+}
 
 pub fn get_scope() -> Scope {
     web::scope("/keys")
@@ -59,11 +81,16 @@ pub fn get_scope() -> Scope {
             web::resource("/")
                 .route(web::get().to(list_data_key))
                 .route(web::post().to(create_data_key)))
+        .service( web::resource("/import").route(web::post().to(import_data_key)))
         .service( web::resource("/{id}")
             .route(web::get().to(show_data_key))
             .route(web::delete().to(delete_data_key)))
-        .service( web::resource("/import").route(web::post().to(import_data_key)))
         .service( web::resource("/{id}/export").route(web::post().to(export_data_key)))
         .service( web::resource("/{id}/enable").route(web::post().to(enable_data_key)))
         .service( web::resource("/{id}/disable").route(web::post().to(disable_data_key)))
+        .service( web::resource("/{id}/renew").route(web::post().to(renew_data_key)))
+        .service( web::resource("/{id}/permissions")
+            .route(web::get().to(list_permissions))
+            .route(web::post().to(grant_permission))
+            .route(web::delete().to(revoke_permission)))
 }
@@ -1,3 +1,5 @@
+pub mod audit_handler;
 pub mod datakey_handler;
 pub mod user_handler;
+pub mod timestamp_handler;
 pub mod model;
\ No newline at end of file
@@ -0,0 +1,80 @@
+use crate::domain::audit::entity::{AuditFilter, SignRecord};
+use crate::util::error::{Error, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationError};
+
+const DEFAULT_LIMIT: u32 = 100;
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct AuditQueryDTO {
+    pub key_id: Option<i32>,
+    //user email or the identity of client certificate
+    pub user: Option<String>,
+    //start and end time in rfc3339 format, end is exclusive
+    #[validate(custom = "validate_utc_time")]
+    pub start: Option<String>,
+    #[validate(custom = "validate_utc_time")]
+    pub end: Option<String>,
+    #[validate(range(min = 1, max = 1000))]
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+fn validate_utc_time(time: &str) -> std::result::Result<(), ValidationError> {
+    if time.parse::<DateTime<Utc>>().is_err() {
+        return Err(ValidationError::new("failed to parse time string to utc"));
+    }
+    Ok(())
+}
+
+impl TryFrom<AuditQueryDTO> for AuditFilter {
+    type Error = Error;
+
+    fn try_from(dto: AuditQueryDTO) -> Result<Self> {
+        Ok(AuditFilter {
+            key_id: dto.key_id,
+            principal: dto.user,
+            start: dto.start.map(|start| start.parse::<DateTime<Utc>>()).transpose()?,
+            end: dto.end.map(|end| end.parse::<DateTime<Utc>>()).transpose()?,
+            visible_to: None,
+            limit: dto.limit.unwrap_or(DEFAULT_LIMIT),
+            offset: dto.offset.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignRecordDTO {
+    pub id: i32,
+    pub key_id: i32,
+    pub key_name: String,
+    pub key_type: String,
+    pub action: String,
+    pub user: String,
+    pub file_name: String,
+    pub digest: String,
+    pub detail: String,
+    pub succeed: bool,
+    pub error: String,
+    pub create_at: String,
+}
+
+impl From<SignRecord> for SignRecordDTO {
+    fn from(record: SignRecord) -> Self {
+        SignRecordDTO {
+            id: record.id,
+            key_id: record.key_id,
+            key_name: record.key_name,
+            key_type: record.key_type,
+            action: record.action.to_string(),
+            user: record.principal,
+            file_name: record.file_name,
+            digest: record.digest,
+            detail: record.detail,
+            succeed: record.succeed,
+            error: record.error,
+            create_at: record.create_at.to_rfc3339(),
+        }
+    }
+}
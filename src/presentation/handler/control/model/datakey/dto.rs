@@ -1,4 +1,4 @@
-use crate::domain::datakey::entity::{DataKey, KeyState};
+use crate::domain::datakey::entity::{DataKey, KeyCursor, KeyFilter, KeyPage, KeyPermission, KeyRole, KeyState, IMPORT_FORMAT, IMPORT_PASSPHRASE, PKCS12_FORMAT, KeySortField, SortOrder};
 use crate::domain::datakey::entity::KeyType;
 
 use crate::util::error::Result;
@@ -55,6 +55,143 @@ pub struct DataKeyDTO {
     pub key_state: String,
 }
 
+#[derive(Debug, Validate, Deserialize, Serialize)]
+pub struct ImportDataKeyDTO {
+    #[validate(length(min = 4, max = 20))]
+    pub name: String,
+    #[validate(email)]
+    pub email: String,
+    #[validate(length(min = 0, max = 100))]
+    pub description: String,
+    pub user: String,
+    pub key_type: String,
+    //armored openpgp secret key or pem encoded x509 private key
+    #[serde(default)]
+    pub private_key: String,
+    //optional, will be extracted from the private key when absent
+    #[serde(default)]
+    pub public_key: String,
+    //pem encoded x509 certificate
+    #[serde(default)]
+    pub certificate: String,
+    //base64 encoded pkcs12 bundle, alternative to the x509 private key and certificate
+    #[serde(default)]
+    pub pkcs12: String,
+    //passphrase of the openpgp secret key or the pkcs12 bundle
+    #[serde(default)]
+    pub passphrase: String,
+}
+
+#[derive(Debug, Validate, Deserialize, Serialize)]
+pub struct PermissionDTO {
+    //user email or the identity of the data server client certificate prefixed with `cert:`, i.e. `cert:build-host`
+    #[validate(length(min = 1, max = 100))]
+    pub principal: String,
+    //admin or signer
+    pub role: String,
+}
+
+#[derive(Debug, Validate, Deserialize, Serialize)]
+pub struct RenewDataKeyDTO {
+    #[validate(custom(function = "validate_utc_time", message="invalid renew attribute 'expire_at'"))]
+    pub expire_at: String,
+}
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct ListKeyQueryDTO {
+    //pgp or x509
+    pub key_type: Option<String>,
+    //enabled or disabled
+    pub key_state: Option<String>,
+    //email of the key owner
+    pub owner: Option<String>,
+    #[validate(length(min = 1, max = 20))]
+    pub name_prefix: Option<String>,
+    //expiry window in rfc3339 format, expire_before is exclusive
+    #[validate(custom = "validate_utc_time")]
+    pub expire_after: Option<String>,
+    #[validate(custom = "validate_utc_time")]
+    pub expire_before: Option<String>,
+    //id, name, create_at or expire_at
+    pub sort: Option<String>,
+    //asc or desc
+    pub order: Option<String>,
+    //starts from 1, can't be specified along with the cursor
+    #[validate(range(min = 1))]
+    pub page: Option<u32>,
+    #[validate(range(min = 1, max = 100))]
+    pub size: Option<u32>,
+    //next_cursor returned in the previous page
+    pub cursor: Option<String>,
+}
+
+impl TryFrom<ListKeyQueryDTO> for KeyFilter {
+    type Error = Error;
+
+    fn try_from(dto: ListKeyQueryDTO) -> Result<Self> {
+        let sort_by = dto.sort.map(|sort| KeySortField::from_str(&sort)).transpose()?.unwrap_or_default();
+        let order = dto.order.map(|order| SortOrder::from_str(&order)).transpose()?.unwrap_or_default();
+        let cursor = dto.cursor.map(|cursor| KeyCursor::from_str(&cursor)).transpose()?;
+        if let Some(cursor) = &cursor {
+            if dto.page.is_some() {
+                return Err(Error::ParameterError("page and cursor can't be specified together".to_string()));
+            }
+            if cursor.sort_by != sort_by {
+                return Err(Error::ParameterError(format!("cursor is sorted by {} rather than {}", cursor.sort_by, sort_by)));
+            }
+            if cursor.order != order {
+                return Err(Error::ParameterError(format!("cursor is in {} order rather than {}", cursor.order, order)));
+            }
+        }
+        let limit = dto.size.unwrap_or(DEFAULT_PAGE_SIZE);
+        Ok(KeyFilter {
+            key_type: dto.key_type.map(|key_type| KeyType::from_str(&key_type)).transpose()?,
+            key_state: dto.key_state.map(|key_state| KeyState::from_str(&key_state)).transpose()?,
+            owner: dto.owner,
+            name_prefix: dto.name_prefix,
+            expire_after: dto.expire_after.map(|after| after.parse::<DateTime<Utc>>()).transpose()?,
+            expire_before: dto.expire_before.map(|before| before.parse::<DateTime<Utc>>()).transpose()?,
+            visible_to: None,
+            sort_by,
+            order,
+            cursor,
+            limit,
+            offset: (dto.page.unwrap_or(1) - 1).saturating_mul(limit),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PagedDataKeyDTO {
+    pub total: i64,
+    //None when paging with cursor
+    pub page: Option<u32>,
+    pub size: u32,
+    pub next_cursor: Option<String>,
+    pub items: Vec<DataKeyDTO>,
+}
+
+impl PagedDataKeyDTO {
+    pub fn new(page: KeyPage, filter: &KeyFilter) -> Result<Self> {
+        let mut items = vec![];
+        for key in page.keys {
+            items.push(DataKeyDTO::try_from(key)?);
+        }
+        Ok(PagedDataKeyDTO {
+            total: page.total,
+            page: match filter.cursor {
+                Some(_) => None,
+                None => Some(filter.offset / filter.limit + 1),
+            },
+            size: filter.limit,
+            next_cursor: page.next_cursor.map(|cursor| cursor.to_string()),
+            items,
+        })
+    }
+}
+
 fn validate_utc_time(expire: &str) -> std::result::Result<(), ValidationError> {
     if expire.parse::<DateTime<Utc>>().is_err() {
         return Err(ValidationError::new("failed to parse time string to utc"));
@@ -85,7 +222,45 @@ impl TryFrom<DataKeyDTO> for DataKey {
             create_at: dto.create_at.parse()?,
             expire_at: dto.expire_at.parse()?,
             soft_delete: false,
-            key_state: KeyState::default()
+            key_state: KeyState::default(),
+            update_at: Utc::now(),
+        })
+    }
+}
+
+impl TryFrom<ImportDataKeyDTO> for DataKey {
+    type Error = Error;
+
+    //key attributes as well as the create and expire time will be parsed from the key material when importing
+    fn try_from(dto: ImportDataKeyDTO) -> Result<Self> {
+        let key_type = KeyType::from_str(dto.key_type.as_str())?;
+        let mut attributes = HashMap::new();
+        if !dto.passphrase.is_empty() {
+            attributes.insert(IMPORT_PASSPHRASE.to_string(), dto.passphrase);
+        }
+        //pkcs12 bundle is decoded by the sign plugin when importing
+        let private_key = if dto.pkcs12.is_empty() {
+            dto.private_key.into_bytes()
+        } else {
+            attributes.insert(IMPORT_FORMAT.to_string(), PKCS12_FORMAT.to_string());
+            dto.pkcs12.into_bytes()
+        };
+        Ok(DataKey {
+            id: 0,
+            name: dto.name,
+            description: dto.description,
+            user: dto.user,
+            email: dto.email,
+            attributes,
+            key_type,
+            private_key,
+            public_key: dto.public_key.into_bytes(),
+            certificate: dto.certificate.into_bytes(),
+            create_at: Utc::now(),
+            expire_at: Utc::now(),
+            soft_delete: false,
+            key_state: KeyState::default(),
+            update_at: Utc::now(),
         })
     }
 }
@@ -108,3 +283,52 @@ impl TryFrom<DataKey> for DataKeyDTO {
         })
     }
 }
+
+impl PermissionDTO {
+    pub fn into_permission(self, key_id: i32) -> Result<KeyPermission> {
+        Ok(KeyPermission::new(key_id, self.principal, KeyRole::from_str(&self.role)?))
+    }
+}
+
+impl From<KeyPermission> for PermissionDTO {
+    fn from(permission: KeyPermission) -> Self {
+        PermissionDTO {
+            principal: permission.principal,
+            role: permission.role.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn query(order: Option<&str>, page: Option<u32>, cursor: Option<&KeyCursor>) -> ListKeyQueryDTO {
+        ListKeyQueryDTO {
+            key_type: None,
+            key_state: None,
+            owner: None,
+            name_prefix: None,
+            expire_after: None,
+            expire_before: None,
+            sort: Some("name".to_string()),
+            order: order.map(|order| order.to_string()),
+            page,
+            size: Some(10),
+            cursor: cursor.map(|cursor| cursor.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_key_filter_with_cursor() {
+        let cursor = KeyCursor { sort_by: KeySortField::Name, order: SortOrder::Asc, id: 3, value: "key_a".to_string() };
+        let filter = KeyFilter::try_from(query(Some("asc"), None, Some(&cursor))).unwrap();
+        assert_eq!((filter.cursor, filter.offset), (Some(cursor.clone()), 0));
+        let filter = KeyFilter::try_from(query(None, Some(3), None)).unwrap();
+        assert_eq!((filter.cursor, filter.offset), (None, 20));
+
+        //the cursor of ascending page can't be reused in descending order
+        assert!(matches!(KeyFilter::try_from(query(Some("desc"), None, Some(&cursor))), Err(Error::ParameterError(_))));
+        assert!(matches!(KeyFilter::try_from(query(Some("asc"), Some(2), Some(&cursor))), Err(Error::ParameterError(_))));
+    }
+}
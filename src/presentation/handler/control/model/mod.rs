@@ -1,3 +1,4 @@
+pub mod audit;
 pub mod datakey;
 pub mod user;
 pub mod token;
\ No newline at end of file
@@ -14,7 +14,7 @@ use crate::domain::token::repository::Repository as tokenRepository;
 use crate::domain::user::entity::User;
 use crate::domain::user::repository::Repository as userRepository;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct UserIdentity {
     pub email: String,
     pub id: i32,
@@ -0,0 +1,44 @@
+use actix_governor::{Governor, GovernorConfig, GovernorConfigBuilder, PeerIpKeyExtractor};
+use actix_governor::governor::middleware::NoOpMiddleware;
+use actix_web::{
+    HttpRequest, HttpResponse, Responder, Result, web
+};
+use actix_web::dev::HttpServiceFactory;
+
+use crate::application::timestamp::TimestampService;
+use crate::util::error::Error;
+
+const TIMESTAMP_REPLY: &str = "application/timestamp-reply";
+
+
+//RFC 3161 timestamp over http, the request body is the der encoded TimeStampReq
+async fn issue_timestamp(req: HttpRequest, body: web::Bytes, timestamp_service: web::Data<dyn TimestampService>) -> Result<impl Responder, Error> {
+    let client_address = req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let response = timestamp_service.issue(&body, client_address).await;
+    Ok(HttpResponse::Ok().content_type(TIMESTAMP_REPLY).body(response))
+}
+
+
+pub type RateLimit = GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware>;
+
+//the timestamp endpoint is unauthenticated, requests are limited per client address and the exceeded ones are
+//replied with 429
+pub fn rate_limit(requests_per_second: u64, burst: u32) -> Result<RateLimit, Error> {
+    if requests_per_second == 0 {
+        return Err(Error::ConfigError("rate limit of timestamp authority should be positive".to_string()));
+    }
+    GovernorConfigBuilder::default()
+        .requests_per_second(requests_per_second)
+        .burst_size(burst)
+        .finish()
+        .ok_or_else(|| Error::ConfigError("invalid rate limit of timestamp authority".to_string()))
+}
+
+//the rate limit is shared by all of the workers
+pub fn get_scope(rate_limit: &RateLimit) -> impl HttpServiceFactory {
+    web::scope("/timestamp")
+        .wrap(Governor::new(rate_limit))
+        .service(
+            web::resource("/")
+                .route(web::post().to(issue_timestamp)))
+}
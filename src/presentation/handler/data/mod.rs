@@ -1 +1,2 @@
-pub mod sign_handler;
\ No newline at end of file
+pub mod sign_handler;
+pub mod token_resolver;
\ No newline at end of file
@@ -7,10 +7,17 @@ use tokio_stream::StreamExt;
 
 use signatrust::{
     signatrust_server::Signatrust, signatrust_server::SignatrustServer, SignStreamRequest,
-    SignStreamResponse,
+    SignStreamResponse, SignDigestRequest, SignDigestResponse,
 };
 use tonic::{Request, Response, Status, Streaming};
-use crate::application::datakey::KeyService;
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use crate::application::datakey::{KeyService, SignCaller};
+use crate::presentation::handler::control::model::user::dto::UserIdentity;
+use crate::util::digest::DigestState;
+use crate::util::error::{Error, Result as SignatrustResult};
+use crate::util::metrics;
+use std::time::Instant;
 
 
 
@@ -30,11 +37,34 @@ where
 {
     pub fn new(key_service: K) -> Self {
         SignHandler {
-            key_service
+            key_service,
         }
     }
 }
 
+//the caller is identified by the user of api token which is resolved by `TokenLayer`, otherwise by the email address
+//of the client certificate, or its common name if absent, the certificate principals are prefixed with `cert:`
+fn get_caller<T>(request: &Request<T>) -> SignatrustResult<SignCaller> {
+    if let Some(user) = request.extensions().get::<UserIdentity>() {
+        return Ok(SignCaller::User(user.clone()));
+    }
+    get_certificate_caller(request)
+}
+
+fn get_certificate_caller<T>(request: &Request<T>) -> SignatrustResult<SignCaller> {
+    let certs = request.peer_certs().ok_or_else(|| Error::ForbiddenError(
+        "either api token or client certificate is required to identify the caller".to_string()))?;
+    let cert = certs.first().ok_or_else(|| Error::ForbiddenError(
+        "client certificate is required to identify the caller".to_string()))?;
+    let x509 = X509::from_der(cert.get_ref())?;
+    for nid in [Nid::PKCS9_EMAILADDRESS, Nid::COMMONNAME] {
+        if let Some(entry) = x509.subject_name().entries_by_nid(nid).next() {
+            return Ok(SignCaller::certificate(&String::from_utf8_lossy(entry.data().as_slice())));
+        }
+    }
+    Err(Error::ForbiddenError("client certificate contains neither email address nor common name".to_string()))
+}
+
 #[tonic::async_trait]
 impl<K> Signatrust for SignHandler<K>
 where
@@ -44,6 +74,8 @@ where
         &self,
         request: Request<Streaming<SignStreamRequest>>,
     ) -> Result<Response<SignStreamResponse>, Status> {
+        let start = Instant::now();
+        let caller = get_caller(&request);
         let mut binaries = request.into_inner();
         let mut data: Vec<u8> = vec![];
         let mut key_name: String = "".to_string();
@@ -56,7 +88,15 @@ where
             key_type = inner_result.key_type;
             options = inner_result.options;
         }
-        match self.key_service.sign(key_type, key_name, &options, data).await {
+        //requests of identified caller are observed in key service once the key is resolved
+        let result = match caller {
+            Ok(caller) => self.key_service.sign(&caller, key_type, key_name, &options, data).await,
+            Err(err) => {
+                metrics::observe_sign::<()>("sign_stream", None, data.len(), start, &Err(err.clone()));
+                Err(err)
+            }
+        };
+        match result {
             Ok(content) => {
                 Ok(Response::new(SignStreamResponse {
                     signature: content,
@@ -71,6 +111,40 @@ where
             }
         }
     }
+
+    async fn sign_digest(
+        &self,
+        request: Request<SignDigestRequest>,
+    ) -> Result<Response<SignDigestResponse>, Status> {
+        let start = Instant::now();
+        let caller = get_caller(&request);
+        let request = request.into_inner();
+        let payload = request.hash_state.len() + request.remainder.len();
+        let result = match caller.and_then(|caller| DigestState::from_parts(
+            &request.hash_algorithm, &request.hash_state, request.hashed_length, request.remainder)
+            .map(|digest| (caller, digest))) {
+            Ok((caller, digest)) => self.key_service.sign_digest(
+                &caller, request.key_type, request.key_id, &request.options, digest).await,
+            Err(err) => {
+                metrics::observe_sign::<()>("sign_digest", None, payload, start, &Err(err.clone()));
+                Err(err)
+            }
+        };
+        match result {
+            Ok(content) => {
+                Ok(Response::new(SignDigestResponse {
+                    signature: content,
+                    error: "".to_string()
+                }))
+            }
+            Err(err) => {
+                Ok(Response::new(SignDigestResponse {
+                    signature: vec![],
+                    error: err.to_string(),
+                }))
+            }
+        }
+    }
 }
 
 pub fn get_grpc_handler<K>(key_service: K) -> SignatrustServer<SignHandler<K>>
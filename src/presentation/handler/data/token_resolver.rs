@@ -0,0 +1,199 @@
+use chrono::Utc;
+use futures::future::BoxFuture;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{HeaderMap, Request, Response};
+use tonic::codegen::Service;
+use tonic::Status;
+use tower::Layer;
+
+use crate::domain::token::repository::Repository as TokenRepositoryTrait;
+use crate::domain::user::repository::Repository as UserRepositoryTrait;
+use crate::infra::database::model::token::repository::TokenRepository;
+use crate::infra::database::model::user::repository::UserRepository;
+use crate::presentation::handler::control::model::user::dto::UserIdentity;
+use crate::util::error::{Error, Result};
+
+pub const AUTHORIZATION: &str = "authorization";
+
+//resolve the api token in request headers into user identity, requests without token are resolved to none
+//and will be identified by their client certificate.
+#[derive(Clone)]
+pub struct TokenResolver {
+    token_repository: TokenRepository,
+    user_repository: UserRepository,
+}
+
+impl TokenResolver {
+    pub fn new(token_repository: TokenRepository, user_repository: UserRepository) -> Self {
+        Self {
+            token_repository,
+            user_repository,
+        }
+    }
+
+    pub async fn resolve(&self, headers: &HeaderMap) -> Result<Option<UserIdentity>> {
+        let value = match headers.get(AUTHORIZATION) {
+            None => return Ok(None),
+            Some(value) => value.to_str().map_err(|_| Error::UnauthorizedError)?,
+        };
+        match self.get_user(value).await {
+            Ok(user) => Ok(Some(user)),
+            Err(err) => {
+                warn!("failed to validate api token: {}", err);
+                Err(Error::UnauthorizedError)
+            }
+        }
+    }
+
+    async fn get_user(&self, value: &str) -> Result<UserIdentity> {
+        let token = self.token_repository.get_token_by_value(value).await?;
+        if token.expire_at.le(&Utc::now()) {
+            warn!("token expired");
+            return Err(Error::UnauthorizedError);
+        }
+        Ok(UserIdentity::from(self.user_repository.get_by_id(token.user_id).await?))
+    }
+}
+
+//layer of the data server which resolves the api token before any of the grpc services is called, the user
+//identity is put into the request extensions. the tokens are refused when tls is disabled, since they would be
+//sent in cleartext.
+#[derive(Clone)]
+pub struct TokenLayer {
+    resolver: TokenResolver,
+    tls_enabled: bool,
+}
+
+impl TokenLayer {
+    pub fn new(resolver: TokenResolver, tls_enabled: bool) -> Self {
+        Self {
+            resolver,
+            tls_enabled,
+        }
+    }
+}
+
+impl<S> Layer<S> for TokenLayer {
+    type Service = TokenService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TokenService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TokenService<S> {
+    inner: S,
+    layer: TokenLayer,
+}
+
+impl<S, B> Service<Request<B>> for TokenService<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, std::result::Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        //the service which has been polled ready is taken, see `tower::Service`
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            if !layer.tls_enabled && request.headers().contains_key(AUTHORIZATION) {
+                return Ok(Status::unauthenticated("api token is refused since tls is disabled").to_http());
+            }
+            match layer.resolver.resolve(request.headers()).await {
+                Ok(Some(user)) => {
+                    request.extensions_mut().insert(user);
+                }
+                Ok(None) => {}
+                Err(err) => return Ok(Status::unauthenticated(err.to_string()).to_http()),
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::token::entity::Token;
+    use crate::domain::user::entity::User;
+    use crate::util::test_support::memory_pool;
+    use chrono::Duration;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceExt};
+
+    async fn resolver_with_tokens() -> (TokenResolver, i32, String, String) {
+        let pool = memory_pool().await;
+        let (token_repository, user_repository) = (TokenRepository::new(pool.clone()), UserRepository::new(pool));
+        let user = user_repository.create(&User::new("signatrust@example.com".to_string()).unwrap()).await.unwrap();
+        let valid = token_repository.create(&Token::new(user.id).unwrap()).await.unwrap();
+        let mut expired = Token::new(user.id).unwrap();
+        expired.expire_at = Utc::now() - Duration::days(1);
+        let expired = token_repository.create(&expired).await.unwrap();
+        (TokenResolver::new(token_repository, user_repository), user.id, valid.token, expired.token)
+    }
+
+    #[tokio::test]
+    async fn test_resolve_token() {
+        let (resolver, user_id, valid, expired) = resolver_with_tokens().await;
+
+        //missing token is left to client certificate
+        assert!(resolver.resolve(&HeaderMap::new()).await.unwrap().is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, valid.parse().unwrap());
+        let identity = resolver.resolve(&headers).await.unwrap().unwrap();
+        assert_eq!((identity.id, identity.email.as_str()), (user_id, "signatrust@example.com"));
+
+        headers.insert(AUTHORIZATION, expired.parse().unwrap());
+        assert!(matches!(resolver.resolve(&headers).await, Err(Error::UnauthorizedError)));
+
+        headers.insert(AUTHORIZATION, "unknown".parse().unwrap());
+        assert!(matches!(resolver.resolve(&headers).await, Err(Error::UnauthorizedError)));
+    }
+
+    #[tokio::test]
+    async fn test_token_layer() {
+        let (resolver, user_id, valid, expired) = resolver_with_tokens().await;
+        //the grpc service responds with the id of resolved user in header, or nothing when it's not resolved
+        let call = |tls_enabled: bool, token: Option<&str>| {
+            let layer = TokenLayer::new(resolver.clone(), tls_enabled);
+            let mut request = Request::new(());
+            if let Some(token) = token {
+                request.headers_mut().insert(AUTHORIZATION, token.parse().unwrap());
+            }
+            async move {
+                let service = layer.layer(service_fn(|request: Request<()>| async move {
+                    let mut response = Response::new(tonic::body::empty_body());
+                    if let Some(user) = request.extensions().get::<UserIdentity>() {
+                        response.headers_mut().insert("user", user.id.into());
+                    }
+                    Ok::<_, Infallible>(response)
+                }));
+                let response = service.oneshot(request).await.unwrap();
+                let status = Status::from_header_map(response.headers()).map(|status| status.code());
+                (status, response.headers().get("user").map(|id| id.to_str().unwrap().parse::<i32>().unwrap()))
+            }
+        };
+        assert_eq!(call(true, Some(&valid)).await, (None, Some(user_id)));
+        assert_eq!(call(true, None).await, (None, None));
+        assert_eq!(call(true, Some(&expired)).await, (Some(tonic::Code::Unauthenticated), None));
+        //valid token isn't accepted in cleartext
+        assert_eq!(call(false, Some(&valid)).await, (Some(tonic::Code::Unauthenticated), None));
+        assert_eq!(call(false, None).await, (None, None));
+    }
+}
@@ -0,0 +1,14 @@
+use actix_web::{HttpResponse, Responder, Result, web, Resource};
+
+use crate::util::error::Error;
+use crate::util::metrics;
+
+async fn get_metrics() -> Result<impl Responder, Error> {
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render()?))
+}
+
+pub fn get_resource() -> Resource {
+    web::resource("/metrics").route(web::get().to(get_metrics))
+}
@@ -1,2 +1,3 @@
 pub mod control;
 pub mod data;
+pub mod metrics_handler;
\ No newline at end of file
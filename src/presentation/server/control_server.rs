@@ -8,11 +8,13 @@ use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
 use actix_identity::IdentityMiddleware;
 use actix_session::{config::PersistentSession, storage::CookieSessionStore, SessionMiddleware};
 use time::Duration as timeDuration;
+use tokio::time::{Duration, interval};
 
 use crate::infra::database::model::datakey::repository as datakeyRepository;
 use crate::infra::database::pool::{create_pool, get_db_pool};
 
 use crate::presentation::handler::control::*;
+use crate::presentation::handler::metrics_handler;
 
 use crate::util::error::Result;
 use openidconnect::core::{
@@ -24,6 +26,11 @@ use crate::infra::database::model::token::repository::TokenRepository;
 use crate::infra::database::model::user::repository::UserRepository;
 use crate::infra::sign_backend::factory::SignBackendFactory;
 use crate::application::user::{DBUserService, UserService};
+use crate::application::audit::{AuditService, DBAuditService};
+use crate::infra::database::model::audit::repository::SignRecordRepository;
+use crate::application::timestamp::{DBTimestampService, TimestampService};
+use crate::infra::database::model::timestamp::repository::TimestampRecordRepository;
+use crate::domain::sign_service::SignBackend;
 
 
 pub struct OIDCConfig {
@@ -72,6 +79,36 @@ impl ControlServer {
 
 
 
+    //re-encrypt all of the data keys with the latest cluster key, performed by administrator before retiring old cluster keys
+    pub async fn reencrypt_keys(&self) -> Result<()> {
+        let sign_backend: Arc<dyn SignBackend> = Arc::from(SignBackendFactory::new_engine(
+            self.server_config.clone(), get_db_pool()?).await?);
+        let key_service = DBKeyService::new(
+            datakeyRepository::DataKeyRepository::new(get_db_pool()?), sign_backend, SignRecordRepository::new(get_db_pool()?));
+        let count = key_service.reencrypt_keys().await?;
+        info!("{} data keys have been re-encrypted with the latest cluster key", count);
+        Ok(())
+    }
+
+    //keys are disabled by the background job once they are expired
+    fn start_expiry_job(&self, key_service: web::Data<dyn KeyService>) -> Result<()> {
+        let period: u64 = self.server_config
+            .read()?
+            .get_string("control-server.key_expiry_check_interval")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse()?;
+        tokio::spawn(async move {
+            let mut ticker = interval(Duration::from_secs(period));
+            loop {
+                ticker.tick().await;
+                if let Err(err) = key_service.disable_expired().await {
+                    error!("failed to disable expired keys: {}", err);
+                }
+            }
+        });
+        Ok(())
+    }
+
     pub async fn run(&self) -> Result<()> {
         //start actix web server
         let addr: SocketAddr = format!(
@@ -97,8 +134,8 @@ impl ControlServer {
         let data_repository = datakeyRepository::DataKeyRepository::new(
             get_db_pool()?,
         );
-        let sign_backend = SignBackendFactory::new_engine(
-            self.server_config.clone(), get_db_pool()?).await?;
+        let sign_backend: Arc<dyn SignBackend> = Arc::from(SignBackendFactory::new_engine(
+            self.server_config.clone(), get_db_pool()?).await?);
         //initialize user repo
         let user_repo = UserRepository::new(get_db_pool()?);
 
@@ -107,7 +144,35 @@ impl ControlServer {
 
         let user_service = web::Data::from(Arc::new(DBUserService::new(user_repo.clone(), token_repo)) as Arc<dyn UserService>);
 
-        let key_service = web::Data::from(Arc::new(DBKeyService::new(data_repository, sign_backend)) as Arc<dyn KeyService>);
+        //initialize audit repo
+        let audit_repo = SignRecordRepository::new(get_db_pool()?);
+
+        let key_service = DBKeyService::new(data_repository.clone(), sign_backend, audit_repo.clone());
+        //keys cached for the timestamp authority are dropped once they are updated
+        let refresh_interval = self.server_config.read()?.get_string("control-server.key_refresh_interval").unwrap_or_else(|_| "10".to_string());
+        key_service.start_key_refresh(Duration::from_secs(refresh_interval.parse()?));
+        let audit_writer = key_service.audit_writer();
+        let key_service = web::Data::from(Arc::new(key_service) as Arc<dyn KeyService>);
+        self.start_expiry_job(key_service.clone())?;
+
+        let audit_service = web::Data::from(Arc::new(DBAuditService::new(audit_repo)) as Arc<dyn AuditService>);
+
+        //built-in timestamp authority is enabled only when the tsa key is configured
+        let tsa_key = self.server_config.read()?.get_string("timestamp-authority.key_name").unwrap_or_default();
+        let timestamp_service = if tsa_key.is_empty() {
+            None
+        } else {
+            info!("timestamp authority enabled with key {}", tsa_key);
+            let rate_limit = timestamp_handler::rate_limit(
+                self.server_config.read()?.get_string("timestamp-authority.rate_limit").unwrap_or_else(|_| "20".to_string()).parse()?,
+                self.server_config.read()?.get_string("timestamp-authority.rate_limit_burst").unwrap_or_else(|_| "40".to_string()).parse()?)?;
+            Some((web::Data::from(Arc::new(DBTimestampService::new(
+                data_repository,
+                TimestampRecordRepository::new(get_db_pool()?),
+                key_service.clone().into_inner(),
+                tsa_key,
+                self.server_config.read()?.get_string("timestamp-authority.policy")?)?) as Arc<dyn TimestampService>), rate_limit))
+        };
 
         let http_server = HttpServer::new(move || {
             App::new()
@@ -115,6 +180,7 @@ impl ControlServer {
                 .app_data(key_service.clone())
                 .app_data(client.clone())
                 .app_data(user_service.clone())
+                .app_data(audit_service.clone())
                 .app_data(oidc_config.clone())
                 .wrap(middleware::Logger::default())
                 .wrap(IdentityMiddleware::default())
@@ -128,9 +194,16 @@ impl ControlServer {
                         .cookie_path("/".to_owned())
                         .build(),
                 )
+                .service(metrics_handler::get_resource())
                 .service(web::scope("/api/v1")
                     .service(user_handler::get_scope())
-                    .service(datakey_handler::get_scope()))
+                    .service(datakey_handler::get_scope())
+                    .service(audit_handler::get_scope())
+                    .configure(|cfg| {
+                        if let Some((timestamp_service, rate_limit)) = &timestamp_service {
+                            cfg.app_data(timestamp_service.clone()).service(timestamp_handler::get_scope(rate_limit));
+                        }
+                    }))
         });
         if self.server_config
             .read()?
@@ -152,6 +225,8 @@ impl ControlServer {
                 self.server_config.read()?.get_string("tls_cert")?).unwrap();
             http_server.bind_openssl(addr, builder)?.run().await?;
         }
+        //the requests have been drained, flush the audit records queued by them
+        audit_writer.close().await;
         Ok(())
     }
 }
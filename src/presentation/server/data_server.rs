@@ -13,11 +13,18 @@ use tonic::{
 use crate::application::datakey::DBKeyService;
 
 use crate::infra::database::model::datakey::repository;
+use crate::infra::database::model::audit::repository::SignRecordRepository;
+use crate::infra::database::model::token::repository::TokenRepository;
+use crate::infra::database::model::user::repository::UserRepository;
 use crate::infra::database::pool::{create_pool, get_db_pool};
 use crate::infra::sign_backend::factory::SignBackendFactory;
+use crate::domain::sign_service::SignBackend;
 
 
 use crate::presentation::handler::data::sign_handler::get_grpc_handler;
+use crate::presentation::handler::metrics_handler;
+use actix_web::{App, HttpServer};
+use crate::presentation::handler::data::token_resolver::{TokenLayer, TokenResolver};
 use crate::util::error::Result;
 
 pub struct DataServer
@@ -26,6 +33,7 @@ pub struct DataServer
     signal: Arc<AtomicBool>,
     server_identity: Option<Identity>,
     ca_cert: Option<Certificate>,
+    require_client_cert: bool,
 }
 
 impl DataServer {
@@ -37,6 +45,7 @@ impl DataServer {
             signal,
             server_identity: None,
             ca_cert: None,
+            require_client_cert: true,
         };
         server.load().await?;
         Ok(server)
@@ -57,12 +66,16 @@ impl DataServer {
             info!("tls key and cert not configured, data server tls will be disabled");
             return Ok(());
         }
-        self.ca_cert = Some(
-            Certificate::from_pem(
-                fs::read(self.server_config.read()?.get_string("ca_root")?).await?));
+        //client certificate can be skipped when all of the clients are authenticated via api token
+        self.require_client_cert = self.server_config.read()?.get_bool("data-server.require_client_cert").unwrap_or(true);
+        let (ca_root, tls_cert, tls_key) = {
+            let config = self.server_config.read()?;
+            (config.get_string("ca_root")?, config.get_string("tls_cert")?, config.get_string("tls_key")?)
+        };
+        self.ca_cert = Some(Certificate::from_pem(fs::read(ca_root).await?));
         self.server_identity = Some(Identity::from_pem(
-            fs::read(self.server_config.read()?.get_string("tls_cert")?).await?,
-            fs::read(self.server_config.read()?.get_string("tls_key")?).await?));
+            fs::read(tls_cert).await?,
+            fs::read(tls_key).await?));
         Ok(())
     }
 
@@ -73,6 +86,29 @@ impl DataServer {
         info!("quit signal received...")
     }
 
+    //prometheus metrics are exposed via http on a side port
+    fn start_metrics_server(&self) -> Result<Option<actix_web::dev::ServerHandle>> {
+        let port = self.server_config.read()?.get_string("data-server.metrics_port").unwrap_or_default();
+        if port.is_empty() {
+            info!("metrics port not configured, data server metrics will be disabled");
+            return Ok(None);
+        }
+        let addr: SocketAddr = format!(
+            "{}:{}",
+            self.server_config.read()?.get_string("data-server.server_ip")?,
+            port
+        ).parse()?;
+        let server = HttpServer::new(|| App::new().service(metrics_handler::get_resource()))
+            .workers(1)
+            .disable_signals()
+            .bind(addr)?
+            .run();
+        let handle = server.handle();
+        tokio::spawn(server);
+        info!("data server metrics starts at {}", addr);
+        Ok(Some(handle))
+    }
+
     pub async fn run(&self) -> Result<()> {
         //start grpc server
         let addr: SocketAddr = format!(
@@ -86,25 +122,48 @@ impl DataServer {
         )
         .parse()?;
 
-        let mut server = Server::builder();
+        let metrics_server = self.start_metrics_server()?;
         info!("data server starts");
-        let sign_backend = SignBackendFactory::new_engine(
-            self.server_config.clone(), get_db_pool()?).await?;
+        let sign_backend: Arc<dyn SignBackend> = Arc::from(SignBackendFactory::new_engine(
+            self.server_config.clone(), get_db_pool()?).await?);
         let data_repository = repository::DataKeyRepository::new(
             get_db_pool()?);
-        let key_service = DBKeyService::new(data_repository, sign_backend);
+        let key_service = DBKeyService::new(
+            data_repository, sign_backend, SignRecordRepository::new(get_db_pool()?));
+        let refresh_interval = self.server_config.read()?.get_string("data-server.key_refresh_interval").unwrap_or_else(|_| "10".to_string());
+        key_service.start_key_refresh(Duration::from_secs(refresh_interval.parse()?));
+        let audit_writer = key_service.audit_writer();
+        let token_resolver = TokenResolver::new(
+            TokenRepository::new(get_db_pool()?), UserRepository::new(get_db_pool()?));
+        //the api token is resolved before the request reaches any of the grpc services
+        let mut server = Server::builder()
+            .layer(TokenLayer::new(token_resolver, self.server_identity.is_some()));
         if let Some(identity) = self.server_identity.clone() {
+            let mut tls_config = ServerTlsConfig::new().identity(identity);
+            //tonic can't request the client certificate optionally, once disabled, all clients including the ones
+            //having certificate must be authenticated with api token
+            if self.require_client_cert {
+                tls_config = tls_config.client_ca_root(self.ca_cert.clone().unwrap());
+            } else {
+                warn!("client certificate is not required, callers can only be identified by api token");
+            }
             server
-                .tls_config(ServerTlsConfig::new().identity(identity).client_ca_root(self.ca_cert.clone().unwrap()))?
+                .tls_config(tls_config)?
                 .add_service(get_grpc_handler(key_service))
                 .serve_with_shutdown(addr, self.shutdown_signal())
                 .await?
         } else {
+            warn!("data server tls is disabled, api tokens are refused and callers can't be identified");
             server
                 .add_service(get_grpc_handler(key_service))
                 .serve_with_shutdown(addr, self.shutdown_signal())
                 .await?
         }
+        //the requests have been drained, flush the audit records queued by them
+        audit_writer.close().await;
+        if let Some(handle) = metrics_server {
+            handle.stop(true).await;
+        }
         Ok(())
     }
 }
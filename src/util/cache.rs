@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+/// In memory cache whose entries are expired after the ttl, used for holding the decrypted
+/// secrets and parsed keys which are expensive to build on every request.
+pub struct TTLCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone
+{
+    ttl: Duration,
+    entries: RwLock<HashMap<K, (V, Instant)>>,
+    //bumped on every removal, the value loaded before the removal isn't cached, the generations are kept for
+    //the removed keys only, which are bounded by the keys ever invalidated
+    generations: Mutex<HashMap<K, u64>>,
+    flights: SingleFlight<K>,
+}
+
+impl<K, V> TTLCache<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone
+{
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+            generations: Mutex::new(HashMap::new()),
+            flights: SingleFlight::new(),
+        }
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.entries.read() {
+            Ok(entries) => entries
+                .get(key)
+                .filter(|(_, create_at)| create_at.elapsed() < self.ttl)
+                .map(|(value, _)| value.clone()),
+            Err(_) => None,
+        }
+    }
+
+    //expired entries are purged when inserting, so the cache never grows beyond the live entries
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_if(key, value, None);
+    }
+
+    //the value is only inserted when the key isn't removed since the generation is read
+    fn insert_if(&self, key: K, value: V, generation: Option<u64>) {
+        if let Ok(mut entries) = self.entries.write() {
+            if generation.is_some_and(|generation| generation != self.generation(&key)) {
+                return;
+            }
+            entries.retain(|_, (_, create_at)| create_at.elapsed() < self.ttl);
+            entries.insert(key, (value, Instant::now()));
+        }
+    }
+
+    pub fn remove(&self, key: &K) {
+        if let Ok(mut entries) = self.entries.write() {
+            entries.remove(key);
+            if let Ok(mut generations) = self.generations.lock() {
+                *generations.entry(key.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    fn generation(&self, key: &K) -> u64 {
+        match self.generations.lock() {
+            Ok(generations) => generations.get(key).copied().unwrap_or_default(),
+            Err(_) => 0,
+        }
+    }
+
+    //concurrent misses of the same key wait for the first loader instead of loading the value once again
+    pub async fn get_or_load<F, Fut, E>(&self, key: K, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        let _flight = self.flights.acquire(key.clone()).await;
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+        //the value loaded before the key is removed is returned to this caller, but never cached
+        let generation = self.generation(&key);
+        let value = load().await?;
+        self.insert_if(key, value.clone(), Some(generation));
+        Ok(value)
+    }
+}
+
+/// Serializes the loading of the same key, the lock of the key is dropped once nobody is waiting for it.
+pub struct SingleFlight<K>
+where
+    K: Eq + Hash + Clone
+{
+    locks: Arc<Mutex<HashMap<K, Arc<AsyncMutex<()>>>>>,
+}
+
+impl<K> SingleFlight<K>
+where
+    K: Eq + Hash + Clone
+{
+    pub fn new() -> Self {
+        Self {
+            locks: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn acquire(&self, key: K) -> Flight<K> {
+        let lock = match self.locks.lock() {
+            Ok(mut locks) => locks.entry(key.clone()).or_default().clone(),
+            Err(_) => Arc::new(AsyncMutex::new(())),
+        };
+        Flight {
+            locks: self.locks.clone(),
+            key,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+}
+
+impl<K> Default for SingleFlight<K>
+where
+    K: Eq + Hash + Clone
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for SingleFlight<K>
+where
+    K: Eq + Hash + Clone
+{
+    //the cloned flights share the locks
+    fn clone(&self) -> Self {
+        Self {
+            locks: self.locks.clone(),
+        }
+    }
+}
+
+pub struct Flight<K>
+where
+    K: Eq + Hash + Clone
+{
+    locks: Arc<Mutex<HashMap<K, Arc<AsyncMutex<()>>>>>,
+    key: K,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl<K> Drop for Flight<K>
+where
+    K: Eq + Hash + Clone
+{
+    fn drop(&mut self) {
+        self.guard.take();
+        if let Ok(mut locks) = self.locks.lock() {
+            //the lock is only referenced by the map when there is neither holder nor waiter
+            if locks.get(&self.key).is_some_and(|lock| Arc::strong_count(lock) == 1) {
+                locks.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let cache = TTLCache::new(Duration::from_millis(50));
+        cache.insert(1, "one".to_string());
+        assert_eq!(cache.get(&1), Some("one".to_string()));
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&1), None);
+    }
+
+    #[test]
+    fn test_removed_entries_are_gone() {
+        let cache = TTLCache::new(Duration::from_secs(60));
+        cache.insert(1, "one".to_string());
+        cache.insert(2, "two".to_string());
+        cache.remove(&1);
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&2), Some("two".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_load_once() {
+        let cache = Arc::new(TTLCache::new(Duration::from_secs(60)));
+        let loads = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..8).map(|_| {
+            let (cache, loads) = (cache.clone(), loads.clone());
+            tokio::spawn(async move {
+                cache.get_or_load(1, || async {
+                    loads.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    Ok::<String, ()>("one".to_string())
+                }).await
+            })
+        }).collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), Ok("one".to_string()));
+        }
+        assert_eq!(loads.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(cache.flights.locks.lock().unwrap().is_empty());
+
+        //failed loading isn't cached
+        assert_eq!(cache.get_or_load(2, || async { Err::<String, &str>("failed") }).await, Err("failed"));
+        assert_eq!(cache.get(&2), None);
+    }
+
+    #[tokio::test]
+    async fn test_remove_during_load() {
+        let cache = Arc::new(TTLCache::new(Duration::from_secs(60)));
+        let (started, loaded) = (Arc::new(tokio::sync::Notify::new()), Arc::new(tokio::sync::Notify::new()));
+        let task = {
+            let (cache, started, loaded) = (cache.clone(), started.clone(), loaded.clone());
+            tokio::spawn(async move {
+                cache.get_or_load(1, || async move {
+                    started.notify_one();
+                    loaded.notified().await;
+                    Ok::<String, ()>("stale".to_string())
+                }).await
+            })
+        };
+        started.notified().await;
+        //the key is invalidated while it's being loaded
+        cache.remove(&1);
+        loaded.notify_one();
+        assert_eq!(task.await.unwrap(), Ok("stale".to_string()));
+        assert_eq!(cache.get(&1), None);
+
+        //the loads started after removal are cached as usual
+        assert_eq!(cache.get_or_load(1, || async { Ok::<String, ()>("fresh".to_string()) }).await, Ok("fresh".to_string()));
+        assert_eq!(cache.get(&1), Some("fresh".to_string()));
+    }
+}
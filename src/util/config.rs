@@ -25,6 +25,7 @@ impl ServerConfig {
             path,
         }
     }
+    #[allow(deprecated)]
     pub fn watch(&self, signal: Arc<AtomicBool>) -> Result<()> {
         let (tx, rx) = channel();
         let watch_file = self.path.clone();
@@ -0,0 +1,169 @@
+//! Minimal DER encoder/decoder used when building or inspecting signature structures openssl
+//! doesn't expose, for instance the Authenticode SpcIndirectDataContent.
+
+use crate::util::error::{Error, Result};
+
+pub const TAG_BOOLEAN: u8 = 0x01;
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_UTF8_STRING: u8 = 0x0c;
+pub const TAG_UTC_TIME: u8 = 0x17;
+pub const TAG_GENERALIZED_TIME: u8 = 0x18;
+pub const TAG_SEQUENCE: u8 = 0x30;
+pub const TAG_SET: u8 = 0x31;
+pub const TAG_CONTEXT_CONSTRUCTED: u8 = 0xa0;
+
+pub fn encode_length(length: usize) -> Vec<u8> {
+    if length < 0x80 {
+        return vec![length as u8];
+    }
+    let bytes: Vec<u8> = length
+        .to_be_bytes()
+        .iter()
+        .skip_while(|b| **b == 0)
+        .cloned()
+        .collect();
+    let mut result = vec![0x80 | bytes.len() as u8];
+    result.extend(bytes);
+    result
+}
+
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut result = vec![tag];
+    result.extend(encode_length(content.len()));
+    result.extend_from_slice(content);
+    result
+}
+
+pub fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    tlv(TAG_SEQUENCE, &items.concat())
+}
+
+/// DER requires the elements of SET OF to be sorted by their encoding
+pub fn set(items: &[Vec<u8>]) -> Vec<u8> {
+    tlv(TAG_SET, &sorted(items))
+}
+
+/// context specific tag, `[number]` in asn1 notation
+pub fn context(number: u8, constructed: bool, content: &[u8]) -> Vec<u8> {
+    let mut tag = 0x80 | number;
+    if constructed {
+        tag |= 0x20;
+    }
+    tlv(tag, content)
+}
+
+/// implicitly tagged SET OF, for example `certificates [0] IMPLICIT SET OF Certificate`
+pub fn context_set(number: u8, items: &[Vec<u8>]) -> Vec<u8> {
+    context(number, true, &sorted(items))
+}
+
+pub fn null() -> Vec<u8> {
+    vec![TAG_NULL, 0x00]
+}
+
+pub fn boolean(value: bool) -> Vec<u8> {
+    tlv(TAG_BOOLEAN, &[if value { 0xff } else { 0x00 }])
+}
+
+pub fn octet_string(content: &[u8]) -> Vec<u8> {
+    tlv(TAG_OCTET_STRING, content)
+}
+
+/// encode unsigned big endian bytes as INTEGER
+pub fn integer(value: &[u8]) -> Vec<u8> {
+    let mut content: Vec<u8> = value.iter().skip_while(|b| **b == 0).cloned().collect();
+    if content.is_empty() || content[0] & 0x80 != 0 {
+        content.insert(0, 0);
+    }
+    tlv(TAG_INTEGER, &content)
+}
+
+pub fn small_integer(value: u64) -> Vec<u8> {
+    integer(&value.to_be_bytes())
+}
+
+pub fn oid(components: &[u64]) -> Vec<u8> {
+    let mut content = vec![(components[0] * 40 + components[1]) as u8];
+    for component in components.iter().skip(2) {
+        let mut encoded = vec![(component & 0x7f) as u8];
+        let mut value = component >> 7;
+        while value > 0 {
+            encoded.insert(0, 0x80 | (value & 0x7f) as u8);
+            value >>= 7;
+        }
+        content.extend(encoded);
+    }
+    tlv(TAG_OID, &content)
+}
+
+/// parse the dotted representation of object identifier, for instance `1.2.840.113549`
+pub fn parse_oid(value: &str) -> Result<Vec<u64>> {
+    let components = value
+        .split('.')
+        .map(|component| component.parse::<u64>())
+        .collect::<std::result::Result<Vec<u64>, _>>()
+        .map_err(|_| Error::ParameterError(format!("invalid object identifier {}", value)))?;
+    if components.len() < 2 || components[0] > 2 || components[1] >= 40 {
+        return Err(Error::ParameterError(format!("invalid object identifier {}", value)));
+    }
+    Ok(components)
+}
+
+/// AlgorithmIdentifier with NULL parameters
+pub fn algorithm_identifier(algorithm: &[u64]) -> Vec<u8> {
+    sequence(&[oid(algorithm), null()])
+}
+
+/// strip the tag and length of a single DER element and return its content
+pub fn content_of(element: &[u8]) -> &[u8] {
+    if element.len() < 2 {
+        return &[];
+    }
+    let header = if element[1] & 0x80 == 0 {
+        2
+    } else {
+        2 + (element[1] & 0x7f) as usize
+    };
+    &element[header.min(element.len())..]
+}
+
+/// split the first element out of data, return its tag, the whole element and the rest of data
+pub fn next(data: &[u8]) -> Result<(u8, &[u8], &[u8])> {
+    let invalid = || Error::EncodeError("invalid der encoding".to_string());
+    let tag = *data.first().ok_or_else(invalid)?;
+    let first = *data.get(1).ok_or_else(invalid)?;
+    let (header, length) = if first & 0x80 == 0 {
+        (2, first as usize)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > std::mem::size_of::<usize>() {
+            return Err(invalid());
+        }
+        let bytes = data.get(2..2 + count).ok_or_else(invalid)?;
+        (2 + count, bytes.iter().fold(0usize, |length, b| (length << 8) | *b as usize))
+    };
+    let end = header.checked_add(length).filter(|end| *end <= data.len()).ok_or_else(invalid)?;
+    Ok((tag, &data[..end], &data[end..]))
+}
+
+/// all of the elements inside of a constructed content, in the form of (tag, whole element)
+pub fn children(content: &[u8]) -> Result<Vec<(u8, &[u8])>> {
+    let mut result = vec![];
+    let mut rest = content;
+    while !rest.is_empty() {
+        let (tag, element, remaining) = next(rest)?;
+        result.push((tag, element));
+        rest = remaining;
+    }
+    Ok(result)
+}
+
+fn sorted(items: &[Vec<u8>]) -> Vec<u8> {
+    let mut sorted = items.to_vec();
+    sorted.sort();
+    sorted.concat()
+}
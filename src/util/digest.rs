@@ -0,0 +1,185 @@
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::str::FromStr;
+
+use generic_array::GenericArray;
+use sha2::compress256;
+
+use crate::util::error::{Error, Result};
+
+const SHA256_BLOCK_SIZE: usize = 64;
+//sha256 encodes the message length in 64 bits, i.e. at most 2^61 bytes
+const SHA256_MAX_LENGTH: u64 = 1 << 61;
+const SHA256_INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    Sha256,
+}
+
+impl FromStr for DigestAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha256" => Ok(DigestAlgorithm::Sha256),
+            _ => Err(Error::UnsupportedTypeError(format!("{} digest algorithm", s))),
+        }
+    }
+}
+
+impl Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut Formatter) -> fmtResult {
+        match self {
+            DigestAlgorithm::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+/// Resumable digest, the client hashes the content locally and only transfers the intermediate state,
+/// the server resumes from it so that signature formats which append their own data to the hashed
+/// content (for instance the openpgp signature trailer) can still be finalized without the content.
+#[derive(Debug, Clone)]
+pub struct DigestState {
+    algorithm: DigestAlgorithm,
+    state: [u32; 8],
+    //bytes which have been compressed into state, always multiple of block size
+    hashed_length: u64,
+    remainder: Vec<u8>,
+}
+
+impl DigestState {
+    pub fn new(algorithm: DigestAlgorithm) -> Self {
+        Self {
+            algorithm,
+            state: SHA256_INITIAL_STATE,
+            hashed_length: 0,
+            remainder: Vec::with_capacity(SHA256_BLOCK_SIZE),
+        }
+    }
+
+    pub fn from_parts(algorithm: &str, state: &[u8], hashed_length: u64, remainder: Vec<u8>) -> Result<Self> {
+        let algorithm = DigestAlgorithm::from_str(algorithm)?;
+        if state.len() != SHA256_INITIAL_STATE.len() * 4 {
+            return Err(Error::ParameterError(format!("invalid {} hash state length {}", algorithm, state.len())));
+        }
+        if hashed_length & (SHA256_BLOCK_SIZE as u64 - 1) != 0 || remainder.len() >= SHA256_BLOCK_SIZE {
+            return Err(Error::ParameterError(format!("invalid {} hashed length or remainder", algorithm)));
+        }
+        if hashed_length >= SHA256_MAX_LENGTH {
+            return Err(Error::ParameterError(format!("{} hashed length {} exceeds the limit", algorithm, hashed_length)));
+        }
+        let mut words = [0u32; 8];
+        for (index, word) in state.chunks_exact(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        Ok(Self {
+            algorithm,
+            state: words,
+            hashed_length,
+            remainder,
+        })
+    }
+
+    pub fn algorithm(&self) -> DigestAlgorithm {
+        self.algorithm
+    }
+
+    pub fn state(&self) -> Vec<u8> {
+        self.state.iter().flat_map(|word| word.to_be_bytes()).collect()
+    }
+
+    pub fn hashed_length(&self) -> u64 {
+        self.hashed_length
+    }
+
+    pub fn remainder(&self) -> &[u8] {
+        &self.remainder
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut data = data;
+        if !self.remainder.is_empty() {
+            let required = (SHA256_BLOCK_SIZE - self.remainder.len()).min(data.len());
+            self.remainder.extend_from_slice(&data[..required]);
+            data = &data[required..];
+            if self.remainder.len() < SHA256_BLOCK_SIZE {
+                return;
+            }
+            let block = std::mem::take(&mut self.remainder);
+            self.compress(&block);
+        }
+        let complete = data.len() - data.len() % SHA256_BLOCK_SIZE;
+        self.compress(&data[..complete]);
+        self.remainder.extend_from_slice(&data[complete..]);
+    }
+
+    pub fn finalize(mut self) -> Vec<u8> {
+        //the length is taken modulo 2^64 bits as other implementations do, in case the appended data exceeds the limit
+        let bit_length = self.hashed_length.wrapping_add(self.remainder.len() as u64).wrapping_mul(8);
+        //0x80, zeros and the 64 bits length should fill up the last block
+        let zeros = (SHA256_BLOCK_SIZE * 2 - 9 - self.remainder.len()) % SHA256_BLOCK_SIZE;
+        let mut padding = vec![0x80];
+        padding.resize(1 + zeros, 0);
+        padding.extend_from_slice(&bit_length.to_be_bytes());
+        self.update(&padding);
+        self.state()
+    }
+
+    fn compress(&mut self, blocks: &[u8]) {
+        let blocks: Vec<GenericArray<u8, _>> = blocks
+            .chunks_exact(SHA256_BLOCK_SIZE)
+            .map(GenericArray::clone_from_slice)
+            .collect();
+        compress256(&mut self.state, &blocks);
+        self.hashed_length += (blocks.len() * SHA256_BLOCK_SIZE) as u64;
+    }
+}
+
+impl std::io::Write for DigestState {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use openssl::sha::sha256;
+
+    #[test]
+    fn test_resumed_digest_matches_sha256() {
+        let content: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        for length in [0, 1, 55, 56, 63, 64, 65, 127, 1000] {
+            let mut local = DigestState::new(DigestAlgorithm::Sha256);
+            local.update(&content[..length / 3]);
+            local.update(&content[length / 3..length]);
+            let resumed = DigestState::from_parts(
+                &local.algorithm().to_string(),
+                &local.state(),
+                local.hashed_length(),
+                local.remainder().to_vec(),
+            ).unwrap();
+            assert_eq!(resumed.finalize(), sha256(&content[..length]).to_vec());
+        }
+    }
+
+    #[test]
+    fn test_invalid_digest_parts() {
+        let state = DigestState::new(DigestAlgorithm::Sha256).state();
+        assert!(DigestState::from_parts("sha256", &state, 128, vec![1; 63]).is_ok());
+        assert!(DigestState::from_parts("sha256", &state[..31], 128, vec![]).is_err());
+        assert!(DigestState::from_parts("sha256", &state, 129, vec![]).is_err());
+        assert!(DigestState::from_parts("sha256", &state, 128, vec![1; 64]).is_err());
+        assert!(DigestState::from_parts("sha256", &state, SHA256_MAX_LENGTH - 64, vec![]).is_ok());
+        assert!(DigestState::from_parts("sha256", &state, SHA256_MAX_LENGTH, vec![]).is_err());
+        assert!(DigestState::from_parts("sha256", &state, u64::MAX - 63, vec![]).is_err());
+        assert!(DigestState::from_parts("sha512", &state, 128, vec![]).is_err());
+    }
+}
@@ -5,6 +5,7 @@ use reqwest::header::{InvalidHeaderValue, ToStrError as StrError};
 use reqwest::Error as RequestError;
 use serde_json::Error as SerdeError;
 use sqlx::Error as SqlxError;
+use sqlx::migrate::MigrateError;
 use std::io::Error as IOError;
 use std::net::AddrParseError;
 use std::num::ParseIntError;
@@ -13,7 +14,7 @@ use std::sync::PoisonError;
 use rpm::RPMError;
 use thiserror::Error as ThisError;
 use tonic::transport::Error as TonicError;
-use bincode::error::EncodeError;
+use bincode::error::{DecodeError, EncodeError};
 use chrono::{OutOfRangeError, ParseError};
 use actix_web::{ResponseError, HttpResponse};
 use validator::ValidationErrors;
@@ -23,6 +24,7 @@ use actix_web::cookie::KeyError;
 use openidconnect::url::ParseError as OIDCParseError;
 use openidconnect::ConfigurationError;
 use openidconnect::UserInfoError;
+use crate::util::metrics;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -57,12 +59,22 @@ pub enum Error {
     PGPInvokeError(String),
     #[error("failed to perform openssl {0}")]
     X509InvokeError(String),
+    #[error("failed to perform pkcs11 {0}")]
+    PKCS11InvokeError(String),
+    #[error("failed to acquire rfc3161 timestamp: {0}")]
+    TimestampError(String),
     #[error("invalid parameter error {0}")]
     ParameterError(String),
+    #[error("key {0} has expired")]
+    KeyExpiredError(String),
+    #[error("failed to audit the operation: {0}")]
+    AuditError(String),
     #[error("record not found error")]
     NotFoundError,
     #[error("invalid user")]
     UnauthorizedError,
+    #[error("permission denied: {0}")]
+    ForbiddenError(String),
     #[error("invalid cookie key found")]
     InvalidCookieKeyError,
     #[error("failed to perform auth operation: {0}")]
@@ -79,15 +91,17 @@ pub enum Error {
     RemoteSignError(String),
     #[error("failed to assemble file: {0}")]
     AssembleFileError(String),
+    #[error("failed to verify signature: {0}")]
+    VerifyError(String),
     #[error("failed to walk through directory: {0}")]
     WalkDirectoryError(String),
     #[error("failed to parse rpm file: {0}")]
     RpmParseError(String),
     #[error("invalid argument: {0}")]
     InvalidArgumentError(String),
-    #[error("failed to encode in bincode: {0}")]
+    #[error("failed to encode/decode in bincode: {0}")]
     BincodeError(String),
-    #[error("failed to sign some of the files")]
+    #[error("failed to sign or verify some of the files")]
     PartialFailureError,
 }
 
@@ -98,27 +112,34 @@ pub struct ErrorMessage {
 
 impl ResponseError for Error {
     fn error_response(&self) -> HttpResponse {
+        metrics::record_error(self);
         match self {
             Error::ParameterError(_) | Error::UnsupportedTypeError(_) => {
-                warn!("parameter error: {}", self.to_string());
+                warn!("parameter error: {}", self);
                 HttpResponse::BadRequest().json(ErrorMessage{
                     detail: self.to_string()
                 })
             }
             Error::NotFoundError => {
-                warn!("record not found error: {}", self.to_string());
+                warn!("record not found error: {}", self);
                 HttpResponse::NotFound().json(ErrorMessage{
                     detail: self.to_string()
                 })
             }
             Error::UnauthorizedError => {
-                warn!("authorized: {}", self.to_string());
+                warn!("authorized: {}", self);
                 HttpResponse::Unauthorized().json(ErrorMessage{
                     detail: self.to_string()
                 })
             }
+            Error::ForbiddenError(_) => {
+                warn!("forbidden: {}", self);
+                HttpResponse::Forbidden().json(ErrorMessage{
+                    detail: self.to_string()
+                })
+            }
             _ => {
-                warn!("internal error: {}", self.to_string());
+                warn!("internal error: {}", self);
                 HttpResponse::InternalServerError().json(ErrorMessage{
                     detail: self.to_string(),
                 })
@@ -147,6 +168,12 @@ impl From<SqlxError> for Error {
     }
 }
 
+impl From<MigrateError> for Error {
+    fn from(error: MigrateError) -> Self {
+        Error::DatabaseError(format!("failed to migrate database: {}", error))
+    }
+}
+
 impl From<ParseIntError> for Error {
     fn from(error: ParseIntError) -> Self {
         Error::ConfigError(error.to_string())
@@ -244,6 +271,12 @@ impl From<EncodeError> for Error {
     }
 }
 
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        Error::BincodeError(err.to_string())
+    }
+}
+
 impl From<OutOfRangeError> for Error {
     fn from(err: OutOfRangeError) -> Self {
         Error::ConvertError(err.to_string())
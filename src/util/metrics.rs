@@ -0,0 +1,148 @@
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    HistogramVec, IntCounter, IntCounterVec, TextEncoder,
+};
+
+use std::time::Instant;
+
+use crate::domain::datakey::entity::DataKey;
+use crate::util::error::{Error, Result};
+
+//label of the key when the requested one can't be resolved, the requested type and name are supplied by caller
+//and would otherwise create unbounded number of series
+pub const UNKNOWN: &str = "unknown";
+
+lazy_static! {
+    pub static ref SIGN_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "signatrust_sign_requests_total",
+        "Number of sign requests",
+        &["key_type", "key_name", "method", "result"]
+    ).expect("register sign requests metric");
+    pub static ref SIGN_LATENCY: HistogramVec = register_histogram_vec!(
+        "signatrust_sign_duration_seconds",
+        "Latency of sign requests in seconds",
+        &["key_type", "key_name", "method"]
+    ).expect("register sign latency metric");
+    //1KB to 1GB
+    pub static ref SIGN_PAYLOAD_BYTES: HistogramVec = register_histogram_vec!(
+        "signatrust_sign_payload_bytes",
+        "Size of content transferred in sign requests",
+        &["key_type", "method"],
+        exponential_buckets(1024.0, 4.0, 11).expect("payload buckets")
+    ).expect("register sign payload metric");
+    pub static ref ERRORS: IntCounterVec = register_int_counter_vec!(
+        "signatrust_errors_total",
+        "Number of errors returned to callers",
+        &["error"]
+    ).expect("register errors metric");
+    pub static ref DATA_KEY_CACHE: IntCounterVec = register_int_counter_vec!(
+        "signatrust_data_key_cache_total",
+        "Number of data key container lookups",
+        &["result"]
+    ).expect("register data key cache metric");
+    pub static ref SIGN_PLUGIN_CACHE: IntCounterVec = register_int_counter_vec!(
+        "signatrust_sign_plugin_cache_total",
+        "Number of sign plugin cache lookups",
+        &["result"]
+    ).expect("register sign plugin cache metric");
+    pub static ref CLUSTER_KEY_DECRYPTS: IntCounter = register_int_counter!(
+        "signatrust_cluster_key_decrypts_total",
+        "Number of cluster key decryptions"
+    ).expect("register cluster key decrypts metric");
+    pub static ref AUDIT_RECORDS_DROPPED: IntCounter = register_int_counter!(
+        "signatrust_audit_records_dropped_total",
+        "Number of audit records dropped since the queue is unavailable or the insert kept failing at shutdown"
+    ).expect("register audit records dropped metric");
+    pub static ref KMS_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "signatrust_kms_requests_total",
+        "Number of kms provider invocations",
+        &["provider", "operation", "result"]
+    ).expect("register kms requests metric");
+}
+
+pub fn result_label<T>(result: &Result<T>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(_) => "failure",
+    }
+}
+
+//sign requests are labeled with the resolved key only
+pub fn observe_sign<T>(method: &str, key: Option<&DataKey>, payload: usize, start: Instant, result: &Result<T>) {
+    let (key_type, key_name) = match key {
+        Some(key) => (key.key_type.to_string(), key.name.clone()),
+        None => (UNKNOWN.to_string(), UNKNOWN.to_string()),
+    };
+    SIGN_REQUESTS.with_label_values(&[&key_type, &key_name, method, result_label(result)]).inc();
+    SIGN_LATENCY.with_label_values(&[&key_type, &key_name, method]).observe(start.elapsed().as_secs_f64());
+    SIGN_PAYLOAD_BYTES.with_label_values(&[&key_type, method]).observe(payload as f64);
+    if let Err(err) = result {
+        record_error(err);
+    }
+}
+
+pub fn record_error(error: &Error) {
+    ERRORS.with_label_values(&[error_label(error)]).inc();
+}
+
+//variant name of the error, for instance `DatabaseError`
+pub fn error_label(error: &Error) -> &'static str {
+    match error {
+        Error::DatabaseError(_) => "DatabaseError",
+        Error::ConfigError(_) => "ConfigError",
+        Error::IOError(_) => "IOError",
+        Error::UnsupportedTypeError(_) => "UnsupportedTypeError",
+        Error::KMSInvokeError(_) => "KMSInvokeError",
+        Error::SerializeError(_) => "SerializeError",
+        Error::HttpRequest(_) => "HttpRequest",
+        Error::ConvertError(_) => "ConvertError",
+        Error::EncodeError(_) => "EncodeError",
+        Error::ClusterError(_) => "ClusterError",
+        Error::KeyParseError(_) => "KeyParseError",
+        Error::SignError(_, _) => "SignError",
+        Error::PGPInvokeError(_) => "PGPInvokeError",
+        Error::X509InvokeError(_) => "X509InvokeError",
+        Error::PKCS11InvokeError(_) => "PKCS11InvokeError",
+        Error::TimestampError(_) => "TimestampError",
+        Error::ParameterError(_) => "ParameterError",
+        Error::KeyExpiredError(_) => "KeyExpiredError",
+        Error::AuditError(_) => "AuditError",
+        Error::NotFoundError => "NotFoundError",
+        Error::UnauthorizedError => "UnauthorizedError",
+        Error::ForbiddenError(_) => "ForbiddenError",
+        Error::InvalidCookieKeyError => "InvalidCookieKeyError",
+        Error::AuthError(_) => "AuthError",
+        Error::FileNotSupportError(_) => "FileNotSupportError",
+        Error::NoFileCandidateError => "NoFileCandidateError",
+        Error::SplitFileError(_) => "SplitFileError",
+        Error::RemoteSignError(_) => "RemoteSignError",
+        Error::AssembleFileError(_) => "AssembleFileError",
+        Error::VerifyError(_) => "VerifyError",
+        Error::WalkDirectoryError(_) => "WalkDirectoryError",
+        Error::RpmParseError(_) => "RpmParseError",
+        Error::InvalidArgumentError(_) => "InvalidArgumentError",
+        Error::BincodeError(_) => "BincodeError",
+        Error::PartialFailureError => "PartialFailureError",
+    }
+}
+
+//metrics in prometheus text format
+pub fn render() -> Result<String> {
+    TextEncoder::new()
+        .encode_to_string(&prometheus::gather())
+        .map_err(|e| Error::SerializeError(e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_label() {
+        assert_eq!(error_label(&Error::SignError("key".to_string(), "reason (detail)".to_string())), "SignError");
+        assert_eq!(error_label(&Error::NotFoundError), "NotFoundError");
+        let before = ERRORS.with_label_values(&["KeyExpiredError"]).get();
+        record_error(&Error::KeyExpiredError("key".to_string()));
+        assert_eq!(ERRORS.with_label_values(&["KeyExpiredError"]).get(), before + 1);
+    }
+}
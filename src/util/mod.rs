@@ -1,4 +1,10 @@
 pub mod config;
 pub mod error;
 pub mod key;
-pub mod signer_container;
\ No newline at end of file
+pub mod der;
+pub mod digest;
+pub mod signer_container;
+pub mod metrics;
+pub mod cache;
+#[cfg(test)]
+pub mod test_support;
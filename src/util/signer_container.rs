@@ -1,10 +1,19 @@
 use std::collections::HashMap;
-use std::sync::{Arc};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Duration, TimeZone, Utc};
 use tokio::sync::RwLock;
 use crate::util::error::Result;
+use crate::util::metrics;
+use crate::util::cache::SingleFlight;
 use crate::domain::datakey::repository::Repository;
 
-use crate::domain::datakey::entity::DataKey;
+use crate::domain::datakey::entity::{DataKey, KeyRole};
+
+//(update_at, id) of the last key which has been synchronized from database
+type SyncCursor = Arc<RwLock<Option<(DateTime<Utc>, i32)>>>;
+//roles of the principals indexed by key id, `None` if the principal has no role
+type KeyRoles = Arc<RwLock<HashMap<i32, HashMap<String, Option<KeyRole>>>>>;
 
 pub struct DataKeyContainer<R>
 where
@@ -12,6 +21,12 @@ where
 {
     repository: R,
     containers: Arc<RwLock<HashMap<String, DataKey>>>,
+    synchronized_at: SyncCursor,
+    //bumped by every refresh, the keys loaded across a refresh aren't cached
+    refreshes: Arc<AtomicU64>,
+    flights: SingleFlight<String>,
+    //dropped along with the changed keys on refresh
+    roles: KeyRoles,
 }
 
 impl<R> DataKeyContainer<R>
@@ -21,21 +36,188 @@ where
     pub fn new(repository: R) -> Self {
         Self {
             repository,
-            containers: Arc::new(RwLock::new(HashMap::new()))
+            containers: Arc::new(RwLock::new(HashMap::new())),
+            synchronized_at: Arc::new(RwLock::new(None)),
+            refreshes: Arc::new(AtomicU64::new(0)),
+            flights: SingleFlight::new(),
+            roles: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
     pub async fn get_data_key(&self, key_type: String, key_name: String) -> Result<DataKey> {
         let identity = self.get_identity(&key_type, &key_name);
         if let Some(dk) = self.containers.read().await.get(&identity) {
+            metrics::DATA_KEY_CACHE.with_label_values(&["hit"]).inc();
             return Ok((*dk).clone())
         }
+        metrics::DATA_KEY_CACHE.with_label_values(&["miss"]).inc();
+        //the concurrent requests of the same key wait for the first query
+        let _flight = self.flights.acquire(identity.clone()).await;
+        if let Some(dk) = self.containers.read().await.get(&identity) {
+            return Ok((*dk).clone())
+        }
+        let refreshes = self.refreshes.load(Ordering::SeqCst);
         let data_key = self.repository.get_enabled_key_by_type_and_name(key_type, key_name).await?;
-        self.containers.write().await.insert(identity, data_key.clone());
+        self.cache_key(identity, data_key.clone(), refreshes).await;
         Ok(data_key)
     }
 
+    //the key read before a refresh could be the stale one which the refresh has dropped, while the cursor has moved
+    //past it, it's not cached then and will be read again on next use.
+    async fn cache_key(&self, identity: String, data_key: DataKey, refreshes: u64) {
+        let mut containers = self.containers.write().await;
+        if self.refreshes.load(Ordering::SeqCst) == refreshes {
+            containers.insert(identity, data_key);
+        }
+    }
+
+    //the principals without any role are cached as well, since the requests of them are rejected repeatedly
+    pub async fn get_role(&self, key_id: i32, principal: &str) -> Result<Option<KeyRole>> {
+        if let Some(role) = self.roles.read().await.get(&key_id).and_then(|roles| roles.get(principal)) {
+            return Ok(role.clone());
+        }
+        let role = self.repository.get_role(key_id, principal).await?;
+        self.roles.write().await.entry(key_id).or_default().insert(principal.to_string(), role.clone());
+        Ok(role)
+    }
+
+    //the permissions changed by this server take effect immediately, the other servers pick them up on refresh
+    pub async fn invalidate_roles(&self, key_id: i32) {
+        self.roles.write().await.remove(&key_id);
+    }
+
+    //drop the cached keys which have been changed in database since last refresh and return the changed keys,
+    //the cached key is only dropped when it differs from the one in database, the keys are reloaded on next use.
+    //on the first refresh, the keys which are not cached are not regarded as changed.
+    //update_at is of limited precision (seconds in MySQL, milliseconds in sqlite), a key updated within the same
+    //tick as the last synchronized key could be ordered before it, therefore the last second is always read again.
+    pub async fn refresh(&self) -> Result<Vec<DataKey>> {
+        let synchronized_at = *self.synchronized_at.read().await;
+        let (since, id) = synchronized_at.unwrap_or_else(|| (Utc.timestamp_opt(0, 0).unwrap(), 0));
+        let keys = self.repository.get_updated_since(since - Duration::seconds(1), id).await?;
+        if let Some(last) = keys.iter().map(|key| (key.update_at, key.id)).max() {
+            *self.synchronized_at.write().await = Some(last.max((since, id)));
+        }
+        let mut changed = vec![];
+        let mut containers = self.containers.write().await;
+        self.refreshes.fetch_add(1, Ordering::SeqCst);
+        let mut roles = self.roles.write().await;
+        for key in keys.into_iter() {
+            let identity = self.get_identity(&key.key_type.to_string(), &key.name);
+            let updated = match containers.get(&identity) {
+                Some(cached) => is_changed(cached, &key),
+                //keys read again are reported only once
+                None => synchronized_at.is_some() && (key.update_at, key.id) > (since, id),
+            };
+            if !updated {
+                continue
+            }
+            roles.remove(&key.id);
+            if containers.remove(&identity).is_some() {
+                info!("cached data key {} is invalidated due to update", key.name);
+            }
+            changed.push(key);
+        }
+        Ok(changed)
+    }
+
     fn get_identity(&self, key_type: &str, key_name: &str) -> String {
         format!("{}-{}",key_type, key_name)
     }
-}
\ No newline at end of file
+}
+
+//the keys could be updated more than once within the precision of update_at
+fn is_changed(cached: &DataKey, key: &DataKey) -> bool {
+    cached.update_at != key.update_at
+        || cached.key_state.to_string() != key.key_state.to_string()
+        || cached.soft_delete != key.soft_delete
+        || cached.expire_at != key.expire_at
+}
+
+impl<R> Clone for DataKeyContainer<R>
+where
+    R: Repository + Clone
+{
+    //the cloned container shares the cached keys
+    fn clone(&self) -> Self {
+        Self {
+            repository: self.repository.clone(),
+            containers: self.containers.clone(),
+            synchronized_at: self.synchronized_at.clone(),
+            refreshes: self.refreshes.clone(),
+            flights: self.flights.clone(),
+            roles: self.roles.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::domain::datakey::entity::{KeyPermission, KeyState};
+    use crate::infra::database::model::datakey::repository::DataKeyRepository;
+    use crate::util::test_support::{data_key, memory_pool};
+
+    #[tokio::test]
+    async fn test_refresh() {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool);
+        let key_a = repository.create(data_key("key_a")).await.unwrap();
+        repository.create(data_key("key_b")).await.unwrap();
+        let container = DataKeyContainer::new(repository.clone());
+        let cached = container.get_data_key("pgp".to_string(), "key_a".to_string()).await.unwrap();
+        assert_eq!(cached.private_key, vec![1, 2, 3]);
+
+        //keys cached are up to date, nothing is invalidated on first refresh and the last key isn't returned again
+        assert!(container.refresh().await.unwrap().is_empty());
+        assert!(container.refresh().await.unwrap().is_empty());
+        assert!(container.containers.read().await.contains_key("pgp-key_a"));
+
+        repository.update_state(key_a.id, KeyState::Disabled).await.unwrap();
+        let changed = container.refresh().await.unwrap();
+        assert_eq!(changed.iter().map(|k| k.name.as_str()).collect::<Vec<&str>>(), vec!["key_a"]);
+        assert!(changed[0].private_key.is_empty());
+        assert!(!container.containers.read().await.contains_key("pgp-key_a"));
+        assert!(container.refresh().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_key_read_across_refresh() {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool);
+        let key = repository.create(data_key("key_a")).await.unwrap();
+        let container = DataKeyContainer::new(repository.clone());
+        container.refresh().await.unwrap();
+
+        //the key is read right before another server disables it and this server refreshes
+        let refreshes = container.refreshes.load(Ordering::SeqCst);
+        let stale = repository.get_enabled_key_by_type_and_name("pgp".to_string(), "key_a".to_string()).await.unwrap();
+        repository.update_state(key.id, KeyState::Disabled).await.unwrap();
+        container.refresh().await.unwrap();
+        container.cache_key("pgp-key_a".to_string(), stale, refreshes).await;
+        assert!(!container.containers.read().await.contains_key("pgp-key_a"));
+        assert!(container.get_data_key("pgp".to_string(), "key_a".to_string()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cached_roles() {
+        let pool = memory_pool().await;
+        let repository = DataKeyRepository::new(pool);
+        let key = repository.create(data_key("key_a")).await.unwrap();
+        let container = DataKeyContainer::new(repository.clone());
+        container.get_data_key("pgp".to_string(), "key_a".to_string()).await.unwrap();
+        container.refresh().await.unwrap();
+        assert_eq!(container.get_role(key.id, "signer@example.com").await.unwrap(), None);
+
+        //granted by another server, the cached role is kept until the key is refreshed
+        let permission = KeyPermission::new(key.id, "signer@example.com".to_string(), KeyRole::Signer);
+        repository.create_permission(permission.clone()).await.unwrap();
+        assert_eq!(container.get_role(key.id, "signer@example.com").await.unwrap(), None);
+        container.refresh().await.unwrap();
+        assert_eq!(container.get_role(key.id, "signer@example.com").await.unwrap(), Some(KeyRole::Signer));
+
+        repository.delete_permission(permission).await.unwrap();
+        container.invalidate_roles(key.id).await;
+        assert_eq!(container.get_role(key.id, "signer@example.com").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::domain::datakey::entity::{DataKey, KeyState, KeyType};
+use crate::infra::database::pool::{connect, migrate, DbPool};
+use chrono::{Duration, Utc};
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::hash::MessageDigest;
+use openssl::pkey::{PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::x509::{X509, X509Extension, X509NameBuilder};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+//enabled openpgp key expiring in 30 days, the key materials are placeholders which are never parsed
+pub fn data_key(name: &str) -> DataKey {
+    let now = Utc::now();
+    DataKey {
+        id: 0,
+        name: name.to_string(),
+        description: "".to_string(),
+        user: "signatrust".to_string(),
+        email: "signatrust@example.com".to_string(),
+        attributes: HashMap::new(),
+        key_type: KeyType::OpenPGP,
+        private_key: vec![1, 2, 3],
+        public_key: vec![4, 5, 6],
+        certificate: vec![],
+        create_at: now,
+        expire_at: now + Duration::days(30),
+        soft_delete: false,
+        key_state: KeyState::Enabled,
+        update_at: now,
+    }
+}
+
+//every in memory connection owns a separate database, hence only one connection
+pub async fn memory_pool() -> DbPool {
+    let pool = connect("sqlite::memory:", 1).await.expect("connect to sqlite in memory");
+    migrate(&pool).await.expect("migrate sqlite in memory");
+    pool
+}
+
+pub fn rsa_key() -> PKey<Private> {
+    PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap()
+}
+
+//self signed certificate valid for 30 days, the extensions such as extended key usage are appended in order
+pub fn certificate(common_name: &str, private_key: &PKey<Private>, extensions: Vec<X509Extension>) -> X509 {
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", common_name).unwrap();
+    let name = name.build();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(private_key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(30).unwrap()).unwrap();
+    for extension in extensions {
+        builder.append_extension(extension).unwrap();
+    }
+    builder.sign(private_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    //header names are in lowercase
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+//local http stand-in of the remote services such as kms and tsa, which answers the given number of requests,
+//one connection per request, with the status and body of the content type returned by handler
+pub fn serve<F, B>(requests: usize, content_type: &'static str, mut handler: F) -> String
+    where F: FnMut(&Request) -> (u16, B) + Send + 'static, B: Into<Vec<u8>> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for _ in 0..requests {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut data = vec![];
+            let mut buffer = [0u8; 4096];
+            let request = loop {
+                let size = stream.read(&mut buffer).unwrap();
+                data.extend_from_slice(&buffer[..size]);
+                let text = String::from_utf8_lossy(&data).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let mut lines = text[..end].lines();
+                    let mut start_line = lines.next().unwrap().split(' ');
+                    let (method, path) = (start_line.next().unwrap().to_string(), start_line.next().unwrap().to_string());
+                    let headers: HashMap<String, String> = lines
+                        .filter_map(|line| line.split_once(':'))
+                        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+                        .collect();
+                    let length: usize = headers.get("content-length").map(|l| l.parse().unwrap()).unwrap_or(0);
+                    if data.len() >= end + 4 + length {
+                        break Request { method, path, headers, body: data[end + 4..end + 4 + length].to_vec() };
+                    }
+                }
+            };
+            let (status, body) = handler(&request);
+            let body = body.into();
+            stream.write_all(format!(
+                "HTTP/1.1 {} STAND-IN\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                status, content_type, body.len()).as_bytes()).unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+    address
+}